@@ -0,0 +1,19 @@
+CREATE TABLE journal_entries
+(
+    id           uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    entry_type   varchar                   NOT NULL,
+    reference_id uuid,
+    created_at   timestamptz DEFAULT NOW() NOT NULL
+);
+
+CREATE TABLE journal_lines
+(
+    id           uuid           DEFAULT uuid_generate_v4() PRIMARY KEY,
+    entry_id     uuid REFERENCES journal_entries (id) NOT NULL,
+    account_kind varchar                              NOT NULL,
+    account_key  varchar                              NOT NULL,
+    debit        NUMERIC(12, 4) DEFAULT 0             NOT NULL CHECK (debit >= 0),
+    credit       NUMERIC(12, 4) DEFAULT 0             NOT NULL CHECK (credit >= 0)
+);
+
+CREATE INDEX journal_lines_account_idx ON journal_lines (account_kind, account_key);
@@ -0,0 +1,5 @@
+ALTER TABLE bank_transactions
+    DROP COLUMN idempotency_key;
+
+ALTER TABLE account_transactions
+    DROP COLUMN idempotency_key;
@@ -0,0 +1,5 @@
+ALTER TABLE bank_transactions
+    ADD COLUMN idempotency_key varchar UNIQUE;
+
+ALTER TABLE account_transactions
+    ADD COLUMN idempotency_key varchar UNIQUE;
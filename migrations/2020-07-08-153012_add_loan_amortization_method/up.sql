@@ -0,0 +1,2 @@
+ALTER TABLE loans
+    ADD COLUMN amortization_method VARCHAR DEFAULT 'level_payment' NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE loans
+    DROP COLUMN approved_by,
+    DROP COLUMN approved_at;
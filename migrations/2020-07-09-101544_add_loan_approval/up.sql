@@ -0,0 +1,3 @@
+ALTER TABLE loans
+    ADD COLUMN approved_by uuid REFERENCES users (id),
+    ADD COLUMN approved_at timestamptz;
@@ -0,0 +1,7 @@
+ALTER TABLE loan_payments
+    DROP COLUMN late_fee,
+    DROP COLUMN fee_transaction_id;
+
+ALTER TABLE loans
+    DROP COLUMN days_past_due,
+    DROP COLUMN delinquency_bucket;
@@ -0,0 +1,7 @@
+ALTER TABLE loans
+    ADD COLUMN days_past_due      INTEGER DEFAULT 0         NOT NULL,
+    ADD COLUMN delinquency_bucket VARCHAR DEFAULT 'current' NOT NULL;
+
+ALTER TABLE loan_payments
+    ADD COLUMN late_fee           NUMERIC(12, 4) DEFAULT 0 NOT NULL,
+    ADD COLUMN fee_transaction_id uuid REFERENCES bank_transactions (id);
@@ -0,0 +1,4 @@
+DROP TABLE accrual_runs;
+
+ALTER TABLE loans
+    DROP COLUMN interest_accrued_through;
@@ -0,0 +1,12 @@
+ALTER TABLE loans
+    ADD COLUMN interest_accrued_through date;
+
+CREATE TABLE accrual_runs
+(
+    id               uuid           DEFAULT uuid_generate_v4() PRIMARY KEY,
+    run_date         date UNIQUE                   NOT NULL,
+    started_at       timestamptz    DEFAULT NOW()  NOT NULL,
+    completed_at     timestamptz,
+    loans_accrued    INTEGER        DEFAULT 0      NOT NULL,
+    interest_accrued NUMERIC(12, 4) DEFAULT 0      NOT NULL
+);
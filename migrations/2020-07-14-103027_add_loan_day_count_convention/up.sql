@@ -0,0 +1,2 @@
+ALTER TABLE loans
+    ADD COLUMN day_count_convention VARCHAR DEFAULT 'thirty_360' NOT NULL;
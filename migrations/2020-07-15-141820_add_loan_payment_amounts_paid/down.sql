@@ -0,0 +1,5 @@
+ALTER TABLE loan_payments
+    DROP COLUMN principal_paid,
+    DROP COLUMN interest_paid,
+    DROP COLUMN fees_paid,
+    DROP COLUMN overpaid;
@@ -0,0 +1,5 @@
+ALTER TABLE loan_payments
+    ADD COLUMN principal_paid NUMERIC(12, 4) DEFAULT 0 NOT NULL,
+    ADD COLUMN interest_paid  NUMERIC(12, 4) DEFAULT 0 NOT NULL,
+    ADD COLUMN fees_paid      NUMERIC(12, 4) DEFAULT 0 NOT NULL,
+    ADD COLUMN overpaid       NUMERIC(12, 4) DEFAULT 0 NOT NULL;
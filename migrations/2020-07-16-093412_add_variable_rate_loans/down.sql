@@ -0,0 +1,10 @@
+DROP TABLE loan_rate_changes;
+
+ALTER TABLE loans
+    DROP COLUMN benchmark,
+    DROP COLUMN rate_margin,
+    DROP COLUMN rate_floor,
+    DROP COLUMN rate_cap,
+    DROP COLUMN rate_reset_frequency;
+
+DROP TABLE benchmark_rates;
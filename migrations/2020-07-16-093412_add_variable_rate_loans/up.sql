@@ -0,0 +1,26 @@
+CREATE TABLE benchmark_rates
+(
+    id             uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    benchmark      VARCHAR                                NOT NULL,
+    effective_date date                                   NOT NULL,
+    rate           SMALLINT                               NOT NULL,
+    created_at     timestamptz DEFAULT NOW()              NOT NULL,
+    UNIQUE (benchmark, effective_date)
+);
+
+ALTER TABLE loans
+    ADD COLUMN benchmark            VARCHAR,
+    ADD COLUMN rate_margin          SMALLINT DEFAULT 0 NOT NULL,
+    ADD COLUMN rate_floor           SMALLINT,
+    ADD COLUMN rate_cap             SMALLINT,
+    ADD COLUMN rate_reset_frequency SMALLINT DEFAULT 0 NOT NULL;
+
+CREATE TABLE loan_rate_changes
+(
+    id             uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    loan_id        uuid REFERENCES loans (id)             NOT NULL,
+    effective_date date                                   NOT NULL,
+    interest_rate  SMALLINT                               NOT NULL,
+    benchmark_rate SMALLINT,
+    created_at     timestamptz DEFAULT NOW()              NOT NULL
+);
@@ -0,0 +1,10 @@
+ALTER TABLE accrual_runs
+    DROP COLUMN accounts_accrued,
+    DROP COLUMN savings_interest_accrued;
+
+ALTER TABLE accounts
+    DROP COLUMN savings_product,
+    DROP COLUMN accrued_interest,
+    DROP COLUMN interest_accrued_through;
+
+DROP TABLE savings_products;
@@ -0,0 +1,16 @@
+CREATE TABLE savings_products
+(
+    name       varchar PRIMARY KEY,
+    vault_name varchar REFERENCES vaults (name) NOT NULL,
+    apy        SMALLINT                         NOT NULL,
+    created_at timestamptz DEFAULT NOW()        NOT NULL
+);
+
+ALTER TABLE accounts
+    ADD COLUMN savings_product         varchar REFERENCES savings_products (name),
+    ADD COLUMN accrued_interest        NUMERIC(12, 4) DEFAULT 0 NOT NULL,
+    ADD COLUMN interest_accrued_through date;
+
+ALTER TABLE accrual_runs
+    ADD COLUMN accounts_accrued         INTEGER        DEFAULT 0 NOT NULL,
+    ADD COLUMN savings_interest_accrued NUMERIC(12, 4) DEFAULT 0 NOT NULL;
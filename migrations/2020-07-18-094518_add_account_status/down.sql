@@ -0,0 +1,10 @@
+ALTER TABLE accounts
+    ADD COLUMN is_open boolean DEFAULT true NOT NULL;
+
+UPDATE accounts
+SET is_open = false
+WHERE status = 'closed';
+
+ALTER TABLE accounts
+    DROP COLUMN status,
+    DROP COLUMN closed_at;
@@ -0,0 +1,10 @@
+ALTER TABLE accounts
+    ADD COLUMN status    VARCHAR DEFAULT 'open' NOT NULL,
+    ADD COLUMN closed_at timestamptz;
+
+UPDATE accounts
+SET status = 'closed'
+WHERE NOT is_open;
+
+ALTER TABLE accounts
+    DROP COLUMN is_open;
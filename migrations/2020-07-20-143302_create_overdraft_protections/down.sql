@@ -0,0 +1,7 @@
+ALTER TABLE account_transactions
+    DROP COLUMN overdraft_protection;
+
+ALTER TABLE bank_transactions
+    DROP COLUMN overdraft_protection;
+
+DROP TABLE overdraft_protections;
@@ -0,0 +1,18 @@
+CREATE TABLE overdraft_protections
+(
+    account_id               uuid REFERENCES accounts (id) PRIMARY KEY,
+    linked_account_id        uuid REFERENCES accounts (id),
+    overdraft_limit          NUMERIC(12, 4) DEFAULT 0         NOT NULL CHECK (overdraft_limit >= 0),
+    overdraft_fee            NUMERIC(12, 4) DEFAULT 0         NOT NULL CHECK (overdraft_fee >= 0),
+    interest_rate            SMALLINT       DEFAULT 0         NOT NULL,
+    vault_name               varchar REFERENCES vaults (name) NOT NULL,
+    accrued_interest         NUMERIC(12, 4) DEFAULT 0         NOT NULL,
+    interest_accrued_through date                             NOT NULL,
+    created_at               timestamptz    DEFAULT NOW()     NOT NULL
+);
+
+ALTER TABLE bank_transactions
+    ADD COLUMN overdraft_protection varchar;
+
+ALTER TABLE account_transactions
+    ADD COLUMN overdraft_protection varchar;
@@ -0,0 +1,14 @@
+DROP TABLE fx_conversions;
+DROP TABLE fx_rates;
+
+ALTER TABLE loans
+    DROP COLUMN currency;
+
+ALTER TABLE bank_transactions
+    DROP COLUMN currency;
+
+ALTER TABLE vaults
+    DROP COLUMN currency;
+
+ALTER TABLE accounts
+    DROP COLUMN currency;
@@ -0,0 +1,35 @@
+ALTER TABLE accounts
+    ADD COLUMN currency VARCHAR(3) DEFAULT 'USD' NOT NULL;
+
+ALTER TABLE vaults
+    ADD COLUMN currency VARCHAR(3) DEFAULT 'USD' NOT NULL;
+
+ALTER TABLE bank_transactions
+    ADD COLUMN currency VARCHAR(3) DEFAULT 'USD' NOT NULL;
+
+ALTER TABLE loans
+    ADD COLUMN currency VARCHAR(3) DEFAULT 'USD' NOT NULL;
+
+CREATE TABLE fx_rates
+(
+    id             uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    base_currency  VARCHAR(3)                             NOT NULL,
+    quote_currency VARCHAR(3)                             NOT NULL,
+    rate           NUMERIC(18, 8)                         NOT NULL CHECK (rate > 0),
+    effective_date date                                   NOT NULL,
+    created_at     timestamptz DEFAULT NOW()              NOT NULL,
+    UNIQUE (base_currency, quote_currency, effective_date)
+);
+
+CREATE TABLE fx_conversions
+(
+    id            uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    sender_id     uuid REFERENCES accounts (id)          NOT NULL,
+    receiver_id   uuid REFERENCES accounts (id)          NOT NULL,
+    sell_amount   NUMERIC(12, 4)                         NOT NULL,
+    sell_currency VARCHAR(3)                             NOT NULL,
+    buy_amount    NUMERIC(12, 4)                         NOT NULL,
+    buy_currency  VARCHAR(3)                             NOT NULL,
+    rate          NUMERIC(18, 8)                         NOT NULL,
+    created_at    timestamptz DEFAULT NOW()              NOT NULL
+);
@@ -0,0 +1,30 @@
+CREATE TABLE statements
+(
+    id                uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    account_id        uuid REFERENCES accounts (id)          NOT NULL,
+    period_start      date                                   NOT NULL,
+    period_end        date                                   NOT NULL,
+    currency          VARCHAR(3)                             NOT NULL,
+    opening_balance   NUMERIC(12, 4)                         NOT NULL,
+    closing_balance   NUMERIC(12, 4)                         NOT NULL,
+    interest_credited NUMERIC(12, 4)                         NOT NULL,
+    interest_charged  NUMERIC(12, 4)                         NOT NULL,
+    fees_charged      NUMERIC(12, 4)                         NOT NULL,
+    created_at        timestamptz DEFAULT NOW()              NOT NULL,
+    UNIQUE (account_id, period_start)
+);
+
+CREATE TABLE statement_lines
+(
+    id              uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    statement_id    uuid REFERENCES statements (id)        NOT NULL,
+    line_number     INTEGER                                NOT NULL,
+    transaction_id  uuid                                   NOT NULL,
+    entry_type      varchar                                NOT NULL,
+    amount          NUMERIC(12, 4)                         NOT NULL,
+    balance         NUMERIC(12, 4)                         NOT NULL,
+    vault_name      varchar,
+    counterparty_id uuid,
+    posted_at       timestamptz                            NOT NULL,
+    UNIQUE (statement_id, line_number)
+);
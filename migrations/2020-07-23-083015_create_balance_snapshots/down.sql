@@ -0,0 +1,2 @@
+DROP TABLE vault_balance_snapshots;
+DROP TABLE account_balance_snapshots;
@@ -0,0 +1,19 @@
+CREATE TABLE account_balance_snapshots
+(
+    id         uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    account_id uuid REFERENCES accounts (id)          NOT NULL,
+    balance    NUMERIC(12, 4)                         NOT NULL,
+    taken_at   timestamptz                            NOT NULL,
+    created_at timestamptz DEFAULT NOW()              NOT NULL,
+    UNIQUE (account_id, taken_at)
+);
+
+CREATE TABLE vault_balance_snapshots
+(
+    id         uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    vault_name varchar REFERENCES vaults (name)       NOT NULL,
+    balance    NUMERIC(12, 4)                         NOT NULL,
+    taken_at   timestamptz                            NOT NULL,
+    created_at timestamptz DEFAULT NOW()              NOT NULL,
+    UNIQUE (vault_name, taken_at)
+);
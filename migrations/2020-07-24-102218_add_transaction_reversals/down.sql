@@ -0,0 +1,5 @@
+ALTER TABLE account_transactions
+    DROP COLUMN reversal_of;
+
+ALTER TABLE bank_transactions
+    DROP COLUMN reversal_of;
@@ -0,0 +1,5 @@
+ALTER TABLE bank_transactions
+    ADD COLUMN reversal_of uuid UNIQUE REFERENCES bank_transactions (id);
+
+ALTER TABLE account_transactions
+    ADD COLUMN reversal_of uuid UNIQUE REFERENCES account_transactions (id);
@@ -0,0 +1,31 @@
+CREATE TABLE scheduled_transfers
+(
+    id                uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    sender_id         uuid REFERENCES accounts (id)          NOT NULL,
+    receiver_id       uuid REFERENCES accounts (id)          NOT NULL,
+    amount            NUMERIC(12, 4)                         NOT NULL CHECK (amount > 0),
+    currency          VARCHAR(3)                             NOT NULL,
+    frequency         varchar                                NOT NULL,
+    day_of_month      SMALLINT CHECK (day_of_month BETWEEN 1 AND 31),
+    start_date        date                                   NOT NULL,
+    end_date          date,
+    next_run_date     date,
+    next_attempt_date date,
+    retries           INTEGER     DEFAULT 0                  NOT NULL,
+    cancelled_at      timestamptz,
+    created_at        timestamptz DEFAULT NOW()              NOT NULL
+);
+
+CREATE INDEX scheduled_transfers_next_attempt_date_idx ON scheduled_transfers (next_attempt_date);
+
+CREATE TABLE scheduled_transfer_runs
+(
+    id                    uuid        DEFAULT uuid_generate_v4() PRIMARY KEY,
+    scheduled_transfer_id uuid REFERENCES scheduled_transfers (id) NOT NULL,
+    scheduled_date        date                                     NOT NULL,
+    run_date              date                                     NOT NULL,
+    outcome               varchar                                  NOT NULL,
+    transaction_id        uuid REFERENCES account_transactions (id),
+    error                 varchar,
+    created_at            timestamptz DEFAULT NOW()                NOT NULL
+);
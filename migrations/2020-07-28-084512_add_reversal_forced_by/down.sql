@@ -0,0 +1,5 @@
+ALTER TABLE account_transactions
+    DROP COLUMN forced_by;
+
+ALTER TABLE bank_transactions
+    DROP COLUMN forced_by;
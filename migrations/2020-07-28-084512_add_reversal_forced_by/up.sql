@@ -0,0 +1,5 @@
+ALTER TABLE bank_transactions
+    ADD COLUMN forced_by uuid REFERENCES users (id);
+
+ALTER TABLE account_transactions
+    ADD COLUMN forced_by uuid REFERENCES users (id);
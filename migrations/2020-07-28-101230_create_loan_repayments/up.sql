@@ -0,0 +1,12 @@
+CREATE TABLE loan_repayments
+(
+    principal_transaction_id uuid PRIMARY KEY REFERENCES bank_transactions (id),
+    loan_id                  uuid REFERENCES loans (id)      NOT NULL,
+    amount                   NUMERIC(12, 4)                  NOT NULL,
+    fees                     NUMERIC(12, 4)                  NOT NULL,
+    past_due_interest        NUMERIC(12, 4)                  NOT NULL,
+    current_interest         NUMERIC(12, 4)                  NOT NULL,
+    principal                NUMERIC(12, 4)                  NOT NULL,
+    prepayment               NUMERIC(12, 4)                  NOT NULL,
+    created_at               timestamptz DEFAULT NOW()       NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE fx_conversions
+    DROP COLUMN overdraft_protection,
+    DROP COLUMN idempotency_key;
@@ -0,0 +1,3 @@
+ALTER TABLE fx_conversions
+    ADD COLUMN idempotency_key varchar UNIQUE,
+    ADD COLUMN overdraft_protection varchar;
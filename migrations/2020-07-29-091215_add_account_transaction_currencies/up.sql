@@ -0,0 +1,7 @@
+ALTER TABLE account_transactions
+    ADD COLUMN currency VARCHAR(3) DEFAULT 'USD' NOT NULL;
+
+UPDATE account_transactions
+SET currency = accounts.currency
+FROM accounts
+WHERE accounts.id = account_transactions.sender_id;
@@ -1,4 +1,5 @@
 use std::borrow::Borrow;
+use std::str::FromStr;
 use std::ops::Neg;
 use std::time::SystemTime;
 
@@ -12,16 +13,18 @@ use diesel::{
 	serialize,
 	sql_types::Varchar,
 };
+use serde::Serialize;
 use strum;
 use strum_macros::{Display, EnumString};
 
 use crate::db;
+use crate::money::{Currency, Money};
 use crate::schema::accounts;
-use crate::types::Time;
+use crate::types::{Date, Time};
 
 /// The user's financial account maintained by the bank to hold and manage funds
 /// A user may have multiple accounts
-#[derive(Queryable, Identifiable, PartialEq, Debug)]
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
 pub struct Account {
 	pub id: uuid::Uuid,
 	/// the account owner's user id
@@ -30,8 +33,34 @@ pub struct Account {
 	/// the account balance
 	pub amount: BigDecimal,
 	pub created_at: Time,
-	/// indicates whether an account is currently open/closed for use
-	pub is_open: bool,
+	/// the savings product the account earns interest with, if any
+	pub savings_product: Option<String>,
+	/// interest earned but not yet credited to the account
+	pub accrued_interest: BigDecimal,
+	/// the day interest was last accrued up to, exclusive
+	pub interest_accrued_through: Option<Date>,
+	/// whether the account can currently be used to move money
+	pub status: AccountStatus,
+	pub closed_at: Option<Time>,
+	/// the currency the account's balance is held in
+	pub currency: Currency,
+}
+
+impl Account {
+	/// The account's balance in its currency
+	pub fn balance(&self) -> Money {
+		Money::new(self.amount.clone(), self.currency)
+	}
+	
+	/// Checks whether money can move into or out of the account
+	pub fn is_open(&self) -> bool {
+		self.status == AccountStatus::Open
+	}
+	
+	/// Checks whether the account earns interest
+	pub fn is_interest_bearing(&self) -> bool {
+		self.savings_product.is_some()
+	}
 }
 
 #[derive(Insertable)]
@@ -39,11 +68,13 @@ pub struct Account {
 pub struct NewAccount {
 	pub user_id: uuid::Uuid,
 	pub account_type: AccountType,
+	pub currency: Currency,
 }
 
-#[derive(AsExpression, FromSqlRow, PartialEq, EnumString, Display, Debug)]
+#[derive(AsExpression, FromSqlRow, Serialize, PartialEq, EnumString, Display, Debug)]
 #[sql_type = "Varchar"]
 #[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum AccountType {
 	Checking,
 	Savings,
@@ -67,26 +98,75 @@ impl deserialize::FromSql<Varchar, Pg> for AccountType {
 	}
 }
 
-/// Data store implementation for operating on accounts in the database
-pub struct Repo {
-	db: db::PgPool,
+/// The status of an account, only open accounts can move money
+///
+/// The allowed transitions are:
+/// - Open -> Frozen, Dormant or Closed
+/// - Frozen -> Open
+/// - Dormant -> Open or Closed
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Serialize, Eq, PartialEq, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+	/// The account can be used normally
+	Open,
+	/// The account has been locked by the bank, e.g. while suspected fraud is investigated
+	Frozen,
+	/// The account hasn't been used in a long time and must be reactivated before it's used again
+	Dormant,
+	/// The account has been permanently closed
+	Closed,
+}
+
+impl AccountStatus {
+	/// Checks whether an account with this status may move to the next status
+	pub fn can_transition_to(&self, next: AccountStatus) -> bool {
+		use AccountStatus::*;
+		match (self, next) {
+			(Open, Frozen) | (Open, Dormant) | (Open, Closed) => true,
+			(Frozen, Open) => true,
+			(Dormant, Open) | (Dormant, Closed) => true,
+			_ => false,
+		}
+	}
+}
+
+impl Default for AccountStatus {
+	fn default() -> Self { AccountStatus::Open }
+}
+
+impl serialize::ToSql<Varchar, Pg> for AccountStatus {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for AccountStatus {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		AccountStatus::from_str(s).map_err(|_| "invalid account status".into())
+	}
 }
 
+/// Data store implementation for operating on accounts in the database
+pub struct Repo;
+
 impl Repo {
-	pub fn new(db: db::PgPool) -> Self {
-		Repo { db }
+	pub fn new() -> Self {
+		Repo
 	}
 	
-	pub fn create_account(&self, new_account: NewAccount) -> db::Result<Account> {
-		let conn = &self.db.get()?;
+	pub fn create_account(&self, conn: &PgConnection, new_account: NewAccount) -> db::Result<Account> {
 		diesel::insert_into(accounts::table)
 			.values(&new_account)
 			.get_result(conn)
 			.map_err(Into::into)
 	}
 	
-	pub fn find_accounts(&self, user_id: &uuid::Uuid) -> db::Result<Vec<Account>> {
-		let conn = &self.db.get()?;
+	pub fn find_accounts(&self, conn: &PgConnection, user_id: &uuid::Uuid) -> db::Result<Vec<Account>> {
 		accounts::table
 			.filter(accounts::user_id.eq(user_id))
 			.select((accounts::all_columns))
@@ -94,27 +174,110 @@ impl Repo {
 			.map_err(Into::into)
 	}
 	
-	pub fn find_by_id(&self, account_id: &uuid::Uuid) -> db::Result<Account> {
-		let conn = &self.db.get()?;
+	pub fn find_all(&self, conn: &PgConnection) -> db::Result<Vec<Account>> {
+		accounts::table
+			.select((accounts::all_columns))
+			.load::<Account>(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_by_id(&self, conn: &PgConnection, account_id: &uuid::Uuid) -> db::Result<Account> {
+		accounts::table
+			.filter(accounts::id.eq(account_id))
+			.select((accounts::all_columns))
+			.first::<Account>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the account and locks its row against concurrent updates
+	///
+	/// The lock is held until the transaction on `conn` ends, so the balance can be checked and updated atomically
+	/// using the same connection
+	pub fn find_for_update(&self, conn: &PgConnection, account_id: &uuid::Uuid) -> db::Result<Account> {
 		accounts::table
 			.filter(accounts::id.eq(account_id))
 			.select((accounts::all_columns))
+			.for_no_key_update()
 			.first::<Account>(conn)
 			.map_err(Into::into)
 	}
 	
-	pub fn increment(&self, account_id: &uuid::Uuid, amount: &BigDecimal) -> db::Result<Account> {
-		self.transact(account_id, amount)
+	/// Finds the accounts that earn interest with a savings product, interest keeps accruing until an account is closed
+	pub fn find_interest_bearing(&self, conn: &PgConnection) -> db::Result<Vec<Account>> {
+		accounts::table
+			.filter(accounts::savings_product.is_not_null()
+				.and(accounts::status.ne(AccountStatus::Closed)))
+			.select(accounts::all_columns)
+			.load::<Account>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Enrolls the account in a savings product, it starts accruing interest from the date
+	pub fn set_savings_product(&self, conn: &PgConnection, account_id: &uuid::Uuid, product_name: &str, start_date: &Date) -> db::Result<Account> {
+		diesel::update(accounts::table)
+			.filter(accounts::id.eq(account_id))
+			.set((
+				accounts::savings_product.eq(product_name),
+				accounts::interest_accrued_through.eq(start_date),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn set_status(&self, conn: &PgConnection, account_id: &uuid::Uuid, status: AccountStatus) -> db::Result<Account> {
+		diesel::update(accounts::table)
+			.filter(accounts::id.eq(account_id))
+			.set(accounts::status.eq(status))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn close(&self, conn: &PgConnection, account_id: &uuid::Uuid) -> db::Result<Account> {
+		diesel::update(accounts::table)
+			.filter(accounts::id.eq(account_id))
+			.set((
+				accounts::status.eq(AccountStatus::Closed),
+				accounts::closed_at.eq(diesel::dsl::now),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Adds a day's interest to the account's accrued interest
+	pub fn accrue_interest(&self, conn: &PgConnection, account_id: &uuid::Uuid, interest: &BigDecimal, accrued_through: &Date) -> db::Result<Account> {
+		diesel::update(accounts::table)
+			.filter(accounts::id.eq(account_id))
+			.set((
+				accounts::accrued_interest.eq(accounts::accrued_interest + interest),
+				accounts::interest_accrued_through.eq(accrued_through),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Moves the amount from the account's accrued interest into its balance
+	pub fn credit_interest(&self, conn: &PgConnection, account_id: &uuid::Uuid, amount: &BigDecimal) -> db::Result<Account> {
+		diesel::update(accounts::table)
+			.filter(accounts::id.eq(account_id))
+			.set((
+				accounts::amount.eq(accounts::amount + amount),
+				accounts::accrued_interest.eq(accounts::accrued_interest - amount),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn increment(&self, conn: &PgConnection, account_id: &uuid::Uuid, amount: &BigDecimal) -> db::Result<Account> {
+		self.transact(conn, account_id, amount)
 	}
 	
-	pub fn decrement(&self, account_id: &uuid::Uuid, amount: &BigDecimal) -> db::Result<Account> {
+	pub fn decrement(&self, conn: &PgConnection, account_id: &uuid::Uuid, amount: &BigDecimal) -> db::Result<Account> {
 		let neg = amount.neg();
-		self.transact(account_id, &neg)
+		self.transact(conn, account_id, &neg)
 	}
 	
 	/// Helper method for incrementing/decrementing funds from an account
-	fn transact(&self, account_id: &uuid::Uuid, amount: &BigDecimal) -> db::Result<Account> {
-		let conn = &self.db.get()?;
+	fn transact(&self, conn: &PgConnection, account_id: &uuid::Uuid, amount: &BigDecimal) -> db::Result<Account> {
 		diesel::update(accounts::table)
 			.filter(accounts::id.eq(account_id))
 			.set(accounts::amount.eq(accounts::amount + amount))
@@ -138,9 +301,10 @@ mod tests {
 		let new_account = NewAccount {
 			user_id: user.id,
 			account_type: AccountType::Checking,
+			currency: Currency::Usd,
 		};
 		
-		let want = suite.account_repo.create_account(new_account).unwrap();
+		let want = suite.account_repo.create_account(&fixture.conn(), new_account).unwrap();
 		
 		let got = accounts::table.find(want.id).first::<Account>(&fixture.conn()).unwrap();
 		assert_eq!(want, got)
@@ -158,7 +322,7 @@ mod tests {
 		want.push(checking);
 		want.push(savings);
 		
-		let got = suite.account_repo.find_accounts(&user.id).unwrap();
+		let got = suite.account_repo.find_accounts(&fixture.conn(), &user.id).unwrap();
 		
 		assert_eq!(want, got)
 	}
@@ -173,13 +337,13 @@ mod tests {
 		
 		// deposit
 		let deposit_amount = BigDecimal::from(500);
-		let got = suite.account_repo.increment(&checking.id, &deposit_amount).unwrap();
+		let got = suite.account_repo.increment(&fixture.conn(), &checking.id, &deposit_amount).unwrap();
 		
 		let want_amount = (checking.amount) + BigDecimal::from(deposit_amount);
 		assert_eq!(got.amount, want_amount, "account's amount should be equal to the deposit");
 		
 		let withdraw_amount = BigDecimal::from(250);
-		let got = suite.account_repo.increment(&checking.id, &withdraw_amount).unwrap();
+		let got = suite.account_repo.decrement(&fixture.conn(), &checking.id, &withdraw_amount).unwrap();
 		
 		let want_amount = (&want_amount) - withdraw_amount;
 		assert_eq!(got.amount, want_amount, "account's amount should be equal to (deposit - withdrawal)");
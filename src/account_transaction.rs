@@ -7,8 +7,12 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::serialize::{Output, ToSql};
 use diesel::sql_types::Varchar;
+use serde::Serialize;
 
 use crate::db;
+use crate::history::{EntryType, Filter};
+use crate::money::{Currency, Money};
+use crate::overdraft::OverdraftProtectionKind;
 use crate::schema::account_transactions;
 use crate::types::{Id, Time};
 
@@ -16,7 +20,7 @@ use crate::types::{Id, Time};
 /// The accounts can be:
 /// 	- held by two different users
 /// 	- held by the same user
-#[derive(Queryable, Identifiable, PartialEq, Debug)]
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
 pub struct AccountTransaction {
 	pub id: Id,
 	/// Sender's account id
@@ -25,6 +29,26 @@ pub struct AccountTransaction {
 	pub receiver_id: Id,
 	pub amount: BigDecimal,
 	pub created_at: Time,
+	/// Key supplied by the client to make retries of the same request safe
+	pub idempotency_key: Option<String>,
+	/// The overdraft protection that covered the transaction, if it needed any
+	pub overdraft_protection: Option<OverdraftProtectionKind>,
+	/// The transfer this transfer reverses, if it's a reversal
+	pub reversal_of: Option<Id>,
+	/// The operator who forced the reversal through even though it overdrew the receiver's account
+	pub forced_by: Option<Id>,
+	/// The currency of the amount, the currency both accounts are held in
+	pub currency: Currency,
+}
+
+impl AccountTransaction {
+	/// Indicates whether a request with these parameters repeats the request that recorded this transfer
+	pub fn is_repeat_of(&self, sender_id: &Id, receiver_id: &Id, amount: &Money) -> bool {
+		self.sender_id.eq(sender_id)
+			&& self.receiver_id.eq(receiver_id)
+			&& self.amount.eq(&amount.amount)
+			&& self.currency == amount.currency
+	}
 }
 
 #[derive(Insertable)]
@@ -33,25 +57,136 @@ pub struct NewAccountTransaction<'a> {
 	pub sender_id: &'a uuid::Uuid,
 	pub receiver_id: &'a uuid::Uuid,
 	pub amount: &'a BigDecimal,
+	pub idempotency_key: Option<&'a str>,
+	pub overdraft_protection: Option<OverdraftProtectionKind>,
+	pub reversal_of: Option<&'a uuid::Uuid>,
+	pub forced_by: Option<&'a uuid::Uuid>,
+	pub currency: Currency,
 }
 
-pub struct Repo {
-	db: db::PgPool
+/// The transfers that the account's history lists as matching the filter
+///
+/// Returns none when the filter's entry type is never a transfer
+fn matching_history<'a>(account_id: &'a Id, filter: &'a Filter) -> Option<account_transactions::BoxedQuery<'a, Pg>> {
+	let sent = account_transactions::sender_id.eq(account_id);
+	let received = account_transactions::receiver_id.eq(account_id);
+	let query = account_transactions::table.into_boxed();
+	let mut query = match filter.entry_type {
+		None => query.filter(sent.or(received)),
+		Some(EntryType::Reversal) => query.filter(sent.or(received)).filter(account_transactions::reversal_of.is_not_null()),
+		Some(EntryType::TransferIn) => query.filter(received).filter(account_transactions::reversal_of.is_null()),
+		Some(EntryType::TransferOut) => query.filter(sent).filter(account_transactions::reversal_of.is_null()),
+		Some(_) => return None,
+	};
+	if let Some(starts_at) = filter.starts_at() {
+		query = query.filter(account_transactions::created_at.ge(starts_at));
+	}
+	if let Some(ends_before) = filter.ends_before() {
+		query = query.filter(account_transactions::created_at.lt(ends_before));
+	}
+	if let Some(min) = &filter.min_amount {
+		query = query.filter(account_transactions::amount.ge(min));
+	}
+	if let Some(max) = &filter.max_amount {
+		query = query.filter(account_transactions::amount.le(max));
+	}
+	Some(query)
 }
 
+pub struct Repo;
+
 /// Data store implementation for operating on account_transactions in the database
 impl Repo {
-	pub fn new(db: db::PgPool) -> Self {
-		Repo { db }
+	pub fn new() -> Self {
+		Repo
 	}
 	
-	pub fn create(&self, new_transaction: NewAccountTransaction) -> db::Result<AccountTransaction> {
-		let conn = &self.db.get()?;
+	pub fn create(&self, conn: &PgConnection, new_transaction: NewAccountTransaction) -> db::Result<AccountTransaction> {
 		diesel::insert_into(account_transactions::table)
 			.values(&new_transaction)
 			.get_result::<>(conn)
 			.map_err(Into::into)
 	}
+	
+	pub fn find_by_idempotency_key(&self, conn: &PgConnection, key: &str) -> db::Result<AccountTransaction> {
+		account_transactions::table
+			.filter(account_transactions::idempotency_key.eq(key))
+			.first::<AccountTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_by_id(&self, conn: &PgConnection, id: &Id) -> db::Result<AccountTransaction> {
+		account_transactions::table
+			.find(id)
+			.first::<AccountTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the transfer that reversed the transfer
+	pub fn find_reversal(&self, conn: &PgConnection, reversal_of: &Id) -> db::Result<AccountTransaction> {
+		account_transactions::table
+			.filter(account_transactions::reversal_of.eq(reversal_of))
+			.first::<AccountTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds every transfer the account sent or received, oldest first
+	pub fn find_by_account(&self, conn: &PgConnection, account_id: &Id) -> db::Result<Vec<AccountTransaction>> {
+		account_transactions::table
+			.filter(account_transactions::sender_id.eq(account_id).or(account_transactions::receiver_id.eq(account_id)))
+			.order((account_transactions::created_at.asc(), account_transactions::id.asc()))
+			.load::<AccountTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the transfers the account sent or received after the time, oldest first
+	pub fn find_by_account_after(&self, conn: &PgConnection, account_id: &Id, after: &Time) -> db::Result<Vec<AccountTransaction>> {
+		account_transactions::table
+			.filter(account_transactions::sender_id.eq(account_id).or(account_transactions::receiver_id.eq(account_id)))
+			.filter(account_transactions::created_at.gt(after))
+			.order((account_transactions::created_at.asc(), account_transactions::id.asc()))
+			.load::<AccountTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the transfers the account sent or received after and before the times that are given, oldest first
+	pub fn find_by_account_within(&self, conn: &PgConnection, account_id: &Id, after: Option<&Time>, before: Option<&Time>) -> db::Result<Vec<AccountTransaction>> {
+		let mut query = account_transactions::table
+			.filter(account_transactions::sender_id.eq(account_id).or(account_transactions::receiver_id.eq(account_id)))
+			.into_boxed();
+		if let Some(after) = after {
+			query = query.filter(account_transactions::created_at.gt(after));
+		}
+		if let Some(before) = before {
+			query = query.filter(account_transactions::created_at.lt(before));
+		}
+		query
+			.order((account_transactions::created_at.asc(), account_transactions::id.asc()))
+			.load::<AccountTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Counts the transfers that the account's history lists as matching the filter
+	pub fn count_history(&self, conn: &PgConnection, account_id: &Id, filter: &Filter) -> db::Result<i64> {
+		match matching_history(account_id, filter) {
+			Some(query) => query.count().get_result(conn).map_err(Into::into),
+			None => Ok(0),
+		}
+	}
+	
+	/// Finds when the nth most recent of the transfers matching the filter of the account's history was made
+	pub fn find_history_cutoff(&self, conn: &PgConnection, account_id: &Id, filter: &Filter, n: i64) -> db::Result<Option<Time>> {
+		match matching_history(account_id, filter) {
+			Some(query) => query
+				.select(account_transactions::created_at)
+				.order(account_transactions::created_at.desc())
+				.offset(n - 1)
+				.first::<Time>(conn)
+				.optional()
+				.map_err(Into::into),
+			None => Ok(None),
+		}
+	}
 }
 
 #[cfg(test)]
@@ -74,10 +209,15 @@ mod tests {
 		
 		let amount = BigDecimal::from(100);
 		
-		let got = suite.account_transaction_repo.create(NewAccountTransaction {
+		let got = suite.account_transaction_repo.create(&fixture.conn(), NewAccountTransaction {
 			sender_id: &sender_account.id,
 			receiver_id: &receiver_account.id,
 			amount: &amount,
+			idempotency_key: None,
+			overdraft_protection: None,
+			reversal_of: None,
+			forced_by: None,
+			currency: Currency::Usd,
 		}).unwrap();
 		
 		let want = AccountTransaction {
@@ -86,6 +226,11 @@ mod tests {
 			receiver_id: receiver_account.id,
 			amount,
 			created_at: got.created_at,
+			idempotency_key: None,
+			overdraft_protection: None,
+			reversal_of: None,
+			forced_by: None,
+			currency: Currency::Usd,
 		};
 		
 		assert_eq!(got, want);
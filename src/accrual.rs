@@ -0,0 +1,101 @@
+/*!
+accrual keeps a log of the end of day runs that accrue interest on loans and savings accounts
+*/
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::db;
+use crate::schema::accrual_runs;
+use crate::types::{Date, Id, Time};
+
+/// A batch run that accrued interest on every active loan and savings account as of the run date
+#[derive(Queryable, Identifiable, Serialize, Debug)]
+pub struct AccrualRun {
+	pub id: Id,
+	/// the date interest was accrued through, only one run is made per date
+	pub run_date: Date,
+	pub started_at: Time,
+	/// the time the run finished, a run without one was interrupted and is resumed by the next run for its date
+	pub completed_at: Option<Time>,
+	/// the number of loans that interest was accrued on
+	pub loans_accrued: i32,
+	/// the total interest accrued across all loans
+	pub interest_accrued: BigDecimal,
+	/// the number of savings accounts that interest was accrued on
+	pub accounts_accrued: i32,
+	/// the total interest accrued across all savings accounts
+	pub savings_interest_accrued: BigDecimal,
+}
+
+impl AccrualRun {
+	pub fn is_complete(&self) -> bool {
+		self.completed_at.is_some()
+	}
+}
+
+/// Data store implementation for operating on accrual runs in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	/// Starts the run for the date, or returns the existing run if one was already started
+	pub fn start(&self, conn: &PgConnection, run_date: &Date) -> db::Result<AccrualRun> {
+		diesel::insert_into(accrual_runs::table)
+			.values(accrual_runs::run_date.eq(run_date))
+			.on_conflict(accrual_runs::run_date)
+			.do_nothing()
+			.execute(conn)?;
+		
+		accrual_runs::table
+			.filter(accrual_runs::run_date.eq(run_date))
+			.select(accrual_runs::all_columns)
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Adds a loan's accrued interest to the run's totals
+	pub fn record_loan(&self, conn: &PgConnection, id: &Id, interest_accrued: &BigDecimal) -> db::Result<AccrualRun> {
+		diesel::update(accrual_runs::table)
+			.filter(accrual_runs::id.eq(id))
+			.set((
+				accrual_runs::loans_accrued.eq(accrual_runs::loans_accrued + 1),
+				accrual_runs::interest_accrued.eq(accrual_runs::interest_accrued + interest_accrued),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Adds a savings account's accrued interest to the run's totals
+	pub fn record_account(&self, conn: &PgConnection, id: &Id, interest_accrued: &BigDecimal) -> db::Result<AccrualRun> {
+		diesel::update(accrual_runs::table)
+			.filter(accrual_runs::id.eq(id))
+			.set((
+				accrual_runs::accounts_accrued.eq(accrual_runs::accounts_accrued + 1),
+				accrual_runs::savings_interest_accrued.eq(accrual_runs::savings_interest_accrued + interest_accrued),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn complete(&self, conn: &PgConnection, id: &Id) -> db::Result<AccrualRun> {
+		diesel::update(accrual_runs::table)
+			.filter(accrual_runs::id.eq(id))
+			.set(accrual_runs::completed_at.eq(diesel::dsl::now))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the most recent runs, newest first
+	pub fn find_recent(&self, conn: &PgConnection, limit: i64) -> db::Result<Vec<AccrualRun>> {
+		accrual_runs::table
+			.select(accrual_runs::all_columns)
+			.order(accrual_runs::run_date.desc())
+			.limit(limit)
+			.load(conn)
+			.map_err(Into::into)
+	}
+}
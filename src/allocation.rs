@@ -0,0 +1,184 @@
+/*!
+allocation splits a payment made against a loan across what the borrower owes
+*/
+use bigdecimal::{BigDecimal, Zero};
+use serde::Serialize;
+
+use crate::loan::{Loan, LoanPayment};
+use crate::types::Date;
+
+/// A part of what the borrower owes that a payment can be applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+	/// late fees charged on unpaid payments
+	Fees,
+	/// interest due on payments that are past due
+	PastDueInterest,
+	/// the rest of the interest accrued on the loan
+	CurrentInterest,
+	/// principal due on past due payments and the next payment
+	Principal,
+	/// the rest of the loan's balance, paying it early shortens the loan's term
+	Prepayment,
+}
+
+/// The order the bank applies loan payments in
+#[derive(Debug, Clone)]
+pub struct AllocationPolicy {
+	/// buckets are filled in this order, a bucket that isn't listed is never paid
+	pub order: Vec<Bucket>,
+}
+
+impl Default for AllocationPolicy {
+	fn default() -> Self {
+		AllocationPolicy {
+			order: vec![
+				Bucket::Fees,
+				Bucket::PastDueInterest,
+				Bucket::CurrentInterest,
+				Bucket::Principal,
+				Bucket::Prepayment,
+			],
+		}
+	}
+}
+
+impl AllocationPolicy {
+	/// Splits the amount across the dues in the policy's order
+	///
+	/// Each bucket is paid up to what's owed in it, any amount left after the last bucket isn't allocated
+	pub fn allocate(&self, amount: &BigDecimal, dues: &Allocation) -> Allocation {
+		let mut remaining = amount.clone();
+		let mut allocation = Allocation::default();
+		for bucket in &self.order {
+			let owed = dues.get(*bucket);
+			let paid = if &remaining < owed { remaining.clone() } else { owed.clone() };
+			remaining -= &paid;
+			*allocation.get_mut(*bucket) += paid;
+		}
+		allocation
+	}
+}
+
+/// An amount in each bucket, used for both what's owed on a loan and how a payment was applied
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Allocation {
+	pub fees: BigDecimal,
+	pub past_due_interest: BigDecimal,
+	pub current_interest: BigDecimal,
+	pub principal: BigDecimal,
+	pub prepayment: BigDecimal,
+}
+
+impl Allocation {
+	pub fn get(&self, bucket: Bucket) -> &BigDecimal {
+		match bucket {
+			Bucket::Fees => &self.fees,
+			Bucket::PastDueInterest => &self.past_due_interest,
+			Bucket::CurrentInterest => &self.current_interest,
+			Bucket::Principal => &self.principal,
+			Bucket::Prepayment => &self.prepayment,
+		}
+	}
+	
+	fn get_mut(&mut self, bucket: Bucket) -> &mut BigDecimal {
+		match bucket {
+			Bucket::Fees => &mut self.fees,
+			Bucket::PastDueInterest => &mut self.past_due_interest,
+			Bucket::CurrentInterest => &mut self.current_interest,
+			Bucket::Principal => &mut self.principal,
+			Bucket::Prepayment => &mut self.prepayment,
+		}
+	}
+	
+	pub fn interest(&self) -> BigDecimal {
+		&self.past_due_interest + &self.current_interest
+	}
+	
+	pub fn total(&self) -> BigDecimal {
+		&self.fees + self.interest() + &self.principal + &self.prepayment
+	}
+}
+
+/// What the borrower owes on the loan in each bucket as of the date
+///
+/// Payments due before the date are past due. Principal owed is capped at the loan's balance, which
+/// may already have been reduced by earlier prepayments.
+pub fn dues(loan: &Loan, unpaid: &[LoanPayment], as_of: Date) -> Allocation {
+	let (past_due, current): (Vec<&LoanPayment>, Vec<&LoanPayment>) = unpaid.iter().partition(|p| p.due_date < as_of);
+	
+	let fees = unpaid.iter().fold(BigDecimal::zero(), |sum, p| sum + p.fees_outstanding());
+	let past_due_interest = past_due.iter().fold(BigDecimal::zero(), |sum, p| sum + p.interest_outstanding())
+		.min(loan.accrued_interest.clone());
+	let current_interest = &loan.accrued_interest - &past_due_interest;
+	
+	let principal = past_due.iter()
+		.chain(current.first())
+		.fold(BigDecimal::zero(), |sum, p| sum + p.principal_outstanding())
+		.min(loan.balance.clone());
+	let prepayment = &loan.balance - &principal;
+	
+	Allocation {
+		fees,
+		past_due_interest,
+		current_interest,
+		principal,
+		prepayment,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn dues() -> Allocation {
+		Allocation {
+			fees: BigDecimal::from(25),
+			past_due_interest: BigDecimal::from(10),
+			current_interest: BigDecimal::from(5),
+			principal: BigDecimal::from(100),
+			prepayment: BigDecimal::from(900),
+		}
+	}
+	
+	#[test]
+	fn underpayment_fills_buckets_in_order() {
+		let allocation = AllocationPolicy::default().allocate(&BigDecimal::from(30), &dues());
+		
+		assert_eq!(allocation.fees, BigDecimal::from(25));
+		assert_eq!(allocation.past_due_interest, BigDecimal::from(5));
+		assert!(allocation.current_interest.is_zero());
+		assert!(allocation.principal.is_zero());
+		assert_eq!(allocation.total(), BigDecimal::from(30));
+	}
+	
+	#[test]
+	fn overpayment_is_prepaid() {
+		let allocation = AllocationPolicy::default().allocate(&BigDecimal::from(240), &dues());
+		
+		assert_eq!(allocation.interest(), BigDecimal::from(15));
+		assert_eq!(allocation.principal, BigDecimal::from(100));
+		assert_eq!(allocation.prepayment, BigDecimal::from(100));
+	}
+	
+	#[test]
+	fn amount_beyond_the_dues_is_not_allocated() {
+		let allocation = AllocationPolicy::default().allocate(&BigDecimal::from(2_000), &dues());
+		assert_eq!(allocation, dues());
+	}
+	
+	#[test]
+	fn custom_order() {
+		let policy = AllocationPolicy {
+			order: vec![Bucket::Principal, Bucket::PastDueInterest, Bucket::Fees],
+		};
+		let allocation = policy.allocate(&BigDecimal::from(120), &dues());
+		
+		assert_eq!(allocation.principal, BigDecimal::from(100));
+		assert_eq!(allocation.past_due_interest, BigDecimal::from(10));
+		assert_eq!(allocation.fees, BigDecimal::from(10));
+		// unlisted buckets are never paid
+		assert!(allocation.current_interest.is_zero());
+		assert!(allocation.prepayment.is_zero());
+	}
+}
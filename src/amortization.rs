@@ -0,0 +1,407 @@
+/*!
+amortization computes the schedule of payments that repays a loan's principal and interest over its term
+*/
+use bigdecimal::{BigDecimal, One, Zero};
+
+use crate::day_count::DayCountConvention;
+use crate::loan::AmortizationMethod;
+use crate::money::{self, Currency, RoundingMode};
+use crate::types::{Date, DateExt};
+
+/// precision kept while compounding the periodic rate
+const RATE_PRECISION: u64 = 32;
+
+/// Terms used to amortize a loan
+pub struct Terms<'a> {
+	/// the amount to repay over the schedule
+	pub principal: &'a BigDecimal,
+	/// the annual interest rate as a decimal, e.g. 2% is 0.02
+	pub annual_rate: BigDecimal,
+	/// the number of months between payments
+	pub payment_frequency: i16,
+	/// the date the schedule starts from, payments are due one payment period after this date
+	pub start_date: Date,
+	/// the date the final payment is due
+	pub maturity_date: Date,
+	pub method: AmortizationMethod,
+	/// how the days in each period are counted when calculating its interest
+	pub day_count: DayCountConvention,
+	/// changes to the annual rate after the start date, in date order
+	pub rate_resets: Vec<RateReset>,
+	/// the currency whose minor unit amounts are rounded to
+	pub currency: Currency,
+	pub rounding: RoundingMode,
+}
+
+impl<'a> Terms<'a> {
+	/// Rounds the amount to the minor unit of the terms' currency
+	fn round(&self, amount: &BigDecimal) -> BigDecimal {
+		money::round(amount, self.currency.minor_units(), self.rounding)
+	}
+}
+
+/// An annual rate that takes effect on a date
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateReset {
+	pub effective_date: Date,
+	pub annual_rate: BigDecimal,
+}
+
+/// A single payment on an amortization schedule
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledPayment {
+	pub due_date: Date,
+	pub principal_due: BigDecimal,
+	pub interest_due: BigDecimal,
+	/// the principal left to repay after this payment is made
+	pub balance: BigDecimal,
+}
+
+impl ScheduledPayment {
+	pub fn total_due(&self) -> BigDecimal {
+		&self.principal_due + &self.interest_due
+	}
+}
+
+/// Builds the full schedule of payments for the terms
+///
+/// Amounts are rounded to the currency's minor unit with the terms' rounding mode. Rounding remainders are spread
+/// over the periods rather than lost: the part of a period's interest rounded off is carried into the next period,
+/// level principal is rounded cumulatively so each period repays its share to the nearest minor unit, and the final
+/// payment repays whatever balance is left so the payments sum exactly to the principal.
+/// Returns an empty schedule when no payment period fits before the maturity date.
+///
+/// The level payment is based on the nominal periodic rate, while each period's interest is counted over
+/// its actual dates with the day count convention, so the principal repaid by each payment varies with
+/// the length of its period.
+///
+/// Interest is charged at the rate in effect on each day of a period. When the rate resets, a level payment
+/// is recalculated at the start of the next period to repay the remaining balance over the remaining periods.
+pub fn schedule(terms: &Terms) -> Vec<ScheduledPayment> {
+	let periods = num_periods(terms);
+	if periods == 0 {
+		return vec![];
+	}
+	
+	let mut resets = terms.rate_resets.iter().peekable();
+	let mut rate = terms.annual_rate.clone();
+	let mut payment_rate = rate.clone();
+	let mut level_payment = annuity_payment(terms, terms.principal, &periodic_rate(terms, &rate), periods);
+	
+	let mut balance = terms.principal.clone();
+	let mut carried_interest = BigDecimal::zero();
+	let mut payments = Vec::with_capacity(periods as usize);
+	let mut period_start = terms.start_date;
+	for period in 1..=periods {
+		let period_end = due_date(terms, period);
+		
+		let mut interest = BigDecimal::zero();
+		let mut from = period_start;
+		loop {
+			while let Some(reset) = resets.peek().filter(|r| r.effective_date <= from) {
+				rate = reset.annual_rate.clone();
+				resets.next();
+			}
+			if from == period_start && rate != payment_rate && terms.method == AmortizationMethod::LevelPayment {
+				level_payment = annuity_payment(terms, &balance, &periodic_rate(terms, &rate), periods - period + 1);
+				payment_rate = rate.clone();
+			}
+			
+			// a reset part way through the period splits its interest
+			let to = match resets.peek() {
+				Some(reset) if reset.effective_date < period_end => reset.effective_date,
+				_ => period_end,
+			};
+			interest += terms.day_count.interest(&balance, &rate, from, to);
+			if to == period_end {
+				break;
+			}
+			from = to;
+		}
+		interest += &carried_interest;
+		let interest_due = terms.round(&interest);
+		carried_interest = interest - &interest_due;
+		
+		let principal_due = if period == periods {
+			balance.clone()
+		} else {
+			let principal_due = match terms.method {
+				// interest can outgrow the payment when the rate resets part way through a period
+				AmortizationMethod::LevelPayment => (&level_payment - &interest_due).max(BigDecimal::zero()),
+				AmortizationMethod::LevelPrincipal => level_principal(terms, period, periods),
+			};
+			principal_due.min(balance.clone())
+		};
+		
+		balance = &balance - &principal_due;
+		period_start = period_end;
+		payments.push(ScheduledPayment {
+			due_date: period_end,
+			principal_due,
+			interest_due,
+			balance: balance.clone(),
+		});
+	}
+	payments
+}
+
+/// The number of payment periods between the start date and maturity date
+///
+/// A trailing partial period is counted as a full period that ends on the maturity date
+fn num_periods(terms: &Terms) -> u16 {
+	if terms.payment_frequency <= 0 || terms.maturity_date <= terms.start_date {
+		return 0;
+	}
+	let months = terms.start_date.months_until(&terms.maturity_date);
+	let frequency = terms.payment_frequency as u16;
+	(months + frequency - 1) / frequency
+}
+
+/// The nominal rate for one payment period
+fn periodic_rate(terms: &Terms, annual_rate: &BigDecimal) -> BigDecimal {
+	annual_rate * BigDecimal::from(terms.payment_frequency) / BigDecimal::from(12)
+}
+
+fn due_date(terms: &Terms, period: u16) -> Date {
+	let due_date = terms.start_date.increment_date_by_months(period * terms.payment_frequency as u16);
+	due_date.min(terms.maturity_date)
+}
+
+/// The level payment that repays the principal and interest over the periods
+///
+/// payment = principal * rate / (1 - (1 + rate)^-periods)
+fn annuity_payment(terms: &Terms, principal: &BigDecimal, rate: &BigDecimal, periods: u16) -> BigDecimal {
+	if rate.is_zero() {
+		return terms.round(&(principal / BigDecimal::from(periods)));
+	}
+	
+	let mut growth = BigDecimal::one();
+	let base = BigDecimal::one() + rate;
+	for _ in 0..periods {
+		growth = (&growth * &base).with_prec(RATE_PRECISION);
+	}
+	terms.round(&(principal * rate * &growth / (growth - BigDecimal::one())))
+}
+
+/// The principal repaid in the period by a level principal schedule
+///
+/// The principal repaid by the end of each period is rounded, so the rounding remainders are spread over the periods
+/// instead of building up into the final payment
+fn level_principal(terms: &Terms, period: u16, periods: u16) -> BigDecimal {
+	let repaid_by = |period: u16| terms.round(&(terms.principal * BigDecimal::from(period) / BigDecimal::from(periods)));
+	repaid_by(period) - repaid_by(period - 1)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+	
+	use super::*;
+	
+	fn dec(s: &str) -> BigDecimal {
+		BigDecimal::from_str(s).unwrap()
+	}
+	
+	fn terms(principal: &BigDecimal, method: AmortizationMethod) -> Terms {
+		let start_date = Date::from_ymd(2020, 1, 1);
+		Terms {
+			principal,
+			annual_rate: dec("0.06"),
+			payment_frequency: 1,
+			start_date,
+			maturity_date: start_date.increment_date_by_months(12),
+			method,
+			day_count: DayCountConvention::Thirty360,
+			rate_resets: vec![],
+			currency: Currency::Usd,
+			rounding: RoundingMode::HalfEven,
+		}
+	}
+	
+	#[test]
+	fn level_payment_schedule() {
+		let principal = BigDecimal::from(10_000);
+		let payments = schedule(&terms(&principal, AmortizationMethod::LevelPayment));
+		
+		assert_eq!(payments.len(), 12);
+		assert_eq!(payments[0].interest_due, dec("50.00"));
+		assert_eq!(payments[0].principal_due, dec("810.66"));
+		assert_eq!(payments[0].due_date, Date::from_ymd(2020, 2, 1));
+		
+		// every payment but the last is level
+		for p in &payments[..11] {
+			assert_eq!(p.total_due(), dec("860.66"));
+		}
+		let last = payments.last().unwrap();
+		assert!(last.balance.is_zero());
+		assert_eq!(last.due_date, Date::from_ymd(2021, 1, 1));
+		
+		let repaid = payments.iter().fold(BigDecimal::zero(), |sum, p| sum + &p.principal_due);
+		assert_eq!(repaid, principal);
+	}
+	
+	#[test]
+	fn month_end_due_dates_do_not_drift() {
+		let principal = BigDecimal::from(10_000);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPayment);
+		terms.start_date = Date::from_ymd(2020, 1, 31);
+		terms.maturity_date = terms.start_date.increment_date_by_months(12);
+		
+		let payments = schedule(&terms);
+		assert_eq!(payments[0].due_date, Date::from_ymd(2020, 2, 29));
+		assert_eq!(payments[1].due_date, Date::from_ymd(2020, 3, 31));
+		assert_eq!(payments[11].due_date, Date::from_ymd(2021, 1, 31));
+		// 30/360 counts January 31st to February 29th as 29 days
+		assert_eq!(payments[0].interest_due, dec("48.33"));
+	}
+	
+	#[test]
+	fn actual_day_count_schedule() {
+		let principal = BigDecimal::from(10_000);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPayment);
+		terms.day_count = DayCountConvention::Actual365;
+		
+		let payments = schedule(&terms);
+		// 31 days in January and 29 in February 2020
+		assert_eq!(payments[0].interest_due, dec("50.96"));
+		assert_eq!(payments[0].principal_due, dec("809.70"));
+		assert_eq!(payments[1].total_due(), dec("860.66"));
+		
+		let last = payments.last().unwrap();
+		assert!(last.balance.is_zero());
+		let repaid = payments.iter().fold(BigDecimal::zero(), |sum, p| sum + &p.principal_due);
+		assert_eq!(repaid, principal);
+	}
+	
+	#[test]
+	fn rate_reset_recalculates_level_payment() {
+		let principal = BigDecimal::from(10_000);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPayment);
+		terms.rate_resets = vec![RateReset {
+			effective_date: Date::from_ymd(2020, 7, 1),
+			annual_rate: dec("0.12"),
+		}];
+		
+		let payments = schedule(&terms);
+		for p in &payments[..6] {
+			assert_eq!(p.total_due(), dec("860.66"));
+		}
+		// the remaining balance is repaid over the last 6 payments at 1% a month
+		assert_eq!(payments[5].balance, dec("5074.83"));
+		assert_eq!(payments[6].interest_due, dec("50.75"));
+		assert_eq!(payments[6].total_due(), dec("875.65"));
+		assert!(payments[11].balance.is_zero());
+	}
+	
+	#[test]
+	fn interest_above_the_level_payment_repays_no_principal() {
+		let principal = BigDecimal::from(10_000);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPayment);
+		terms.maturity_date = terms.start_date.increment_date_by_months(360);
+		terms.rate_resets = vec![RateReset {
+			effective_date: Date::from_ymd(2020, 1, 2),
+			annual_rate: dec("0.12"),
+		}];
+		
+		let payments = schedule(&terms);
+		// the first payment was set at 6% but most of its period is charged 12%
+		assert_eq!(payments[0].interest_due, dec("98.33"));
+		assert!(payments[0].principal_due.is_zero());
+		assert_eq!(payments[0].balance, principal);
+		
+		// the payment is recalculated at 12% from the next period
+		assert_eq!(payments[1].interest_due, dec("100.00"));
+		assert!(payments[1].principal_due > BigDecimal::zero());
+		assert!(payments.iter().all(|p| p.principal_due >= BigDecimal::zero()));
+		assert!(payments.last().unwrap().balance.is_zero());
+	}
+	
+	#[test]
+	fn rate_reset_part_way_through_a_period() {
+		let principal = BigDecimal::from(1_200);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPrincipal);
+		terms.payment_frequency = 3;
+		terms.rate_resets = vec![RateReset {
+			effective_date: Date::from_ymd(2020, 2, 1),
+			annual_rate: dec("0.12"),
+		}];
+		
+		let payments = schedule(&terms);
+		// one month at 6% and two at 12%
+		assert_eq!(payments[0].interest_due, dec("30.00"));
+		assert_eq!(payments[1].interest_due, dec("27.00"));
+	}
+	
+	#[test]
+	fn level_principal_schedule() {
+		let principal = BigDecimal::from(1_000);
+		let payments = schedule(&terms(&principal, AmortizationMethod::LevelPrincipal));
+		
+		assert_eq!(payments.len(), 12);
+		// a cent of rounding remainder is repaid every third period
+		for (i, p) in payments.iter().enumerate() {
+			let principal_due = if i % 3 == 1 { dec("83.34") } else { dec("83.33") };
+			assert_eq!(p.principal_due, principal_due, "period {}", i + 1);
+		}
+		let repaid = payments.iter().fold(BigDecimal::zero(), |sum, p| sum + &p.principal_due);
+		assert_eq!(repaid, principal);
+		
+		// interest shrinks with the balance
+		assert_eq!(payments[0].interest_due, dec("5.00"));
+		assert_eq!(payments[1].interest_due, dec("4.58"));
+		assert!(payments[11].balance.is_zero());
+	}
+	
+	#[test]
+	fn amounts_are_rounded_to_the_currency_minor_unit() {
+		let principal = BigDecimal::from(100_000);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPrincipal);
+		terms.currency = Currency::Jpy;
+		
+		let payments = schedule(&terms);
+		assert_eq!(payments[0].principal_due, dec("8333"));
+		assert_eq!(payments[1].principal_due, dec("8334"));
+		assert_eq!(payments[0].interest_due, dec("500"));
+		// 458.335 is rounded off, the .335 is carried into the next period's interest
+		assert_eq!(payments[1].interest_due, dec("458"));
+		assert_eq!(payments[2].interest_due, dec("417"));
+		
+		let repaid = payments.iter().fold(BigDecimal::zero(), |sum, p| sum + &p.principal_due);
+		assert_eq!(repaid, principal);
+	}
+	
+	#[test]
+	fn zero_rate_schedule() {
+		let principal = BigDecimal::from(1_200);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPayment);
+		terms.annual_rate = BigDecimal::zero();
+		terms.payment_frequency = 3;
+		
+		let payments = schedule(&terms);
+		assert_eq!(payments.len(), 4);
+		for p in &payments {
+			assert_eq!(p.principal_due, dec("300.00"));
+			assert!(p.interest_due.is_zero());
+		}
+	}
+	
+	#[test]
+	fn partial_final_period_ends_at_maturity() {
+		let principal = BigDecimal::from(1_000);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPrincipal);
+		terms.payment_frequency = 5;
+		
+		let payments = schedule(&terms);
+		assert_eq!(payments.len(), 3);
+		assert_eq!(payments[2].due_date, terms.maturity_date);
+	}
+	
+	#[test]
+	fn no_periods_before_maturity() {
+		let principal = BigDecimal::from(1_000);
+		let mut terms = terms(&principal, AmortizationMethod::LevelPayment);
+		terms.maturity_date = terms.start_date;
+		
+		assert!(schedule(&terms).is_empty());
+	}
+}
@@ -0,0 +1,159 @@
+use std::convert::Infallible;
+
+use serde::Serialize;
+use warp::http::StatusCode;
+use warp::reject::{InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader, PayloadTooLarge, UnsupportedMediaType};
+use warp::reply::{self, Json, WithStatus};
+use warp::Rejection;
+
+use crate::bank::error::{Error, ErrorKind};
+use crate::db;
+
+/// JSON body returned when a request fails
+#[derive(Serialize, Debug)]
+pub struct ErrorResponse {
+	/// machine readable error code
+	pub code: &'static str,
+	/// human readable description of the error
+	pub message: String,
+}
+
+/// Converts the result of a bank operation into a JSON reply with the matching HTTP status code
+pub fn respond<T: Serialize>(result: Result<T, Error>) -> WithStatus<Json> {
+	match result {
+		Ok(val) => reply::with_status(reply::json(&val), StatusCode::OK),
+		Err(e) => {
+			let body = ErrorResponse {
+				code: error_code(e.kind()),
+				message: message(&e),
+			};
+			reply::with_status(reply::json(&body), status_code(e.kind()))
+		}
+	}
+}
+
+/// Logs an error the client can't act on and replies with a generic 500 that doesn't expose it
+pub fn internal_error(e: &dyn std::fmt::Display) -> WithStatus<Json> {
+	log::error!("internal error: {}", e);
+	let body = ErrorResponse {
+		code: "internal_error",
+		message: String::from(INTERNAL_ERROR_MESSAGE),
+	};
+	reply::with_status(reply::json(&body), StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Replies to a request no route accepted with the same JSON error body bank operations fail with
+pub async fn recover(rejection: Rejection) -> Result<WithStatus<Json>, Infallible> {
+	let (code, status, message) = if rejection.is_not_found() {
+		("not_found", StatusCode::NOT_FOUND, String::from("resource not found"))
+	} else if let Some(e) = rejection.find::<warp::body::BodyDeserializeError>() {
+		("invalid_body", StatusCode::BAD_REQUEST, e.to_string())
+	} else if let Some(e) = rejection.find::<InvalidQuery>() {
+		("invalid_query", StatusCode::BAD_REQUEST, e.to_string())
+	} else if let Some(e) = rejection.find::<InvalidHeader>() {
+		("invalid_header", StatusCode::BAD_REQUEST, e.to_string())
+	} else if let Some(e) = rejection.find::<MissingHeader>() {
+		("invalid_header", StatusCode::BAD_REQUEST, e.to_string())
+	} else if let Some(e) = rejection.find::<MethodNotAllowed>() {
+		("method_not_allowed", StatusCode::METHOD_NOT_ALLOWED, e.to_string())
+	} else if let Some(e) = rejection.find::<UnsupportedMediaType>() {
+		("unsupported_media_type", StatusCode::UNSUPPORTED_MEDIA_TYPE, e.to_string())
+	} else if let Some(e) = rejection.find::<LengthRequired>() {
+		("length_required", StatusCode::LENGTH_REQUIRED, e.to_string())
+	} else if let Some(e) = rejection.find::<PayloadTooLarge>() {
+		("payload_too_large", StatusCode::PAYLOAD_TOO_LARGE, e.to_string())
+	} else {
+		return Ok(internal_error(&format!("unhandled rejection: {:?}", rejection)));
+	};
+	let body = ErrorResponse { code, message };
+	Ok(reply::with_status(reply::json(&body), status))
+}
+
+/// The message returned in place of errors whose details are only logged
+const INTERNAL_ERROR_MESSAGE: &str = "internal server error";
+
+/// Describes the error for the client, database failures are logged and described generically so their details
+/// aren't leaked
+fn message(e: &Error) -> String {
+	match e.kind() {
+		ErrorKind::Database(db::Error::DatabaseError(_)) => {
+			log::error!("{}", e);
+			String::from(INTERNAL_ERROR_MESSAGE)
+		}
+		ErrorKind::Database(db::Error::Connection(_)) => {
+			log::error!("{}", e);
+			String::from("database unavailable")
+		}
+		_ => e.to_string(),
+	}
+}
+
+/// Maps an error to the HTTP status code returned to the client
+pub fn status_code(kind: &ErrorKind) -> StatusCode {
+	match kind {
+		ErrorKind::Database(e) => match e {
+			db::Error::RecordNotFound => StatusCode::NOT_FOUND,
+			db::Error::RecordAlreadyExists => StatusCode::CONFLICT,
+			db::Error::Connection(_) => StatusCode::SERVICE_UNAVAILABLE,
+			db::Error::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+		},
+		ErrorKind::InadequateFunds => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::InvalidDate(_) => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::InvalidStateNegativeValue => StatusCode::BAD_REQUEST,
+		ErrorKind::ZeroAmount => StatusCode::BAD_REQUEST,
+		ErrorKind::ExcessPrecision(_) => StatusCode::BAD_REQUEST,
+		ErrorKind::SelfTransfer => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::ReceiverNotFound => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::IdempotencyKeyConflict => StatusCode::CONFLICT,
+		ErrorKind::InvalidLoanStateTransition { .. } => StatusCode::CONFLICT,
+		ErrorKind::AccountNotOwnedByBorrower => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::MissingBenchmarkRate(..) => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::AccountFrozen => StatusCode::CONFLICT,
+		ErrorKind::AccountDormant => StatusCode::CONFLICT,
+		ErrorKind::AccountClosed => StatusCode::CONFLICT,
+		ErrorKind::InvalidAccountStatusTransition { .. } => StatusCode::CONFLICT,
+		ErrorKind::AccountBalanceNotZero => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::InvalidLinkedAccount => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::CurrencyMismatch { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::MissingFxRate(..) => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::AlreadyReversed => StatusCode::CONFLICT,
+		ErrorKind::NotReversible => StatusCode::UNPROCESSABLE_ENTITY,
+		ErrorKind::LoanPaymentAlreadyPaid => StatusCode::CONFLICT,
+		ErrorKind::SelfApproval => StatusCode::FORBIDDEN,
+	}
+}
+
+/// Maps an error to the code included in the error response body
+pub fn error_code(kind: &ErrorKind) -> &'static str {
+	match kind {
+		ErrorKind::Database(e) => match e {
+			db::Error::RecordNotFound => "record_not_found",
+			db::Error::RecordAlreadyExists => "record_already_exists",
+			db::Error::Connection(_) => "database_unavailable",
+			db::Error::DatabaseError(_) => "database_error",
+		},
+		ErrorKind::InadequateFunds => "inadequate_funds",
+		ErrorKind::InvalidDate(_) => "invalid_date",
+		ErrorKind::InvalidStateNegativeValue => "invalid_state_negative_value",
+		ErrorKind::ZeroAmount => "zero_amount",
+		ErrorKind::ExcessPrecision(_) => "excess_precision",
+		ErrorKind::SelfTransfer => "self_transfer",
+		ErrorKind::ReceiverNotFound => "receiver_not_found",
+		ErrorKind::IdempotencyKeyConflict => "idempotency_key_conflict",
+		ErrorKind::InvalidLoanStateTransition { .. } => "invalid_loan_state_transition",
+		ErrorKind::AccountNotOwnedByBorrower => "account_not_owned_by_borrower",
+		ErrorKind::MissingBenchmarkRate(..) => "missing_benchmark_rate",
+		ErrorKind::AccountFrozen => "account_frozen",
+		ErrorKind::AccountDormant => "account_dormant",
+		ErrorKind::AccountClosed => "account_closed",
+		ErrorKind::InvalidAccountStatusTransition { .. } => "invalid_account_status_transition",
+		ErrorKind::AccountBalanceNotZero => "account_balance_not_zero",
+		ErrorKind::InvalidLinkedAccount => "invalid_linked_account",
+		ErrorKind::CurrencyMismatch { .. } => "currency_mismatch",
+		ErrorKind::MissingFxRate(..) => "missing_fx_rate",
+		ErrorKind::AlreadyReversed => "already_reversed",
+		ErrorKind::NotReversible => "not_reversible",
+		ErrorKind::LoanPaymentAlreadyPaid => "loan_payment_already_paid",
+		ErrorKind::SelfApproval => "self_approval",
+	}
+}
@@ -0,0 +1,25 @@
+/*!
+api exposes the bank service over HTTP as a JSON API
+*/
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use warp::Filter;
+
+use crate::db;
+
+pub use self::routes::{Context, routes};
+
+mod routes;
+mod error;
+
+#[cfg(test)]
+mod routes_test;
+
+/// Serve the JSON API on the given address until the process is stopped
+pub async fn serve(db: db::PgPool, addr: SocketAddr) {
+	let ctx = Arc::new(Context::new(db));
+	let api = routes(ctx).with(warp::log("bank_api"));
+	
+	warp::serve(api).run(addr).await
+}
@@ -0,0 +1,829 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use bigdecimal::BigDecimal;
+use chrono::Datelike;
+use serde::Deserialize;
+use warp::{Filter, Rejection, Reply};
+use warp::reply::{Json, Response, WithStatus};
+
+use crate::{account, account_transaction, accrual, bank_transaction, benchmark, db, fx, history, ledger, loan, overdraft, savings, scheduled_transfer, snapshot, statement, user, vault};
+use crate::bank::error::Error;
+use crate::benchmark::NewBenchmarkRate;
+use crate::fx::NewFxRate;
+use crate::history::EntryType;
+use crate::money::{Currency, Money};
+use crate::overdraft::OverdraftTerms;
+use crate::statement::Format;
+use crate::savings::NewSavingsProduct;
+use crate::loan::{Loan, LoanApplication};
+use crate::bank::service::{Calendar, NewService, Service, SystemCalendar};
+use crate::allocation::AllocationPolicy;
+use crate::delinquency::DelinquencyPolicy;
+use crate::scheduled_transfer::{RetryPolicy, TransferSchedule};
+use crate::types::{Date, Id, Time};
+
+use super::error::{internal_error, recover, respond};
+
+/// Shared state used to handle API requests
+pub struct Context {
+	db: db::PgPool,
+	user_repo: user::Repo,
+	account_repo: account::Repo,
+	vault_repo: vault::Repo,
+	bank_transaction_repo: bank_transaction::Repo,
+	account_transaction_repo: account_transaction::Repo,
+	loan_repo: loan::Repo,
+	loan_payment_repo: loan::PaymentRepo,
+	loan_rate_change_repo: loan::RateChangeRepo,
+	benchmark_repo: benchmark::Repo,
+	ledger_repo: ledger::Repo,
+	accrual_run_repo: accrual::Repo,
+	savings_product_repo: savings::Repo,
+	overdraft_repo: overdraft::Repo,
+	fx_repo: fx::Repo,
+	statement_repo: statement::Repo,
+	snapshot_repo: snapshot::Repo,
+	scheduled_transfer_repo: scheduled_transfer::Repo,
+	calendar: SystemCalendar,
+	delinquency_policy: DelinquencyPolicy,
+	allocation_policy: AllocationPolicy,
+	retry_policy: RetryPolicy,
+}
+
+impl Context {
+	pub fn new(db: db::PgPool) -> Self {
+		Context {
+			user_repo: user::Repo::new(),
+			account_repo: account::Repo::new(),
+			vault_repo: vault::Repo::new(),
+			bank_transaction_repo: bank_transaction::Repo::new(),
+			account_transaction_repo: account_transaction::Repo::new(),
+			loan_repo: loan::Repo::new(),
+			loan_payment_repo: loan::PaymentRepo::new(),
+			loan_rate_change_repo: loan::RateChangeRepo::new(),
+			benchmark_repo: benchmark::Repo::new(),
+			ledger_repo: ledger::Repo::new(),
+			accrual_run_repo: accrual::Repo::new(),
+			savings_product_repo: savings::Repo::new(),
+			overdraft_repo: overdraft::Repo::new(),
+			fx_repo: fx::Repo::new(),
+			statement_repo: statement::Repo::new(),
+			snapshot_repo: snapshot::Repo::new(),
+			scheduled_transfer_repo: scheduled_transfer::Repo::new(),
+			calendar: SystemCalendar,
+			delinquency_policy: DelinquencyPolicy::default(),
+			allocation_policy: AllocationPolicy::default(),
+			retry_policy: RetryPolicy::default(),
+			db,
+		}
+	}
+	
+	fn bank_service(&self) -> Service {
+		Service::new(NewService {
+			db: self.db.clone(),
+			user_repo: &self.user_repo,
+			account_repo: &self.account_repo,
+			vault_repo: &self.vault_repo,
+			bank_transaction_repo: &self.bank_transaction_repo,
+			account_transaction_repo: &self.account_transaction_repo,
+			loan_repo: &self.loan_repo,
+			loan_payment_repo: &self.loan_payment_repo,
+			loan_rate_change_repo: &self.loan_rate_change_repo,
+			benchmark_repo: &self.benchmark_repo,
+			ledger_repo: &self.ledger_repo,
+			accrual_run_repo: &self.accrual_run_repo,
+			savings_product_repo: &self.savings_product_repo,
+			overdraft_repo: &self.overdraft_repo,
+			fx_repo: &self.fx_repo,
+			statement_repo: &self.statement_repo,
+			snapshot_repo: &self.snapshot_repo,
+			scheduled_transfer_repo: &self.scheduled_transfer_repo,
+			calendar: &self.calendar,
+		})
+	}
+	
+	fn find_loan(&self, loan_id: &Id) -> Result<Loan, Error> {
+		let conn = &self.db.get()?;
+		self.loan_repo.find_by_id(conn, loan_id).map_err(Into::into)
+	}
+}
+
+/// The number of accrual runs returned when listing recent runs
+const RECENT_ACCRUAL_RUNS: i64 = 30;
+
+/// Request body for moving funds between an account and a vault
+#[derive(Deserialize, Debug)]
+pub struct VaultTransferRequest {
+	pub vault_name: String,
+	pub amount: BigDecimal,
+	pub currency: Currency,
+}
+
+/// Request body for sending funds to another account
+#[derive(Deserialize, Debug)]
+pub struct SendFundsRequest {
+	pub receiver_id: Id,
+	pub amount: BigDecimal,
+	pub currency: Currency,
+}
+
+/// Request body for converting funds to an account held in another currency, the amount is in the sender's currency
+#[derive(Deserialize, Debug)]
+pub struct ConvertFundsRequest {
+	pub receiver_id: Id,
+	pub amount: BigDecimal,
+	pub currency: Currency,
+}
+
+/// Request body for closing an account, the balance is swept to `sweep_to` when given
+#[derive(Deserialize, Debug)]
+pub struct CloseAccountRequest {
+	#[serde(default)]
+	pub sweep_to: Option<Id>,
+}
+
+/// Request body for operations that draw on or credit a user's account
+#[derive(Deserialize, Debug)]
+pub struct AccountRequest {
+	pub account_id: Id,
+}
+
+/// Query parameters for quoting a loan payoff, the date defaults to today
+#[derive(Deserialize, Debug)]
+pub struct PayoffQuoteQuery {
+	pub date: Option<Date>,
+}
+
+/// Query parameters for a page of an account's history, every filter is optional
+#[derive(Deserialize, Debug)]
+pub struct HistoryQuery {
+	pub from: Option<Date>,
+	pub to: Option<Date>,
+	#[serde(rename = "type")]
+	pub entry_type: Option<EntryType>,
+	pub min_amount: Option<BigDecimal>,
+	pub max_amount: Option<BigDecimal>,
+	#[serde(default)]
+	pub offset: usize,
+	pub limit: Option<usize>,
+}
+
+/// Request body for generating an account's statement, the month defaults to the one before the current month
+#[derive(Deserialize, Debug)]
+pub struct StatementRequest {
+	pub month: Option<Date>,
+}
+
+/// Query parameters for rendering a statement, the format defaults to JSON
+#[derive(Deserialize, Debug)]
+pub struct StatementQuery {
+	#[serde(default)]
+	pub format: Format,
+}
+
+/// Query parameters for an account's or vault's balance at a point in time
+#[derive(Deserialize, Debug)]
+pub struct BalanceQuery {
+	pub at: Time,
+}
+
+/// Request body for paying an amount against a loan
+#[derive(Deserialize, Debug)]
+pub struct LoanPaymentRequest {
+	pub account_id: Id,
+	pub amount: BigDecimal,
+	pub currency: Currency,
+}
+
+/// Request body for opening a savings account with a savings product
+#[derive(Deserialize, Debug)]
+pub struct SavingsAccountRequest {
+	pub user_id: Id,
+	pub product: String,
+}
+
+/// Request body for approving a loan
+#[derive(Deserialize, Debug)]
+pub struct ApproveLoanRequest {
+	pub approver_id: Id,
+}
+
+/// All routes served by the API
+///
+/// Money movements accept an optional `Idempotency-Key` header so that clients can safely retry them
+///
+/// - `POST /accounts/:id/deposit`
+/// - `POST /accounts/:id/withdraw`
+/// - `POST /accounts/:id/send_funds`
+/// - `POST /accounts/:id/convert_funds`
+/// - `POST /accounts/:id/freeze`
+/// - `POST /accounts/:id/mark_dormant`
+/// - `POST /accounts/:id/reactivate`
+/// - `POST /accounts/:id/close`
+/// - `POST /accounts/:id/overdraft_protection`
+/// - `GET  /accounts/:id/history?from=:date&to=:date&type=:type&min_amount=:amount&max_amount=:amount&offset=:n&limit=:n`
+/// - `POST /accounts/:id/statements`
+/// - `GET  /accounts/:id/statements/:month?format=:format`
+/// - `POST /statements`
+/// - `GET  /accounts/:id/balance?at=:time`
+/// - `GET  /vaults/:name/balance?at=:time`
+/// - `POST /bank_transactions/:id/reverse`
+/// - `POST /account_transactions/:id/reverse`
+/// - `POST /scheduled_transfers`
+/// - `POST /scheduled_transfers/:id/cancel`
+/// - `GET  /scheduled_transfers/:id/runs`
+/// - `POST /scheduled_transfer_runs`
+/// - `POST /loans`
+/// - `POST /loans/:id/approve`
+/// - `POST /loans/:id/reject`
+/// - `POST /loans/:id/cancel`
+/// - `POST /loans/:id/disburse`
+/// - `GET  /loans/delinquent`
+/// - `POST /loans/assess_delinquency`
+/// - `GET  /loans/:id/schedule`
+/// - `GET  /loans/:id/next_payment`
+/// - `GET  /loans/:id/payoff_quote?date=:date`
+/// - `POST /loans/:id/payoff`
+/// - `GET  /loans/:id/rate_history`
+/// - `POST /loans/:id/pay`
+/// - `POST /loan_payments/:id/pay`
+/// - `GET  /accrual_runs`
+/// - `POST /accrual_runs`
+/// - `POST /benchmark_rates`
+/// - `POST /fx_rates`
+/// - `POST /savings_products`
+/// - `POST /savings_accounts`
+pub fn routes(ctx: Arc<Context>) -> impl Filter<Extract=impl Reply, Error=Infallible> + Clone {
+	let deposit = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "deposit"))
+		.and(warp::post())
+		.and(idempotency_key())
+		.and(warp::body::json())
+		.and_then(deposit);
+	
+	let withdraw = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "withdraw"))
+		.and(warp::post())
+		.and(idempotency_key())
+		.and(warp::body::json())
+		.and_then(withdraw);
+	
+	let send_funds = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "send_funds"))
+		.and(warp::post())
+		.and(idempotency_key())
+		.and(warp::body::json())
+		.and_then(send_funds);
+	
+	let convert_funds = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "convert_funds"))
+		.and(warp::post())
+		.and(idempotency_key())
+		.and(warp::body::json())
+		.and_then(convert_funds);
+	
+	let freeze_account = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "freeze"))
+		.and(warp::post())
+		.and_then(freeze_account);
+	
+	let mark_account_dormant = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "mark_dormant"))
+		.and(warp::post())
+		.and_then(mark_account_dormant);
+	
+	let reactivate_account = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "reactivate"))
+		.and(warp::post())
+		.and_then(reactivate_account);
+	
+	let close_account = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "close"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(close_account);
+	
+	let set_overdraft_protection = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "overdraft_protection"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(set_overdraft_protection);
+	
+	let get_account_history = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "history"))
+		.and(warp::get())
+		.and(warp::query())
+		.and_then(get_account_history);
+	
+	let generate_statement = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "statements"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(generate_statement);
+	
+	let get_statement = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "statements" / Date))
+		.and(warp::get())
+		.and(warp::query())
+		.and_then(get_statement);
+	
+	let generate_monthly_statements = with_context(ctx.clone())
+		.and(warp::path!("statements"))
+		.and(warp::post())
+		.and_then(generate_monthly_statements);
+	
+	let get_account_balance = with_context(ctx.clone())
+		.and(warp::path!("accounts" / Id / "balance"))
+		.and(warp::get())
+		.and(warp::query())
+		.and_then(get_account_balance);
+	
+	let get_vault_balance = with_context(ctx.clone())
+		.and(warp::path!("vaults" / String / "balance"))
+		.and(warp::get())
+		.and(warp::query())
+		.and_then(get_vault_balance);
+	
+	let reverse_bank_transaction = with_context(ctx.clone())
+		.and(warp::path!("bank_transactions" / Id / "reverse"))
+		.and(warp::post())
+		.and_then(reverse_bank_transaction);
+	
+	let reverse_account_transaction = with_context(ctx.clone())
+		.and(warp::path!("account_transactions" / Id / "reverse"))
+		.and(warp::post())
+		.and_then(reverse_account_transaction);
+	
+	let schedule_transfer = with_context(ctx.clone())
+		.and(warp::path!("scheduled_transfers"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(schedule_transfer);
+	
+	let cancel_scheduled_transfer = with_context(ctx.clone())
+		.and(warp::path!("scheduled_transfers" / Id / "cancel"))
+		.and(warp::post())
+		.and_then(cancel_scheduled_transfer);
+	
+	let get_scheduled_transfer_runs = with_context(ctx.clone())
+		.and(warp::path!("scheduled_transfers" / Id / "runs"))
+		.and(warp::get())
+		.and_then(get_scheduled_transfer_runs);
+	
+	let run_scheduled_transfers = with_context(ctx.clone())
+		.and(warp::path!("scheduled_transfer_runs"))
+		.and(warp::post())
+		.and_then(run_scheduled_transfers);
+	
+	let apply_for_loan = with_context(ctx.clone())
+		.and(warp::path!("loans"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(apply_for_loan);
+	
+	let approve_loan = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "approve"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(approve_loan);
+	
+	let reject_loan = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "reject"))
+		.and(warp::post())
+		.and_then(reject_loan);
+	
+	let cancel_loan = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "cancel"))
+		.and(warp::post())
+		.and_then(cancel_loan);
+	
+	let disburse_loan = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "disburse"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(disburse_loan);
+	
+	let find_delinquent_loans = with_context(ctx.clone())
+		.and(warp::path!("loans" / "delinquent"))
+		.and(warp::get())
+		.and_then(find_delinquent_loans);
+	
+	let assess_delinquency = with_context(ctx.clone())
+		.and(warp::path!("loans" / "assess_delinquency"))
+		.and(warp::post())
+		.and_then(assess_delinquency);
+	
+	let get_loan_schedule = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "schedule"))
+		.and(warp::get())
+		.and_then(get_loan_schedule);
+	
+	let get_next_loan_payment = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "next_payment"))
+		.and(warp::get())
+		.and_then(get_next_loan_payment);
+	
+	let quote_loan_payoff = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "payoff_quote"))
+		.and(warp::get())
+		.and(warp::query())
+		.and_then(quote_loan_payoff);
+	
+	let pay_off_loan = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "payoff"))
+		.and(warp::post())
+		.and(idempotency_key())
+		.and(warp::body::json())
+		.and_then(pay_off_loan);
+	
+	let get_loan_rate_history = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "rate_history"))
+		.and(warp::get())
+		.and_then(get_loan_rate_history);
+	
+	let pay_loan = with_context(ctx.clone())
+		.and(warp::path!("loans" / Id / "pay"))
+		.and(warp::post())
+		.and(idempotency_key())
+		.and(warp::body::json())
+		.and_then(pay_loan);
+	
+	let pay_loan_payment_due = with_context(ctx.clone())
+		.and(warp::path!("loan_payments" / Id / "pay"))
+		.and(warp::post())
+		.and(idempotency_key())
+		.and(warp::body::json())
+		.and_then(pay_loan_payment_due);
+	
+	let find_recent_accrual_runs = with_context(ctx.clone())
+		.and(warp::path!("accrual_runs"))
+		.and(warp::get())
+		.and_then(find_recent_accrual_runs);
+	
+	let run_end_of_day_accrual = with_context(ctx.clone())
+		.and(warp::path!("accrual_runs"))
+		.and(warp::post())
+		.and_then(run_end_of_day_accrual);
+	
+	let publish_benchmark_rate = with_context(ctx.clone())
+		.and(warp::path!("benchmark_rates"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(publish_benchmark_rate);
+	
+	let publish_fx_rate = with_context(ctx.clone())
+		.and(warp::path!("fx_rates"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(publish_fx_rate);
+	
+	let create_savings_product = with_context(ctx.clone())
+		.and(warp::path!("savings_products"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(create_savings_product);
+	
+	let open_savings_account = with_context(ctx)
+		.and(warp::path!("savings_accounts"))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and_then(open_savings_account);
+	
+	deposit
+		.or(withdraw)
+		.or(send_funds)
+		.or(convert_funds)
+		.or(freeze_account)
+		.or(mark_account_dormant)
+		.or(reactivate_account)
+		.or(close_account)
+		.or(set_overdraft_protection)
+		.or(get_account_history)
+		.or(generate_statement)
+		.or(get_statement)
+		.or(generate_monthly_statements)
+		.or(get_account_balance)
+		.or(get_vault_balance)
+		.or(reverse_bank_transaction)
+		.or(reverse_account_transaction)
+		.or(schedule_transfer)
+		.or(cancel_scheduled_transfer)
+		.or(get_scheduled_transfer_runs)
+		.or(run_scheduled_transfers)
+		.or(apply_for_loan)
+		.or(approve_loan)
+		.or(reject_loan)
+		.or(cancel_loan)
+		.or(disburse_loan)
+		.or(find_delinquent_loans)
+		.or(assess_delinquency)
+		.or(get_loan_schedule)
+		.or(get_next_loan_payment)
+		.or(quote_loan_payoff)
+		.or(pay_off_loan)
+		.or(get_loan_rate_history)
+		.or(pay_loan)
+		.or(pay_loan_payment_due)
+		.or(find_recent_accrual_runs)
+		.or(run_end_of_day_accrual)
+		.or(publish_benchmark_rate)
+		.or(publish_fx_rate)
+		.or(create_savings_product)
+		.or(open_savings_account)
+		.recover(recover)
+}
+
+/// Runs the handler on the blocking thread pool, the bank service's database calls would otherwise stall the server
+async fn blocking<R: Reply + Send + 'static>(handler: impl FnOnce() -> R + Send + 'static) -> Result<Response, Infallible> {
+	match tokio::task::spawn_blocking(handler).await {
+		Ok(reply) => Ok(reply.into_response()),
+		Err(e) => Ok(internal_error(&e).into_response()),
+	}
+}
+
+fn with_context(ctx: Arc<Context>) -> impl Filter<Extract=(Arc<Context>, ), Error=Infallible> + Clone {
+	warp::any().map(move || ctx.clone())
+}
+
+/// Reads the optional `Idempotency-Key` header used to make retries of money movements safe
+fn idempotency_key() -> impl Filter<Extract=(Option<String>, ), Error=Rejection> + Clone {
+	warp::header::optional::<String>("idempotency-key")
+}
+
+async fn deposit(ctx: Arc<Context>, account_id: Id, key: Option<String>, body: VaultTransferRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		let amount = Money::new(body.amount, body.currency);
+		respond(ctx.bank_service().deposit(&account_id, &body.vault_name, &amount, key.as_deref()))
+	}).await
+}
+
+async fn withdraw(ctx: Arc<Context>, account_id: Id, key: Option<String>, body: VaultTransferRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		let amount = Money::new(body.amount, body.currency);
+		respond(ctx.bank_service().withdraw(&account_id, &body.vault_name, &amount, key.as_deref()))
+	}).await
+}
+
+async fn send_funds(ctx: Arc<Context>, sender_id: Id, key: Option<String>, body: SendFundsRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		let amount = Money::new(body.amount, body.currency);
+		respond(ctx.bank_service().send_funds(&sender_id, &body.receiver_id, &amount, key.as_deref()))
+	}).await
+}
+
+async fn freeze_account(ctx: Arc<Context>, account_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().freeze_account(&account_id))
+	}).await
+}
+
+async fn mark_account_dormant(ctx: Arc<Context>, account_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().mark_account_dormant(&account_id))
+	}).await
+}
+
+async fn reactivate_account(ctx: Arc<Context>, account_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().reactivate_account(&account_id))
+	}).await
+}
+
+async fn close_account(ctx: Arc<Context>, account_id: Id, body: CloseAccountRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().close_account(&account_id, body.sweep_to.as_ref()))
+	}).await
+}
+
+async fn convert_funds(ctx: Arc<Context>, sender_id: Id, key: Option<String>, body: ConvertFundsRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		let amount = Money::new(body.amount, body.currency);
+		respond(ctx.bank_service().convert_funds(&sender_id, &body.receiver_id, &amount, key.as_deref()))
+	}).await
+}
+
+async fn set_overdraft_protection(ctx: Arc<Context>, account_id: Id, body: OverdraftTerms) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().set_overdraft_protection(&account_id, body))
+	}).await
+}
+
+async fn get_account_history(ctx: Arc<Context>, account_id: Id, query: HistoryQuery) -> Result<Response, Infallible> {
+	blocking(move || {
+		let filter = history::Filter {
+			from: query.from,
+			to: query.to,
+			entry_type: query.entry_type,
+			min_amount: query.min_amount,
+			max_amount: query.max_amount,
+		};
+		let page = history::Page {
+			offset: query.offset,
+			limit: query.limit.unwrap_or(history::DEFAULT_PAGE_SIZE),
+		};
+		respond(ctx.bank_service().get_account_history(&account_id, &filter, page))
+	}).await
+}
+
+async fn generate_statement(ctx: Arc<Context>, account_id: Id, body: StatementRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		let month = body.month.unwrap_or_else(|| ctx.calendar.current_date().with_day(1).unwrap().pred());
+		respond(ctx.bank_service().generate_statement(&account_id, month))
+	}).await
+}
+
+/// Renders the statement in the requested format, errors are still returned as JSON
+async fn get_statement(ctx: Arc<Context>, account_id: Id, month: Date, query: StatementQuery) -> Result<Response, Infallible> {
+	blocking(move || {
+		match ctx.bank_service().get_statement(&account_id, month) {
+			Ok(statement) => warp::reply::with_header(statement.render(query.format), "content-type", query.format.content_type()).into_response(),
+			Err(e) => respond::<()>(Err(e)).into_response(),
+		}
+	}).await
+}
+
+async fn generate_monthly_statements(ctx: Arc<Context>) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().generate_monthly_statements())
+	}).await
+}
+
+async fn get_account_balance(ctx: Arc<Context>, account_id: Id, query: BalanceQuery) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().get_account_balance_at(&account_id, &query.at))
+	}).await
+}
+
+async fn get_vault_balance(ctx: Arc<Context>, vault_name: String, query: BalanceQuery) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().get_vault_balance_at(&vault_name, &query.at))
+	}).await
+}
+
+async fn reverse_bank_transaction(ctx: Arc<Context>, transaction_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().reverse_bank_transaction(&transaction_id, None))
+	}).await
+}
+
+async fn reverse_account_transaction(ctx: Arc<Context>, transaction_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().reverse_account_transaction(&transaction_id, None))
+	}).await
+}
+
+async fn schedule_transfer(ctx: Arc<Context>, body: TransferSchedule) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().schedule_transfer(body))
+	}).await
+}
+
+async fn cancel_scheduled_transfer(ctx: Arc<Context>, scheduled_transfer_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().cancel_scheduled_transfer(&scheduled_transfer_id))
+	}).await
+}
+
+async fn get_scheduled_transfer_runs(ctx: Arc<Context>, scheduled_transfer_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().get_scheduled_transfer_runs(&scheduled_transfer_id))
+	}).await
+}
+
+async fn run_scheduled_transfers(ctx: Arc<Context>) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().run_scheduled_transfers(&ctx.retry_policy))
+	}).await
+}
+
+async fn apply_for_loan(ctx: Arc<Context>, body: LoanApplication) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().apply_for_loan(body))
+	}).await
+}
+
+async fn approve_loan(ctx: Arc<Context>, loan_id: Id, body: ApproveLoanRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().approve_loan(&loan_id, &body.approver_id))
+	}).await
+}
+
+async fn reject_loan(ctx: Arc<Context>, loan_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().reject_loan(&loan_id))
+	}).await
+}
+
+async fn cancel_loan(ctx: Arc<Context>, loan_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().cancel_loan(&loan_id))
+	}).await
+}
+
+async fn disburse_loan(ctx: Arc<Context>, loan_id: Id, body: AccountRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		let result = ctx.find_loan(&loan_id)
+			.and_then(|loan| {
+				ctx.bank_service().disburse_loan(&loan, &body.account_id)?;
+				ctx.find_loan(&loan.id)
+			});
+		respond(result)
+	}).await
+}
+
+async fn find_delinquent_loans(ctx: Arc<Context>) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().find_delinquent_loans())
+	}).await
+}
+
+async fn assess_delinquency(ctx: Arc<Context>) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().assess_delinquency(&ctx.delinquency_policy))
+	}).await
+}
+
+async fn get_loan_schedule(ctx: Arc<Context>, loan_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		let result = ctx.find_loan(&loan_id)
+			.and_then(|loan| ctx.bank_service().get_loan_schedule(&loan));
+		respond(result)
+	}).await
+}
+
+async fn get_next_loan_payment(ctx: Arc<Context>, loan_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		let result = ctx.find_loan(&loan_id)
+			.and_then(|loan| ctx.bank_service().get_next_loan_payment(&loan));
+		respond(result)
+	}).await
+}
+
+async fn quote_loan_payoff(ctx: Arc<Context>, loan_id: Id, query: PayoffQuoteQuery) -> Result<Response, Infallible> {
+	blocking(move || {
+		let payoff_date = query.date.unwrap_or_else(|| ctx.calendar.current_date());
+		respond(ctx.bank_service().quote_loan_payoff(&loan_id, payoff_date))
+	}).await
+}
+
+async fn pay_off_loan(ctx: Arc<Context>, loan_id: Id, key: Option<String>, body: AccountRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().pay_off_loan(&loan_id, &body.account_id, key.as_deref()))
+	}).await
+}
+
+async fn get_loan_rate_history(ctx: Arc<Context>, loan_id: Id) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().get_loan_rate_history(&loan_id))
+	}).await
+}
+
+async fn pay_loan(ctx: Arc<Context>, loan_id: Id, key: Option<String>, body: LoanPaymentRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		let amount = Money::new(body.amount, body.currency);
+		respond(ctx.bank_service().pay_loan(&loan_id, &body.account_id, &amount, &ctx.allocation_policy, key.as_deref()))
+	}).await
+}
+
+async fn pay_loan_payment_due(ctx: Arc<Context>, loan_payment_id: Id, key: Option<String>, body: AccountRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().pay_loan_payment_due(&loan_payment_id, &body.account_id, key.as_deref()))
+	}).await
+}
+
+async fn find_recent_accrual_runs(ctx: Arc<Context>) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().find_recent_accrual_runs(RECENT_ACCRUAL_RUNS))
+	}).await
+}
+
+async fn run_end_of_day_accrual(ctx: Arc<Context>) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().run_end_of_day_accrual())
+	}).await
+}
+
+async fn publish_benchmark_rate(ctx: Arc<Context>, body: NewBenchmarkRate) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().publish_benchmark_rate(body))
+	}).await
+}
+
+async fn publish_fx_rate(ctx: Arc<Context>, body: NewFxRate) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().publish_fx_rate(body))
+	}).await
+}
+
+async fn create_savings_product(ctx: Arc<Context>, body: NewSavingsProduct) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().create_savings_product(body))
+	}).await
+}
+
+async fn open_savings_account(ctx: Arc<Context>, body: SavingsAccountRequest) -> Result<Response, Infallible> {
+	blocking(move || {
+		respond(ctx.bank_service().open_savings_account(&body.user_id, &body.product))
+	}).await
+}
@@ -0,0 +1,554 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use warp::http::StatusCode;
+
+use crate::loan;
+use crate::money::Currency;
+use crate::testutil::*;
+use crate::testutil::Suite as RepoSuite;
+use crate::types::{Date, DateExt};
+
+use super::routes::{Context, routes};
+
+fn body_json(body: &[u8]) -> Value {
+	serde_json::from_slice(body).expect("response body is json")
+}
+
+#[tokio::test]
+async fn deposit() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	let vault = f.insert_main_vault(0);
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", account.id))
+		.json(&json!({ "vault_name": vault.name, "amount": "300", "currency": "USD" }))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::OK);
+	let body = body_json(res.body());
+	assert_eq!(body["account_id"], json!(account.id));
+	assert_eq!(body["transaction_type"], "deposit");
+	assert_eq!(body["amount"].as_str().unwrap().parse::<BigDecimal>().unwrap(), BigDecimal::from(300));
+}
+
+#[tokio::test]
+async fn withdraw_inadequate_funds() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	let vault = f.insert_main_vault(0);
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/withdraw", account.id))
+		.json(&json!({ "vault_name": vault.name, "amount": "500", "currency": "USD" }))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+	assert_eq!(body_json(res.body())["code"], "inadequate_funds");
+}
+
+#[tokio::test]
+async fn deposit_invalid_amount() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	let vault = f.insert_main_vault(0);
+	
+	for (amount, code) in vec![("0", "zero_amount"), ("-300", "invalid_state_negative_value"), ("300.001", "excess_precision")] {
+		let res = warp::test::request()
+			.method("POST")
+			.path(&format!("/accounts/{}/deposit", account.id))
+			.json(&json!({ "vault_name": vault.name, "amount": amount, "currency": "USD" }))
+			.reply(&api)
+			.await;
+		
+		assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", amount);
+		assert_eq!(body_json(res.body())["code"], code);
+	}
+}
+
+#[tokio::test]
+async fn send_funds() {
+	let f = Fixture::new();
+	let s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.account_repo.increment(&f.conn(), &bob_account.id, &BigDecimal::from(500)).unwrap();
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/send_funds", bob_account.id))
+		.json(&json!({ "receiver_id": lucy_account.id, "amount": "200", "currency": "USD" }))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::OK);
+	let body = body_json(res.body());
+	assert_eq!(body["sender_id"], json!(bob_account.id));
+	assert_eq!(body["receiver_id"], json!(lucy_account.id));
+	
+	let lucy_account = s.account_repo.find_by_id(&f.conn(), &lucy_account.id).unwrap();
+	assert_eq!(lucy_account.amount, BigDecimal::from(200));
+}
+
+#[tokio::test]
+async fn account_history() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let vault = f.insert_main_vault(0);
+	for (path, amount) in vec![("deposit", "300"), ("withdraw", "50"), ("deposit", "25")] {
+		let res = warp::test::request()
+			.method("POST")
+			.path(&format!("/accounts/{}/{}", account.id, path))
+			.json(&json!({ "vault_name": vault.name, "amount": amount, "currency": "USD" }))
+			.reply(&api)
+			.await;
+		assert_eq!(res.status(), StatusCode::OK);
+	}
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/accounts/{}/history?type=deposit&limit=1", account.id))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::OK);
+	let body = body_json(res.body());
+	assert_eq!(body["total"], 2);
+	assert_eq!(body["entries"].as_array().unwrap().len(), 1);
+	assert_eq!(body["entries"][0]["entry_type"], "deposit");
+	assert_eq!(body["entries"][0]["balance"].as_str().unwrap().parse::<BigDecimal>().unwrap(), BigDecimal::from(275));
+}
+
+#[tokio::test]
+async fn account_statement() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/statements", account.id))
+		.json(&json!({}))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::OK);
+	let month = body_json(res.body())["period_start"].as_str().unwrap().to_string();
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/accounts/{}/statements/{}?format=csv", account.id, month))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(res.headers()["content-type"], "text/csv");
+	let csv = String::from_utf8(res.body().to_vec()).unwrap();
+	assert_eq!(csv.lines().nth(1).unwrap(), format!("{},opening_balance,,0.00,,,", month));
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/accounts/{}/statements/2000-01-01?format=text", account.id))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn account_balance_at_a_point_in_time() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let vault = f.insert_main_vault(0);
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", account.id))
+		.json(&json!({ "vault_name": vault.name, "amount": "300", "currency": "USD" }))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	
+	let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+	for (at, want) in vec![(now.as_str(), 300), ("2000-01-01T00:00:00Z", 0)] {
+		let res = warp::test::request()
+			.method("GET")
+			.path(&format!("/accounts/{}/balance?at={}", account.id, at))
+			.reply(&api)
+			.await;
+		
+		assert_eq!(res.status(), StatusCode::OK);
+		let body = body_json(res.body());
+		assert_eq!(body["amount"].as_str().unwrap().parse::<BigDecimal>().unwrap(), BigDecimal::from(want));
+		assert_eq!(body["currency"], "USD");
+	}
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/vaults/{}/balance?at={}", vault.name, now))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(body_json(res.body())["amount"].as_str().unwrap().parse::<BigDecimal>().unwrap(), BigDecimal::from(300));
+}
+
+#[tokio::test]
+async fn reverse_deposit() {
+	let f = Fixture::new();
+	let s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let vault = f.insert_main_vault(0);
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", account.id))
+		.json(&json!({ "vault_name": vault.name, "amount": "300", "currency": "USD" }))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	let deposit = s.bank_transaction_repo.find_by_account(&f.conn(), &account.id).unwrap().remove(0);
+	
+	let reverse = || warp::test::request()
+		.method("POST")
+		.path(&format!("/bank_transactions/{}/reverse", deposit.id))
+		.reply(&api);
+	
+	let res = reverse().await;
+	assert_eq!(res.status(), StatusCode::OK);
+	let body = body_json(res.body());
+	assert_eq!(body["transaction_type"], "withdraw");
+	assert_eq!(body["reversal_of"], json!(deposit.id));
+	assert_eq!(s.account_repo.find_by_id(&f.conn(), &account.id).unwrap().amount, BigDecimal::from(0));
+	
+	let res = reverse().await;
+	assert_eq!(res.status(), StatusCode::CONFLICT);
+	assert_eq!(body_json(res.body())["code"], "already_reversed");
+}
+
+#[tokio::test]
+async fn run_scheduled_transfer() {
+	let f = Fixture::new();
+	let s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	let vault = f.insert_main_vault(0);
+	warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", bob_account.id))
+		.json(&json!({ "vault_name": vault.name, "amount": "300", "currency": "USD" }))
+		.reply(&api)
+		.await;
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path("/scheduled_transfers")
+		.json(&json!({
+			"sender_id": bob_account.id,
+			"receiver_id": lucy_account.id,
+			"amount": "200",
+			"frequency": "weekly",
+			"start_date": chrono::Utc::today().naive_utc(),
+		}))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	let order_id = body_json(res.body())["id"].as_str().unwrap().to_string();
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path("/scheduled_transfer_runs")
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(body_json(res.body())[0]["outcome"], "succeeded");
+	assert_eq!(s.account_repo.find_by_id(&f.conn(), &lucy_account.id).unwrap().amount, BigDecimal::from(200));
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/scheduled_transfers/{}/cancel", order_id))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(body_json(res.body())["next_run_date"], Value::Null);
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/scheduled_transfers/{}/runs", order_id))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(body_json(res.body()).as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn loan_not_found() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/loans/{}/next_payment", uuid::Uuid::new_v4()))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::NOT_FOUND);
+	assert_eq!(body_json(res.body())["code"], "record_not_found");
+}
+
+#[tokio::test]
+async fn malformed_body_rejected() {
+	let f = Fixture::new();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", uuid::Uuid::new_v4()))
+		.json(&json!({ "amount": "300" }))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+	let body = body_json(res.body());
+	assert_eq!(body["code"], "invalid_body");
+	assert!(body["message"].as_str().unwrap().contains("vault_name"), "{}", body);
+	
+	// the currency is never assumed
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", uuid::Uuid::new_v4()))
+		.json(&json!({ "vault_name": "main", "amount": "300" }))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+	assert_eq!(body_json(res.body())["code"], "invalid_body");
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", uuid::Uuid::new_v4()))
+		.header("content-type", "application/json")
+		.body("{\"vault_name\": ")
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+	assert_eq!(body_json(res.body())["code"], "invalid_body");
+}
+
+#[tokio::test]
+async fn unmatched_requests_rejected_with_json_errors() {
+	let f = Fixture::new();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path("/accounts/not-an-id/balance")
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::NOT_FOUND);
+	assert_eq!(body_json(res.body())["code"], "not_found");
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path("/no/such/route")
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::NOT_FOUND);
+	assert_eq!(body_json(res.body())["code"], "not_found");
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/accounts/{}/deposit", uuid::Uuid::new_v4()))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+	assert_eq!(body_json(res.body())["code"], "method_not_allowed");
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/accounts/{}/history?limit=many", uuid::Uuid::new_v4()))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+	assert_eq!(body_json(res.body())["code"], "invalid_query");
+}
+
+#[tokio::test]
+async fn deposit_with_idempotency_key() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let vault = f.insert_main_vault(0);
+	
+	let deposit = |amount: &str| {
+		warp::test::request()
+			.method("POST")
+			.path(&format!("/accounts/{}/deposit", account.id))
+			.header("idempotency-key", "deposit-1")
+			.json(&json!({ "vault_name": vault.name, "amount": amount, "currency": "USD" }))
+	};
+	
+	let res = deposit("300").reply(&api).await;
+	assert_eq!(res.status(), StatusCode::OK);
+	let first = body_json(res.body());
+	assert_eq!(first["amount"].as_str().unwrap().parse::<BigDecimal>().unwrap(), BigDecimal::from(300));
+	
+	let res = deposit("300").reply(&api).await;
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(body_json(res.body()), first);
+	
+	let res = deposit("100").reply(&api).await;
+	assert_eq!(res.status(), StatusCode::CONFLICT);
+	assert_eq!(body_json(res.body())["code"], "idempotency_key_conflict");
+}
+
+#[tokio::test]
+async fn loan_schedule() {
+	let f = Fixture::new();
+	let s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let issue_date = Date::from_ymd(2020, 1, 1);
+	let loan = s.loan_repo.create(&f.conn(), loan::NewLoan {
+		maturity_date: issue_date.increment_date_by_months(6),
+		state: loan::LoanState::Active,
+		..new_loan(bob.id, 1000)
+	}).unwrap();
+	
+	let res = warp::test::request()
+		.method("GET")
+		.path(&format!("/loans/{}/schedule", loan.id))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::OK);
+	let body = body_json(res.body());
+	let payments = body.as_array().unwrap();
+	assert_eq!(payments.len(), 6);
+	assert_eq!(payments[0]["due_date"], "2020-02-01");
+	assert_eq!(payments[5]["due_date"], "2020-07-01");
+}
+
+#[tokio::test]
+async fn loan_approval_workflow() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let lucy = f.user_factory.lucy();
+	let bob_account = f.account_factory.checking_account(bob.id);
+	let lucy_account = f.account_factory.checking_account(lucy.id);
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path("/loans")
+		.json(&json!({
+			"user_id": bob.id,
+			"vault_name": vault.name,
+			"principal": "1000",
+			"interest_rate": 200,
+			"issue_date": "2020-01-01",
+			"maturity_date": "2021-01-01",
+			"payment_frequency": 1,
+			"compound_frequency": 1,
+		}))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	let body = body_json(res.body());
+	assert_eq!(body["state"], "pending_approval");
+	assert_eq!(body["amortization_method"], "level_payment");
+	let loan_id = body["id"].as_str().unwrap().to_string();
+	
+	let disburse = |account_id: uuid::Uuid| {
+		warp::test::request()
+			.method("POST")
+			.path(&format!("/loans/{}/disburse", loan_id))
+			.json(&json!({ "account_id": account_id }))
+	};
+	
+	let res = disburse(bob_account.id).reply(&api).await;
+	assert_eq!(res.status(), StatusCode::CONFLICT);
+	assert_eq!(body_json(res.body())["code"], "invalid_loan_state_transition");
+	
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/loans/{}/approve", loan_id))
+		.json(&json!({ "approver_id": lucy.id }))
+		.reply(&api)
+		.await;
+	assert_eq!(res.status(), StatusCode::OK);
+	let body = body_json(res.body());
+	assert_eq!(body["state"], "approved");
+	assert_eq!(body["approved_by"], json!(lucy.id));
+	
+	let res = disburse(lucy_account.id).reply(&api).await;
+	assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+	assert_eq!(body_json(res.body())["code"], "account_not_owned_by_borrower");
+	
+	let res = disburse(bob_account.id).reply(&api).await;
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(body_json(res.body())["state"], "active");
+}
+
+#[tokio::test]
+async fn database_errors_are_not_leaked() {
+	let f = Fixture::new();
+	let _s = RepoSuite::setup();
+	let api = routes(Arc::new(Context::new(f.pool.clone())));
+	
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	let vault = f.insert_main_vault(0);
+	
+	// the amount overflows the vault's balance column
+	let res = warp::test::request()
+		.method("POST")
+		.path(&format!("/accounts/{}/deposit", account.id))
+		.json(&json!({ "vault_name": vault.name, "amount": "1000000000000", "currency": "USD" }))
+		.reply(&api)
+		.await;
+	
+	assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+	let body = body_json(res.body());
+	assert_eq!(body["code"], "database_error");
+	assert_eq!(body["message"], "internal server error");
+}
@@ -2,6 +2,10 @@ use std::error;
 use std::fmt;
 
 use crate::{account, db};
+use crate::account::AccountStatus;
+use crate::loan::LoanState;
+use crate::money::{Currency, CurrencyMismatch};
+use crate::types::Date;
 
 /// An error that can occur when interacting with this module
 #[derive(Debug, PartialEq)]
@@ -25,6 +29,46 @@ pub enum ErrorKind {
 	InadequateFunds,
 	InvalidDate(String),
 	InvalidStateNegativeValue,
+	/// The amount must be greater than zero
+	ZeroAmount,
+	/// The amount has digits smaller than the currency's minor unit
+	ExcessPrecision(Currency),
+	/// Funds can't be sent from an account to itself
+	SelfTransfer,
+	/// The account funds are sent to does not exist
+	ReceiverNotFound,
+	/// The idempotency key was already used for a request with different parameters
+	IdempotencyKeyConflict,
+	/// The loan is not allowed to move between the states
+	InvalidLoanStateTransition { from: LoanState, to: LoanState },
+	/// The account does not belong to the loan's borrower
+	AccountNotOwnedByBorrower,
+	/// No rate for the benchmark was in effect on the date
+	MissingBenchmarkRate(String, Date),
+	/// The account has been frozen and can't move money
+	AccountFrozen,
+	/// The account is dormant and must be reactivated before it can move money
+	AccountDormant,
+	/// The account has been closed
+	AccountClosed,
+	/// The account is not allowed to move between the statuses
+	InvalidAccountStatusTransition { from: AccountStatus, to: AccountStatus },
+	/// The account can't be closed while it holds a balance that isn't swept to another account
+	AccountBalanceNotZero,
+	/// The overdraft protection's linked account must be another account owned by the same user
+	InvalidLinkedAccount,
+	/// Money in one currency was used where another currency was expected
+	CurrencyMismatch { expected: Currency, found: Currency },
+	/// No rate to convert between the currencies was in effect on the date
+	MissingFxRate(Currency, Currency, Date),
+	/// The transaction has already been reversed
+	AlreadyReversed,
+	/// Only deposits, withdrawals and transfers that aren't reversals themselves can be reversed
+	NotReversible,
+	/// The loan payment has already been paid
+	LoanPaymentAlreadyPaid,
+	/// A borrower tried to approve their own loan
+	SelfApproval,
 }
 
 impl fmt::Display for Error {
@@ -33,7 +77,27 @@ impl fmt::Display for Error {
 			ErrorKind::Database(e) => write!(f, "db error: {}", e),
 			ErrorKind::InadequateFunds => write!(f, "not enough funds in account"),
 			ErrorKind::InvalidDate(msg) => write!(f, "invalid date: {}", msg),
-			ErrorKind::InvalidStateNegativeValue => write!(f, "invalid state: negative value not allowed")
+			ErrorKind::InvalidStateNegativeValue => write!(f, "invalid state: negative value not allowed"),
+			ErrorKind::ZeroAmount => write!(f, "amount must be greater than zero"),
+			ErrorKind::ExcessPrecision(currency) => write!(f, "{} amounts can't have more than {} decimal places", currency, currency.minor_units()),
+			ErrorKind::SelfTransfer => write!(f, "funds can't be sent to the account they're sent from"),
+			ErrorKind::ReceiverNotFound => write!(f, "receiving account does not exist"),
+			ErrorKind::IdempotencyKeyConflict => write!(f, "idempotency key was already used with different parameters"),
+			ErrorKind::InvalidLoanStateTransition { from, to } => write!(f, "loan cannot move from {} to {}", from, to),
+			ErrorKind::AccountNotOwnedByBorrower => write!(f, "account does not belong to the borrower"),
+			ErrorKind::MissingBenchmarkRate(benchmark, date) => write!(f, "no {} rate was in effect on {}", benchmark, date),
+			ErrorKind::AccountFrozen => write!(f, "account is frozen"),
+			ErrorKind::AccountDormant => write!(f, "account is dormant"),
+			ErrorKind::AccountClosed => write!(f, "account is closed"),
+			ErrorKind::InvalidAccountStatusTransition { from, to } => write!(f, "account cannot move from {} to {}", from, to),
+			ErrorKind::AccountBalanceNotZero => write!(f, "account balance must be zero or swept to another account"),
+			ErrorKind::InvalidLinkedAccount => write!(f, "linked account must be another account owned by the same user"),
+			ErrorKind::CurrencyMismatch { expected, found } => write!(f, "expected {} but found {}", expected, found),
+			ErrorKind::MissingFxRate(from, to, date) => write!(f, "no {}/{} rate was in effect on {}", from, to, date),
+			ErrorKind::AlreadyReversed => write!(f, "transaction has already been reversed"),
+			ErrorKind::NotReversible => write!(f, "only deposits, withdrawals and transfers can be reversed"),
+			ErrorKind::LoanPaymentAlreadyPaid => write!(f, "loan payment has already been paid"),
+			ErrorKind::SelfApproval => write!(f, "borrowers cannot approve their own loans"),
 		}
 	}
 }
@@ -44,6 +108,12 @@ impl From<db::Error> for Error {
 	}
 }
 
+impl From<CurrencyMismatch> for Error {
+	fn from(e: CurrencyMismatch) -> Self {
+		Error::new(ErrorKind::CurrencyMismatch { expected: e.expected, found: e.found })
+	}
+}
+
 impl From<r2d2::Error> for Error {
 	fn from(e: r2d2::Error) -> Self {
 		Error::new(ErrorKind::Database(db::Error::from(e)))
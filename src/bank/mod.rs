@@ -1,5 +1,5 @@
-mod service;
-mod error;
+pub mod service;
+pub mod error;
 
 #[cfg(test)]
 mod service_test;
@@ -1,15 +1,30 @@
 use std::env;
+use std::iter;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 use bigdecimal::{BigDecimal, Signed, Zero};
-use diesel::Connection;
+use chrono::{Datelike, TimeZone, Utc};
+use diesel::{Connection, PgConnection};
 
-use crate::{account_transaction, db, loan};
-use crate::account::{self, Account};
+use crate::{account_transaction, accrual, allocation, amortization, benchmark, db, delinquency, fx, history, ledger, loan, money, overdraft, savings, scheduled_transfer, snapshot, statement};
+use crate::allocation::{Allocation, AllocationPolicy};
+use crate::account::{self, Account, AccountStatus, AccountType, NewAccount};
+use crate::accrual::AccrualRun;
 use crate::account_transaction::{AccountTransaction, NewAccountTransaction};
-use crate::bank_transaction::{self, BankTransactionType, NewBankTransaction};
-use crate::loan::{Loan, LoanPayment, LoanState, NewPayment};
-use crate::types::{Date, DateExt, Id};
+use crate::bank_transaction::{self, BankTransaction, BankTransactionType, NewBankTransaction};
+use crate::fx::{FxConversion, FxRate, NewFxConversion, NewFxRate};
+use crate::history::HistoryPage;
+use crate::ledger::{Discrepancy, JournalEntryType, LedgerAccount, LedgerAccountKind, NewJournalEntry, Posting, Reconciliation};
+use crate::delinquency::DelinquencyPolicy;
+use crate::amortization::RateReset;
+use crate::benchmark::{BenchmarkRate, NewBenchmarkRate};
+use crate::overdraft::{NewOverdraftProtection, OverdraftProtection, OverdraftProtectionKind, OverdraftTerms};
+use crate::savings::{NewSavingsProduct, SavingsProduct};
+use crate::money::{Currency, Money, RoundingMode};
+use crate::loan::{DelinquencyBucket, Loan, LoanApplication, LoanPayment, LoanState, NewLoan, NewPayment, NewRateChange, NewRepayment, PayoffQuote, RateChange, Repayment};
+use crate::snapshot::Movement;
+use crate::scheduled_transfer::{Frequency, NewScheduledTransfer, NewScheduledTransferRun, RetryPolicy, RunOutcome, ScheduledTransfer, ScheduledTransferRun, TransferSchedule};
+use crate::types::{Date, DateExt, Id, Time};
 use crate::user::{self, User};
 use crate::vault::{self, Vault};
 
@@ -27,6 +42,16 @@ pub struct Service<'a> {
 	account_transaction_repo: &'a account_transaction::Repo,
 	loan_repo: &'a loan::Repo,
 	loan_payments_repo: &'a loan::PaymentRepo,
+	loan_rate_change_repo: &'a loan::RateChangeRepo,
+	benchmark_repo: &'a benchmark::Repo,
+	ledger_repo: &'a ledger::Repo,
+	accrual_run_repo: &'a accrual::Repo,
+	savings_product_repo: &'a savings::Repo,
+	overdraft_repo: &'a overdraft::Repo,
+	fx_repo: &'a fx::Repo,
+	statement_repo: &'a statement::Repo,
+	snapshot_repo: &'a snapshot::Repo,
+	scheduled_transfer_repo: &'a scheduled_transfer::Repo,
 	calendar: &'a dyn Calendar,
 }
 
@@ -40,6 +65,16 @@ pub struct NewService<'a> {
 	pub account_transaction_repo: &'a account_transaction::Repo,
 	pub loan_repo: &'a loan::Repo,
 	pub loan_payment_repo: &'a loan::PaymentRepo,
+	pub loan_rate_change_repo: &'a loan::RateChangeRepo,
+	pub benchmark_repo: &'a benchmark::Repo,
+	pub ledger_repo: &'a ledger::Repo,
+	pub accrual_run_repo: &'a accrual::Repo,
+	pub savings_product_repo: &'a savings::Repo,
+	pub overdraft_repo: &'a overdraft::Repo,
+	pub fx_repo: &'a fx::Repo,
+	pub statement_repo: &'a statement::Repo,
+	pub snapshot_repo: &'a snapshot::Repo,
+	pub scheduled_transfer_repo: &'a scheduled_transfer::Repo,
 	pub calendar: &'a dyn Calendar,
 }
 
@@ -54,6 +89,16 @@ impl<'a> Service<'a> {
 			account_transaction_repo: v.account_transaction_repo,
 			loan_repo: v.loan_repo,
 			loan_payments_repo: v.loan_payment_repo,
+			loan_rate_change_repo: v.loan_rate_change_repo,
+			benchmark_repo: v.benchmark_repo,
+			ledger_repo: v.ledger_repo,
+			accrual_run_repo: v.accrual_run_repo,
+			savings_product_repo: v.savings_product_repo,
+			overdraft_repo: v.overdraft_repo,
+			fx_repo: v.fx_repo,
+			statement_repo: v.statement_repo,
+			snapshot_repo: v.snapshot_repo,
+			scheduled_transfer_repo: v.scheduled_transfer_repo,
 			calendar: v.calendar,
 		}
 	}
@@ -64,20 +109,53 @@ impl<'a> Service<'a> {
     /// * `account_id` - user's account id in which funds belong to
     /// * `vault_name` - vault's unique name where the funds are held for safekeeping
     /// * `amount` - amount deposited
-	pub fn deposit(&self, account_id: &uuid::Uuid, vault_name: &str, amount: &BigDecimal) -> Result<Account> {
+    /// * `idempotency_key` - optional key that makes retrying the deposit safe; a retry returns the original transaction
+	pub fn deposit(&self, account_id: &uuid::Uuid, vault_name: &str, amount: &Money, idempotency_key: Option<&str>) -> Result<BankTransaction> {
+		check_amount(amount)?;
+		
 		let conn = &self.db.get()?;
-		conn.transaction::<Account, Error, _>(|| {
-			self.bank_transaction_repo.create(bank_transaction::NewBankTransaction {
+		conn.transaction::<BankTransaction, Error, _>(|| {
+			match self.find_original(conn, idempotency_key)? {
+				Some(Original::Bank(t)) if t.is_repeat_of(BankTransactionType::Deposit, account_id, vault_name, amount) => {
+					return Ok(t);
+				}
+				Some(_) => return Err(Error::new(ErrorKind::IdempotencyKeyConflict)),
+				None => {}
+			}
+			
+			// lock the account so it can't be closed while the deposit is made
+			let account = self.account_repo.find_for_update(conn, account_id)?;
+			check_account_open(&account)?;
+			money::check_currency(account.currency, amount.currency)?;
+			let vault = self.vault_repo.find_by_name(conn, vault_name)?;
+			money::check_currency(account.currency, vault.currency)?;
+			
+			let transaction = self.bank_transaction_repo.create(conn, bank_transaction::NewBankTransaction {
 				account_id,
 				vault_name,
 				transaction_type: BankTransactionType::Deposit,
-				amount,
+				amount: &amount.amount,
+				idempotency_key,
+				overdraft_protection: None,
+				currency: account.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::Deposit,
+				reference_id: Some(&transaction.id),
+				postings: vec![Posting {
+					debit: LedgerAccount::Vault(vault_name.to_owned()),
+					credit: LedgerAccount::Deposit(*account_id),
+					amount: &amount.amount,
+				}],
 			})?;
 			
-			let account = self.account_repo.increment(account_id, amount)?;
-			self.vault_repo.increment(vault_name, amount)?;
+			self.account_repo.increment(conn, account_id, &amount.amount)?;
+			self.vault_repo.increment(conn, vault_name, &amount.amount)?;
 			
-			Ok(account)
+			Ok(transaction)
 		})
 	}
 	
@@ -87,28 +165,55 @@ impl<'a> Service<'a> {
     /// * `account_id` - user's account id that the funds belong to
     /// * `vault_name` - vault's unique name where the funds are stored and withdrawn from
     /// * `amount` - amount withdrawn
-	pub fn withdraw(&self, account_id: &uuid::Uuid, vault_name: &str, amount: &BigDecimal) -> Result<Account> {
-		let mut account = self.account_repo.find_by_id(account_id)?;
-		if account.amount.lt(amount) {
-			return Err(Error::new(ErrorKind::InadequateFunds));
-		}
+    /// * `idempotency_key` - optional key that makes retrying the withdrawal safe; a retry returns the original transaction
+	pub fn withdraw(&self, account_id: &uuid::Uuid, vault_name: &str, amount: &Money, idempotency_key: Option<&str>) -> Result<BankTransaction> {
+		check_amount(amount)?;
 		
 		let conn = &self.db.get()?;
-		conn.transaction::<(), Error, _>(|| {
-			self.bank_transaction_repo.create(bank_transaction::NewBankTransaction {
+		conn.transaction::<BankTransaction, Error, _>(|| {
+			match self.find_original(conn, idempotency_key)? {
+				Some(Original::Bank(t)) if t.is_repeat_of(BankTransactionType::Withdraw, account_id, vault_name, amount) => {
+					return Ok(t);
+				}
+				Some(_) => return Err(Error::new(ErrorKind::IdempotencyKeyConflict)),
+				None => {}
+			}
+			
+			// lock the account so concurrent withdrawals can't both pass the funds check
+			let account = self.lock_account(conn, account_id)?;
+			check_account_open(&account)?;
+			money::check_currency(account.currency, amount.currency)?;
+			let vault = self.vault_repo.find_by_name(conn, vault_name)?;
+			money::check_currency(account.currency, vault.currency)?;
+			let overdraft_protection = self.protect_overdraft(conn, &account, &amount.amount)?;
+			
+			let transaction = self.bank_transaction_repo.create(conn, bank_transaction::NewBankTransaction {
 				account_id,
 				vault_name,
 				transaction_type: BankTransactionType::Withdraw,
-				amount,
+				amount: &amount.amount,
+				idempotency_key,
+				overdraft_protection,
+				currency: account.currency,
+				reversal_of: None,
+				forced_by: None,
 			})?;
 			
-			account = self.account_repo.decrement(account_id, amount)?;
-			self.vault_repo.decrement(vault_name, amount)?;
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::Withdraw,
+				reference_id: Some(&transaction.id),
+				postings: vec![Posting {
+					debit: LedgerAccount::Deposit(*account_id),
+					credit: LedgerAccount::Vault(vault_name.to_owned()),
+					amount: &amount.amount,
+				}],
+			})?;
 			
-			Ok(())
-		});
-		
-		Ok(account)
+			self.account_repo.decrement(conn, account_id, &amount.amount)?;
+			self.vault_repo.decrement(conn, vault_name, &amount.amount)?;
+			
+			Ok(transaction)
+		})
 	}
 	
 	/// Transfer funds from account to account
@@ -118,172 +223,2195 @@ impl<'a> Service<'a> {
     /// * `account_id` - user's account id in which funds belong to
     /// * `vault_name` - vault's unique name where the funds are transferred to for safekeeping and use by the bank
     /// * `amount` - amount deposited
-	pub fn send_funds(&self, sender_id: &uuid::Uuid, receiver_id: &uuid::Uuid, amount: &BigDecimal) -> Result<AccountTransaction> {
-		let mut sender_account = self.account_repo.find_by_id(sender_id)?;
-		if sender_account.amount.lt(amount) {
-			return Err(Error::new(ErrorKind::InadequateFunds));
+    /// * `idempotency_key` - optional key that makes retrying the transfer safe; a retry returns the original transaction
+	pub fn send_funds(&self, sender_id: &uuid::Uuid, receiver_id: &uuid::Uuid, amount: &Money, idempotency_key: Option<&str>) -> Result<AccountTransaction> {
+		let conn = &self.db.get()?;
+		conn.transaction::<AccountTransaction, Error, _>(|| {
+			self.send_funds_on(conn, sender_id, receiver_id, amount, idempotency_key)
+		})
+	}
+	
+	/// Submit a loan application
+	///
+	/// The loan is created pending approval and can't be disbursed until it is approved.
+	/// A variable rate loan's rate is set from its benchmark's rate in effect on the issue date.
+	/// The rate the loan is issued at is the first entry in its rate history.
+	pub fn apply_for_loan(&self, application: LoanApplication) -> Result<Loan> {
+		if application.maturity_date <= application.issue_date {
+			let msg = format!("maturity date({}) must be after issue date({})", application.maturity_date, application.issue_date);
+			return Err(Error::new(ErrorKind::InvalidDate(msg)));
 		}
 		
 		let conn = &self.db.get()?;
-		conn.transaction::<AccountTransaction, Error, _>(|| {
-			let transaction = self.account_transaction_repo.create(NewAccountTransaction {
-				sender_id,
-				receiver_id,
-				amount,
+		conn.transaction::<_, Error, _>(|| {
+			let vault = self.vault_repo.find_by_name(conn, &application.vault_name)?;
+			check_amount(&Money::new(application.principal.clone(), vault.currency))?;
+			let variable_rate = application.variable_rate;
+			let (interest_rate, benchmark_rate) = match &variable_rate {
+				Some(terms) => {
+					let benchmark_rate = self.find_benchmark_rate(conn, &terms.benchmark, &application.issue_date)?;
+					(loan::index_rate(benchmark_rate, terms.margin, terms.floor, terms.cap), Some(benchmark_rate))
+				}
+				None => (application.interest_rate, None),
+			};
+			
+			let loan = self.loan_repo.create(conn, NewLoan {
+				user_id: application.user_id,
+				vault_name: application.vault_name,
+				orig_principal: application.principal.clone(),
+				balance: application.principal,
+				interest_rate,
+				issue_date: application.issue_date,
+				maturity_date: application.maturity_date,
+				payment_frequency: application.payment_frequency,
+				compound_frequency: application.compound_frequency,
+				state: LoanState::PendingApproval,
+				amortization_method: application.amortization_method,
+				day_count_convention: application.day_count_convention,
+				benchmark: variable_rate.as_ref().map(|terms| terms.benchmark.clone()),
+				rate_margin: variable_rate.as_ref().map_or(0, |terms| terms.margin),
+				rate_floor: variable_rate.as_ref().and_then(|terms| terms.floor),
+				rate_cap: variable_rate.as_ref().and_then(|terms| terms.cap),
+				rate_reset_frequency: variable_rate.as_ref().map_or(0, |terms| terms.reset_frequency),
+				currency: vault.currency,
 			})?;
 			
-			self.account_repo.increment(receiver_id, amount)?;
-			self.account_repo.decrement(sender_id, amount)?;
+			self.loan_rate_change_repo.create(conn, NewRateChange {
+				loan_id: loan.id,
+				effective_date: loan.issue_date,
+				interest_rate,
+				benchmark_rate,
+			})?;
+			Ok(loan)
+		})
+	}
+	
+	/// Approve a pending loan and create its payment schedule
+	///
+	/// A borrower can't approve their own loan.
+	///
+	/// # Arguments
+	/// * `loan_id` - id of the loan pending approval
+	/// * `approver_id` - id of the user approving the loan, recorded on the loan with the time of approval
+	pub fn approve_loan(&self, loan_id: &Id, approver_id: &Id) -> Result<Loan> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let loan = self.loan_repo.find_for_update(conn, loan_id)?;
+			check_loan_transition(&loan, LoanState::Approved)?;
+			if loan.user_id == *approver_id {
+				return Err(Error::new(ErrorKind::SelfApproval));
+			}
 			
-			Ok(transaction)
+			let loan = self.loan_repo.approve(conn, loan_id, approver_id)?;
+			self.create_loan_schedule(conn, &loan)?;
+			Ok(loan)
+		})
+	}
+	
+	/// Reject a pending loan application
+	pub fn reject_loan(&self, loan_id: &Id) -> Result<Loan> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let loan = self.loan_repo.find_for_update(conn, loan_id)?;
+			self.transition_loan(conn, &loan, LoanState::Rejected)
+		})
+	}
+	
+	/// Cancel a loan that hasn't been disbursed, removing its payment schedule
+	pub fn cancel_loan(&self, loan_id: &Id) -> Result<Loan> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let loan = self.loan_repo.find_for_update(conn, loan_id)?;
+			let loan = self.transition_loan(conn, &loan, LoanState::Cancelled)?;
+			self.loan_payments_repo.delete_unpaid(conn, loan_id)?;
+			Ok(loan)
 		})
 	}
 	
-	/// Transfer the loan principal from the bank to the borrower's account
+	/// Transfer the loan principal from the bank to the borrower's account and activate the loan
+	///
+	/// The principal is booked as a receivable owed to the bank and credited to the borrower's account,
+	/// and the vault's cash is drawn down by the same amount.
 	///
 	/// # Arguments
-    /// * `loan` - the loan with information about the bank, user, and loan principal
-    /// * `account_id` - the user's account id that funds will be transferred to
+    /// * `loan` - the approved loan with information about the bank, user, and loan principal
+    /// * `account_id` - the borrower's account id that funds will be transferred to
 	pub fn disburse_loan(&self, loan: &Loan, account_id: &Id) -> Result<()> {
-		//todo:
-		// - validate the loan has been granted approval
-		// - validate that the account belongs to the user under loan.user_id
 		let conn = &self.db.get()?;
 		
 		conn.transaction::<_, Error, _>(|| {
-			self.vault_repo.decrement(&loan.vault_name, &loan.orig_principal)?;
-			self.account_repo.increment(account_id, &loan.orig_principal)?;
+			let loan = self.loan_repo.find_for_update(conn, &loan.id)?;
+			check_loan_transition(&loan, LoanState::Active)?;
+			
+			let account = self.account_repo.find_for_update(conn, account_id)?;
+			check_account_open(&account)?;
+			if account.user_id != loan.user_id {
+				return Err(Error::new(ErrorKind::AccountNotOwnedByBorrower));
+			}
+			money::check_currency(loan.currency, account.currency)?;
+			
+			let transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id,
+				vault_name: &loan.vault_name,
+				transaction_type: BankTransactionType::LoanPrincipal,
+				amount: &loan.orig_principal,
+				idempotency_key: None,
+				overdraft_protection: None,
+				currency: loan.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			
+			// the vault pays out the principal that's credited to the borrower's account
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::LoanDisbursement,
+				reference_id: Some(&transaction.id),
+				postings: vec![
+					Posting {
+						debit: LedgerAccount::LoanReceivable(loan.id),
+						credit: LedgerAccount::Deposit(*account_id),
+						amount: &loan.orig_principal,
+					},
+					Posting {
+						debit: LedgerAccount::LoanFunding(loan.vault_name.clone()),
+						credit: LedgerAccount::Vault(loan.vault_name.clone()),
+						amount: &loan.orig_principal,
+					},
+				],
+			})?;
+			
+			self.account_repo.increment(conn, account_id, &loan.orig_principal)?;
+			self.vault_repo.decrement(conn, &loan.vault_name, &loan.orig_principal)?;
+			self.loan_repo.set_state(conn, &loan.id, LoanState::Active)?;
+			
+			if self.loan_payments_repo.find_by_loan(conn, &loan.id)?.is_empty() {
+				self.create_loan_schedule(conn, &loan)?;
+			}
 			
 			Ok(())
 		})
 	}
 	
+	/// Gets the loan's full payment schedule ordered by due date
+	///
+	/// Creates the schedule if it doesn't exist
+	pub fn get_loan_schedule(&self, loan: &Loan) -> Result<Vec<LoanPayment>> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let schedule = self.loan_payments_repo.find_by_loan(conn, &loan.id)?;
+			if !schedule.is_empty() {
+				return Ok(schedule);
+			}
+			self.create_loan_schedule(conn, loan)?;
+			self.loan_payments_repo.find_by_loan(conn, &loan.id).map_err(Into::into)
+		})
+	}
+	
+	/// Replaces the loan's unpaid payments by amortizing its current balance over the rest of its term
+	///
+	/// Used after a prepayment reduces the balance below what the schedule expects.
+	/// Payments that have been made are kept on the schedule.
+	pub fn regenerate_loan_schedule(&self, loan: &Loan) -> Result<Vec<LoanPayment>> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			self.create_loan_schedule(conn, loan)?;
+			self.loan_payments_repo.find_by_loan(conn, &loan.id).map_err(Into::into)
+		})
+	}
+	
 	/// Gets the next loan payment due for the loan
 	///
-	/// Creates the loan payment if it doesn't exist
+	/// Creates the loan's schedule if it has no unpaid payments left
 	/// Updates the loan payment based on the loan's current balance and accrued interest
 	pub fn get_next_loan_payment(&self, loan: &Loan) -> Result<LoanPayment> {
-		let loan_payment = match self.loan_payments_repo.find_by_id(&loan.id) {
-			Ok(val) => val,
-			Err(e) => return match e {
-				db::Error::RecordNotFound => self.create_next_loan_payment(loan),
-				_ => Err(Error::from(e))
-			},
-		};
-		let loan_payment = self.update_loan_payment(loan, &loan_payment.id)?;
-		Ok(loan_payment)
+		let conn = &self.db.get()?;
+		conn.transaction::<LoanPayment, Error, _>(|| {
+			let loan_payment = match self.loan_payments_repo.find_first_unpaid(conn, &loan.id) {
+				Ok(val) => val,
+				Err(db::Error::RecordNotFound) => {
+					self.create_loan_schedule(conn, loan)?;
+					self.loan_payments_repo.find_first_unpaid(conn, &loan.id)?
+				}
+				Err(e) => return Err(e.into()),
+			};
+			self.set_loan_payment_dues(conn, loan, &loan_payment)
+		})
 	}
 	
-	/// Gets the next loan payment due for the loan
-	///
-	/// Creates the loan payment if it doesn't exist
 	/// Updates the loan payment based on the loan's current balance and accrued interest
 	pub fn update_loan_payment(&self, loan: &Loan, loan_payment_id: &Id) -> Result<LoanPayment> {
-		self.loan_payments_repo.set_dues(loan_payment_id,
-										 &loan.principal_due(self.calendar.current_date()),
-										 &loan.accrued_interest).map_err(Into::into)
+		let conn = &self.db.get()?;
+		conn.transaction::<LoanPayment, Error, _>(|| {
+			let loan_payment = self.loan_payments_repo.find_by_id(conn, loan_payment_id)?;
+			self.set_loan_payment_dues(conn, loan, &loan_payment)
+		})
 	}
 	
-	/// Calculate and accrue interest on the loan
-	/// Updates the loan with the current accrued interest
+	/// Accrue one period of interest on the loan
+	///
+	/// Adds the interest on the loan's balance for the period after the date interest has been accrued through.
+	/// Interest left unpaid at a compounding date is capitalized first, so the period accrues on the capitalized balance.
 	pub fn accrue(&self, loan: &Loan) -> Result<Loan> {
-		let divisor = BigDecimal::from(12 / loan.payment_frequency);
-		let accrued_interest = (&loan.balance).mul(loan.interest_rate()).div(divisor);
-		self.loan_repo.set_accrued_interest(&loan.id, &accrued_interest).map_err(Into::into)
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let loan = self.loan_repo.find_for_update(conn, &loan.id)?;
+			let (loan, _) = self.accrue_period(conn, loan)?;
+			Ok(loan)
+		})
 	}
 	
-	/// Pay the current loan payment dues
+	/// Publish a benchmark's rate from its effective date
+	///
+	/// Variable rate loans indexed to the benchmark pick up the new rate at their next reset on or after the effective date
+	pub fn publish_benchmark_rate(&self, new_rate: NewBenchmarkRate) -> Result<BenchmarkRate> {
+		let conn = &self.db.get()?;
+		self.benchmark_repo.create(conn, new_rate).map_err(Into::into)
+	}
+	
+	/// Gets every rate the loan has been charged, the earliest first
+	pub fn get_loan_rate_history(&self, loan_id: &Id) -> Result<Vec<RateChange>> {
+		let conn = &self.db.get()?;
+		self.loan_rate_change_repo.find_by_loan(conn, loan_id).map_err(Into::into)
+	}
+	
+	/// Create a savings product that pays interest at the APY, funded by the vault
+	pub fn create_savings_product(&self, new_product: NewSavingsProduct) -> Result<SavingsProduct> {
+		let conn = &self.db.get()?;
+		self.savings_product_repo.create(conn, new_product).map_err(Into::into)
+	}
+	
+	/// Open a savings account for the user that earns interest with the product from the calendar's current date
+	pub fn open_savings_account(&self, user_id: &Id, product_name: &str) -> Result<Account> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let product = self.savings_product_repo.find_by_name(conn, product_name)?;
+			let vault = self.vault_repo.find_by_name(conn, &product.vault_name)?;
+			let account = self.account_repo.create_account(conn, NewAccount {
+				user_id: *user_id,
+				account_type: AccountType::Savings,
+				currency: vault.currency,
+			})?;
+			self.account_repo.set_savings_product(conn, &account.id, &product.name, &self.calendar.current_date())
+				.map_err(Into::into)
+		})
+	}
+	
+	/// Freeze an open account so no money can move into or out of it
+	pub fn freeze_account(&self, account_id: &Id) -> Result<Account> {
+		self.transition_account(account_id, AccountStatus::Frozen)
+	}
+	
+	/// Mark an open account that hasn't been used in a long time as dormant
+	pub fn mark_account_dormant(&self, account_id: &Id) -> Result<Account> {
+		self.transition_account(account_id, AccountStatus::Dormant)
+	}
+	
+	/// Reopen a frozen or dormant account for use
+	pub fn reactivate_account(&self, account_id: &Id) -> Result<Account> {
+		self.transition_account(account_id, AccountStatus::Open)
+	}
+	
+	/// Close an open or dormant account permanently
+	///
+	/// A savings account is credited the whole cents of its accrued interest first. The account's balance must then be
+	/// zero, unless the account to sweep the balance to is given, in which case the balance is transferred there.
+	/// An account can't be swept into itself.
 	///
 	/// # Arguments
-	/// `loan_payment_id` - id of loan payment
-	/// `account_id` - id of the user's account that will be used to pay the dues
-	pub fn pay_loan_payment_due(&self, loan_payment_id: &uuid::Uuid, account_id: &uuid::Uuid) -> Result<LoanPayment> {
-		//todo: validate we're within loan payment's due date range
-		let mut loan_payment = self.loan_payments_repo.find_by_id(loan_payment_id)?;
-		let mut loan = self.loan_repo.find_by_id(&loan_payment.loan_id)?;
+	/// * `account_id` - the account to close
+	/// * `sweep_to` - optional open account the remaining balance is transferred to
+	pub fn close_account(&self, account_id: &Id, sweep_to: Option<&Id>) -> Result<Account> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			if let Some(receiver_id) = sweep_to {
+				check_not_self_transfer(account_id, receiver_id)?;
+			}
+			
+			// the account swept to is locked with the account, in id order like a transfer between them
+			let (mut account, mut receiver) = (None, None);
+			for (id, locked) in self.lock_in_order(conn, iter::once(account_id).chain(sweep_to).collect()) {
+				if id == account_id {
+					account = Some(locked?);
+				} else {
+					receiver = Some(locked.map_err(receiver_error)?);
+				}
+			}
+			let mut account = account.expect("the account is locked");
+			check_account_transition(&account, AccountStatus::Closed)?;
+			
+			if let Some(product_name) = &account.savings_product {
+				let product = self.savings_product_repo.find_by_name(conn, product_name)?;
+				account = self.credit_savings_interest(conn, account, &product)?;
+			}
+			
+			match (sweep_to, receiver) {
+				(Some(receiver_id), Some(receiver)) if account.amount.is_positive() => {
+					check_account_open(&receiver)?;
+					money::check_currency(account.currency, receiver.currency)?;
+					
+					self.transfer(conn, NewAccountTransaction {
+						sender_id: account_id,
+						receiver_id,
+						amount: &account.amount,
+						idempotency_key: None,
+						overdraft_protection: None,
+						reversal_of: None,
+						forced_by: None,
+						currency: account.currency,
+					})?;
+				}
+				_ if !account.amount.is_zero() => return Err(Error::new(ErrorKind::AccountBalanceNotZero)),
+				_ => {}
+			}
+			
+			self.account_repo.close(conn, account_id).map_err(Into::into)
+		})
+	}
+	
+	/// Set the overdraft protection that covers withdrawals and transfers the account can't cover on its own
+	///
+	/// The linked account must belong to the same user. Interest on a negative balance accrues from the calendar's
+	/// current date.
+	pub fn set_overdraft_protection(&self, account_id: &Id, terms: OverdraftTerms) -> Result<OverdraftProtection> {
+		if terms.overdraft_limit.is_negative() || terms.overdraft_fee.is_negative() || terms.interest_rate < 0 {
+			return Err(Error::new(ErrorKind::InvalidStateNegativeValue));
+		}
 		
 		let conn = &self.db.get()?;
-		conn.transaction::<LoanPayment, Error, _>(|| {
-			let principal_transaciton = self.bank_transaction_repo.create(NewBankTransaction {
-				account_id,
-				vault_name: &loan.vault_name,
-				transaction_type: BankTransactionType::PrincipalRepayment,
-				amount: &loan_payment.principal_due,
-			})?;
-			let interest_transaction = self.bank_transaction_repo.create(NewBankTransaction {
-				account_id,
-				vault_name: &loan.vault_name,
-				transaction_type: BankTransactionType::InterestRepayment,
-				amount: &loan_payment.interest_due,
+		conn.transaction::<_, Error, _>(|| {
+			let account = self.account_repo.find_for_update(conn, account_id)?;
+			check_account_open(&account)?;
+			if let Some(linked_id) = &terms.linked_account_id {
+				let linked = self.account_repo.find_by_id(conn, linked_id)?;
+				if linked.id == account.id || linked.user_id != account.user_id {
+					return Err(Error::new(ErrorKind::InvalidLinkedAccount));
+				}
+				money::check_currency(account.currency, linked.currency)?;
+			}
+			let vault = self.vault_repo.find_by_name(conn, &terms.vault_name)?;
+			money::check_currency(account.currency, vault.currency)?;
+			check_precision(&Money::new(terms.overdraft_limit.clone(), account.currency))?;
+			check_precision(&Money::new(terms.overdraft_fee.clone(), account.currency))?;
+			
+			self.overdraft_repo.set(conn, NewOverdraftProtection {
+				account_id: account.id,
+				linked_account_id: terms.linked_account_id,
+				overdraft_limit: terms.overdraft_limit,
+				overdraft_fee: terms.overdraft_fee,
+				interest_rate: terms.interest_rate,
+				vault_name: terms.vault_name,
+				interest_accrued_through: self.calendar.current_date(),
+			}).map_err(Into::into)
+		})
+	}
+	
+	/// Publish a currency pair's rate from its effective date
+	pub fn publish_fx_rate(&self, new_rate: NewFxRate) -> Result<FxRate> {
+		if new_rate.rate.is_zero() {
+			return Err(Error::new(ErrorKind::ZeroAmount));
+		}
+		if new_rate.rate.is_negative() {
+			return Err(Error::new(ErrorKind::InvalidStateNegativeValue));
+		}
+		let conn = &self.db.get()?;
+		self.fx_repo.create_rate(conn, new_rate).map_err(Into::into)
+	}
+	
+	/// Convert funds from the sender's account to the receiver's account held in another currency
+	///
+	/// The amount is taken from the sender in the sender's currency and converted at the pair's rate in effect on the
+	/// calendar's current date, using the inverse of the opposite pair's rate when only that has been published.
+	/// The conversion records the rate it applied. A sender that can't cover the amount is covered by its overdraft
+	/// protection, as it is for a withdrawal.
+	///
+	/// # Arguments
+	/// * `sender_id` - the account the amount is taken from
+	/// * `receiver_id` - the account the converted amount is credited to
+	/// * `amount` - the amount sold, in the sender's currency
+	/// * `idempotency_key` - optional key that makes retrying the conversion safe; a retry returns the original conversion
+	pub fn convert_funds(&self, sender_id: &Id, receiver_id: &Id, amount: &Money, idempotency_key: Option<&str>) -> Result<FxConversion> {
+		check_amount(amount)?;
+		check_not_self_transfer(sender_id, receiver_id)?;
+		
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			match self.find_original(conn, idempotency_key)? {
+				Some(Original::Conversion(c)) if c.is_repeat_of(sender_id, receiver_id, &amount.amount, amount.currency) => {
+					return Ok(c);
+				}
+				Some(_) => return Err(Error::new(ErrorKind::IdempotencyKeyConflict)),
+				None => {}
+			}
+			
+			let (sender, receiver) = self.lock_transfer_accounts(conn, sender_id, receiver_id)?;
+			money::check_currency(sender.currency, amount.currency)?;
+			let rate = self.find_fx_rate(conn, sender.currency, receiver.currency, &self.calendar.current_date())?;
+			let overdraft_protection = self.protect_overdraft(conn, &sender, &amount.amount)?;
+			let bought = amount.convert(&rate, receiver.currency);
+			
+			let conversion = self.fx_repo.create_conversion(conn, NewFxConversion {
+				sender_id,
+				receiver_id,
+				sell_amount: &amount.amount,
+				sell_currency: amount.currency,
+				buy_amount: &bought.amount,
+				buy_currency: bought.currency,
+				rate: &money::round(&rate, fx::RATE_SCALE, RoundingMode::HalfEven),
+				idempotency_key,
+				overdraft_protection,
 			})?;
 			
-			let total_payment = &loan_payment.principal_due + &loan_payment.interest_due;
+			// the bank buys the sender's currency and sells the receiver's
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::FxConversion,
+				reference_id: Some(&conversion.id),
+				postings: vec![
+					Posting {
+						debit: LedgerAccount::Deposit(*sender_id),
+						credit: LedgerAccount::FxPosition(amount.currency),
+						amount: &amount.amount,
+					},
+					Posting {
+						debit: LedgerAccount::FxPosition(bought.currency),
+						credit: LedgerAccount::Deposit(*receiver_id),
+						amount: &bought.amount,
+					},
+				],
+			})?;
 			
-			// deduct funds from the user's account
-			self.account_repo.decrement(account_id, &total_payment)?;
+			self.account_repo.decrement(conn, sender_id, &amount.amount)?;
+			self.account_repo.increment(conn, receiver_id, &bought.amount)?;
+			Ok(conversion)
+		})
+	}
+	
+	/// Reverse a deposit or withdrawal, moving the funds back between the account and the vault
+	///
+	/// A deposit is reversed by a withdrawal and a withdrawal by a deposit, linked to the original by `reversal_of`.
+	/// A transaction can only be reversed once and a reversal can't be reversed itself.
+	/// Reversing a deposit the account can no longer cover fails with `InadequateFunds` unless an operator forces it,
+	/// which lets the account's balance go negative. The operator who forced it is recorded on the reversal.
+	pub fn reverse_bank_transaction(&self, transaction_id: &Id, forced_by: Option<&Id>) -> Result<BankTransaction> {
+		let conn = &self.db.get()?;
+		conn.transaction::<BankTransaction, Error, _>(|| {
+			self.check_operator(conn, forced_by)?;
+			let original = self.bank_transaction_repo.find_by_id(conn, transaction_id)?;
+			let reverses_deposit = match original.transaction_type {
+				_ if original.reversal_of.is_some() => return Err(Error::new(ErrorKind::NotReversible)),
+				BankTransactionType::Deposit => true,
+				BankTransactionType::Withdraw => false,
+				_ => return Err(Error::new(ErrorKind::NotReversible)),
+			};
 			
-			// increment funds in the bank's vault
-			self.vault_repo.increment(&loan.vault_name, &total_payment)?;
+			// lock the account so concurrent reversals of the transaction are made one at a time
+			let account = self.account_repo.find_for_update(conn, &original.account_id)?;
+			check_not_reversed(self.bank_transaction_repo.find_reversal(conn, &original.id))?;
+			check_account_open(&account)?;
+			if reverses_deposit {
+				check_reversal_funds(&account, &original.amount, forced_by)?;
+			}
 			
-			// decrement the dues from the loan
-			loan = self.loan_repo.decrement(&loan.id, &total_payment)?;
+			let transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id: &account.id,
+				vault_name: &original.vault_name,
+				transaction_type: if reverses_deposit { BankTransactionType::Withdraw } else { BankTransactionType::Deposit },
+				amount: &original.amount,
+				idempotency_key: None,
+				overdraft_protection: None,
+				currency: original.currency,
+				reversal_of: Some(&original.id),
+				forced_by,
+			})?;
 			
-			// attach the transaction ids to the loan payment
-			loan_payment = self.loan_payments_repo.set_transaction_ids(loan_payment_id,
-																	   &principal_transaciton.id,
-																	   &interest_transaction.id)?;
+			let (deposit, vault) = (LedgerAccount::Deposit(account.id), LedgerAccount::Vault(original.vault_name.clone()));
+			let (debit, credit) = if reverses_deposit { (deposit, vault) } else { (vault, deposit) };
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::Reversal,
+				reference_id: Some(&transaction.id),
+				postings: vec![Posting { debit, credit, amount: &original.amount }],
+			})?;
 			
-			if loan.balance.is_zero() {
-				loan = self.loan_repo.set_state(&loan.id, LoanState::Paid)?;
+			if reverses_deposit {
+				self.account_repo.decrement(conn, &account.id, &original.amount)?;
+				self.vault_repo.decrement(conn, &original.vault_name, &original.amount)?;
+			} else {
+				self.account_repo.increment(conn, &account.id, &original.amount)?;
+				self.vault_repo.increment(conn, &original.vault_name, &original.amount)?;
+			}
+			Ok(transaction)
+		})
+	}
+	
+	/// Reverse a transfer, sending the funds back from the receiver to the sender
+	///
+	/// The reversal is a transfer linked to the original by `reversal_of`. A transfer can only be reversed once and a
+	/// reversal can't be reversed itself. Reversing a transfer the receiver can no longer cover fails with
+	/// `InadequateFunds` unless an operator forces it, which lets the receiver's balance go negative. The operator who
+	/// forced it is recorded on the reversal.
+	pub fn reverse_account_transaction(&self, transaction_id: &Id, forced_by: Option<&Id>) -> Result<AccountTransaction> {
+		let conn = &self.db.get()?;
+		conn.transaction::<AccountTransaction, Error, _>(|| {
+			self.check_operator(conn, forced_by)?;
+			let original = self.account_transaction_repo.find_by_id(conn, transaction_id)?;
+			if original.reversal_of.is_some() {
+				return Err(Error::new(ErrorKind::NotReversible));
 			}
 			
-			// invalid balance check
-			assert!(!loan.balance.is_negative(), "invalid state: loan balance should never be negative");
+			// locking both accounts also makes concurrent reversals of the transfer wait for each other
+			let (receiver, _) = self.lock_transfer_accounts(conn, &original.receiver_id, &original.sender_id)?;
+			check_not_reversed(self.account_transaction_repo.find_reversal(conn, &original.id))?;
+			check_reversal_funds(&receiver, &original.amount, forced_by)?;
 			
-			Ok(loan_payment)
+			self.transfer(conn, NewAccountTransaction {
+				sender_id: &original.receiver_id,
+				receiver_id: &original.sender_id,
+				amount: &original.amount,
+				idempotency_key: None,
+				overdraft_protection: None,
+				reversal_of: Some(&original.id),
+				forced_by,
+				currency: original.currency,
+			})
 		})
 	}
 	
-	/// Create the next loan payment due on the loan
-	fn create_next_loan_payment(&self, loan: &Loan) -> Result<LoanPayment> {
-		// Look up the previous payment to see if we are creating the first payment due on this loan
-		let previous_payment = match self.loan_payments_repo.find_last_paid(&loan.id) {
-			Ok(v) => Some(v),
-			Err(db::Error::RecordNotFound) => None,
-			Err(e) => return Err(e.into())
-		};
+	/// Get a page of the account's history, most recent first
+	///
+	/// The history merges the account's deposits, withdrawals, transfers in and out, loan disbursements and payments,
+	/// fees, interest and conversions. Each entry carries the account's balance once it was applied.
+	/// The filter is applied by the database, and only the history the page can reach is loaded. Its balances start
+	/// from the account's balance just before that history.
+	///
+	/// # Arguments
+	/// * `account_id` - the account whose history is returned
+	/// * `filter` - the date range, type and amount range of the entries returned
+	/// * `page` - which of the matching entries are returned
+	pub fn get_account_history(&self, account_id: &Id, filter: &history::Filter, page: history::Page) -> Result<HistoryPage> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let account = self.account_repo.find_by_id(conn, account_id)?;
+			let total = self.bank_transaction_repo.count_history(conn, account_id, filter)?
+				+ self.account_transaction_repo.count_history(conn, account_id, filter)?
+				+ self.fx_repo.count_conversion_history(conn, account_id, filter)?;
+			
+			// the page can't reach further back than the newest `offset + limit` matching entries of any one kind
+			let reach = (page.offset + page.limit.min(history::MAX_PAGE_SIZE)).max(1) as i64;
+			let cutoff = vec![
+				self.bank_transaction_repo.find_history_cutoff(conn, account_id, filter, reach)?,
+				self.account_transaction_repo.find_history_cutoff(conn, account_id, filter, reach)?,
+				self.fx_repo.find_conversion_history_cutoff(conn, account_id, filter, reach)?,
+			].into_iter().flatten().max();
+			let after = cutoff.or_else(|| filter.starts_at()).map(|t| t - chrono::Duration::microseconds(1));
+			let opening_balance = match &after {
+				Some(after) => self.account_balance_at(conn, &account, after)?,
+				None => BigDecimal::zero(),
+			};
+			
+			let before = filter.ends_before();
+			let entries = history::merge_from(
+				&opening_balance,
+				&account.id,
+				self.bank_transaction_repo.find_by_account_within(conn, account_id, after.as_ref(), before.as_ref())?,
+				self.account_transaction_repo.find_by_account_within(conn, account_id, after.as_ref(), before.as_ref())?,
+				self.fx_repo.find_conversions_by_account_within(conn, account_id, after.as_ref(), before.as_ref())?,
+			);
+			Ok(HistoryPage { total: total as usize, ..history::paginate(entries, filter, page) })
+		})
+	}
+	
+	/// Generate the account's statement for the calendar month containing the date
+	///
+	/// The month must have ended by the calendar's current date. A month's statement is only generated once, and
+	/// generating it again returns the stored statement with the figures it was generated with.
+	pub fn generate_statement(&self, account_id: &Id, month: Date) -> Result<statement::Document> {
+		let (period_start, period_end) = statement_period(month);
+		if period_end >= self.calendar.current_date() {
+			let msg = format!("statement period ending {} hasn't ended", period_end);
+			return Err(Error::new(ErrorKind::InvalidDate(msg)));
+		}
 		
-		let mut due_date: Date;
-		let months_to_add = loan.payment_frequency;
-		due_date = match previous_payment {
-			Some(prev) => prev.due_date.increment_date_by_months(months_to_add as u16),
-			None => loan.issue_date.increment_date_by_months(months_to_add as u16),
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			// concurrent requests for the same statement wait for the first to store it
+			db::lock_key(conn, &format!("statement:{}:{}", account_id, period_start))?;
+			match self.statement_repo.find(conn, account_id, &period_start) {
+				Ok(document) => return Ok(document),
+				Err(db::Error::RecordNotFound) => {}
+				Err(e) => return Err(e.into()),
+			}
+			
+			let account = self.account_repo.find_by_id(conn, account_id)?;
+			let entries = self.load_history(conn, &account)?;
+			let draft = statement::Draft::new(account.id, account.currency, period_start, period_end, entries);
+			self.statement_repo.create(conn, draft).map_err(Into::into)
+		})
+	}
+	
+	/// Generate the statements for the calendar month before the current one
+	///
+	/// Every account opened by the end of the month and not closed before it started gets a statement.
+	pub fn generate_monthly_statements(&self) -> Result<Vec<statement::Document>> {
+		let (period_start, period_end) = statement_period(self.calendar.current_date().with_day(1).unwrap().pred());
+		let accounts = {
+			let conn = &self.db.get()?;
+			self.account_repo.find_all(conn)?
 		};
-		
-		if due_date.gt(&loan.maturity_date) {
-			let msg = format!("due date({}) exceeds maturity date({})", due_date, loan.maturity_date);
+		accounts.iter()
+			.filter(|a| a.created_at.naive_utc().date() <= period_end)
+			.filter(|a| a.closed_at.map_or(true, |closed_at| closed_at.naive_utc().date() >= period_start))
+			.map(|a| self.generate_statement(&a.id, period_start))
+			.collect()
+	}
+	
+	/// Get the account's statement for the calendar month containing the date, as it was generated
+	pub fn get_statement(&self, account_id: &Id, month: Date) -> Result<statement::Document> {
+		let (period_start, _) = statement_period(month);
+		let conn = &self.db.get()?;
+		self.statement_repo.find(conn, account_id, &period_start).map_err(Into::into)
+	}
+	
+	/// Get the account's balance at the time
+	///
+	/// The balance is rolled forward from the latest daily snapshot taken at or before the time through the transactions
+	/// made since. Without such a snapshot the current balance is rolled back through the transactions made after the time.
+	pub fn get_account_balance_at(&self, account_id: &Id, at: &Time) -> Result<Money> {
+		let conn = &self.db.get()?;
+		// read the balance and the transactions at the same point so in-flight operations can't skew the replay
+		conn.build_transaction().repeatable_read().read_only().run::<Money, Error, _>(|| {
+			let account = self.account_repo.find_by_id(conn, account_id)?;
+			let balance = self.account_balance_at(conn, &account, at)?;
+			Ok(Money::new(balance, account.currency))
+		})
+	}
+	
+	/// Get the vault's balance at the time, worked out the same way as an account's
+	pub fn get_vault_balance_at(&self, vault_name: &str, at: &Time) -> Result<Money> {
+		let conn = &self.db.get()?;
+		conn.build_transaction().repeatable_read().read_only().run::<Money, Error, _>(|| {
+			let vault = self.vault_repo.find_by_name(conn, vault_name)?;
+			let balance = self.vault_balance_at(conn, &vault, at)?;
+			Ok(Money::new(balance, vault.currency))
+		})
+	}
+	
+	/// Schedule a standing order that sends funds from one account to another
+	///
+	/// The order first runs on the first date on or after its start date that its frequency gives, and recurs until
+	/// its end date. The amount is sent in the sender's currency, which the receiver's account must also hold.
+	pub fn schedule_transfer(&self, schedule: TransferSchedule) -> Result<ScheduledTransfer> {
+		check_not_self_transfer(&schedule.sender_id, &schedule.receiver_id)?;
+		let day_of_month = check_day_of_month(&schedule)?;
+		let first_run_date = schedule.frequency.first_on_or_after(schedule.start_date, day_of_month);
+		if let Some(end_date) = schedule.end_date.filter(|end_date| end_date < &first_run_date) {
+			let msg = format!("end date({}) is before the first run date({})", end_date, first_run_date);
 			return Err(Error::new(ErrorKind::InvalidDate(msg)));
 		}
 		
-		let principal_due = loan.principal_due(self.calendar.current_date());
-		let interest_due = loan.accrued_interest.clone();
+		let conn = &self.db.get()?;
+		let sender = self.account_repo.find_by_id(conn, &schedule.sender_id)?;
+		let receiver = self.account_repo.find_by_id(conn, &schedule.receiver_id).map_err(receiver_error)?;
+		check_amount(&Money::new(schedule.amount.clone(), sender.currency))?;
+		money::check_currency(sender.currency, receiver.currency)?;
 		
-		self.loan_payments_repo.create(
-			{
-				NewPayment {
-					loan_id: loan.id,
-					principal_due,
-					interest_due,
-					due_date,
-				}
-			}).map_err(Into::into)
+		self.scheduled_transfer_repo.create(conn, NewScheduledTransfer {
+			sender_id: schedule.sender_id,
+			receiver_id: schedule.receiver_id,
+			amount: schedule.amount,
+			currency: sender.currency,
+			frequency: schedule.frequency,
+			day_of_month: schedule.day_of_month,
+			start_date: schedule.start_date,
+			end_date: schedule.end_date,
+			next_run_date: Some(first_run_date),
+			next_attempt_date: Some(first_run_date),
+		}).map_err(Into::into)
 	}
-}
-
-/// Used by Service to get the current date
-pub trait Calendar {
-	fn current_date(&self) -> Date {
-		chrono::Utc::today().naive_utc()
+	
+	/// Cancel a scheduled transfer so none of its remaining occurrences run
+	pub fn cancel_scheduled_transfer(&self, scheduled_transfer_id: &Id) -> Result<ScheduledTransfer> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			self.scheduled_transfer_repo.find_for_update(conn, scheduled_transfer_id)?;
+			self.scheduled_transfer_repo.cancel(conn, scheduled_transfer_id).map_err(Into::into)
+		})
 	}
-}
+	
+	/// Get the attempts made to run a scheduled transfer, ordered by occurrence
+	pub fn get_scheduled_transfer_runs(&self, scheduled_transfer_id: &Id) -> Result<Vec<ScheduledTransferRun>> {
+		let conn = &self.db.get()?;
+		self.scheduled_transfer_repo.find_by_id(conn, scheduled_transfer_id)?;
+		self.scheduled_transfer_repo.find_runs(conn, scheduled_transfer_id).map_err(Into::into)
+	}
+	
+	/// Run the scheduled transfers due by the calendar's current date through `send_funds`
+	///
+	/// Every occurrence that has come due is attempted, the earliest first, and each attempt is recorded. An occurrence
+	/// the sender can't cover is retried the next day or skipped according to the policy. One that fails for any other
+	/// reason is recorded as failed and the transfer moves on to its next occurrence.
+	/// Each occurrence is sent with its own idempotency key, so a run that is interrupted after the funds were sent
+	/// records the original transfer when it's restarted rather than sending the funds again.
+	/// Returns the attempts made by the run.
+	pub fn run_scheduled_transfers(&self, policy: &RetryPolicy) -> Result<Vec<ScheduledTransferRun>> {
+		let conn = &self.db.get()?;
+		let today = self.calendar.current_date();
+		
+		let mut runs = vec![];
+		for transfer in self.scheduled_transfer_repo.find_due(conn, &today)? {
+			// each transfer is run in its own transaction so it's only locked while its occurrences are attempted
+			conn.transaction::<_, Error, _>(|| {
+				let mut transfer = self.scheduled_transfer_repo.find_for_update(conn, &transfer.id)?;
+				while let Some(scheduled_date) = transfer.due_occurrence(today) {
+					let (run, next) = self.run_scheduled_transfer(conn, &transfer, scheduled_date, today, policy)?;
+					runs.push(run);
+					transfer = next;
+				}
+				Ok(())
+			})?;
+		}
+		Ok(runs)
+	}
+	
+	/// Run the end of day job that accrues interest on every active loan and savings account
+	///
+	/// Each loan accrues interest for every period that has ended by the calendar's current date since it last accrued.
+	/// Each savings account accrues interest for every day that has ended since it last accrued, and is credited the
+	/// interest accrued over each month that ended. Overdrawn accounts are charged interest the same way.
+	/// Interest and the run log are updated in the same transaction, so a run that is interrupted can be
+	/// restarted without accruing interest twice. Running again for a date that has completed returns its run.
+	///
+	/// The run also snapshots the balance of every account and vault at the end of the day before the run date.
+	pub fn run_end_of_day_accrual(&self) -> Result<AccrualRun> {
+		let conn = &self.db.get()?;
+		let run_date = self.calendar.current_date();
+		
+		let run = self.accrual_run_repo.start(conn, &run_date)?;
+		if run.is_complete() {
+			return Ok(run);
+		}
+		
+		for loan in self.loan_repo.find_by_states(conn, &[LoanState::Active])? {
+			conn.transaction::<_, Error, _>(|| {
+				let mut loan = self.loan_repo.find_for_update(conn, &loan.id)?;
+				let mut periods = 0;
+				let mut interest_accrued = BigDecimal::zero();
+				while loan.next_accrual_date() <= run_date {
+					let (accrued, interest) = self.accrue_period(conn, loan)?;
+					loan = accrued;
+					interest_accrued += interest;
+					periods += 1;
+				}
+				
+				if periods > 0 {
+					self.accrual_run_repo.record_loan(conn, &run.id, &interest_accrued)?;
+				}
+				Ok(())
+			})?;
+		}
+		
+		for account in self.account_repo.find_interest_bearing(conn)? {
+			conn.transaction::<_, Error, _>(|| {
+				let mut account = self.account_repo.find_for_update(conn, &account.id)?;
+				let first_day = account.interest_accrued_through.expect("invalid state: savings account should have an accrual date");
+				if first_day >= run_date {
+					return Ok(());
+				}
+				if !account.is_open() {
+					// frozen and dormant accounts earn nothing for the days they aren't open
+					self.account_repo.accrue_interest(conn, &account.id, &BigDecimal::zero(), &run_date)?;
+					return Ok(());
+				}
+				
+				let product = self.savings_product_repo.find_by_name(conn, account.savings_product.as_ref().unwrap())?;
+				// loaded before any interest is credited, so the days are rolled back from a balance that includes the credits
+				let movements = self.account_movements_after(conn, &account, &start_of_day(first_day.succ()))?;
+				let mut days = 0;
+				let mut interest_accrued = BigDecimal::zero();
+				while account.interest_accrued_through.map_or(false, |date| date < run_date) {
+					let (accrued, interest) = self.accrue_savings_day(conn, account, &product, &movements)?;
+					account = accrued;
+					interest_accrued += interest;
+					days += 1;
+				}
+				
+				if days > 0 {
+					self.accrual_run_repo.record_account(conn, &run.id, &interest_accrued)?;
+				}
+				Ok(())
+			})?;
+		}
+		
+		for protection in self.overdraft_repo.find_charging_interest(conn)? {
+			conn.transaction::<_, Error, _>(|| {
+				let account = self.account_repo.find_for_update(conn, &protection.account_id)?;
+				let mut protection = self.overdraft_repo.find_by_account(conn, &account.id)?;
+				while protection.interest_accrued_through < run_date {
+					protection = self.accrue_overdraft_day(conn, protection)?;
+				}
+				Ok(())
+			})?;
+		}
+		
+		self.snapshot_balances(conn, &start_of_day(run_date))?;
+		
+		self.accrual_run_repo.complete(conn, &run.id).map_err(Into::into)
+	}
+	
+	/// Gets the most recent end of day accrual runs, newest first
+	pub fn find_recent_accrual_runs(&self, limit: i64) -> Result<Vec<AccrualRun>> {
+		let conn = &self.db.get()?;
+		self.accrual_run_repo.find_recent(conn, limit).map_err(Into::into)
+	}
+	
+	/// Pay the current loan payment dues
+	///
+	/// The loan must have been disbursed and not yet paid off, and the payment must not already be paid.
+	/// After a prepayment only what's left of the loan's balance is collected, and the payment that pays off the
+	/// balance closes out the rest of the schedule and marks the loan paid.
+	///
+	/// # Arguments
+	/// `loan_payment_id` - id of loan payment
+	/// `account_id` - id of the user's account that will be used to pay the dues
+	/// `idempotency_key` - optional key that makes retrying the payment safe; a retry returns the paid loan payment
+	pub fn pay_loan_payment_due(&self, loan_payment_id: &uuid::Uuid, account_id: &uuid::Uuid, idempotency_key: Option<&str>) -> Result<LoanPayment> {
+		let conn = &self.db.get()?;
+		conn.transaction::<LoanPayment, Error, _>(|| {
+			// the key is recorded on the principal repayment transaction
+			match self.find_original(conn, idempotency_key)? {
+				Some(Original::Bank(t)) if t.transaction_type == BankTransactionType::PrincipalRepayment && t.account_id.eq(account_id) => {
+					let paid = self.loan_payments_repo.find_by_principle_transaction_id(conn, &t.id)?;
+					if paid.id.ne(loan_payment_id) {
+						return Err(Error::new(ErrorKind::IdempotencyKeyConflict));
+					}
+					return Ok(paid);
+				}
+				Some(_) => return Err(Error::new(ErrorKind::IdempotencyKeyConflict)),
+				None => {}
+			}
+			
+			let loan_id = self.loan_payments_repo.find_by_id(conn, loan_payment_id)?.loan_id;
+			// payments are only made while the loan is locked, so the payment is read again once it's locked
+			let mut loan = self.loan_repo.find_for_update(conn, &loan_id)?;
+			check_loan_transition(&loan, LoanState::Paid)?;
+			let mut loan_payment = self.loan_payments_repo.find_by_id(conn, loan_payment_id)?;
+			if loan_payment.principle_transaction_id.is_some() && loan_payment.interest_transaction_id.is_some() {
+				return Err(Error::new(ErrorKind::LoanPaymentAlreadyPaid));
+			}
+			
+			// only what's still owed is collected when part of the dues were already paid, and never more than the
+			// loan's balance and accrued interest, which a prepayment can leave below the scheduled dues
+			let principal = loan_payment.principal_outstanding().min(loan.balance.clone());
+			let interest = loan_payment.interest_outstanding().min(&loan.balance + &loan.accrued_interest - &principal);
+			let fees = loan_payment.fees_outstanding();
+			
+			let account = self.account_repo.find_for_update(conn, account_id)?;
+			check_account_open(&account)?;
+			money::check_currency(loan.currency, account.currency)?;
+			if account.amount.lt(&(&principal + &interest + &fees)) {
+				return Err(Error::new(ErrorKind::InadequateFunds));
+			}
+			
+			let principal_transaciton = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id,
+				vault_name: &loan.vault_name,
+				transaction_type: BankTransactionType::PrincipalRepayment,
+				amount: &principal,
+				idempotency_key,
+				overdraft_protection: None,
+				currency: loan.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			let interest_transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id,
+				vault_name: &loan.vault_name,
+				transaction_type: BankTransactionType::InterestRepayment,
+				amount: &interest,
+				idempotency_key: None,
+				overdraft_protection: None,
+				currency: loan.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			
+			let total_payment = &principal + &interest;
+			
+			// the payment first earns the bank the loan's accrued interest and the remainder pays down the receivable.
+			// accrued interest the payment doesn't cover is capitalized into the loan's balance.
+			let interest_earned = total_payment.clone().min(loan.accrued_interest.clone());
+			let receivable_payment = &total_payment - &interest_earned;
+			let capitalized_interest = &loan.accrued_interest - &interest_earned;
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::LoanRepayment,
+				reference_id: Some(&principal_transaciton.id),
+				postings: vec![
+					Posting {
+						debit: LedgerAccount::Deposit(*account_id),
+						credit: LedgerAccount::LoanReceivable(loan.id),
+						amount: &receivable_payment,
+					},
+					Posting {
+						debit: LedgerAccount::Deposit(*account_id),
+						credit: LedgerAccount::InterestIncome(loan.vault_name.clone()),
+						amount: &interest_earned,
+					},
+					Posting {
+						debit: LedgerAccount::LoanReceivable(loan.id),
+						credit: LedgerAccount::InterestIncome(loan.vault_name.clone()),
+						amount: &capitalized_interest,
+					},
+					Posting {
+						debit: LedgerAccount::Vault(loan.vault_name.clone()),
+						credit: LedgerAccount::LoanFunding(loan.vault_name.clone()),
+						amount: &total_payment,
+					},
+				],
+			})?;
+			
+			// deduct funds from the user's account
+			self.account_repo.decrement(conn, account_id, &total_payment)?;
+			
+			// increment funds in the bank's vault
+			self.vault_repo.increment(conn, &loan.vault_name, &total_payment)?;
+			
+			// decrement the dues from the loan
+			loan = self.loan_repo.decrement(conn, &loan.id, &total_payment)?;
+			
+			// attach the transaction ids to the loan payment
+			self.loan_payments_repo.record_payment(conn, loan_payment_id, &principal, &interest, &fees, &BigDecimal::zero())?;
+			loan_payment = self.loan_payments_repo.set_transaction_ids(conn,
+																	   loan_payment_id,
+																	   &principal_transaciton.id,
+																	   &interest_transaction.id)?;
+			
+			if !fees.is_zero() {
+				let fee_transaction = self.collect_late_fee(conn, &loan, account_id, &fees)?;
+				loan_payment = self.loan_payments_repo.set_fee_transaction_id(conn, loan_payment_id, &fee_transaction.id)?;
+			}
+			
+			if loan.balance.is_negative() {
+				return Err(Error::new(ErrorKind::InvalidStateNegativeValue));
+			}
+			if loan.balance.is_zero() {
+				// the payments a prepayment made unnecessary are closed out with this payment's transactions
+				self.loan_payments_repo.close_unpaid(conn,
+													 &loan.id,
+													 &principal_transaciton.id,
+													 &interest_transaction.id,
+													 None)?;
+				self.transition_loan(conn, &loan, LoanState::Paid)?;
+			}
+			
+			Ok(loan_payment)
+		})
+	}
+	
+	/// Pay any amount against the loan
+	///
+	/// The amount is split across the loan's fees, interest, principal and prepayment in the policy's order and
+	/// applied to the unpaid payments oldest first. A payment that's paid in full is marked paid by this payment's
+	/// transactions, and a prepayment is recorded as an overpayment that shortens the loan's term. Any amount beyond
+	/// what's owed on the loan isn't collected. Returns how the amount was applied.
+	///
+	/// # Arguments
+	/// `loan_id` - id of the loan being paid
+	/// `account_id` - id of the user's account that will be used to make the payment
+	/// `amount` - amount paid
+	/// `policy` - the order the amount is applied in
+	/// `idempotency_key` - optional key that makes retrying the payment safe; a retry returns the original allocation
+	pub fn pay_loan(&self, loan_id: &Id, account_id: &Id, amount: &Money, policy: &AllocationPolicy, idempotency_key: Option<&str>) -> Result<Allocation> {
+		check_amount(amount)?;
+		
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			if let Some((transaction, repayment)) = self.find_original_repayment(conn, loan_id, account_id, idempotency_key)? {
+				if repayment.amount.ne(&amount.amount) || transaction.currency != amount.currency {
+					return Err(Error::new(ErrorKind::IdempotencyKeyConflict));
+				}
+				return Ok(repayment.allocation());
+			}
+			
+			let loan = self.loan_repo.find_for_update(conn, loan_id)?;
+			check_loan_transition(&loan, LoanState::Paid)?;
+			money::check_currency(loan.currency, amount.currency)?;
+			
+			let as_of = self.calendar.current_date();
+			let unpaid = self.loan_payments_repo.find_unpaid(conn, &loan.id)?;
+			let dues = allocation::dues(&loan, &unpaid, as_of);
+			let allocation = policy.allocate(&amount.amount, &dues);
+			
+			let account = self.account_repo.find_for_update(conn, account_id)?;
+			check_account_open(&account)?;
+			money::check_currency(loan.currency, account.currency)?;
+			if account.amount.lt(&allocation.total()) {
+				return Err(Error::new(ErrorKind::InadequateFunds));
+			}
+			
+			let principal = &allocation.principal + &allocation.prepayment;
+			let interest = allocation.interest();
+			let principal_transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id,
+				vault_name: &loan.vault_name,
+				transaction_type: BankTransactionType::PrincipalRepayment,
+				amount: &principal,
+				idempotency_key,
+				overdraft_protection: None,
+				currency: loan.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			let interest_transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id,
+				vault_name: &loan.vault_name,
+				transaction_type: BankTransactionType::InterestRepayment,
+				amount: &interest,
+				idempotency_key: None,
+				overdraft_protection: None,
+				currency: loan.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::LoanRepayment,
+				reference_id: Some(&principal_transaction.id),
+				postings: vec![
+					Posting {
+						debit: LedgerAccount::Deposit(*account_id),
+						credit: LedgerAccount::LoanReceivable(loan.id),
+						amount: &principal,
+					},
+					Posting {
+						debit: LedgerAccount::Deposit(*account_id),
+						credit: LedgerAccount::InterestIncome(loan.vault_name.clone()),
+						amount: &interest,
+					},
+					Posting {
+						debit: LedgerAccount::Vault(loan.vault_name.clone()),
+						credit: LedgerAccount::LoanFunding(loan.vault_name.clone()),
+						amount: &(&principal + &interest),
+					},
+				],
+			})?;
+			
+			self.loan_payments_repo.create_repayment(conn, NewRepayment {
+				principal_transaction_id: &principal_transaction.id,
+				loan_id: &loan.id,
+				amount: &amount.amount,
+				fees: &allocation.fees,
+				past_due_interest: &allocation.past_due_interest,
+				current_interest: &allocation.current_interest,
+				principal: &allocation.principal,
+				prepayment: &allocation.prepayment,
+			})?;
+			
+			self.account_repo.decrement(conn, account_id, &(&principal + &interest))?;
+			self.vault_repo.increment(conn, &loan.vault_name, &(&principal + &interest))?;
+			let loan = self.loan_repo.apply_payment(conn, &loan.id, &principal, &interest)?;
+			
+			let fee_transaction = if allocation.fees.is_zero() {
+				None
+			} else {
+				Some(self.collect_late_fee(conn, &loan, account_id, &allocation.fees)?)
+			};
+			let transaction_ids = PaymentTransactionIds {
+				principal: principal_transaction.id,
+				interest: interest_transaction.id,
+				fee: fee_transaction.map(|t| t.id),
+			};
+			self.apply_to_loan_payments(conn, &unpaid, as_of, &allocation, &transaction_ids)?;
+			
+			if loan.balance.is_zero() {
+				self.loan_payments_repo.close_unpaid(conn,
+													 &loan.id,
+													 &transaction_ids.principal,
+													 &transaction_ids.interest,
+													 transaction_ids.fee.as_ref())?;
+				self.transition_loan(conn, &loan, LoanState::Paid)?;
+			}
+			
+			Ok(allocation)
+		})
+	}
+	
+	/// Quote the amount needed to pay off the loan in full on the payoff date
+	///
+	/// The quote covers the outstanding balance, the interest already accrued, per diem interest counted with the
+	/// loan's day count convention from its last accrual date up to the payoff date, and late fees on unpaid payments.
+	pub fn quote_loan_payoff(&self, loan_id: &Id, payoff_date: Date) -> Result<PayoffQuote> {
+		let conn = &self.db.get()?;
+		let loan = self.loan_repo.find_by_id(conn, loan_id)?;
+		self.payoff_quote(conn, &loan, payoff_date)
+	}
+	
+	/// Pay off the loan in full as of the calendar's current date
+	///
+	/// The quoted amount is collected from the account in a single transaction, the loan's unpaid payments are
+	/// closed out with the payoff transactions and the loan is marked paid.
+	///
+	/// # Arguments
+	/// `loan_id` - id of the loan being paid off
+	/// `account_id` - id of the user's account that will be used to pay off the loan
+	/// `idempotency_key` - optional key that makes retrying the payoff safe; a retry returns the paid off loan
+	pub fn pay_off_loan(&self, loan_id: &Id, account_id: &Id, idempotency_key: Option<&str>) -> Result<Loan> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			if self.find_original_repayment(conn, loan_id, account_id, idempotency_key)?.is_some() {
+				// a payment that didn't pay off the loan was made with the key
+				let loan = self.loan_repo.find_by_id(conn, loan_id)?;
+				if loan.state != LoanState::Paid {
+					return Err(Error::new(ErrorKind::IdempotencyKeyConflict));
+				}
+				return Ok(loan);
+			}
+			
+			let loan = self.loan_repo.find_for_update(conn, loan_id)?;
+			let quote = self.payoff_quote(conn, &loan, self.calendar.current_date())?;
+			
+			let account = self.account_repo.find_for_update(conn, account_id)?;
+			check_account_open(&account)?;
+			money::check_currency(loan.currency, account.currency)?;
+			if account.amount.lt(&quote.total) {
+				return Err(Error::new(ErrorKind::InadequateFunds));
+			}
+			
+			// bring the interest up to the payoff date so it's collected with the interest already accrued
+			let loan = self.loan_repo.accrue_interest(conn, &loan.id, &quote.per_diem_interest, &quote.payoff_date)?;
+			
+			let principal_transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id,
+				vault_name: &loan.vault_name,
+				transaction_type: BankTransactionType::PrincipalRepayment,
+				amount: &loan.balance,
+				idempotency_key,
+				overdraft_protection: None,
+				currency: loan.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			let interest_transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+				account_id,
+				vault_name: &loan.vault_name,
+				transaction_type: BankTransactionType::InterestRepayment,
+				amount: &loan.accrued_interest,
+				idempotency_key: None,
+				overdraft_protection: None,
+				currency: loan.currency,
+				reversal_of: None,
+				forced_by: None,
+			})?;
+			
+			let total_payment = &loan.balance + &loan.accrued_interest;
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::LoanRepayment,
+				reference_id: Some(&principal_transaction.id),
+				postings: vec![
+					Posting {
+						debit: LedgerAccount::Deposit(*account_id),
+						credit: LedgerAccount::LoanReceivable(loan.id),
+						amount: &loan.balance,
+					},
+					Posting {
+						debit: LedgerAccount::Deposit(*account_id),
+						credit: LedgerAccount::InterestIncome(loan.vault_name.clone()),
+						amount: &loan.accrued_interest,
+					},
+					Posting {
+						debit: LedgerAccount::Vault(loan.vault_name.clone()),
+						credit: LedgerAccount::LoanFunding(loan.vault_name.clone()),
+						amount: &total_payment,
+					},
+				],
+			})?;
+			
+			self.loan_payments_repo.create_repayment(conn, NewRepayment {
+				principal_transaction_id: &principal_transaction.id,
+				loan_id: &loan.id,
+				amount: &quote.total,
+				fees: &quote.fees,
+				past_due_interest: &BigDecimal::zero(),
+				current_interest: &loan.accrued_interest,
+				principal: &loan.balance,
+				prepayment: &BigDecimal::zero(),
+			})?;
+			
+			self.account_repo.decrement(conn, account_id, &total_payment)?;
+			self.vault_repo.increment(conn, &loan.vault_name, &total_payment)?;
+			let loan = self.loan_repo.decrement(conn, &loan.id, &total_payment)?;
+			
+			let fee_transaction = if quote.fees.is_zero() {
+				None
+			} else {
+				Some(self.collect_late_fee(conn, &loan, account_id, &quote.fees)?)
+			};
+			self.loan_payments_repo.close_unpaid(conn,
+												 &loan.id,
+												 &principal_transaction.id,
+												 &interest_transaction.id,
+												 fee_transaction.as_ref().map(|t| &t.id))?;
+			
+			self.transition_loan(conn, &loan, LoanState::Paid)
+		})
+	}
+	
+	/// Assess the delinquency of every active or defaulted loan as of the calendar's current date
+	///
+	/// Tracks how many days past due each loan's oldest unpaid payment is, charges late fees on payments
+	/// past the policy's grace period and moves active loans past the policy's threshold into default.
+	/// Returns the loans that are past due, the most delinquent first.
+	pub fn assess_delinquency(&self, policy: &DelinquencyPolicy) -> Result<Vec<Loan>> {
+		let conn = &self.db.get()?;
+		let as_of = self.calendar.current_date();
+		
+		let mut delinquent = vec![];
+		for loan in self.loan_repo.find_by_states(conn, &[LoanState::Active, LoanState::Default])? {
+			// each loan is assessed in its own transaction so loans are only locked while they're assessed
+			let loan = conn.transaction::<_, Error, _>(|| self.assess_loan_delinquency(conn, &loan.id, policy, as_of))?;
+			if loan.days_past_due > 0 {
+				delinquent.push(loan);
+			}
+		}
+		delinquent.sort_by(|a, b| b.days_past_due.cmp(&a.days_past_due));
+		Ok(delinquent)
+	}
+	
+	/// Gets the loans that were past due as of the last delinquency assessment, the most delinquent first
+	pub fn find_delinquent_loans(&self) -> Result<Vec<Loan>> {
+		let conn = &self.db.get()?;
+		self.loan_repo.find_delinquent(conn).map_err(Into::into)
+	}
+	
+	/// Check the balances stored on accounts, vaults and disbursed loans against the general ledger
+	pub fn reconcile(&self) -> Result<Reconciliation> {
+		let conn = &self.db.get()?;
+		// read every balance from the same snapshot so in-flight operations can't produce false discrepancies
+		conn.build_transaction().repeatable_read().read_only().run::<Reconciliation, Error, _>(|| {
+			let mut discrepancies = Vec::new();
+			
+			let mut ledger_balances = self.ledger_repo.balances(conn, LedgerAccountKind::Deposit)?;
+			for account in self.account_repo.find_all(conn)? {
+				let derived = ledger_balances.remove(&account.id.to_string()).unwrap_or_default();
+				if derived != account.amount {
+					discrepancies.push(Discrepancy {
+						account: LedgerAccount::Deposit(account.id),
+						stored: account.amount,
+						derived,
+					});
+				}
+			}
+			
+			let mut vault_balances = self.ledger_repo.balances(conn, LedgerAccountKind::Vault)?;
+			for vault in self.vault_repo.find_all(conn)? {
+				let derived = vault_balances.remove(&vault.name).unwrap_or_default();
+				if derived != vault.amount {
+					discrepancies.push(Discrepancy {
+						account: LedgerAccount::Vault(vault.name),
+						stored: vault.amount,
+						derived,
+					});
+				}
+			}
+			
+			// only loans that have been disbursed are booked in the ledger
+			for (key, derived) in self.ledger_repo.balances(conn, LedgerAccountKind::LoanReceivable)? {
+				let loan_id = key.parse::<Id>().expect("invalid state: loan receivable key should be a loan id");
+				let loan = self.loan_repo.find_by_id(conn, &loan_id)?;
+				if derived != loan.balance {
+					discrepancies.push(Discrepancy {
+						account: LedgerAccount::LoanReceivable(loan.id),
+						stored: loan.balance,
+						derived,
+					});
+				}
+			}
+			
+			Ok(Reconciliation {
+				trial_balance: self.ledger_repo.trial_balance(conn)?,
+				discrepancies,
+			})
+		})
+	}
+	
+	/// Looks up the transaction recorded by an earlier request with the same idempotency key
+	///
+	/// The key is locked until the current transaction ends so concurrent retries are handled one at a time
+	fn find_original(&self, conn: &PgConnection, idempotency_key: Option<&str>) -> Result<Option<Original>> {
+		let key = match idempotency_key {
+			Some(key) => key,
+			None => return Ok(None),
+		};
+		db::lock_key(conn, key)?;
+		
+		match self.bank_transaction_repo.find_by_idempotency_key(conn, key) {
+			Ok(t) => return Ok(Some(Original::Bank(t))),
+			Err(db::Error::RecordNotFound) => {}
+			Err(e) => return Err(e.into()),
+		}
+		match self.account_transaction_repo.find_by_idempotency_key(conn, key) {
+			Ok(t) => return Ok(Some(Original::Account(t))),
+			Err(db::Error::RecordNotFound) => {}
+			Err(e) => return Err(e.into()),
+		}
+		match self.fx_repo.find_conversion_by_idempotency_key(conn, key) {
+			Ok(c) => Ok(Some(Original::Conversion(c))),
+			Err(db::Error::RecordNotFound) => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	}
+	
+	/// Looks up the loan repayment made by an earlier request with the same idempotency key
+	///
+	/// The key conflicts if it was used for anything but a repayment of the loan from the account
+	fn find_original_repayment(&self, conn: &PgConnection, loan_id: &Id, account_id: &Id, idempotency_key: Option<&str>) -> Result<Option<(BankTransaction, Repayment)>> {
+		match self.find_original(conn, idempotency_key)? {
+			Some(Original::Bank(t)) if t.transaction_type == BankTransactionType::PrincipalRepayment && t.account_id.eq(account_id) => {
+				match self.loan_payments_repo.find_repayment(conn, &t.id) {
+					Ok(repayment) if repayment.loan_id.eq(loan_id) => Ok(Some((t, repayment))),
+					Ok(_) | Err(db::Error::RecordNotFound) => Err(Error::new(ErrorKind::IdempotencyKeyConflict)),
+					Err(e) => Err(e.into()),
+				}
+			}
+			Some(_) => Err(Error::new(ErrorKind::IdempotencyKeyConflict)),
+			None => Ok(None),
+		}
+	}
+	
+	fn assess_loan_delinquency(&self, conn: &PgConnection, loan_id: &Id, policy: &DelinquencyPolicy, as_of: Date) -> Result<Loan> {
+		let loan = self.loan_repo.find_for_update(conn, loan_id)?;
+		let overdue = self.loan_payments_repo.find_overdue(conn, loan_id, &as_of)?;
+		
+		for payment in &overdue {
+			if policy.charges_late_fee(payment, as_of) {
+				self.loan_payments_repo.set_late_fee(conn, &payment.id, &policy.late_fee)?;
+			}
+		}
+		
+		let days_past_due = overdue.first()
+			.map(|p| delinquency::days_past_due(p.due_date, as_of))
+			.unwrap_or(0);
+		let bucket = DelinquencyBucket::from_days_past_due(days_past_due);
+		let loan = self.loan_repo.set_delinquency(conn, loan_id, days_past_due as i32, bucket)?;
+		
+		if loan.state == LoanState::Active && policy.is_default(days_past_due) {
+			return self.transition_loan(conn, &loan, LoanState::Default);
+		}
+		Ok(loan)
+	}
+	
+	/// Finds the price of one unit of a currency in another on the date
+	///
+	/// The inverse of the opposite pair's rate is used when the pair's rate hasn't been published
+	fn find_fx_rate(&self, conn: &PgConnection, from: Currency, to: Currency, date: &Date) -> Result<BigDecimal> {
+		if from == to {
+			return Ok(BigDecimal::from(1));
+		}
+		match self.fx_repo.find_effective(conn, from, to, date) {
+			Ok(rate) => return Ok(rate.rate),
+			Err(db::Error::RecordNotFound) => {}
+			Err(e) => return Err(e.into()),
+		}
+		match self.fx_repo.find_effective(conn, to, from, date) {
+			// the inverse is kept at full precision, only the converted amount and the recorded rate are rounded
+			Ok(rate) => Ok(BigDecimal::from(1) / rate.rate),
+			Err(db::Error::RecordNotFound) => Err(Error::new(ErrorKind::MissingFxRate(from, to, *date))),
+			Err(e) => Err(e.into()),
+		}
+	}
+	
+	/// Accrues interest on the loan for its next accrual period, returning the loan and the interest accrued
+	///
+	/// Interest still unpaid at a compounding date is capitalized into the balance before the period accrues.
+	/// The capitalized interest is earned by the bank and added to the loan receivable in the ledger.
+	/// A variable rate loan's rate is reset before a period that starts on a reset date.
+	fn accrue_period(&self, conn: &PgConnection, mut loan: Loan) -> Result<(Loan, BigDecimal)> {
+		if loan.is_compounding_date(loan.accrued_through()) && loan.accrued_interest.is_positive() {
+			self.ledger_repo.post(conn, NewJournalEntry {
+				entry_type: JournalEntryType::InterestCapitalization,
+				reference_id: None,
+				postings: vec![Posting {
+					debit: LedgerAccount::LoanReceivable(loan.id),
+					credit: LedgerAccount::InterestIncome(loan.vault_name.clone()),
+					amount: &loan.accrued_interest,
+				}],
+			})?;
+			loan = self.loan_repo.capitalize_interest(conn, &loan.id)?;
+		}
+		
+		if loan.is_rate_reset_date(loan.accrued_through()) {
+			loan = self.reset_rate(conn, loan)?;
+		}
+		
+		let interest = loan.periodic_interest();
+		let loan = self.loan_repo.accrue_interest(conn, &loan.id, &interest, &loan.next_accrual_date())?;
+		Ok((loan, interest))
+	}
+	
+	/// Accrues a day's interest on the savings account's end of day balance
+	///
+	/// The day's balance is the account's balance rolled back through the movements made after the day.
+	/// Interest accrued over the previous month is credited before the first day of a month accrues, so it earns
+	/// interest from then on. Only whole cents are credited, the rest is carried to the next month, as is interest
+	/// the product's vault can't pay yet.
+	fn accrue_savings_day(&self, conn: &PgConnection, mut account: Account, product: &SavingsProduct, movements: &[Movement]) -> Result<(Account, BigDecimal)> {
+		let day = account.interest_accrued_through.expect("invalid state: savings account should have an accrual date");
+		if day.day() == 1 {
+			let account_id = account.id;
+			account = match self.credit_savings_interest(conn, account, product) {
+				Ok(account) => account,
+				Err(e) if matches!(e.kind(), ErrorKind::InadequateFunds) => self.account_repo.find_by_id(conn, &account_id)?,
+				Err(e) => return Err(e),
+			};
+		}
+		
+		let balance = snapshot::roll_back(&account.amount, movements, &start_of_day(day.succ()));
+		let interest = product.daily_interest(&balance, day);
+		let account = self.account_repo.accrue_interest(conn, &account.id, &interest, &day.succ())?;
+		Ok((account, interest))
+	}
+	
+	/// Credits the whole cents of the savings account's accrued interest to its balance from the product's vault
+	///
+	/// Fails with `InadequateFunds` when the vault can't pay the interest
+	fn credit_savings_interest(&self, conn: &PgConnection, account: Account, product: &SavingsProduct) -> Result<Account> {
+		let credit = money::round(&account.accrued_interest, account.currency.minor_units(), RoundingMode::Down);
+		if !credit.is_positive() {
+			return Ok(account);
+		}
+		let vault = self.vault_repo.find_by_name(conn, &product.vault_name)?;
+		money::check_currency(account.currency, vault.currency)?;
+		if vault.amount < credit {
+			return Err(Error::new(ErrorKind::InadequateFunds));
+		}
+		
+		let transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+			account_id: &account.id,
+			vault_name: &product.vault_name,
+			transaction_type: BankTransactionType::InterestCredit,
+			amount: &credit,
+			idempotency_key: None,
+			overdraft_protection: None,
+			currency: account.currency,
+			reversal_of: None,
+			forced_by: None,
+		})?;
+		self.ledger_repo.post(conn, NewJournalEntry {
+			entry_type: JournalEntryType::InterestCredit,
+			reference_id: Some(&transaction.id),
+			postings: vec![
+				Posting {
+					debit: LedgerAccount::InterestExpense(product.vault_name.clone()),
+					credit: LedgerAccount::Deposit(account.id),
+					amount: &credit,
+				},
+				Posting {
+					debit: LedgerAccount::LoanFunding(product.vault_name.clone()),
+					credit: LedgerAccount::Vault(product.vault_name.clone()),
+					amount: &credit,
+				},
+			],
+		})?;
+		self.vault_repo.decrement(conn, &product.vault_name, &credit)?;
+		self.account_repo.credit_interest(conn, &account.id, &credit).map_err(Into::into)
+	}
+	
+	/// Makes sure the account can cover the amount, using its overdraft protection when its balance can't
+	///
+	/// The shortfall is swept from the linked account when it can cover it, otherwise the balance may go negative
+	/// within the overdraft line, which is charged the overdraft fee. Returns the protection that was used, if any.
+	fn protect_overdraft(&self, conn: &PgConnection, account: &Account, amount: &BigDecimal) -> Result<Option<OverdraftProtectionKind>> {
+		if &account.amount >= amount {
+			return Ok(None);
+		}
+		let protection = match self.overdraft_repo.find_by_account(conn, &account.id) {
+			Ok(protection) => protection,
+			Err(db::Error::RecordNotFound) => return Err(Error::new(ErrorKind::InadequateFunds)),
+			Err(e) => return Err(e.into()),
+		};
+		
+		let shortfall = amount - &account.amount;
+		if let Some(linked_id) = &protection.linked_account_id {
+			// the linked account was locked along with the account, unless it was linked since
+			let linked = self.account_repo.find_for_update(conn, linked_id)?;
+			if linked.is_open() && linked.amount >= shortfall {
+				self.transfer(conn, NewAccountTransaction {
+					sender_id: linked_id,
+					receiver_id: &account.id,
+					amount: &shortfall,
+					idempotency_key: None,
+					overdraft_protection: Some(OverdraftProtectionKind::LinkedAccount),
+					reversal_of: None,
+					forced_by: None,
+					currency: account.currency,
+				})?;
+				return Ok(Some(OverdraftProtectionKind::LinkedAccount));
+			}
+		}
+		
+		let balance_after = &account.amount - amount - &protection.overdraft_fee;
+		if !protection.covers(&balance_after) {
+			return Err(Error::new(ErrorKind::InadequateFunds));
+		}
+		if protection.overdraft_fee.is_positive() {
+			self.charge_overdraft(conn, &protection, account.currency, BankTransactionType::OverdraftFee, &protection.overdraft_fee)?;
+		}
+		Ok(Some(OverdraftProtectionKind::OverdraftLine))
+	}
+	
+	/// Accrues a day's interest on the overdrawn account's end of day balance
+	///
+	/// Interest accrued over the previous month is charged before the first day of a month accrues. Only whole cents
+	/// are charged, the rest is carried to the next month.
+	fn accrue_overdraft_day(&self, conn: &PgConnection, mut protection: OverdraftProtection) -> Result<OverdraftProtection> {
+		let day = protection.interest_accrued_through;
+		let mut account = self.account_repo.find_by_id(conn, &protection.account_id)?;
+		let charge = money::round(&protection.accrued_interest, account.currency.minor_units(), RoundingMode::Down);
+		if day.day() == 1 && charge.is_positive() {
+			self.charge_overdraft(conn, &protection, account.currency, BankTransactionType::OverdraftInterest, &charge)?;
+			protection = self.overdraft_repo.collect_interest(conn, &protection.account_id, &charge)?;
+			account = self.account_repo.find_by_id(conn, &account.id)?;
+		}
+		
+		let interest = protection.daily_interest(&account.amount, day);
+		self.overdraft_repo.accrue_interest(conn, &account.id, &interest, &day.succ()).map_err(Into::into)
+	}
+	
+	/// Charges the account an overdraft fee or interest, earned by the protection's vault
+	fn charge_overdraft(&self, conn: &PgConnection, protection: &OverdraftProtection, currency: Currency, transaction_type: BankTransactionType, amount: &BigDecimal) -> Result<BankTransaction> {
+		let income = match transaction_type {
+			BankTransactionType::OverdraftInterest => LedgerAccount::InterestIncome(protection.vault_name.clone()),
+			_ => LedgerAccount::FeeIncome(protection.vault_name.clone()),
+		};
+		let transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+			account_id: &protection.account_id,
+			vault_name: &protection.vault_name,
+			transaction_type,
+			amount,
+			idempotency_key: None,
+			overdraft_protection: Some(OverdraftProtectionKind::OverdraftLine),
+			currency,
+			reversal_of: None,
+			forced_by: None,
+		})?;
+		self.ledger_repo.post(conn, NewJournalEntry {
+			entry_type: JournalEntryType::OverdraftCharge,
+			reference_id: Some(&transaction.id),
+			postings: vec![
+				Posting {
+					debit: LedgerAccount::Deposit(protection.account_id),
+					credit: income,
+					amount,
+				},
+				Posting {
+					debit: LedgerAccount::Vault(protection.vault_name.clone()),
+					credit: LedgerAccount::LoanFunding(protection.vault_name.clone()),
+					amount,
+				},
+			],
+		})?;
+		self.account_repo.decrement(conn, &protection.account_id, amount)?;
+		self.vault_repo.increment(conn, &protection.vault_name, amount)?;
+		Ok(transaction)
+	}
+	
+	/// Loads everything that moved money into or out of the account, oldest first
+	fn load_history(&self, conn: &PgConnection, account: &Account) -> Result<Vec<history::Entry>> {
+		Ok(history::merge(
+			&account.id,
+			self.bank_transaction_repo.find_by_account(conn, &account.id)?,
+			self.account_transaction_repo.find_by_account(conn, &account.id)?,
+			self.fx_repo.find_conversions_by_account(conn, &account.id)?,
+		))
+	}
+	
+	/// Snapshots the balance of every account and vault at the time
+	///
+	/// Accounts opened after the time or closed before it are skipped. Snapshots already taken at the time are kept.
+	fn snapshot_balances(&self, conn: &PgConnection, taken_at: &Time) -> Result<()> {
+		// every balance is read at the same point so transactions committed during the run can't skew the snapshots
+		conn.build_transaction().repeatable_read().run::<_, Error, _>(|| {
+			for account in self.account_repo.find_all(conn)? {
+				if &account.created_at > taken_at || account.closed_at.map_or(false, |closed_at| &closed_at < taken_at) {
+					continue;
+				}
+				let balance = self.account_balance_at(conn, &account, taken_at)?;
+				self.snapshot_repo.create_for_account(conn, &account.id, &balance, taken_at)?;
+			}
+			
+			for vault in self.vault_repo.find_all(conn)? {
+				let balance = self.vault_balance_at(conn, &vault, taken_at)?;
+				self.snapshot_repo.create_for_vault(conn, &vault.name, &balance, taken_at)?;
+			}
+			Ok(())
+		})
+	}
+	
+	/// Works out the account's balance at the time from its snapshots and the transactions around them
+	fn account_balance_at(&self, conn: &PgConnection, account: &Account, at: &Time) -> Result<BigDecimal> {
+		Ok(match self.snapshot_repo.find_latest_for_account(conn, &account.id, at)? {
+			Some(s) => snapshot::roll_forward(&s.balance, &self.account_movements_after(conn, account, &s.taken_at)?, at),
+			None => snapshot::roll_back(&account.amount, &self.account_movements_after(conn, account, at)?, at),
+		})
+	}
+	
+	/// Works out the vault's balance at the time from its snapshots and the transactions around them
+	fn vault_balance_at(&self, conn: &PgConnection, vault: &Vault, at: &Time) -> Result<BigDecimal> {
+		Ok(match self.snapshot_repo.find_latest_for_vault(conn, &vault.name, at)? {
+			Some(s) => snapshot::roll_forward(&s.balance, &self.vault_movements_after(conn, &vault.name, &s.taken_at)?, at),
+			None => snapshot::roll_back(&vault.amount, &self.vault_movements_after(conn, &vault.name, at)?, at),
+		})
+	}
+	
+	/// Loads the money that moved into or out of the account after the time, oldest first
+	fn account_movements_after(&self, conn: &PgConnection, account: &Account, after: &Time) -> Result<Vec<Movement>> {
+		let entries = history::merge(
+			&account.id,
+			self.bank_transaction_repo.find_by_account_after(conn, &account.id, after)?,
+			self.account_transaction_repo.find_by_account_after(conn, &account.id, after)?,
+			self.fx_repo.find_conversions_by_account_after(conn, &account.id, after)?,
+		);
+		Ok(entries.into_iter().map(|e| Movement { amount: e.amount, created_at: e.created_at }).collect())
+	}
+	
+	/// Loads the money that moved into or out of the vault after the time, oldest first
+	fn vault_movements_after(&self, conn: &PgConnection, vault_name: &str, after: &Time) -> Result<Vec<Movement>> {
+		Ok(self.bank_transaction_repo.find_by_vault_after(conn, vault_name, after)?
+			.into_iter()
+			.map(|t| Movement { amount: t.vault_amount(), created_at: t.created_at })
+			.collect())
+	}
+	
+	/// Attempts the scheduled transfer's occurrence on the date, records the attempt and moves the transfer on
+	///
+	/// The transfer is made in a savepoint of the caller's transaction, so a failed transfer is rolled back
+	/// while the attempt is still recorded.
+	/// Returns the attempt and the transfer as it stands after it
+	fn run_scheduled_transfer(&self, conn: &PgConnection, transfer: &ScheduledTransfer, scheduled_date: Date, today: Date, policy: &RetryPolicy) -> Result<(ScheduledTransferRun, ScheduledTransfer)> {
+		let idempotency_key = format!("scheduled_transfer:{}:{}", transfer.id, scheduled_date);
+		let amount = Money::new(transfer.amount.clone(), transfer.currency);
+		let sent = conn.transaction::<_, Error, _>(|| {
+			self.send_funds_on(conn, &transfer.sender_id, &transfer.receiver_id, &amount, Some(&idempotency_key))
+		});
+		let (outcome, transaction_id, error) = match sent {
+			Ok(transaction) => (RunOutcome::Succeeded, Some(transaction.id), None),
+			Err(e) if e.kind() == &ErrorKind::InadequateFunds && policy.retries(transfer.retries) => (RunOutcome::Retrying, None, Some(e.to_string())),
+			Err(e) if e.kind() == &ErrorKind::InadequateFunds => (RunOutcome::Skipped, None, Some(e.to_string())),
+			Err(e) => (RunOutcome::Failed, None, Some(e.to_string())),
+		};
+		
+		let run = self.scheduled_transfer_repo.create_run(conn, NewScheduledTransferRun {
+			scheduled_transfer_id: transfer.id,
+			scheduled_date,
+			run_date: today,
+			outcome,
+			transaction_id,
+			error,
+		})?;
+		let transfer = match outcome {
+			RunOutcome::Retrying => self.scheduled_transfer_repo.retry(conn, &transfer.id, &today.succ())?,
+			_ => self.scheduled_transfer_repo.advance(conn, &transfer.id, transfer.next_occurrence(scheduled_date))?,
+		};
+		Ok((run, transfer))
+	}
+	
+	/// Transfers funds from account to account on the connection, as part of the caller's transaction
+	fn send_funds_on(&self, conn: &PgConnection, sender_id: &Id, receiver_id: &Id, amount: &Money, idempotency_key: Option<&str>) -> Result<AccountTransaction> {
+		check_amount(amount)?;
+		check_not_self_transfer(sender_id, receiver_id)?;
+		
+		match self.find_original(conn, idempotency_key)? {
+			Some(Original::Account(t)) if t.is_repeat_of(sender_id, receiver_id, amount) => {
+				return Ok(t);
+			}
+			Some(_) => return Err(Error::new(ErrorKind::IdempotencyKeyConflict)),
+			None => {}
+		}
+		
+		let (sender_account, receiver_account) = self.lock_transfer_accounts(conn, sender_id, receiver_id)?;
+		money::check_currency(sender_account.currency, amount.currency)?;
+		money::check_currency(sender_account.currency, receiver_account.currency)?;
+		let overdraft_protection = self.protect_overdraft(conn, &sender_account, &amount.amount)?;
+		
+		self.transfer(conn, NewAccountTransaction {
+			sender_id,
+			receiver_id,
+			amount: &amount.amount,
+			idempotency_key,
+			overdraft_protection,
+			reversal_of: None,
+			forced_by: None,
+			currency: amount.currency,
+		})
+	}
+	
+	/// Checks that the operator forcing a reversal, if there is one, is a known user
+	fn check_operator(&self, conn: &PgConnection, forced_by: Option<&Id>) -> Result<()> {
+		if let Some(operator_id) = forced_by {
+			self.user_repo.find_by_key(conn, user::FindKey::ID(*operator_id))?;
+		}
+		Ok(())
+	}
+	
+	/// Locks the sender's and receiver's accounts, which must both be open
+	///
+	/// The account the sender's overdraft protection draws on is locked with them, and the accounts are locked
+	/// in order of their ids so opposing transfers can't deadlock.
+	fn lock_transfer_accounts(&self, conn: &PgConnection, sender_id: &Id, receiver_id: &Id) -> Result<(Account, Account)> {
+		let linked_id = self.find_linked_account_id(conn, sender_id)?;
+		let (mut sender, mut receiver) = (None, None);
+		for (id, account) in self.lock_in_order(conn, [sender_id, receiver_id].iter().copied().chain(linked_id.as_ref()).collect()) {
+			if id == receiver_id {
+				receiver = Some(account.map_err(receiver_error)?);
+			} else if id == sender_id {
+				sender = Some(account?);
+			} else {
+				account?;
+			}
+		}
+		let sender = sender.expect("the sender's account is locked");
+		let receiver = receiver.expect("the receiver's account is locked");
+		
+		check_account_open(&sender)?;
+		check_account_open(&receiver)?;
+		Ok((sender, receiver))
+	}
+	
+	/// Locks the account along with the account its overdraft protection draws on, if it has one
+	fn lock_account(&self, conn: &PgConnection, account_id: &Id) -> Result<Account> {
+		let linked_id = self.find_linked_account_id(conn, account_id)?;
+		let mut account = None;
+		for (id, locked) in self.lock_in_order(conn, iter::once(account_id).chain(linked_id.as_ref()).collect()) {
+			let locked = locked?;
+			if id == account_id {
+				account = Some(locked);
+			}
+		}
+		Ok(account.expect("the account is locked"))
+	}
+	
+	/// Locks the accounts in order of their ids, stopping at the first account that can't be locked
+	///
+	/// Every operation that locks more than one account locks them in this order so they can't deadlock.
+	/// An account given more than once is locked once.
+	fn lock_in_order<'b>(&self, conn: &PgConnection, mut ids: Vec<&'b Id>) -> Vec<(&'b Id, db::Result<Account>)> {
+		ids.sort();
+		ids.dedup();
+		let mut locked = Vec::with_capacity(ids.len());
+		for id in ids {
+			let account = self.account_repo.find_for_update(conn, id);
+			let failed = account.is_err();
+			locked.push((id, account));
+			if failed {
+				break;
+			}
+		}
+		locked
+	}
+	
+	/// Finds the account the overdraft protection on the account draws on, if it has protection linked to one
+	fn find_linked_account_id(&self, conn: &PgConnection, account_id: &Id) -> Result<Option<Id>> {
+		match self.overdraft_repo.find_by_account(conn, account_id) {
+			Ok(protection) => Ok(protection.linked_account_id),
+			Err(db::Error::RecordNotFound) => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	}
+	
+	/// Moves funds from the sender's account to the receiver's
+	fn transfer(&self, conn: &PgConnection, new_transaction: NewAccountTransaction) -> Result<AccountTransaction> {
+		let (sender_id, receiver_id, amount) = (new_transaction.sender_id, new_transaction.receiver_id, new_transaction.amount);
+		let entry_type = if new_transaction.reversal_of.is_some() { JournalEntryType::Reversal } else { JournalEntryType::Transfer };
+		let transaction = self.account_transaction_repo.create(conn, new_transaction)?;
+		
+		self.ledger_repo.post(conn, NewJournalEntry {
+			entry_type,
+			reference_id: Some(&transaction.id),
+			postings: vec![Posting {
+				debit: LedgerAccount::Deposit(*sender_id),
+				credit: LedgerAccount::Deposit(*receiver_id),
+				amount,
+			}],
+		})?;
+		
+		self.account_repo.increment(conn, receiver_id, amount)?;
+		self.account_repo.decrement(conn, sender_id, amount)?;
+		Ok(transaction)
+	}
+	
+	/// Spreads the payment's allocation over the loan's past due payments and the next payment, the oldest first
+	///
+	/// Fees, interest and principal are applied to each payment up to what it still owes, and the fee transaction
+	/// is attached to the payments whose fees it paid. Interest beyond the
+	/// scheduled interest and any prepayment are recorded on the first payment left owing, or the last payment
+	/// when every payment is paid in full. Payments paid in full are marked paid by the payment's transactions.
+	fn apply_to_loan_payments(&self,
+							  conn: &PgConnection,
+							  unpaid: &[LoanPayment],
+							  as_of: Date,
+							  allocation: &Allocation,
+							  transaction_ids: &PaymentTransactionIds) -> Result<()> {
+		let past_due = unpaid.iter().take_while(|p| p.due_date < as_of).count();
+		let due = &unpaid[..unpaid.len().min(past_due + 1)];
+		
+		let mut fees = allocation.fees.clone();
+		let mut interest = allocation.interest();
+		let mut principal = allocation.principal.clone();
+		
+		let mut owing = None;
+		for payment in due {
+			let fees_paid = take(&mut fees, &payment.fees_outstanding());
+			let interest_paid = take(&mut interest, &payment.interest_outstanding());
+			let principal_paid = take(&mut principal, &payment.principal_outstanding());
+			let zero = BigDecimal::zero();
+			if fees_paid.is_zero() && interest_paid.is_zero() && principal_paid.is_zero() {
+				owing = owing.or(Some(payment.id));
+				continue;
+			}
+			
+			let payment = self.loan_payments_repo.record_payment(conn, &payment.id, &principal_paid, &interest_paid, &fees_paid, &zero)?;
+			if let (Some(fee), false) = (&transaction_ids.fee, fees_paid.is_zero()) {
+				self.loan_payments_repo.set_fee_transaction_id(conn, &payment.id, fee)?;
+			}
+			if !payment.underpaid().is_zero() {
+				owing = owing.or(Some(payment.id));
+				continue;
+			}
+			
+			self.loan_payments_repo.set_transaction_ids(conn, &payment.id, &transaction_ids.principal, &transaction_ids.interest)?;
+		}
+		
+		let remaining = &interest + &principal;
+		if remaining.is_zero() && allocation.prepayment.is_zero() {
+			return Ok(());
+		}
+		if let Some(payment_id) = owing.or_else(|| due.last().map(|p| p.id)) {
+			let zero = BigDecimal::zero();
+			self.loan_payments_repo.record_payment(conn, &payment_id, &principal, &interest, &zero, &allocation.prepayment)?;
+		}
+		Ok(())
+	}
+	
+	/// Builds the quote for paying off the loan on the payoff date
+	///
+	/// Per diem interest is kept to the 4 decimal places amounts are stored with, any fraction beyond that is not charged
+	fn payoff_quote(&self, conn: &PgConnection, loan: &Loan, payoff_date: Date) -> Result<PayoffQuote> {
+		check_loan_transition(loan, LoanState::Paid)?;
+		if payoff_date < loan.accrued_through() {
+			let msg = format!("payoff date({}) is before interest was accrued through {}", payoff_date, loan.accrued_through());
+			return Err(Error::new(ErrorKind::InvalidDate(msg)));
+		}
+		
+		let per_diem_interest = money::round(&loan.interest_between(loan.accrued_through(), payoff_date), money::ACCRUAL_SCALE, RoundingMode::HalfEven);
+		let fees = self.loan_payments_repo.find_unpaid(conn, &loan.id)?
+			.iter()
+			.filter(|p| p.fee_transaction_id.is_none())
+			.fold(BigDecimal::zero(), |sum, p| sum + &p.late_fee);
+		let total = &loan.balance + &loan.accrued_interest + &per_diem_interest + &fees;
+		
+		Ok(PayoffQuote {
+			loan_id: loan.id,
+			payoff_date,
+			balance: loan.balance.clone(),
+			accrued_interest: loan.accrued_interest.clone(),
+			per_diem_interest,
+			fees,
+			total,
+		})
+	}
+	
+	/// Collects a late fee from the account
+	///
+	/// Late fees are earned by the bank and don't reduce the loan balance
+	fn collect_late_fee(&self, conn: &PgConnection, loan: &Loan, account_id: &Id, amount: &BigDecimal) -> Result<BankTransaction> {
+		let transaction = self.bank_transaction_repo.create(conn, NewBankTransaction {
+			account_id,
+			vault_name: &loan.vault_name,
+			transaction_type: BankTransactionType::LateFee,
+			amount,
+			idempotency_key: None,
+			overdraft_protection: None,
+			currency: loan.currency,
+			reversal_of: None,
+			forced_by: None,
+		})?;
+		self.ledger_repo.post(conn, NewJournalEntry {
+			entry_type: JournalEntryType::LoanRepayment,
+			reference_id: Some(&transaction.id),
+			postings: vec![
+				Posting {
+					debit: LedgerAccount::Deposit(*account_id),
+					credit: LedgerAccount::FeeIncome(loan.vault_name.clone()),
+					amount,
+				},
+				Posting {
+					debit: LedgerAccount::Vault(loan.vault_name.clone()),
+					credit: LedgerAccount::LoanFunding(loan.vault_name.clone()),
+					amount,
+				},
+			],
+		})?;
+		self.account_repo.decrement(conn, account_id, amount)?;
+		self.vault_repo.increment(conn, &loan.vault_name, amount)?;
+		Ok(transaction)
+	}
+	
+	/// Resets a variable rate loan's rate from its benchmark's rate in effect on the date interest was accrued through
+	///
+	/// The new rate is recorded in the loan's rate history when it differs from the current rate
+	fn reset_rate(&self, conn: &PgConnection, loan: Loan) -> Result<Loan> {
+		let benchmark = match &loan.benchmark {
+			Some(benchmark) => benchmark,
+			None => return Ok(loan),
+		};
+		let effective_date = loan.accrued_through();
+		let benchmark_rate = self.find_benchmark_rate(conn, benchmark, &effective_date)?;
+		let interest_rate = loan.indexed_rate(benchmark_rate);
+		if interest_rate == loan.interest_rate_basis_points() {
+			return Ok(loan);
+		}
+		
+		self.loan_rate_change_repo.create(conn, NewRateChange {
+			loan_id: loan.id,
+			effective_date,
+			interest_rate,
+			benchmark_rate: Some(benchmark_rate),
+		})?;
+		self.loan_repo.set_interest_rate(conn, &loan.id, interest_rate).map_err(Into::into)
+	}
+	
+	/// Finds the benchmark's rate in basis points in effect on the date
+	fn find_benchmark_rate(&self, conn: &PgConnection, benchmark: &str, date: &Date) -> Result<i16> {
+		match self.benchmark_repo.find_effective(conn, benchmark, date) {
+			Ok(rate) => Ok(rate.rate),
+			Err(db::Error::RecordNotFound) => Err(Error::new(ErrorKind::MissingBenchmarkRate(benchmark.to_owned(), *date))),
+			Err(e) => Err(e.into()),
+		}
+	}
+	
+	/// The known rate resets of a variable rate loan after the start date
+	///
+	/// Resets after the benchmark's latest published rate assume that rate stays in effect
+	fn scheduled_rate_resets(&self, conn: &PgConnection, loan: &Loan, start_date: Date) -> Result<Vec<RateReset>> {
+		let benchmark = match &loan.benchmark {
+			Some(benchmark) => benchmark,
+			None => return Ok(vec![]),
+		};
+		
+		let mut resets = vec![];
+		let mut interest_rate = loan.interest_rate_basis_points();
+		for date in loan.rate_reset_dates(start_date) {
+			let reset_rate = loan.indexed_rate(self.find_benchmark_rate(conn, benchmark, &date)?);
+			if reset_rate != interest_rate {
+				resets.push(RateReset {
+					effective_date: date,
+					annual_rate: loan::rate_from_basis_points(reset_rate),
+				});
+				interest_rate = reset_rate;
+			}
+		}
+		Ok(resets)
+	}
+	
+	/// Moves the account to the next status if the transition is allowed
+	fn transition_account(&self, account_id: &Id, to: AccountStatus) -> Result<Account> {
+		let conn = &self.db.get()?;
+		conn.transaction::<_, Error, _>(|| {
+			let account = self.account_repo.find_for_update(conn, account_id)?;
+			check_account_transition(&account, to)?;
+			self.account_repo.set_status(conn, account_id, to).map_err(Into::into)
+		})
+	}
+	
+	/// Moves the loan to the next state if the transition is allowed
+	fn transition_loan(&self, conn: &PgConnection, loan: &Loan, to: LoanState) -> Result<Loan> {
+		check_loan_transition(loan, to)?;
+		self.loan_repo.set_state(conn, &loan.id, to).map_err(Into::into)
+	}
+	
+	/// Updates the interest due on a loan payment to the loan's accrued interest
+	///
+	/// The scheduled principal is kept unless it exceeds the loan's remaining balance
+	fn set_loan_payment_dues(&self, conn: &PgConnection, loan: &Loan, loan_payment: &LoanPayment) -> Result<LoanPayment> {
+		let principal_due = (&loan_payment.principal_due).min(&loan.balance);
+		self.loan_payments_repo.set_dues(conn,
+										 &loan_payment.id,
+										 principal_due,
+										 &loan.accrued_interest).map_err(Into::into)
+	}
+	
+	/// Replaces the loan's unpaid payments with a schedule that amortizes its current balance
+	///
+	/// The schedule starts from the due date of the last payment made, or the issue date if none have been made
+	fn create_loan_schedule(&self, conn: &PgConnection, loan: &Loan) -> Result<Vec<LoanPayment>> {
+		let loan = self.loan_repo.find_by_id(conn, &loan.id)?;
+		let start_date = match self.loan_payments_repo.find_last_paid(conn, &loan.id) {
+			Ok(payment) => payment.due_date,
+			Err(db::Error::RecordNotFound) => loan.issue_date,
+			Err(e) => return Err(e.into()),
+		};
+		
+		self.loan_payments_repo.delete_unpaid(conn, &loan.id)?;
+		if loan.balance.is_zero() {
+			return Ok(vec![]);
+		}
+		
+		let schedule = amortization::schedule(&amortization::Terms {
+			principal: &loan.balance,
+			annual_rate: loan.interest_rate(),
+			payment_frequency: loan.payment_frequency,
+			start_date,
+			maturity_date: loan.maturity_date,
+			method: loan.amortization_method,
+			day_count: loan.day_count_convention,
+			rate_resets: self.scheduled_rate_resets(conn, &loan, start_date)?,
+			currency: loan.currency,
+			rounding: RoundingMode::HalfEven,
+		});
+		if schedule.is_empty() {
+			let msg = format!("no payment period fits between {} and the maturity date({})", start_date, loan.maturity_date);
+			return Err(Error::new(ErrorKind::InvalidDate(msg)));
+		}
+		
+		let new_payments: Vec<NewPayment> = schedule.into_iter()
+			.map(|p| NewPayment {
+				loan_id: loan.id,
+				principal_due: p.principal_due,
+				interest_due: p.interest_due,
+				due_date: p.due_date,
+			})
+			.collect();
+		self.loan_payments_repo.create_all(conn, &new_payments).map_err(Into::into)
+	}
+}
+
+/// The first and last days of the calendar month containing the date
+fn statement_period(month: Date) -> (Date, Date) {
+	let period_start = month.with_day(1).unwrap();
+	(period_start, period_start.increment_date_by_months(1).pred())
+}
+
+/// The start of the day, which is when the balances at the end of the previous day are taken
+fn start_of_day(day: Date) -> Time {
+	Utc.from_utc_datetime(&day.and_hms(0, 0, 0))
+}
+
+/// Checks that looking up a transaction's reversal found none
+fn check_not_reversed<T>(reversal: db::Result<T>) -> Result<()> {
+	match reversal {
+		Ok(_) => Err(Error::new(ErrorKind::AlreadyReversed)),
+		Err(db::Error::RecordNotFound) => Ok(()),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Checks that the account can cover the amount a reversal takes from it, unless an operator forces the reversal
+fn check_reversal_funds(account: &Account, amount: &BigDecimal, forced_by: Option<&Id>) -> Result<()> {
+	if forced_by.is_none() && &account.amount < amount {
+		return Err(Error::new(ErrorKind::InadequateFunds));
+	}
+	Ok(())
+}
+
+/// Checks that the amount is greater than zero and a whole number of its currency's minor unit
+fn check_amount(amount: &Money) -> Result<()> {
+	if amount.amount.is_zero() {
+		return Err(Error::new(ErrorKind::ZeroAmount));
+	}
+	if amount.amount.is_negative() {
+		return Err(Error::new(ErrorKind::InvalidStateNegativeValue));
+	}
+	check_precision(amount)
+}
+
+/// Checks that the amount doesn't have digits smaller than its currency's minor unit
+fn check_precision(amount: &Money) -> Result<()> {
+	if amount.is_whole_minor_units() {
+		Ok(())
+	} else {
+		Err(Error::new(ErrorKind::ExcessPrecision(amount.currency)))
+	}
+}
+
+fn check_not_self_transfer(sender_id: &Id, receiver_id: &Id) -> Result<()> {
+	if sender_id == receiver_id {
+		Err(Error::new(ErrorKind::SelfTransfer))
+	} else {
+		Ok(())
+	}
+}
+
+/// Checks that a monthly transfer has a day of the month to run on and other transfers don't, returning the day
+fn check_day_of_month(schedule: &TransferSchedule) -> Result<u32> {
+	match (schedule.frequency, schedule.day_of_month) {
+		(Frequency::Monthly, Some(day)) if (1..=31).contains(&day) => Ok(day as u32),
+		(Frequency::Monthly, _) => Err(Error::new(ErrorKind::InvalidDate("monthly transfers must run on a day of the month from 1 to 31".to_string()))),
+		(_, Some(_)) => Err(Error::new(ErrorKind::InvalidDate(format!("{} transfers can't run on a day of the month", schedule.frequency)))),
+		(_, None) => Ok(1),
+	}
+}
+
+/// The receiving account not being found is reported as such rather than as a missing record
+fn receiver_error(e: db::Error) -> Error {
+	match e {
+		db::Error::RecordNotFound => Error::new(ErrorKind::ReceiverNotFound),
+		e => e.into(),
+	}
+}
+
+/// Checks that money can move into or out of the account
+fn check_account_open(account: &Account) -> Result<()> {
+	match account.status {
+		AccountStatus::Open => Ok(()),
+		AccountStatus::Frozen => Err(Error::new(ErrorKind::AccountFrozen)),
+		AccountStatus::Dormant => Err(Error::new(ErrorKind::AccountDormant)),
+		AccountStatus::Closed => Err(Error::new(ErrorKind::AccountClosed)),
+	}
+}
+
+fn check_account_transition(account: &Account, to: AccountStatus) -> Result<()> {
+	if account.status.can_transition_to(to) {
+		Ok(())
+	} else {
+		Err(Error::new(ErrorKind::InvalidAccountStatusTransition { from: account.status, to }))
+	}
+}
+
+fn check_loan_transition(loan: &Loan, to: LoanState) -> Result<()> {
+	if loan.state.can_transition_to(to) {
+		Ok(())
+	} else {
+		Err(Error::new(ErrorKind::InvalidLoanStateTransition { from: loan.state, to }))
+	}
+}
+
+/// The transactions made for a payment against a loan
+struct PaymentTransactionIds {
+	principal: Id,
+	interest: Id,
+	fee: Option<Id>,
+}
+
+/// Takes up to the amount owed from the amount left, returning the amount taken
+fn take(left: &mut BigDecimal, owed: &BigDecimal) -> BigDecimal {
+	let taken = if &*left < owed { left.clone() } else { owed.clone() };
+	*left -= &taken;
+	taken
+}
+
+/// A transaction recorded by an earlier request with the same idempotency key
+enum Original {
+	Bank(BankTransaction),
+	Account(AccountTransaction),
+	Conversion(FxConversion),
+}
+
+/// Used by Service to get the current date
+pub trait Calendar {
+	fn current_date(&self) -> Date {
+		chrono::Utc::today().naive_utc()
+	}
+}
+
+/// Calendar that uses the system clock
+pub struct SystemCalendar;
+
+impl Calendar for SystemCalendar {}
 
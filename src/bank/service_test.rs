@@ -1,14 +1,30 @@
 use std::ops::Sub;
 
-use bigdecimal::{BigDecimal, Zero};
+use bigdecimal::{BigDecimal, Signed, Zero};
+use chrono::{Datelike, TimeZone};
 
 use crate::bank::error::*;
 use crate::bank::service::*;
+use crate::day_count::DayCountConvention;
+use crate::allocation::AllocationPolicy;
+use crate::account::{Account, AccountStatus, AccountType};
+use crate::bank_transaction::{self, BankTransactionType};
+use crate::benchmark::NewBenchmarkRate;
+use crate::overdraft::{OverdraftProtectionKind, OverdraftTerms};
+use crate::savings::NewSavingsProduct;
+use crate::scheduled_transfer::{Frequency, InsufficientFundsAction, RetryPolicy, RunOutcome, ScheduledTransferRun, TransferSchedule};
+use crate::delinquency::DelinquencyPolicy;
+use crate::fx::NewFxRate;
+use crate::history;
+use crate::statement;
+use crate::ledger::LedgerAccount;
 use crate::loan;
-use crate::loan::LoanState;
+use crate::loan::{AmortizationMethod, DelinquencyBucket, LoanState};
+use crate::money::{Currency, Money};
+use crate::schema::{account_transactions, accounts, bank_transactions, journal_lines};
 use crate::testutil::*;
 use crate::testutil::Suite as RepoSuite;
-use crate::types::{Date, DateExt};
+use crate::types::{Date, DateExt, Time};
 
 struct Suite<'a> {
 	pub repos: RepoSuite,
@@ -38,6 +54,16 @@ impl<'a> Suite<'a> {
 			account_transaction_repo: &self.repos.account_transaction_repo,
 			loan_repo: &self.repos.loan_repo,
 			loan_payment_repo: &self.repos.loan_payment_repo,
+			loan_rate_change_repo: &self.repos.loan_rate_change_repo,
+			benchmark_repo: &self.repos.benchmark_repo,
+			ledger_repo: &self.repos.ledger_repo,
+			accrual_run_repo: &self.repos.accrual_run_repo,
+			savings_product_repo: &self.repos.savings_product_repo,
+			overdraft_repo: &self.repos.overdraft_repo,
+			fx_repo: &self.repos.fx_repo,
+			statement_repo: &self.repos.statement_repo,
+			snapshot_repo: &self.repos.snapshot_repo,
+			scheduled_transfer_repo: &self.repos.scheduled_transfer_repo,
 			calendar: &self.mock_calendar,
 		})
 	}
@@ -72,11 +98,16 @@ fn deposit() {
 	let vault = f.insert_main_vault(0);
 	
 	
-	let deposit_amount = BigDecimal::from(300);
-	let bob_account = suite.bank_service().deposit(&bob_account.id, &vault.name, &deposit_amount).unwrap();
-	assert_eq!(bob_account.amount, deposit_amount);
+	let deposit_amount = usd(300);
+	let transaction = suite.bank_service().deposit(&bob_account.id, &vault.name, &deposit_amount, None).unwrap();
+	assert_eq!(transaction.transaction_type, BankTransactionType::Deposit);
+	assert_eq!(transaction.account_id, bob_account.id);
+	assert_eq!(transaction.amount, deposit_amount.amount);
 	
-	let vault = suite.repos.vault_repo.find_by_name(&vault.name).unwrap();
+	let bob_account = suite.repos.account_repo.find_by_id(&f.conn(), &bob_account.id).unwrap();
+	assert_eq!(bob_account.amount, deposit_amount.amount);
+	
+	let vault = suite.repos.vault_repo.find_by_name(&f.conn(), &vault.name).unwrap();
 	assert_eq!(bob_account.amount, vault.amount);
 }
 
@@ -90,15 +121,18 @@ fn withdraw() {
 	let vault = f.insert_main_vault(0);
 	
 	let deposit_amount = BigDecimal::from(500);
-	s.repos.account_repo.increment(&account.id, &deposit_amount);
-	s.repos.vault_repo.increment(&vault.name, &deposit_amount);
+	s.repos.account_repo.increment(&f.conn(), &account.id, &deposit_amount);
+	s.repos.vault_repo.increment(&f.conn(), &vault.name, &deposit_amount);
 	
-	let withdraw_amount = BigDecimal::from(300);
-	let account = s.bank_service().withdraw(&account.id, &vault.name, &withdraw_amount).unwrap();
+	let withdraw_amount = usd(300);
+	let transaction = s.bank_service().withdraw(&account.id, &vault.name, &withdraw_amount, None).unwrap();
+	assert_eq!(transaction.transaction_type, BankTransactionType::Withdraw);
+	assert_eq!(transaction.amount, withdraw_amount.amount);
 	
-	assert_eq!(account.amount, deposit_amount - withdraw_amount);
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id).unwrap();
+	assert_eq!(account.amount, deposit_amount - withdraw_amount.amount);
 	
-	let vault = s.repos.vault_repo.find_by_name(&vault.name).unwrap();
+	let vault = s.repos.vault_repo.find_by_name(&f.conn(), &vault.name).unwrap();
 	assert_eq!(account.amount, vault.amount);
 }
 
@@ -110,8 +144,8 @@ fn withdraw_invalid_funds_err() {
 	let bob_account = f.account_factory.checking_account(bob.id);
 	let vault = f.insert_main_vault(0);
 	
-	let withdraw_amount = BigDecimal::from(500);
-	let got_err = s.bank_service().withdraw(&bob_account.id, &vault.name, &withdraw_amount).unwrap_err();
+	let withdraw_amount = usd(500);
+	let got_err = s.bank_service().withdraw(&bob_account.id, &vault.name, &withdraw_amount, None).unwrap_err();
 	
 	assert_eq!(got_err, Error::new(ErrorKind::InadequateFunds))
 }
@@ -131,20 +165,20 @@ fn send_funds() {
 	let receiver_id = &lucy_account.id;
 	
 	let bob_initial_amount = BigDecimal::from(500);
-	s.repos.account_repo.increment(sender_id, &bob_initial_amount);
+	s.repos.account_repo.increment(&f.conn(), sender_id, &bob_initial_amount);
 	
-	let transfer_amount = BigDecimal::from(250);
-	let transaction = s.bank_service().send_funds(sender_id, receiver_id, &transfer_amount).unwrap();
+	let transfer_amount = usd(250);
+	let transaction = s.bank_service().send_funds(sender_id, receiver_id, &transfer_amount, None).unwrap();
 	
-	let bob_account = s.repos.account_repo.find_by_id(sender_id).unwrap();
-	assert_eq!(bob_account.amount, &bob_initial_amount - &transfer_amount);
+	let bob_account = s.repos.account_repo.find_by_id(&f.conn(), sender_id).unwrap();
+	assert_eq!(bob_account.amount, &bob_initial_amount - &transfer_amount.amount);
 	
-	let lucy_account = s.repos.account_repo.find_by_id(receiver_id).unwrap();
-	assert_eq!(lucy_account.amount, transfer_amount);
+	let lucy_account = s.repos.account_repo.find_by_id(&f.conn(), receiver_id).unwrap();
+	assert_eq!(lucy_account.amount, transfer_amount.amount);
 	
 	/* expect error on overdrawn account */
-	let transfer_amount = BigDecimal::from(1_000);
-	let err = s.bank_service().send_funds(sender_id, receiver_id, &transfer_amount).unwrap_err();
+	let transfer_amount = usd(1_000);
+	let err = s.bank_service().send_funds(sender_id, receiver_id, &transfer_amount, None).unwrap_err();
 	assert_eq!(err, Error::new(ErrorKind::InadequateFunds))
 }
 
@@ -157,42 +191,42 @@ fn pay_loan_payment_due() -> Result<()> {
 	
 	let bob = f.user_factory.bob();
 	let orig_principal = BigDecimal::from(1000);
-	let issue_date = Date::from_ymd(2020, 1, 1);
-	let maturity_date = issue_date.increment_date_by_months(12);
-	let loan = s.repos.loan_repo.create(loan::NewLoan {
-		user_id: bob.id,
-		vault_name: vault.name,
-		orig_principal: orig_principal.clone(),
-		balance: orig_principal.clone(),
-		interest_rate: 200,
-		issue_date,
-		maturity_date,
-		payment_frequency: 1,
-		compound_frequency: 1,
-		state: Default::default(),
-	})?;
-	let loan = s.repos.loan_repo.find_by_id(&loan.id)?;
+	let loan = s.repos.loan_repo.create(&f.conn(), new_loan(bob.id, 1000))?;
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
 	assert_eq!(loan.state, LoanState::PendingApproval);
 	
-	// activate loan
-	let loan = s.repos.loan_repo.set_state(&loan.id, LoanState::Active)?;
-	assert_eq!(loan.state, LoanState::Active);
+	// approve loan
+	let loan = s.bank_service().approve_loan(&loan.id, &f.user_factory.lucy().id)?;
+	assert_eq!(loan.state, LoanState::Approved);
 	
-	// disburse funds
+	// nothing is owed before the loan is disbursed
 	let bob_account = f.account_factory.checking_account(bob.id);
-	s.bank_service().disburse_loan(&loan, &bob_account.id);
-	let bob_account = s.repos.account_repo.find_by_id(&bob_account.id)?;
+	let first_payment = s.repos.loan_payment_repo.find_first_unpaid(&f.conn(), &loan.id)?;
+	let err = s.bank_service().pay_loan_payment_due(&first_payment.id, &bob_account.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::Approved, to: LoanState::Paid }));
+	
+	// disburse funds
+	s.bank_service().disburse_loan(&loan, &bob_account.id)?;
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.state, LoanState::Active);
+	let bob_account = s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?;
 	assert_eq!(bob_account.amount, loan.orig_principal);
 	
 	//todo: add more assertions in this section
 	// check that first loan payment due
-	let next_payment_due = s.repos.loan_payment_repo.find_first_unpaid(&loan.id)?;
+	let next_payment_due = s.repos.loan_payment_repo.find_first_unpaid(&f.conn(), &loan.id)?;
 	
-	let next_payment_due = s.bank_service().pay_loan_payment_due(&next_payment_due.id, &bob_account.id)?;
+	let next_payment_due = s.bank_service().pay_loan_payment_due(&next_payment_due.id, &bob_account.id, None)?;
 	assert!(next_payment_due.principle_transaction_id.is_some());
 	assert!(next_payment_due.interest_transaction_id.is_some());
 	
-	let next_payment_due = s.repos.loan_payment_repo.find_first_unpaid(&loan.id)?;
+	// a paid payment can't be paid again
+	let err = s.bank_service().pay_loan_payment_due(&next_payment_due.id, &bob_account.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::LoanPaymentAlreadyPaid));
+	let paid = s.repos.loan_payment_repo.find_by_id(&f.conn(), &next_payment_due.id)?;
+	assert_eq!(paid.principle_transaction_id, next_payment_due.principle_transaction_id);
+	
+	let next_payment_due = s.repos.loan_payment_repo.find_first_unpaid(&f.conn(), &loan.id)?;
 	assert!(next_payment_due.principle_transaction_id.is_none());
 	assert!(next_payment_due.interest_transaction_id.is_none());
 	
@@ -206,34 +240,28 @@ fn payback_loan_in_full() -> Result<()> {
 	let vault = fixture.insert_main_vault(0);
 	
 	let bob = fixture.user_factory.bob();
-	let orig_principal = BigDecimal::from(1000);
 	let start_date = Date::from_ymd(2020, 1, 1);
 	let issue_date = start_date.clone();
 	suite.mock_calendar.set_curr_date(start_date);
 	
 	let maturity_date = issue_date.increment_date_by_months(6);
-	let mut loan = suite.repos.loan_repo.create(loan::NewLoan {
-		user_id: bob.id,
-		vault_name: vault.name,
-		orig_principal: orig_principal.clone(),
-		balance: orig_principal.clone(),
-		interest_rate: 200,
-		issue_date,
+	let mut loan = suite.repos.loan_repo.create(&fixture.conn(), loan::NewLoan {
 		maturity_date,
-		payment_frequency: 1,
-		compound_frequency: 1,
-		state: Default::default(),
+		..new_loan(bob.id, 1000)
 	})?;
 	
+	loan = suite.bank_service().approve_loan(&loan.id, &fixture.user_factory.lucy().id)?;
 	let bob_account = fixture.account_factory.checking_account(bob.id);
-	suite.bank_service().disburse_loan(&loan, &bob_account.id);
+	suite.bank_service().disburse_loan(&loan, &bob_account.id)?;
+	// payments are only collected from funded accounts, so cover the interest as well as the principal
+	suite.bank_service().deposit(&bob_account.id, &vault.name, &usd(100), None)?;
 	
 	let mut new_date = start_date;
 	while loan.state.ne(&LoanState::Paid) {
 		loan = suite.bank_service().accrue(&loan)?;
 		let next_payment = suite.bank_service().get_next_loan_payment(&loan)?;
-		suite.bank_service().pay_loan_payment_due(&next_payment.id, &bob_account.id);
-		loan = suite.repos.loan_repo.find_by_id(&loan.id)?;
+		suite.bank_service().pay_loan_payment_due(&next_payment.id, &bob_account.id, None);
+		loan = suite.repos.loan_repo.find_by_id(&fixture.conn(), &loan.id)?;
 		new_date = new_date.increment_date_by_months(1);
 		suite.mock_calendar.set_curr_date(new_date);
 	}
@@ -244,3 +272,2411 @@ fn payback_loan_in_full() -> Result<()> {
 	Ok(())
 }
 
+
+#[test]
+fn ledger_reconciles_with_balances() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	
+	let bob = f.user_factory.bob();
+	let bob_account = f.account_factory.checking_account(bob.id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(500), None)?;
+	s.bank_service().withdraw(&bob_account.id, &vault.name, &usd(100), None)?;
+	s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &usd(150), None)?;
+	
+	let issue_date = Date::from_ymd(2020, 1, 1);
+	s.mock_calendar.set_curr_date(issue_date);
+	let loan = s.repos.loan_repo.create(&f.conn(), loan::NewLoan {
+		interest_rate: 1200,
+		state: LoanState::Approved,
+		..new_loan(bob.id, 1200)
+	})?;
+	s.bank_service().disburse_loan(&loan, &bob_account.id)?;
+	
+	let loan = s.bank_service().accrue(&loan)?;
+	let payment = s.bank_service().get_next_loan_payment(&loan)?;
+	s.bank_service().pay_loan_payment_due(&payment.id, &bob_account.id, None)?;
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	let income = s.repos.ledger_repo.balance(&f.conn(), &LedgerAccount::InterestIncome(vault.name.clone()))?;
+	assert_eq!(income, payment.interest_due);
+	
+	// the vault paid out the principal and took back the payment
+	let paid = &payment.principal_due + &payment.interest_due;
+	let stored_vault = s.repos.vault_repo.find_by_name(&f.conn(), &vault.name)?;
+	assert_eq!(stored_vault.amount, BigDecimal::from(400 - 1200) + &paid);
+	
+	// interest accrued beyond what a payment covers is capitalized rather than overdrawing the receivable
+	s.repos.loan_repo.accrue_interest(&f.conn(), &loan.id, &BigDecimal::from(500), &loan.accrued_through())?;
+	let payment = s.bank_service().get_next_loan_payment(&loan)?;
+	s.bank_service().pay_loan_payment_due(&payment.id, &bob_account.id, None)?;
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	// a balance changed outside of the service is reported
+	s.repos.account_repo.increment(&f.conn(), &lucy_account.id, &BigDecimal::from(1))?;
+	let reconciliation = s.bank_service().reconcile()?;
+	assert_eq!(reconciliation.discrepancies.len(), 1);
+	assert_eq!(reconciliation.discrepancies[0].account, LedgerAccount::Deposit(lucy_account.id));
+	
+	Ok(())
+}
+
+#[test]
+fn concurrent_withdrawals_never_overdraw() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	s.bank_service().deposit(&account.id, &vault.name, &usd(100), None).unwrap();
+	
+	// each thread attempts more withdrawals than the balance can cover
+	let num_threads = 8;
+	let withdrawals_per_thread = 5;
+	let withdraw_amount = usd(5);
+	
+	let results: Vec<Result<_>> = std::thread::scope(|scope| {
+		let handles: Vec<_> = (0..num_threads).map(|_| {
+			scope.spawn(|| {
+				(0..withdrawals_per_thread)
+					.map(|_| s.bank_service().withdraw(&account.id, &vault.name, &withdraw_amount, None))
+					.collect::<Vec<_>>()
+			})
+		}).collect();
+		handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+	});
+	
+	let succeeded = results.iter().filter(|r| r.is_ok()).count();
+	assert_eq!(succeeded, 20, "only the withdrawals covered by the balance should succeed");
+	for err in results.iter().filter_map(|r| r.as_ref().err()) {
+		assert_eq!(err, &Error::new(ErrorKind::InadequateFunds));
+	}
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id).unwrap();
+	assert!(account.amount.is_zero());
+	let vault = s.repos.vault_repo.find_by_name(&f.conn(), &vault.name).unwrap();
+	assert!(vault.amount.is_zero());
+}
+
+#[test]
+fn concurrent_transfers_never_overdraw() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(50), None).unwrap();
+	s.bank_service().deposit(&lucy_account.id, &vault.name, &usd(50), None).unwrap();
+	
+	// half the threads send bob -> lucy and the other half lucy -> bob
+	let amount = usd(10);
+	std::thread::scope(|scope| {
+		for i in 0..8 {
+			let (sender, receiver) = if i % 2 == 0 { (&bob_account, &lucy_account) } else { (&lucy_account, &bob_account) };
+			let amount = &amount;
+			let s = &s;
+			scope.spawn(move || {
+				for _ in 0..10 {
+					match s.bank_service().send_funds(&sender.id, &receiver.id, amount, None) {
+						Ok(_) => {}
+						Err(e) => assert_eq!(e, Error::new(ErrorKind::InadequateFunds)),
+					}
+				}
+			});
+		}
+	});
+	
+	let bob_account = s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id).unwrap();
+	let lucy_account = s.repos.account_repo.find_by_id(&f.conn(), &lucy_account.id).unwrap();
+	assert!(!bob_account.amount.is_negative());
+	assert!(!lucy_account.amount.is_negative());
+	assert_eq!(bob_account.amount + lucy_account.amount, BigDecimal::from(100));
+}
+
+#[test]
+fn concurrent_overdraft_sweeps_never_deadlock() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let checking = f.account_factory.checking_account(bob.id);
+	let savings = f.account_factory.checking_account(bob.id);
+	let lucys = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&checking.id, &vault.name, &usd(20), None).unwrap();
+	s.bank_service().deposit(&savings.id, &vault.name, &usd(100), None).unwrap();
+	let mut terms = overdraft_terms(&vault.name);
+	terms.linked_account_id = Some(savings.id);
+	s.bank_service().set_overdraft_protection(&checking.id, terms).unwrap();
+	
+	// half the threads overdraw checking, sweeping from savings, while the other half send savings -> checking
+	std::thread::scope(|scope| {
+		for i in 0..8 {
+			let (sender, receiver, amount) = if i % 2 == 0 { (&checking, &lucys, usd(10)) } else { (&savings, &checking, usd(5)) };
+			let s = &s;
+			scope.spawn(move || {
+				for _ in 0..10 {
+					match s.bank_service().send_funds(&sender.id, &receiver.id, &amount, None) {
+						Ok(_) => {}
+						Err(e) => assert_eq!(e, Error::new(ErrorKind::InadequateFunds)),
+					}
+				}
+			});
+		}
+	});
+	
+	let balances: Vec<BigDecimal> = [&checking, &savings, &lucys].iter()
+		.map(|a| s.repos.account_repo.find_by_id(&f.conn(), &a.id).unwrap().amount)
+		.collect();
+	assert!(balances.iter().all(|b| !b.is_negative()), "{:?}", balances);
+	assert_eq!(balances.iter().sum::<BigDecimal>(), BigDecimal::from(120));
+}
+
+/// Counts the rows written by a Service operation so tests can assert that a failed operation left nothing behind
+fn count_rows(f: &Fixture) -> (i64, i64, i64) {
+	let conn = f.conn();
+	let bank_transactions = bank_transactions::table.count().get_result(&conn).unwrap();
+	let account_transactions = account_transactions::table.count().get_result(&conn).unwrap();
+	let journal_lines = journal_lines::table.count().get_result(&conn).unwrap();
+	(bank_transactions, account_transactions, journal_lines)
+}
+
+#[test]
+fn failed_deposit_rolls_back() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	
+	// the vault is at the column's maximum so the vault increment fails after the account has been credited
+	let vault = f.insert_main_vault(99_999_999);
+	
+	let err = s.bank_service().deposit(&account.id, &vault.name, &usd(1), None).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::Database(_)));
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id).unwrap();
+	assert!(account.amount.is_zero());
+	assert_eq!(count_rows(&f), (0, 0, 0));
+}
+
+#[test]
+fn failed_loan_disbursement_rolls_back() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	
+	// crediting the principal overflows the account's balance after the transaction and journal entry are written
+	let initial_amount = BigDecimal::from(99_999_500);
+	s.repos.account_repo.increment(&f.conn(), &account.id, &initial_amount)?;
+	
+	let loan = s.repos.loan_repo.create(&f.conn(), loan::NewLoan {
+		state: LoanState::Approved,
+		..new_loan(bob.id, 1000)
+	})?;
+	
+	let err = s.bank_service().disburse_loan(&loan, &account.id).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::Database(_)));
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, initial_amount);
+	assert_eq!(count_rows(&f), (0, 0, 0));
+	
+	Ok(())
+}
+
+#[test]
+fn failed_loan_payment_rolls_back() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	// the vault is so full that crediting it the repayment overflows its amount column
+	let vault = f.insert_main_vault(99_999_950);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	
+	let issue_date = Date::from_ymd(2020, 1, 1);
+	let loan = s.repos.loan_repo.create(&f.conn(), loan::NewLoan {
+		state: LoanState::Active,
+		..new_loan(bob.id, 1000)
+	})?;
+	
+	let payment = s.repos.loan_payment_repo.create(&f.conn(), loan::NewPayment {
+		loan_id: loan.id,
+		principal_due: BigDecimal::from(100),
+		interest_due: BigDecimal::zero(),
+		due_date: issue_date.increment_date_by_months(1),
+	})?;
+	let funds = BigDecimal::from(100);
+	s.repos.account_repo.increment(&f.conn(), &account.id, &funds)?;
+	
+	let err = s.bank_service().pay_loan_payment_due(&payment.id, &account.id, None).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::Database(_)));
+	
+	let payment = s.repos.loan_payment_repo.find_by_id(&f.conn(), &payment.id)?;
+	assert!(payment.principle_transaction_id.is_none());
+	assert!(payment.interest_transaction_id.is_none());
+	assert!(payment.principal_paid.is_zero());
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, funds);
+	assert_eq!(count_rows(&f), (0, 0, 0));
+	
+	Ok(())
+}
+
+#[test]
+fn deposit_with_idempotency_key_is_applied_once() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	
+	let amount = usd(300);
+	let first = s.bank_service().deposit(&account.id, &vault.name, &amount, Some("deposit-1")).unwrap();
+	let retry = s.bank_service().deposit(&account.id, &vault.name, &amount, Some("deposit-1")).unwrap();
+	assert_eq!(first, retry);
+	assert_eq!(retry.amount, amount.amount);
+	
+	let vault = s.repos.vault_repo.find_by_name(&f.conn(), &vault.name).unwrap();
+	assert_eq!(vault.amount, amount.amount);
+	assert_eq!(count_rows(&f), (1, 0, 2));
+	
+	// reusing the key with different parameters is rejected
+	let err = s.bank_service().deposit(&account.id, &vault.name, &usd(1), Some("deposit-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	let err = s.bank_service().withdraw(&account.id, &vault.name, &amount, Some("deposit-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	let err = s.bank_service().deposit(&account.id, &vault.name, &Money::new(amount.amount.clone(), Currency::Eur), Some("deposit-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+}
+
+#[test]
+fn withdraw_retry_does_not_recheck_funds() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	s.bank_service().deposit(&account.id, &vault.name, &usd(100), None).unwrap();
+	
+	let amount = usd(100);
+	let first = s.bank_service().withdraw(&account.id, &vault.name, &amount, Some("withdraw-1")).unwrap();
+	let retry = s.bank_service().withdraw(&account.id, &vault.name, &amount, Some("withdraw-1")).unwrap();
+	assert_eq!(first, retry);
+	
+	// the retry returns the original withdrawal rather than the account's current state
+	s.bank_service().deposit(&account.id, &vault.name, &usd(40), None).unwrap();
+	let retry = s.bank_service().withdraw(&account.id, &vault.name, &amount, Some("withdraw-1")).unwrap();
+	assert_eq!(retry, first);
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id).unwrap();
+	assert_eq!(account.amount, BigDecimal::from(40));
+	
+	let vault = s.repos.vault_repo.find_by_name(&f.conn(), &vault.name).unwrap();
+	assert_eq!(vault.amount, BigDecimal::from(40));
+}
+
+#[test]
+fn send_funds_with_idempotency_key_is_applied_once() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(500), Some("deposit-1")).unwrap();
+	
+	let amount = usd(200);
+	let first = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &amount, Some("transfer-1")).unwrap();
+	let retry = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &amount, Some("transfer-1")).unwrap();
+	assert_eq!(first, retry);
+	
+	let lucy_account = s.repos.account_repo.find_by_id(&f.conn(), &lucy_account.id).unwrap();
+	assert_eq!(lucy_account.amount, amount.amount);
+	
+	let err = s.bank_service().send_funds(&lucy_account.id, &bob_account.id, &amount, Some("transfer-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	let euros = Money::new(amount.amount.clone(), Currency::Eur);
+	let err = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &euros, Some("transfer-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	
+	// keys are unique across deposits, withdrawals and transfers
+	let err = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &amount, Some("deposit-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+}
+
+#[test]
+fn pay_loan_payment_due_with_idempotency_key_is_applied_once() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	
+	let issue_date = Date::from_ymd(2020, 1, 1);
+	let loan = s.repos.loan_repo.create(&f.conn(), loan::NewLoan {
+		state: LoanState::Approved,
+		..new_loan(bob.id, 1000)
+	})?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	
+	let new_payment = |due_date| loan::NewPayment {
+		loan_id: loan.id,
+		principal_due: BigDecimal::from(100),
+		interest_due: BigDecimal::zero(),
+		due_date,
+	};
+	let payment = s.repos.loan_payment_repo.create(&f.conn(), new_payment(issue_date.increment_date_by_months(1)))?;
+	let other_payment = s.repos.loan_payment_repo.create(&f.conn(), new_payment(issue_date.increment_date_by_months(2)))?;
+	
+	let first = s.bank_service().pay_loan_payment_due(&payment.id, &account.id, Some("payment-1"))?;
+	let retry = s.bank_service().pay_loan_payment_due(&payment.id, &account.id, Some("payment-1"))?;
+	assert_eq!(first.principle_transaction_id, retry.principle_transaction_id);
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, BigDecimal::from(900));
+	
+	let err = s.bank_service().pay_loan_payment_due(&other_payment.id, &account.id, Some("payment-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	
+	Ok(())
+}
+
+fn sum_principal_due(payments: &[loan::LoanPayment]) -> BigDecimal {
+	payments.iter().fold(BigDecimal::zero(), |sum, p| sum + &p.principal_due)
+}
+
+#[test]
+fn loan_schedule_created_on_disbursement() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	
+	let issue_date = Date::from_ymd(2020, 1, 15);
+	let loan = s.repos.loan_repo.create(&f.conn(), loan::NewLoan {
+		interest_rate: 600,
+		issue_date,
+		maturity_date: issue_date.increment_date_by_months(24),
+		payment_frequency: 3,
+		state: LoanState::Approved,
+		amortization_method: AmortizationMethod::LevelPrincipal,
+		..new_loan(bob.id, 12_000)
+	})?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	
+	let schedule = s.bank_service().get_loan_schedule(&loan)?;
+	assert_eq!(schedule.len(), 8);
+	assert_eq!(schedule[0].due_date, Date::from_ymd(2020, 4, 15));
+	assert_eq!(schedule[7].due_date, loan.maturity_date);
+	assert_eq!(schedule[0].principal_due, BigDecimal::from(1_500));
+	assert_eq!(schedule[0].interest_due, BigDecimal::from(180));
+	assert_eq!(sum_principal_due(&schedule), loan.orig_principal);
+	
+	let next_payment = s.bank_service().get_next_loan_payment(&loan)?;
+	assert_eq!(next_payment.id, schedule[0].id);
+	
+	Ok(())
+}
+
+#[test]
+fn regenerate_loan_schedule_after_prepayment() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	
+	let issue_date = Date::from_ymd(2020, 1, 1);
+	s.mock_calendar.set_curr_date(issue_date);
+	let loan = s.repos.loan_repo.create(&f.conn(), loan::NewLoan {
+		state: LoanState::Approved,
+		..new_loan(bob.id, 1_200)
+	})?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	
+	let loan = s.bank_service().accrue(&loan)?;
+	let first_payment = s.bank_service().get_next_loan_payment(&loan)?;
+	s.bank_service().pay_loan_payment_due(&first_payment.id, &account.id, None)?;
+	let level_payment = &first_payment.principal_due + &first_payment.interest_due;
+	
+	// prepay part of the principal
+	let loan = s.repos.loan_repo.decrement(&f.conn(), &loan.id, &BigDecimal::from(500))?;
+	
+	let schedule = s.bank_service().regenerate_loan_schedule(&loan)?;
+	assert_eq!(schedule.len(), 12);
+	assert_eq!(schedule[0].id, first_payment.id);
+	assert!(schedule[0].principle_transaction_id.is_some());
+	
+	let remaining = &schedule[1..];
+	assert!(remaining.iter().all(|p| p.principle_transaction_id.is_none()));
+	assert_eq!(remaining[0].due_date, Date::from_ymd(2020, 3, 1));
+	assert_eq!(sum_principal_due(remaining), loan.balance);
+	assert!(&remaining[0].principal_due + &remaining[0].interest_due < level_payment);
+	
+	Ok(())
+}
+
+fn loan_application(user_id: uuid::Uuid, vault_name: String) -> loan::LoanApplication {
+	let issue_date = Date::from_ymd(2020, 1, 1);
+	loan::LoanApplication {
+		user_id,
+		vault_name,
+		principal: BigDecimal::from(1_000),
+		interest_rate: 200,
+		issue_date,
+		maturity_date: issue_date.increment_date_by_months(12),
+		payment_frequency: 1,
+		compound_frequency: 1,
+		amortization_method: AmortizationMethod::LevelPayment,
+		day_count_convention: Default::default(),
+		variable_rate: None,
+	}
+}
+
+#[test]
+fn loan_approval_workflow() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let lucy = f.user_factory.lucy();
+	let bob_account = f.account_factory.checking_account(bob.id);
+	let lucy_account = f.account_factory.checking_account(lucy.id);
+	
+	let loan = s.bank_service().apply_for_loan(loan_application(bob.id, vault.name))?;
+	assert_eq!(loan.state, LoanState::PendingApproval);
+	assert_eq!(loan.balance, loan.orig_principal);
+	
+	// loans can't be disbursed before they're approved
+	let err = s.bank_service().disburse_loan(&loan, &bob_account.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::PendingApproval, to: LoanState::Active }));
+	
+	// borrowers can't approve their own loans
+	let err = s.bank_service().approve_loan(&loan.id, &bob.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::SelfApproval));
+	
+	let loan = s.bank_service().approve_loan(&loan.id, &lucy.id)?;
+	assert_eq!(loan.state, LoanState::Approved);
+	assert_eq!(loan.approved_by, Some(lucy.id));
+	assert!(loan.approved_at.is_some());
+	assert_eq!(s.repos.loan_payment_repo.find_by_loan(&f.conn(), &loan.id)?.len(), 12);
+	
+	let err = s.bank_service().approve_loan(&loan.id, &lucy.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::Approved, to: LoanState::Approved }));
+	
+	// funds only go to the borrower's own accounts
+	let err = s.bank_service().disburse_loan(&loan, &lucy_account.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::AccountNotOwnedByBorrower));
+	
+	s.bank_service().disburse_loan(&loan, &bob_account.id)?;
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.state, LoanState::Active);
+	let bob_account = s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?;
+	assert_eq!(bob_account.amount, loan.orig_principal);
+	
+	// a loan is only disbursed once
+	let err = s.bank_service().disburse_loan(&loan, &bob_account.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::Active, to: LoanState::Active }));
+	
+	Ok(())
+}
+
+#[test]
+fn reject_and_cancel_loans() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let lucy = f.user_factory.lucy();
+	let bob_account = f.account_factory.checking_account(bob.id);
+	
+	let rejected = s.bank_service().apply_for_loan(loan_application(bob.id, vault.name.clone()))?;
+	let rejected = s.bank_service().reject_loan(&rejected.id)?;
+	assert_eq!(rejected.state, LoanState::Rejected);
+	let err = s.bank_service().approve_loan(&rejected.id, &lucy.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::Rejected, to: LoanState::Approved }));
+	
+	let cancelled = s.bank_service().apply_for_loan(loan_application(bob.id, vault.name.clone()))?;
+	s.bank_service().approve_loan(&cancelled.id, &lucy.id)?;
+	let cancelled = s.bank_service().cancel_loan(&cancelled.id)?;
+	assert_eq!(cancelled.state, LoanState::Cancelled);
+	assert!(s.repos.loan_payment_repo.find_by_loan(&f.conn(), &cancelled.id)?.is_empty());
+	let err = s.bank_service().disburse_loan(&cancelled, &bob_account.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::Cancelled, to: LoanState::Active }));
+	
+	// disbursed loans can't be cancelled
+	let active = s.bank_service().apply_for_loan(loan_application(bob.id, vault.name))?;
+	s.bank_service().approve_loan(&active.id, &lucy.id)?;
+	s.bank_service().disburse_loan(&active, &bob_account.id)?;
+	let err = s.bank_service().cancel_loan(&active.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::Active, to: LoanState::Cancelled }));
+	
+	Ok(())
+}
+
+#[test]
+fn apply_for_loan_validates_terms() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	
+	let mut application = loan_application(bob.id, vault.name.clone());
+	application.principal = BigDecimal::zero();
+	let err = s.bank_service().apply_for_loan(application).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ZeroAmount));
+	
+	let mut application = loan_application(bob.id, vault.name.clone());
+	application.principal = dec("-1000");
+	let err = s.bank_service().apply_for_loan(application).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidStateNegativeValue));
+	
+	let mut application = loan_application(bob.id, vault.name.clone());
+	application.principal = dec("1000.005");
+	let err = s.bank_service().apply_for_loan(application).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ExcessPrecision(Currency::Usd)));
+	
+	let mut application = loan_application(bob.id, vault.name);
+	application.maturity_date = application.issue_date;
+	let err = s.bank_service().apply_for_loan(application).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::InvalidDate(_)));
+}
+
+#[test]
+fn delinquent_loans_are_charged_late_fees_and_defaulted() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	let policy = DelinquencyPolicy::default();
+	
+	let mut application = loan_application(bob.id, vault.name.clone());
+	application.principal = BigDecimal::from(1_200);
+	application.interest_rate = 0;
+	let loan = s.bank_service().apply_for_loan(application)?;
+	let loan = s.bank_service().approve_loan(&loan.id, &f.user_factory.lucy().id)?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	let schedule = s.repos.loan_payment_repo.find_by_loan(&f.conn(), &loan.id)?;
+	
+	// nothing is due yet
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 1));
+	assert!(s.bank_service().assess_delinquency(&policy)?.is_empty());
+	
+	// within the grace period
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 10));
+	let delinquent = s.bank_service().assess_delinquency(&policy)?;
+	assert_eq!(delinquent.len(), 1);
+	assert_eq!(delinquent[0].days_past_due, 9);
+	assert_eq!(delinquent[0].delinquency_bucket, DelinquencyBucket::Days1To29);
+	assert!(s.repos.loan_payment_repo.find_by_id(&f.conn(), &schedule[0].id)?.late_fee.is_zero());
+	
+	// past the grace period the late fee is charged once
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 20));
+	s.bank_service().assess_delinquency(&policy)?;
+	s.bank_service().assess_delinquency(&policy)?;
+	assert_eq!(s.repos.loan_payment_repo.find_by_id(&f.conn(), &schedule[0].id)?.late_fee, policy.late_fee);
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 5, 5));
+	let delinquent = s.bank_service().assess_delinquency(&policy)?;
+	assert_eq!(delinquent[0].days_past_due, 94);
+	assert_eq!(delinquent[0].delinquency_bucket, DelinquencyBucket::Days90Plus);
+	assert_eq!(delinquent[0].state, LoanState::Default);
+	assert_eq!(s.bank_service().find_delinquent_loans()?.len(), 1);
+	
+	let late_fees: Vec<BigDecimal> = s.repos.loan_payment_repo.find_by_loan(&f.conn(), &loan.id)?
+		.into_iter()
+		.take(4)
+		.map(|p| p.late_fee)
+		.collect();
+	assert_eq!(late_fees, vec![policy.late_fee.clone(), policy.late_fee.clone(), policy.late_fee.clone(), BigDecimal::zero()]);
+	
+	// the late fee is collected with the payment
+	let paid = s.bank_service().pay_loan_payment_due(&schedule[0].id, &account.id, None)?;
+	assert!(paid.fee_transaction_id.is_some());
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, BigDecimal::from(1_200 - 100 - 25));
+	let fee_income = s.repos.ledger_repo.balance(&f.conn(), &LedgerAccount::FeeIncome(vault.name.clone()))?;
+	assert_eq!(fee_income, policy.late_fee);
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	// catching up moves the loan to a lower bucket, but it stays in default
+	let delinquent = s.bank_service().assess_delinquency(&policy)?;
+	assert_eq!(delinquent[0].days_past_due, 65);
+	assert_eq!(delinquent[0].delinquency_bucket, DelinquencyBucket::Days60To89);
+	assert_eq!(delinquent[0].state, LoanState::Default);
+	
+	Ok(())
+}
+
+/// Creates and disburses an approved loan of 1,200 issued on 2020-01-01
+fn disbursed_loan(s: &Suite, f: &Fixture, vault_name: &str, interest_rate: i16, payment_frequency: i16, compound_frequency: i16) -> Result<loan::Loan> {
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	let mut application = loan_application(bob.id, vault_name.to_string());
+	application.principal = BigDecimal::from(1_200);
+	application.interest_rate = interest_rate;
+	application.payment_frequency = payment_frequency;
+	application.compound_frequency = compound_frequency;
+	
+	let loan = s.bank_service().apply_for_loan(application)?;
+	let loan = s.bank_service().approve_loan(&loan.id, &f.user_factory.lucy().id)?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	s.repos.loan_repo.find_by_id(&f.conn(), &loan.id).map_err(Into::into)
+}
+
+#[test]
+fn accrue_adds_to_accrued_interest() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let loan = disbursed_loan(&s, &f, &vault.name, 200, 1, 12)?;
+	
+	s.bank_service().accrue(&loan)?;
+	let loan = s.bank_service().accrue(&loan)?;
+	assert_eq!(loan.accrued_interest, BigDecimal::from(4));
+	assert_eq!(loan.interest_accrued_through, Some(Date::from_ymd(2020, 3, 1)));
+	
+	Ok(())
+}
+
+#[test]
+fn accrue_counts_actual_days() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	let mut application = loan_application(bob.id, vault.name.clone());
+	application.principal = BigDecimal::from(1_200);
+	application.interest_rate = 1_000;
+	application.compound_frequency = 12;
+	application.day_count_convention = DayCountConvention::Actual365;
+	
+	let loan = s.bank_service().apply_for_loan(application)?;
+	let loan = s.bank_service().approve_loan(&loan.id, &f.user_factory.lucy().id)?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.day_count_convention, DayCountConvention::Actual365);
+	
+	// 31 days in January and 29 in February 2020
+	let loan = s.bank_service().accrue(&loan)?;
+	assert_eq!(loan.accrued_interest, dec("10.1918"));
+	let loan = s.bank_service().accrue(&loan)?;
+	assert_eq!(loan.accrued_interest, dec("19.7260"));
+	
+	// the schedule counts the same days
+	let schedule = s.bank_service().get_loan_schedule(&loan)?;
+	assert_eq!(schedule[0].interest_due, dec("10.19"));
+	assert_eq!(schedule[1].interest_due, dec("8.78"));
+	
+	Ok(())
+}
+
+#[test]
+fn end_of_day_accrual_is_restartable() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	// annual compounding keeps the interest simple over the months covered here
+	let monthly = disbursed_loan(&s, &f, &vault.name, 200, 1, 12)?;
+	let quarterly = disbursed_loan(&s, &f, &vault.name, 200, 3, 12)?;
+	let pending = s.bank_service().apply_for_loan(loan_application(f.user_factory.bob().id, vault.name.clone()))?;
+	
+	// the monthly loan accrues for the periods ending Feb 1 and Mar 1
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 3, 15));
+	let run = s.bank_service().run_end_of_day_accrual()?;
+	assert!(run.is_complete());
+	assert_eq!(run.run_date, Date::from_ymd(2020, 3, 15));
+	assert_eq!(run.loans_accrued, 1);
+	assert_eq!(run.interest_accrued, BigDecimal::from(4));
+	
+	// running again for the same date doesn't accrue twice
+	let rerun = s.bank_service().run_end_of_day_accrual()?;
+	assert_eq!(rerun.id, run.id);
+	let monthly = s.repos.loan_repo.find_by_id(&f.conn(), &monthly.id)?;
+	assert_eq!(monthly.accrued_interest, BigDecimal::from(4));
+	let quarterly = s.repos.loan_repo.find_by_id(&f.conn(), &quarterly.id)?;
+	assert!(quarterly.accrued_interest.is_zero());
+	
+	// simulate a run that crashed after accruing the monthly loan
+	let next_date = Date::from_ymd(2020, 4, 2);
+	let crashed = s.repos.accrual_run_repo.start(&f.conn(), &next_date)?;
+	s.repos.loan_repo.accrue_interest(&f.conn(), &monthly.id, &monthly.periodic_interest(), &monthly.next_accrual_date())?;
+	s.repos.accrual_run_repo.record_loan(&f.conn(), &crashed.id, &monthly.periodic_interest())?;
+	
+	s.mock_calendar.set_curr_date(next_date);
+	let run = s.bank_service().run_end_of_day_accrual()?;
+	assert_eq!(run.id, crashed.id);
+	assert!(run.is_complete());
+	assert_eq!(run.loans_accrued, 2);
+	assert_eq!(run.interest_accrued, BigDecimal::from(8));
+	
+	let monthly = s.repos.loan_repo.find_by_id(&f.conn(), &monthly.id)?;
+	assert_eq!(monthly.accrued_interest, BigDecimal::from(6));
+	let quarterly = s.repos.loan_repo.find_by_id(&f.conn(), &quarterly.id)?;
+	assert_eq!(quarterly.accrued_interest, BigDecimal::from(6));
+	assert_eq!(quarterly.interest_accrued_through, Some(Date::from_ymd(2020, 4, 1)));
+	let pending = s.repos.loan_repo.find_by_id(&f.conn(), &pending.id)?;
+	assert!(pending.interest_accrued_through.is_none());
+	
+	let runs = s.bank_service().find_recent_accrual_runs(10)?;
+	assert_eq!(runs.iter().map(|r| r.run_date).collect::<Vec<_>>(), vec![next_date, Date::from_ymd(2020, 3, 15)]);
+	
+	Ok(())
+}
+
+fn dec(s: &str) -> BigDecimal {
+	s.parse().unwrap()
+}
+
+fn usd(amount: i64) -> Money {
+	Money::new(BigDecimal::from(amount), Currency::Usd)
+}
+
+fn accrue_periods(s: &Suite, loan: &loan::Loan, periods: usize) -> Result<loan::Loan> {
+	let mut loan = s.bank_service().accrue(loan)?;
+	for _ in 1..periods {
+		loan = s.bank_service().accrue(&loan)?;
+	}
+	Ok(loan)
+}
+
+#[test]
+fn monthly_compounding_with_monthly_payments() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	
+	// 12% a year is 1% a month
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 1, 1)?;
+	assert_eq!(loan.accrual_frequency(), 1);
+	
+	// interest left unpaid on Feb 1 and Mar 1 is capitalized and earns interest
+	let loan = accrue_periods(&s, &loan, 3)?;
+	assert_eq!(loan.interest_accrued_through, Some(Date::from_ymd(2020, 4, 1)));
+	assert_eq!(loan.capitalized_interest, dec("24.12"));
+	assert_eq!(loan.balance, dec("1224.12"));
+	assert_eq!(loan.accrued_interest, dec("12.2412"));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+#[test]
+fn quarterly_compounding_with_monthly_payments() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 1, 3)?;
+	assert_eq!(loan.accrual_frequency(), 1);
+	
+	// interest accrues simply until it is capitalized after Apr 1
+	let loan = accrue_periods(&s, &loan, 3)?;
+	assert_eq!(loan.accrued_interest, BigDecimal::from(36));
+	assert!(loan.capitalized_interest.is_zero());
+	
+	let loan = s.bank_service().accrue(&loan)?;
+	assert_eq!(loan.capitalized_interest, BigDecimal::from(36));
+	assert_eq!(loan.balance, BigDecimal::from(1236));
+	assert_eq!(loan.accrued_interest, dec("12.36"));
+	
+	Ok(())
+}
+
+#[test]
+fn annual_compounding_with_quarterly_payments() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 3, 12)?;
+	assert_eq!(loan.accrual_frequency(), 3);
+	
+	let loan = accrue_periods(&s, &loan, 4)?;
+	assert_eq!(loan.interest_accrued_through, Some(Date::from_ymd(2021, 1, 1)));
+	assert_eq!(loan.accrued_interest, BigDecimal::from(144));
+	assert!(loan.capitalized_interest.is_zero());
+	
+	let loan = s.bank_service().accrue(&loan)?;
+	assert_eq!(loan.balance, BigDecimal::from(1344));
+	assert_eq!(loan.capitalized_interest, BigDecimal::from(144));
+	assert_eq!(loan.accrued_interest, dec("40.32"));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+#[test]
+fn monthly_compounding_with_quarterly_payments_in_end_of_day_accrual() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	
+	// interest accrues monthly so it can compound between payments
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 3, 1)?;
+	assert_eq!(loan.accrual_frequency(), 1);
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 4, 2));
+	let run = s.bank_service().run_end_of_day_accrual()?;
+	assert_eq!(run.loans_accrued, 1);
+	
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.interest_accrued_through, Some(Date::from_ymd(2020, 4, 1)));
+	assert_eq!(loan.balance, dec("1224.12"));
+	assert_eq!(loan.accrued_interest, dec("12.2412"));
+	
+	Ok(())
+}
+
+#[test]
+fn interest_paid_before_compounding_is_not_capitalized() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 1, 1)?;
+	let account = s.repos.account_repo.find_accounts(&f.conn(), &loan.user_id)?.remove(0);
+	
+	let loan = s.bank_service().accrue(&loan)?;
+	let payment = s.bank_service().get_next_loan_payment(&loan)?;
+	assert_eq!(payment.interest_due, BigDecimal::from(12));
+	s.bank_service().pay_loan_payment_due(&payment.id, &account.id, None)?;
+	
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	let loan = s.bank_service().accrue(&loan)?;
+	assert!(loan.capitalized_interest.is_zero());
+	assert_eq!(loan.accrued_interest, &loan.balance / BigDecimal::from(100));
+	
+	Ok(())
+}
+
+#[test]
+fn simple_interest_without_compounding() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 1, 0)?;
+	let loan = accrue_periods(&s, &loan, 3)?;
+	assert_eq!(loan.accrued_interest, BigDecimal::from(36));
+	assert_eq!(loan.balance, BigDecimal::from(1200));
+	assert!(loan.capitalized_interest.is_zero());
+	
+	Ok(())
+}
+
+#[test]
+fn loan_payoff() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 1, 12)?;
+	let account = s.repos.account_repo.find_accounts(&f.conn(), &loan.user_id)?.remove(0);
+	
+	// the Feb 1 payment is past its grace period and charged a late fee
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 17));
+	let loan = s.bank_service().accrue(&loan)?;
+	s.bank_service().assess_delinquency(&DelinquencyPolicy::default())?;
+	
+	// 16 days of per diem interest after Feb 1 counted with 30/360
+	let quote = s.bank_service().quote_loan_payoff(&loan.id, Date::from_ymd(2020, 2, 17))?;
+	assert_eq!(quote.balance, BigDecimal::from(1200));
+	assert_eq!(quote.accrued_interest, BigDecimal::from(12));
+	assert_eq!(quote.per_diem_interest, dec("6.4"));
+	assert_eq!(quote.fees, BigDecimal::from(25));
+	assert_eq!(quote.total, dec("1243.4"));
+	
+	let err = s.bank_service().quote_loan_payoff(&loan.id, Date::from_ymd(2020, 1, 15)).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::InvalidDate(_)));
+	
+	// the account only holds the disbursed principal
+	let err = s.bank_service().pay_off_loan(&loan.id, &account.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	
+	s.bank_service().deposit(&account.id, &vault.name, &usd(100), None)?;
+	let loan = s.bank_service().pay_off_loan(&loan.id, &account.id, Some("payoff-1"))?;
+	assert_eq!(loan.state, LoanState::Paid);
+	assert!(loan.balance.is_zero());
+	assert!(loan.accrued_interest.is_zero());
+	
+	// a retry returns the paid off loan without collecting the payoff again
+	let retried = s.bank_service().pay_off_loan(&loan.id, &account.id, Some("payoff-1"))?;
+	assert_eq!(retried.id, loan.id);
+	assert_eq!(retried.state, LoanState::Paid);
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, dec("56.6"));
+	
+	let payments = s.repos.loan_payment_repo.find_by_loan(&f.conn(), &loan.id)?;
+	assert_eq!(payments.len(), 12);
+	assert!(payments.iter().all(|p| p.principle_transaction_id.is_some() && p.interest_transaction_id.is_some()));
+	assert!(payments[0].fee_transaction_id.is_some());
+	assert!(payments[1].fee_transaction_id.is_none());
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	// a paid off loan can't be paid off again
+	let err = s.bank_service().pay_off_loan(&loan.id, &account.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLoanStateTransition { from: LoanState::Paid, to: LoanState::Paid }));
+	
+	Ok(())
+}
+
+#[test]
+fn partial_payments_and_prepayment() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let policy = AllocationPolicy::default();
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 1, 12)?;
+	let account = s.repos.account_repo.find_accounts(&f.conn(), &loan.user_id)?.remove(0);
+	
+	// the Feb 1 payment is past its grace period and charged a late fee
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 17));
+	let loan = s.bank_service().accrue(&loan)?;
+	s.bank_service().assess_delinquency(&DelinquencyPolicy::default())?;
+	
+	let err = s.bank_service().pay_loan(&loan.id, &account.id, &usd(0), &policy, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ZeroAmount));
+	let err = s.bank_service().pay_loan(&loan.id, &account.id, &usd(-30), &policy, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidStateNegativeValue));
+	let err = s.bank_service().pay_loan(&loan.id, &account.id, &Money::new(dec("30.001"), Currency::Usd), &policy, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ExcessPrecision(Currency::Usd)));
+	
+	// an underpayment covers the fee and part of the past due interest
+	let allocation = s.bank_service().pay_loan(&loan.id, &account.id, &usd(30), &policy, None)?;
+	assert_eq!(allocation.fees, BigDecimal::from(25));
+	assert_eq!(allocation.past_due_interest, BigDecimal::from(5));
+	assert!(allocation.principal.is_zero());
+	
+	let schedule = s.bank_service().get_loan_schedule(&loan)?;
+	assert_eq!(schedule[0].principal_due, dec("94.62"));
+	assert_eq!(schedule[0].underpaid(), dec("101.62"));
+	assert!(schedule[0].principle_transaction_id.is_none());
+	assert!(schedule[0].fee_transaction_id.is_some());
+	
+	// an overpayment pays the past due payment, the next payment's principal and prepays the rest
+	let allocation = s.bank_service().pay_loan(&loan.id, &account.id, &usd(300), &policy, Some("prepay-1"))?;
+	assert_eq!(allocation.past_due_interest, BigDecimal::from(7));
+	assert_eq!(allocation.principal, dec("190.19"));
+	assert_eq!(allocation.prepayment, dec("102.81"));
+	
+	// a retry returns the original allocation without paying again
+	let retried = s.bank_service().pay_loan(&loan.id, &account.id, &usd(300), &policy, Some("prepay-1"))?;
+	assert_eq!(retried, allocation);
+	let err = s.bank_service().pay_loan(&loan.id, &account.id, &usd(200), &policy, Some("prepay-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	let err = s.bank_service().deposit(&account.id, &vault.name, &usd(300), Some("prepay-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	
+	let schedule = s.bank_service().get_loan_schedule(&loan)?;
+	assert!(schedule[0].principle_transaction_id.is_some());
+	assert!(schedule[0].underpaid().is_zero());
+	assert_eq!(schedule[1].principal_paid, dec("95.57"));
+	assert_eq!(schedule[1].overpaid, dec("102.81"));
+	assert!(schedule[1].principle_transaction_id.is_none());
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.balance, BigDecimal::from(907));
+	assert!(loan.accrued_interest.is_zero());
+	
+	// payments are rejected when the account can't cover what's allocated
+	let err = s.bank_service().pay_loan(&loan.id, &account.id, &usd(1_000), &policy, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	let empty_account = f.account_factory.checking_account(loan.user_id);
+	let err = s.bank_service().pay_loan_payment_due(&schedule[2].id, &empty_account.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	
+	// only what's owed is collected
+	s.bank_service().deposit(&account.id, &vault.name, &usd(100), None)?;
+	let allocation = s.bank_service().pay_loan(&loan.id, &account.id, &usd(2_000), &policy, None)?;
+	assert_eq!(allocation.total(), BigDecimal::from(907));
+	
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.state, LoanState::Paid);
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, BigDecimal::from(1_200 + 100 - 30 - 300 - 907));
+	assert!(s.repos.loan_payment_repo.find_unpaid(&f.conn(), &loan.id)?.is_empty());
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+#[test]
+fn scheduled_payments_after_a_prepayment_collect_only_the_balance() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let loan = disbursed_loan(&s, &f, &vault.name, 1200, 1, 12)?;
+	let account = s.repos.account_repo.find_accounts(&f.conn(), &loan.user_id)?.remove(0);
+	s.bank_service().deposit(&account.id, &vault.name, &usd(100), None)?;
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 10));
+	s.bank_service().pay_loan(&loan.id, &account.id, &usd(1_000), &AllocationPolicy::default(), None)?;
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.balance, BigDecimal::from(200));
+	
+	// the scheduled dues still expect the full principal, but only the balance that's left is collected
+	let mut payments = 0;
+	loop {
+		let unpaid = s.repos.loan_payment_repo.find_unpaid(&f.conn(), &loan.id)?;
+		if unpaid.is_empty() {
+			break;
+		}
+		s.bank_service().pay_loan_payment_due(&unpaid[0].id, &account.id, None)?;
+		payments += 1;
+	}
+	assert_eq!(payments, 3);
+	
+	let loan = s.repos.loan_repo.find_by_id(&f.conn(), &loan.id)?;
+	assert_eq!(loan.state, LoanState::Paid);
+	assert!(loan.balance.is_zero());
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, BigDecimal::from(1_200 + 100 - 1_000 - 200));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+fn publish_prime_rate(s: &Suite, effective_date: Date, rate: i16) -> Result<()> {
+	s.bank_service().publish_benchmark_rate(NewBenchmarkRate {
+		benchmark: "prime".to_string(),
+		effective_date,
+		rate,
+	})?;
+	Ok(())
+}
+
+fn variable_rate_application(user_id: uuid::Uuid, vault_name: String) -> loan::LoanApplication {
+	let mut application = loan_application(user_id, vault_name);
+	application.principal = BigDecimal::from(1_200);
+	application.compound_frequency = 12;
+	application.variable_rate = Some(loan::VariableRate {
+		benchmark: "prime".to_string(),
+		margin: 200,
+		floor: Some(300),
+		cap: Some(900),
+		reset_frequency: 3,
+	});
+	application
+}
+
+#[test]
+fn variable_rate_loan_resets_from_benchmark() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	publish_prime_rate(&s, Date::from_ymd(2019, 12, 1), 400)?;
+	publish_prime_rate(&s, Date::from_ymd(2020, 3, 15), 600)?;
+	
+	let loan = s.bank_service().apply_for_loan(variable_rate_application(bob.id, vault.name.clone()))?;
+	assert_eq!(loan.interest_rate_basis_points(), 600);
+	assert_eq!(loan.benchmark, Some("prime".to_string()));
+	let loan = s.bank_service().approve_loan(&loan.id, &f.user_factory.lucy().id)?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	
+	// the schedule uses the benchmark's latest rate from the Apr 1 reset on
+	let schedule = s.bank_service().get_loan_schedule(&loan)?;
+	assert_eq!(schedule[0].interest_due, dec("6.00"));
+	// the fraction of a cent left over from rounding the second period's interest is carried into the third
+	assert_eq!(schedule[2].interest_due, dec("5.03"));
+	assert!(schedule[3].interest_due > dec("5.03"));
+	
+	// the rate resets to 8% on Apr 1 but not on Feb 1 or Mar 1
+	let loan = accrue_periods(&s, &loan, 4)?;
+	assert_eq!(loan.interest_rate_basis_points(), 800);
+	
+	let history = s.bank_service().get_loan_rate_history(&loan.id)?;
+	assert_eq!(history.len(), 2);
+	assert_eq!((history[0].effective_date, history[0].interest_rate, history[0].benchmark_rate), (Date::from_ymd(2020, 1, 1), 600, Some(400)));
+	assert_eq!((history[1].effective_date, history[1].interest_rate, history[1].benchmark_rate), (Date::from_ymd(2020, 4, 1), 800, Some(600)));
+	
+	Ok(())
+}
+
+#[test]
+fn variable_rate_is_held_within_floor_and_cap() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let account = f.account_factory.checking_account(bob.id);
+	publish_prime_rate(&s, Date::from_ymd(2020, 1, 1), 0)?;
+	publish_prime_rate(&s, Date::from_ymd(2020, 4, 1), 1_500)?;
+	
+	let loan = s.bank_service().apply_for_loan(variable_rate_application(bob.id, vault.name.clone()))?;
+	assert_eq!(loan.interest_rate_basis_points(), 300);
+	let loan = s.bank_service().approve_loan(&loan.id, &f.user_factory.lucy().id)?;
+	s.bank_service().disburse_loan(&loan, &account.id)?;
+	
+	let loan = accrue_periods(&s, &loan, 4)?;
+	assert_eq!(loan.interest_rate_basis_points(), 900);
+	
+	Ok(())
+}
+
+#[test]
+fn variable_rate_loan_requires_benchmark_rate() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	publish_prime_rate(&s, Date::from_ymd(2020, 2, 1), 400).unwrap();
+	
+	let err = s.bank_service().apply_for_loan(variable_rate_application(bob.id, vault.name)).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::MissingBenchmarkRate("prime".to_string(), Date::from_ymd(2020, 1, 1))));
+}
+
+#[test]
+fn savings_interest_accrues_daily_and_is_credited_monthly() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	s.bank_service().create_savings_product(NewSavingsProduct {
+		name: "high_yield".to_string(),
+		vault_name: vault.name.clone(),
+		apy: 1268,
+	})?;
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 15));
+	let account = s.bank_service().open_savings_account(&bob.id, "high_yield")?;
+	assert_eq!(account.account_type, AccountType::Savings);
+	assert_eq!(account.interest_accrued_through, Some(Date::from_ymd(2020, 1, 15)));
+	let deposit = s.bank_service().deposit(&account.id, &vault.name, &usd(10_000), None)?;
+	backdate(&f, &deposit, Date::from_ymd(2020, 1, 15))?;
+	let checking = f.account_factory.checking_account(bob.id);
+	s.bank_service().deposit(&checking.id, &vault.name, &usd(10_000), None)?;
+	
+	// 17 days in January accrue but aren't credited until the month has ended
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 1));
+	let run = s.bank_service().run_end_of_day_accrual()?;
+	assert_eq!(run.accounts_accrued, 1);
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.accrued_interest, dec("54.8284"));
+	assert_eq!(account.amount, BigDecimal::from(10_000));
+	
+	// the whole cents are credited from the vault before Feb 1 accrues
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 2));
+	s.bank_service().run_end_of_day_accrual()?;
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, dec("10054.82"));
+	assert_eq!(account.accrued_interest, dec("3.4749"));
+	assert_eq!(account.interest_accrued_through, Some(Date::from_ymd(2020, 2, 2)));
+	
+	// running again for the same date doesn't accrue twice
+	s.bank_service().run_end_of_day_accrual()?;
+	let rerun = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(rerun, account);
+	
+	let checking = s.repos.account_repo.find_by_id(&f.conn(), &checking.id)?;
+	assert!(checking.accrued_interest.is_zero());
+	
+	let credits = bank_transactions::table
+		.filter(bank_transactions::transaction_type.eq(BankTransactionType::InterestCredit))
+		.select(bank_transactions::amount)
+		.load::<BigDecimal>(&f.conn())?;
+	assert_eq!(credits, vec![dec("54.82")]);
+	
+	let vault = s.repos.vault_repo.find_by_name(&f.conn(), &vault.name)?;
+	assert_eq!(vault.amount, dec("19945.18"));
+	let expense = s.repos.ledger_repo.balance(&f.conn(), &LedgerAccount::InterestExpense(vault.name))?;
+	assert_eq!(expense, dec("54.82"));
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+#[test]
+fn savings_interest_stays_accrued_until_the_vault_can_pay_it() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let interest_vault = f.insert_vault("interest", 0, Currency::Usd);
+	let bob = f.user_factory.bob();
+	s.bank_service().create_savings_product(NewSavingsProduct {
+		name: "high_yield".to_string(),
+		vault_name: interest_vault.name.clone(),
+		apy: 1268,
+	})?;
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 15));
+	let account = s.bank_service().open_savings_account(&bob.id, "high_yield")?;
+	let deposit = s.bank_service().deposit(&account.id, &vault.name, &usd(10_000), None)?;
+	backdate(&f, &deposit, Date::from_ymd(2020, 1, 15))?;
+	
+	// the empty vault can't pay January's interest, so it's carried to the next month
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 2));
+	s.bank_service().run_end_of_day_accrual()?;
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, BigDecimal::from(10_000));
+	assert_eq!(account.accrued_interest, dec("58.2760"));
+	let err = s.bank_service().close_account(&account.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	
+	let checking = f.account_factory.checking_account(bob.id);
+	s.bank_service().deposit(&checking.id, &interest_vault.name, &usd(200), None)?;
+	// once it can, January's and February's interest are credited together
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 3, 2));
+	s.bank_service().run_end_of_day_accrual()?;
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	let credited = &account.amount - BigDecimal::from(10_000);
+	assert_eq!(credited, dec("154.80"));
+	assert_eq!(account.accrued_interest, dec("3.2839"));
+	let interest_vault = s.repos.vault_repo.find_by_name(&f.conn(), &interest_vault.name)?;
+	assert_eq!(interest_vault.amount, BigDecimal::from(200) - credited);
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+/// Moves the transaction to midday on the date, so it falls on the mock calendar's days
+fn backdate(f: &Fixture, transaction: &bank_transaction::BankTransaction, date: Date) -> Result<()> {
+	diesel::update(bank_transactions::table.find(transaction.id))
+		.set(bank_transactions::created_at.eq(chrono::Utc.from_utc_datetime(&date.and_hms(12, 0, 0))))
+		.execute(&f.conn())?;
+	Ok(())
+}
+
+#[test]
+fn savings_interest_accrues_on_each_days_balance() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let product = s.bank_service().create_savings_product(NewSavingsProduct {
+		name: "high_yield".to_string(),
+		vault_name: vault.name.clone(),
+		apy: 1268,
+	})?;
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 3, 2));
+	let account = s.bank_service().open_savings_account(&bob.id, "high_yield")?;
+	let deposit = s.bank_service().deposit(&account.id, &vault.name, &usd(10_000), None)?;
+	backdate(&f, &deposit, Date::from_ymd(2020, 3, 2))?;
+	let withdrawal = s.bank_service().withdraw(&account.id, &vault.name, &usd(6_000), None)?;
+	backdate(&f, &withdrawal, Date::from_ymd(2020, 3, 5))?;
+	
+	// the missed days earn interest on the balance each of them ended with, not the current balance
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 3, 9));
+	s.bank_service().run_end_of_day_accrual()?;
+	let daily = |balance: i64| product.daily_interest(&BigDecimal::from(balance), Date::from_ymd(2020, 3, 2));
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	let accrued = daily(10_000) * BigDecimal::from(3) + daily(4_000) * BigDecimal::from(4);
+	assert_eq!(account.accrued_interest, accrued);
+	
+	// a frozen account earns nothing until it's reactivated
+	s.bank_service().freeze_account(&account.id)?;
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 3, 12));
+	let run = s.bank_service().run_end_of_day_accrual()?;
+	assert_eq!(run.accounts_accrued, 0);
+	let frozen = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(frozen.accrued_interest, accrued);
+	assert_eq!(frozen.interest_accrued_through, Some(Date::from_ymd(2020, 3, 12)));
+	
+	s.bank_service().reactivate_account(&account.id)?;
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 3, 13));
+	s.bank_service().run_end_of_day_accrual()?;
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.accrued_interest, accrued + daily(4_000));
+	
+	Ok(())
+}
+
+#[test]
+fn money_only_moves_through_open_accounts() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let checking = f.account_factory.checking_account(bob.id);
+	let other = f.account_factory.checking_account(bob.id);
+	let amount = usd(100);
+	s.bank_service().deposit(&checking.id, &vault.name, &usd(500), None)?;
+	
+	let frozen = s.bank_service().freeze_account(&checking.id)?;
+	assert_eq!(frozen.status, AccountStatus::Frozen);
+	assert!(!frozen.is_open());
+	let frozen_err = Error::new(ErrorKind::AccountFrozen);
+	assert_eq!(s.bank_service().deposit(&checking.id, &vault.name, &amount, None).unwrap_err(), frozen_err);
+	assert_eq!(s.bank_service().withdraw(&checking.id, &vault.name, &amount, None).unwrap_err(), frozen_err);
+	assert_eq!(s.bank_service().send_funds(&checking.id, &other.id, &amount, None).unwrap_err(), frozen_err);
+	assert_eq!(s.bank_service().send_funds(&other.id, &checking.id, &amount, None).unwrap_err(), frozen_err);
+	
+	// a frozen account can't be made dormant or closed until it's reactivated
+	let err = s.bank_service().close_account(&checking.id, Some(&other.id)).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidAccountStatusTransition { from: AccountStatus::Frozen, to: AccountStatus::Closed }));
+	s.bank_service().reactivate_account(&checking.id)?;
+	s.bank_service().withdraw(&checking.id, &vault.name, &amount, None)?;
+	
+	s.bank_service().mark_account_dormant(&checking.id)?;
+	let err = s.bank_service().deposit(&checking.id, &vault.name, &amount, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::AccountDormant));
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &checking.id)?;
+	assert_eq!(account.amount, BigDecimal::from(400));
+	
+	Ok(())
+}
+
+#[test]
+fn close_account_requires_zero_balance_or_sweep() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let checking = f.account_factory.checking_account(bob.id);
+	let other = f.account_factory.checking_account(bob.id);
+	let empty = f.account_factory.checking_account(bob.id);
+	s.bank_service().deposit(&checking.id, &vault.name, &usd(250), None)?;
+	
+	let err = s.bank_service().close_account(&checking.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::AccountBalanceNotZero));
+	let err = s.bank_service().close_account(&checking.id, Some(&checking.id)).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::SelfTransfer));
+	
+	let closed = s.bank_service().close_account(&empty.id, None)?;
+	assert_eq!(closed.status, AccountStatus::Closed);
+	assert!(closed.closed_at.is_some());
+	
+	// the balance can't be swept into a closed account
+	let err = s.bank_service().close_account(&checking.id, Some(&empty.id)).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::AccountClosed));
+	
+	let closed = s.bank_service().close_account(&checking.id, Some(&other.id))?;
+	assert_eq!(closed.status, AccountStatus::Closed);
+	assert!(closed.amount.is_zero());
+	let other = s.repos.account_repo.find_by_id(&f.conn(), &other.id)?;
+	assert_eq!(other.amount, BigDecimal::from(250));
+	
+	let err = s.bank_service().reactivate_account(&checking.id).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidAccountStatusTransition { from: AccountStatus::Closed, to: AccountStatus::Open }));
+	let err = s.bank_service().send_funds(&other.id, &checking.id, &usd(10), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::AccountClosed));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+fn overdraft_terms(vault_name: &str) -> OverdraftTerms {
+	OverdraftTerms {
+		linked_account_id: None,
+		overdraft_limit: BigDecimal::zero(),
+		overdraft_fee: BigDecimal::zero(),
+		interest_rate: 0,
+		vault_name: vault_name.to_string(),
+	}
+}
+
+#[test]
+fn overdraft_swept_from_linked_account() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let checking = f.account_factory.checking_account(bob.id);
+	let savings = f.account_factory.checking_account(bob.id);
+	let lucys = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&checking.id, &vault.name, &usd(50), None)?;
+	s.bank_service().deposit(&savings.id, &vault.name, &usd(100), None)?;
+	
+	let err = s.bank_service().withdraw(&checking.id, &vault.name, &usd(120), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	
+	let mut terms = overdraft_terms(&vault.name);
+	terms.linked_account_id = Some(lucys.id);
+	let err = s.bank_service().set_overdraft_protection(&checking.id, terms).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidLinkedAccount));
+	let mut terms = overdraft_terms(&vault.name);
+	terms.linked_account_id = Some(savings.id);
+	s.bank_service().set_overdraft_protection(&checking.id, terms)?;
+	
+	// the 70 shortfall is swept from the linked account
+	s.bank_service().withdraw(&checking.id, &vault.name, &usd(120), None)?;
+	let checking = s.repos.account_repo.find_by_id(&f.conn(), &checking.id)?;
+	let savings = s.repos.account_repo.find_by_id(&f.conn(), &savings.id)?;
+	assert!(checking.amount.is_zero());
+	assert_eq!(savings.amount, BigDecimal::from(30));
+	
+	let withdrawal = bank_transactions::table
+		.filter(bank_transactions::transaction_type.eq(BankTransactionType::Withdraw))
+		.select(bank_transactions::overdraft_protection)
+		.first::<Option<OverdraftProtectionKind>>(&f.conn())?;
+	assert_eq!(withdrawal, Some(OverdraftProtectionKind::LinkedAccount));
+	
+	// the linked account can't cover the next shortfall and there's no overdraft line
+	let err = s.bank_service().send_funds(&checking.id, &lucys.id, &usd(40), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	let transfer = s.bank_service().send_funds(&checking.id, &lucys.id, &usd(30), None)?;
+	assert_eq!(transfer.overdraft_protection, Some(OverdraftProtectionKind::LinkedAccount));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+#[test]
+fn overdraft_line_charges_fees_and_interest() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let checking = f.account_factory.checking_account(bob.id);
+	let lucys = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&checking.id, &vault.name, &usd(50), None)?;
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 30));
+	let mut terms = overdraft_terms(&vault.name);
+	terms.overdraft_limit = BigDecimal::from(100);
+	terms.overdraft_fee = BigDecimal::from(10);
+	terms.interest_rate = 1825;
+	s.bank_service().set_overdraft_protection(&checking.id, terms)?;
+	
+	let transfer = s.bank_service().send_funds(&checking.id, &lucys.id, &usd(120), None)?;
+	assert_eq!(transfer.overdraft_protection, Some(OverdraftProtectionKind::OverdraftLine));
+	let checking = s.repos.account_repo.find_by_id(&f.conn(), &checking.id)?;
+	assert_eq!(checking.amount, BigDecimal::from(-80));
+	
+	// the fee would take the balance past the limit
+	let err = s.bank_service().withdraw(&checking.id, &vault.name, &usd(15), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	
+	// 18.25% is 0.05% a day, Jan 30 and 31 are charged on Feb 1 before it accrues
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 2));
+	s.bank_service().run_end_of_day_accrual()?;
+	let checking = s.repos.account_repo.find_by_id(&f.conn(), &checking.id)?;
+	assert_eq!(checking.amount, dec("-80.08"));
+	let protection = s.repos.overdraft_repo.find_by_account(&f.conn(), &checking.id)?;
+	assert_eq!(protection.accrued_interest, dec("0.0400"));
+	assert_eq!(protection.interest_accrued_through, Date::from_ymd(2020, 2, 2));
+	
+	let charges = bank_transactions::table
+		.filter(bank_transactions::account_id.eq(checking.id))
+		.filter(bank_transactions::overdraft_protection.eq(OverdraftProtectionKind::OverdraftLine))
+		.order(bank_transactions::created_at)
+		.select((bank_transactions::transaction_type, bank_transactions::amount))
+		.load::<(BankTransactionType, BigDecimal)>(&f.conn())?;
+	assert_eq!(charges, vec![
+		(BankTransactionType::OverdraftFee, BigDecimal::from(10)),
+		(BankTransactionType::OverdraftInterest, dec("0.08")),
+	]);
+	
+	// the fee and interest are earned by the protection's vault
+	let vault = s.repos.vault_repo.find_by_name(&f.conn(), &vault.name)?;
+	assert_eq!(vault.amount, dec("60.08"));
+	let charged_interest_at = bank_transactions::table
+		.filter(bank_transactions::transaction_type.eq(BankTransactionType::OverdraftInterest))
+		.select(bank_transactions::created_at)
+		.first::<Time>(&f.conn())?;
+	let before_interest = charged_interest_at - chrono::Duration::microseconds(1);
+	assert_eq!(s.bank_service().get_vault_balance_at(&vault.name, &before_interest)?, usd(60));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+fn mismatch(expected: Currency, found: Currency) -> Error {
+	Error::new(ErrorKind::CurrencyMismatch { expected, found })
+}
+
+#[test]
+fn money_never_moves_between_currencies() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let usd_vault = f.insert_main_vault(0);
+	let eur_vault = f.insert_vault("euro", 0, Currency::Eur);
+	let bob = f.user_factory.bob();
+	let dollars = f.account_factory.checking_account(bob.id);
+	let euros = f.account_factory.checking_account_in(bob.id, Currency::Eur);
+	
+	let err = s.bank_service().deposit(&dollars.id, &eur_vault.name, &usd(100), None).unwrap_err();
+	assert_eq!(err, mismatch(Currency::Usd, Currency::Eur));
+	let err = s.bank_service().deposit(&euros.id, &eur_vault.name, &usd(100), None).unwrap_err();
+	assert_eq!(err, mismatch(Currency::Eur, Currency::Usd));
+	s.bank_service().deposit(&euros.id, &eur_vault.name, &Money::new(BigDecimal::from(100), Currency::Eur), None)?;
+	let err = s.bank_service().withdraw(&euros.id, &usd_vault.name, &Money::new(BigDecimal::from(10), Currency::Eur), None).unwrap_err();
+	assert_eq!(err, mismatch(Currency::Eur, Currency::Usd));
+	
+	let deposit = bank_transactions::table
+		.filter(bank_transactions::account_id.eq(euros.id))
+		.select(bank_transactions::currency)
+		.first::<Currency>(&f.conn())?;
+	assert_eq!(deposit, Currency::Eur);
+	
+	let err = s.bank_service().send_funds(&euros.id, &dollars.id, &Money::new(BigDecimal::from(10), Currency::Eur), None).unwrap_err();
+	assert_eq!(err, mismatch(Currency::Eur, Currency::Usd));
+	let lucy_euros = f.account_factory.checking_account_in(f.user_factory.lucy().id, Currency::Eur);
+	let transfer = s.bank_service().send_funds(&euros.id, &lucy_euros.id, &Money::new(BigDecimal::from(10), Currency::Eur), None)?;
+	assert_eq!(transfer.currency, Currency::Eur);
+	let reversal = s.bank_service().reverse_account_transaction(&transfer.id, None)?;
+	assert_eq!(reversal.currency, Currency::Eur);
+	
+	// the loan is lent in its vault's currency
+	let loan = s.bank_service().apply_for_loan(loan_application(bob.id, eur_vault.name.clone()))?;
+	assert_eq!(loan.currency, Currency::Eur);
+	let loan = s.bank_service().approve_loan(&loan.id, &f.user_factory.lucy().id)?;
+	let err = s.bank_service().disburse_loan(&loan, &dollars.id).unwrap_err();
+	assert_eq!(err, mismatch(Currency::Eur, Currency::Usd));
+	s.bank_service().disburse_loan(&loan, &euros.id)?;
+	
+	let mut terms = overdraft_terms(&eur_vault.name);
+	terms.linked_account_id = Some(euros.id);
+	let err = s.bank_service().set_overdraft_protection(&dollars.id, terms).unwrap_err();
+	assert_eq!(err, mismatch(Currency::Usd, Currency::Eur));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+#[test]
+fn convert_funds_at_published_rate() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let usd_vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let dollars = f.account_factory.checking_account(bob.id);
+	let euros = f.account_factory.checking_account_in(bob.id, Currency::Eur);
+	let pounds = f.account_factory.checking_account_in(f.user_factory.lucy().id, Currency::Gbp);
+	s.bank_service().deposit(&dollars.id, &usd_vault.name, &usd(200), None)?;
+	
+	for (rate, effective_date) in vec![("0.9125", Date::from_ymd(2020, 7, 1)), ("0.95", Date::from_ymd(2020, 8, 1))] {
+		s.bank_service().publish_fx_rate(NewFxRate {
+			base_currency: Currency::Usd,
+			quote_currency: Currency::Eur,
+			rate: dec(rate),
+			effective_date,
+		})?;
+	}
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 7, 21));
+	
+	let conversion = s.bank_service().convert_funds(&dollars.id, &euros.id, &Money::new(BigDecimal::from(100), Currency::Usd), Some("fx-1"))?;
+	assert_eq!(conversion.rate, dec("0.9125"));
+	assert_eq!(conversion.buy_amount, dec("91.25"));
+	assert_eq!(conversion.buy_currency, Currency::Eur);
+	
+	// a retry returns the original conversion without converting again
+	let retried = s.bank_service().convert_funds(&dollars.id, &euros.id, &Money::new(BigDecimal::from(100), Currency::Usd), Some("fx-1"))?;
+	assert_eq!(retried, conversion);
+	let err = s.bank_service().convert_funds(&dollars.id, &euros.id, &Money::new(BigDecimal::from(90), Currency::Usd), Some("fx-1")).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::IdempotencyKeyConflict));
+	
+	// the inverse of the USD/EUR rate converts euros back to dollars
+	let conversion = s.bank_service().convert_funds(&euros.id, &dollars.id, &Money::new(BigDecimal::from(50), Currency::Eur), None)?;
+	assert_eq!(conversion.rate, dec("1.09589041"));
+	// converted amounts are rounded to the cent
+	assert_eq!(conversion.buy_amount, dec("54.79"));
+	
+	let dollars = s.repos.account_repo.find_by_id(&f.conn(), &dollars.id)?;
+	let euros = s.repos.account_repo.find_by_id(&f.conn(), &euros.id)?;
+	assert_eq!(dollars.amount, dec("154.79"));
+	assert_eq!(euros.amount, dec("41.25"));
+	
+	let err = s.bank_service().convert_funds(&dollars.id, &euros.id, &Money::new(BigDecimal::from(10), Currency::Eur), None).unwrap_err();
+	assert_eq!(err, mismatch(Currency::Usd, Currency::Eur));
+	let err = s.bank_service().convert_funds(&dollars.id, &pounds.id, &Money::new(BigDecimal::from(10), Currency::Usd), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::MissingFxRate(Currency::Usd, Currency::Gbp, Date::from_ymd(2020, 7, 21))));
+	let err = s.bank_service().convert_funds(&euros.id, &dollars.id, &Money::new(BigDecimal::from(50), Currency::Eur), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	
+	// the shortfall is swept from the linked account as it is for a withdrawal
+	let eur_vault = f.insert_vault("euro", 0, Currency::Eur);
+	let euro_savings = f.account_factory.checking_account_in(bob.id, Currency::Eur);
+	s.bank_service().deposit(&euro_savings.id, &eur_vault.name, &Money::new(BigDecimal::from(20), Currency::Eur), None)?;
+	let mut terms = overdraft_terms(&eur_vault.name);
+	terms.linked_account_id = Some(euro_savings.id);
+	s.bank_service().set_overdraft_protection(&euros.id, terms)?;
+	let conversion = s.bank_service().convert_funds(&euros.id, &dollars.id, &Money::new(BigDecimal::from(50), Currency::Eur), None)?;
+	assert_eq!(conversion.overdraft_protection, Some(OverdraftProtectionKind::LinkedAccount));
+	assert!(s.repos.account_repo.find_by_id(&f.conn(), &euros.id)?.amount.is_zero());
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &euro_savings.id)?.amount, dec("11.25"));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+/// Amounts that are zero, negative or finer than a cent, with the error each is rejected with
+fn invalid_amounts() -> Vec<(Money, Error)> {
+	vec![
+		(usd(0), Error::new(ErrorKind::ZeroAmount)),
+		(usd(-100), Error::new(ErrorKind::InvalidStateNegativeValue)),
+		(Money::new(dec("10.001"), Currency::Usd), Error::new(ErrorKind::ExcessPrecision(Currency::Usd))),
+	]
+}
+
+#[test]
+fn deposit_and_withdraw_reject_invalid_amounts() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	s.bank_service().deposit(&account.id, &vault.name, &usd(100), None)?;
+	
+	for (amount, expected) in invalid_amounts() {
+		let err = s.bank_service().deposit(&account.id, &vault.name, &amount, None).unwrap_err();
+		assert_eq!(err, expected, "deposit {}", amount);
+		let err = s.bank_service().withdraw(&account.id, &vault.name, &amount, None).unwrap_err();
+		assert_eq!(err, expected, "withdraw {}", amount);
+	}
+	
+	// a negative deposit doesn't get to withdraw without a funds check
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, BigDecimal::from(100));
+	assert_eq!(count_rows(&f), (1, 0, 2));
+	
+	Ok(())
+}
+
+#[test]
+fn send_funds_rejects_invalid_transfers() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(100), None)?;
+	
+	for (amount, expected) in invalid_amounts() {
+		let err = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &amount, None).unwrap_err();
+		assert_eq!(err, expected, "send {}", amount);
+	}
+	
+	let err = s.bank_service().send_funds(&bob_account.id, &bob_account.id, &usd(10), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::SelfTransfer));
+	
+	// the receiver is reported missing whichever order the accounts are locked in
+	for missing_id in vec![uuid::Uuid::nil(), uuid::Uuid::from_u128(u128::MAX)] {
+		let err = s.bank_service().send_funds(&bob_account.id, &missing_id, &usd(10), None).unwrap_err();
+		assert_eq!(err, Error::new(ErrorKind::ReceiverNotFound));
+		let err = s.bank_service().send_funds(&missing_id, &bob_account.id, &usd(10), None).unwrap_err();
+		assert_eq!(err, Error::new(ErrorKind::Database(crate::db::Error::RecordNotFound)));
+	}
+	
+	let bob_account = s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?;
+	assert_eq!(bob_account.amount, BigDecimal::from(100));
+	assert_eq!(count_rows(&f).1, 0);
+	
+	Ok(())
+}
+
+#[test]
+fn convert_funds_rejects_invalid_conversions() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let bob = f.user_factory.bob();
+	let dollars = f.account_factory.checking_account(bob.id);
+	let yen = f.account_factory.checking_account_in(bob.id, Currency::Jpy);
+	
+	for (amount, expected) in invalid_amounts() {
+		let err = s.bank_service().convert_funds(&dollars.id, &yen.id, &amount, None).unwrap_err();
+		assert_eq!(err, expected, "convert {}", amount);
+	}
+	
+	// yen have no minor unit
+	let err = s.bank_service().convert_funds(&yen.id, &dollars.id, &Money::new(dec("1000.5"), Currency::Jpy), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ExcessPrecision(Currency::Jpy)));
+	let err = s.bank_service().convert_funds(&dollars.id, &dollars.id, &usd(10), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::SelfTransfer));
+	let err = s.bank_service().convert_funds(&dollars.id, &uuid::Uuid::nil(), &usd(10), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ReceiverNotFound));
+	
+	let err = s.bank_service().publish_fx_rate(NewFxRate {
+		base_currency: Currency::Usd,
+		quote_currency: Currency::Jpy,
+		rate: BigDecimal::zero(),
+		effective_date: Date::from_ymd(2020, 7, 1),
+	}).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ZeroAmount));
+	
+	Ok(())
+}
+
+#[test]
+fn close_account_rejects_missing_sweep_account() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	s.bank_service().deposit(&account.id, &vault.name, &usd(100), None)?;
+	
+	let err = s.bank_service().close_account(&account.id, Some(&uuid::Uuid::nil())).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ReceiverNotFound));
+	
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.status, AccountStatus::Open);
+	
+	Ok(())
+}
+
+#[test]
+fn overdraft_terms_must_be_in_whole_cents() {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	
+	let mut terms = overdraft_terms(&vault.name);
+	terms.overdraft_fee = dec("35.005");
+	let err = s.bank_service().set_overdraft_protection(&account.id, terms).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ExcessPrecision(Currency::Usd)));
+	
+	let mut terms = overdraft_terms(&vault.name);
+	terms.overdraft_limit = dec("-500");
+	let err = s.bank_service().set_overdraft_protection(&account.id, terms).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InvalidStateNegativeValue));
+}
+
+#[test]
+fn account_history_merges_every_movement_with_a_running_balance() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let loan = disbursed_loan(&s, &f, &vault.name, 600, 1, 12)?;
+	let account = s.repos.account_repo.find_accounts(&f.conn(), &loan.user_id)?.remove(0);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&lucy_account.id, &vault.name, &usd(100), None)?;
+	
+	s.bank_service().deposit(&account.id, &vault.name, &usd(300), None)?;
+	s.bank_service().send_funds(&account.id, &lucy_account.id, &usd(100), None)?;
+	s.bank_service().send_funds(&lucy_account.id, &account.id, &usd(40), None)?;
+	s.bank_service().withdraw(&account.id, &vault.name, &usd(50), None)?;
+	s.bank_service().pay_loan(&loan.id, &account.id, &usd(100), &AllocationPolicy::default(), None)?;
+	
+	let history = s.bank_service().get_account_history(&account.id, &history::Filter::default(), history::Page::default())?;
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &account.id)?;
+	assert_eq!(account.amount, BigDecimal::from(1_290));
+	assert_eq!(history.entries[0].balance, account.amount);
+	let total = history.entries.iter().fold(BigDecimal::zero(), |sum, e| sum + &e.amount);
+	assert_eq!(total, account.amount);
+	let oldest = history.entries.last().unwrap();
+	assert_eq!((oldest.entry_type, oldest.balance.clone()), (history::EntryType::LoanDisbursement, BigDecimal::from(1_200)));
+	
+	let filter = history::Filter { entry_type: Some(history::EntryType::LoanPayment), ..Default::default() };
+	let payments = s.bank_service().get_account_history(&account.id, &filter, history::Page::default())?;
+	let paid = payments.entries.iter().fold(BigDecimal::zero(), |sum, e| sum + &e.amount);
+	assert_eq!(paid, BigDecimal::from(-100));
+	assert_eq!(payments.entries[0].balance, account.amount);
+	
+	let filter = history::Filter { entry_type: Some(history::EntryType::TransferIn), ..Default::default() };
+	let transfers_in = s.bank_service().get_account_history(&account.id, &filter, history::Page::default())?;
+	assert_eq!(transfers_in.total, 1);
+	assert_eq!(transfers_in.entries[0].amount, BigDecimal::from(40));
+	assert_eq!(transfers_in.entries[0].counterparty_id, Some(lucy_account.id));
+	
+	let filter = history::Filter { min_amount: Some(BigDecimal::from(100)), max_amount: Some(BigDecimal::from(300)), ..Default::default() };
+	let amounts: Vec<BigDecimal> = s.bank_service().get_account_history(&account.id, &filter, history::Page::default())?
+		.entries.into_iter()
+		.filter(|e| e.entry_type != history::EntryType::LoanPayment)
+		.map(|e| e.amount)
+		.collect();
+	assert_eq!(amounts, vec![BigDecimal::from(-100), BigDecimal::from(300)]);
+	
+	// history is filtered by the day the money moved
+	let today = chrono::Utc::today().naive_utc();
+	let filter = history::Filter { from: Some(today.succ()), ..Default::default() };
+	assert_eq!(s.bank_service().get_account_history(&account.id, &filter, history::Page::default())?.total, 0);
+	let filter = history::Filter { from: Some(today.pred()), to: Some(today), ..Default::default() };
+	assert_eq!(s.bank_service().get_account_history(&account.id, &filter, history::Page::default())?.total, history.total);
+	
+	let page = s.bank_service().get_account_history(&account.id, &history::Filter::default(), history::Page { offset: 1, limit: 2 })?;
+	assert_eq!(page.total, history.total);
+	assert_eq!(page.entries, history.entries[1..3].to_vec());
+	
+	let err = s.bank_service().get_account_history(&uuid::Uuid::nil(), &history::Filter::default(), history::Page::default()).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::Database(crate::db::Error::RecordNotFound)));
+	
+	Ok(())
+}
+
+#[test]
+fn no_statement_until_the_month_has_ended() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let deposit = s.bank_service().deposit(&account.id, &vault.name, &usd(100), None)?;
+	backdate(&f, &deposit, Date::from_ymd(2020, 7, 10))?;
+	
+	// on the month's last day it hasn't ended yet, and neither has any later month
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 7, 31));
+	for month in vec![Date::from_ymd(2020, 7, 1), Date::from_ymd(2020, 7, 31), Date::from_ymd(2020, 8, 15)] {
+		let err = s.bank_service().generate_statement(&account.id, month).unwrap_err();
+		assert!(matches!(err.kind(), ErrorKind::InvalidDate(_)), "{:?}", err);
+	}
+	assert!(s.repos.statement_repo.find(&f.conn(), &account.id, &Date::from_ymd(2020, 7, 1)).is_err());
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 8, 1));
+	let generated = s.bank_service().generate_statement(&account.id, Date::from_ymd(2020, 7, 31))?;
+	assert_eq!(generated.statement.period_end, Date::from_ymd(2020, 7, 31));
+	assert_eq!(generated.statement.closing_balance, BigDecimal::from(100));
+	
+	Ok(())
+}
+
+#[test]
+fn account_history_pages_match_the_full_history() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob = f.user_factory.bob();
+	let dollars = f.account_factory.checking_account(bob.id);
+	let euros = f.account_factory.checking_account_in(bob.id, Currency::Eur);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().publish_fx_rate(NewFxRate {
+		base_currency: Currency::Usd,
+		quote_currency: Currency::Eur,
+		rate: dec("0.9125"),
+		effective_date: Date::from_ymd(2020, 7, 1),
+	})?;
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 7, 21));
+	
+	s.bank_service().deposit(&dollars.id, &vault.name, &usd(300), None)?;
+	let mistake = s.bank_service().deposit(&dollars.id, &vault.name, &usd(25), None)?;
+	s.bank_service().convert_funds(&dollars.id, &euros.id, &usd(100), None)?;
+	s.bank_service().convert_funds(&euros.id, &dollars.id, &Money::new(BigDecimal::from(50), Currency::Eur), None)?;
+	let sent = s.bank_service().send_funds(&dollars.id, &lucy_account.id, &usd(40), None)?;
+	s.bank_service().reverse_bank_transaction(&mistake.id, None)?;
+	s.bank_service().reverse_account_transaction(&sent.id, None)?;
+	s.bank_service().withdraw(&dollars.id, &vault.name, &usd(10), None)?;
+	
+	let everything = history::Page { offset: 0, limit: history::MAX_PAGE_SIZE };
+	let full = s.bank_service().get_account_history(&dollars.id, &history::Filter::default(), everything)?;
+	assert_eq!(full.total, 8);
+	let account = s.repos.account_repo.find_by_id(&f.conn(), &dollars.id)?;
+	assert_eq!(full.entries[0].balance, account.amount);
+	
+	// every page of every filter holds the matching entries of the full history with their running balances
+	let mut filters: Vec<history::Filter> = vec![
+		history::EntryType::Deposit,
+		history::EntryType::Withdraw,
+		history::EntryType::TransferIn,
+		history::EntryType::TransferOut,
+		history::EntryType::LoanPayment,
+		history::EntryType::ConversionIn,
+		history::EntryType::ConversionOut,
+		history::EntryType::Reversal,
+	].into_iter().map(|entry_type| history::Filter { entry_type: Some(entry_type), ..Default::default() }).collect();
+	filters.push(history::Filter::default());
+	filters.push(history::Filter { min_amount: Some(BigDecimal::from(40)), max_amount: Some(BigDecimal::from(100)), ..Default::default() });
+	for filter in &filters {
+		let matching: Vec<history::Entry> = full.entries.iter().filter(|e| filter.matches(e)).cloned().collect();
+		for limit in 1..=3 {
+			for offset in 0..=matching.len() {
+				let page = s.bank_service().get_account_history(&dollars.id, filter, history::Page { offset, limit })?;
+				assert_eq!(page.total, matching.len(), "{:?}", filter);
+				let want: Vec<history::Entry> = matching.iter().skip(offset).take(limit).cloned().collect();
+				assert_eq!(page.entries, want, "{:?} offset {} limit {}", filter, offset, limit);
+			}
+		}
+	}
+	
+	Ok(())
+}
+
+#[test]
+fn monthly_statements_are_immutable_snapshots() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	let today = chrono::Utc::today().naive_utc();
+	let month_start = today.with_day(1).unwrap();
+	
+	// the first deposit was made last month and carries into this month's opening balance
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(500), None)?;
+	diesel::update(bank_transactions::table)
+		.set(bank_transactions::created_at.eq(chrono::Utc.from_utc_datetime(&month_start.pred().and_hms(12, 0, 0))))
+		.execute(&f.conn())?;
+	
+	let mut terms = overdraft_terms(&vault.name);
+	terms.overdraft_limit = BigDecimal::from(100);
+	terms.overdraft_fee = BigDecimal::from(35);
+	s.bank_service().set_overdraft_protection(&bob_account.id, terms)?;
+	s.bank_service().deposit(&lucy_account.id, &vault.name, &usd(100), None)?;
+	s.bank_service().withdraw(&bob_account.id, &vault.name, &usd(100), None)?;
+	s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &usd(50), None)?;
+	s.bank_service().send_funds(&lucy_account.id, &bob_account.id, &usd(20), None)?;
+	s.bank_service().withdraw(&bob_account.id, &vault.name, &usd(400), None)?;
+	
+	// this month's statement can't be generated until the month is over
+	let err = s.bank_service().generate_statement(&bob_account.id, today).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::InvalidDate(_)), "{:?}", err);
+	
+	s.mock_calendar.set_curr_date(month_start.increment_date_by_months(1));
+	let generated = s.bank_service().generate_statement(&bob_account.id, today)?;
+	let statement = &generated.statement;
+	assert_eq!((statement.period_start, statement.period_end), (month_start, month_start.increment_date_by_months(1).pred()));
+	assert_eq!(statement.opening_balance, BigDecimal::from(500));
+	assert_eq!(statement.closing_balance, BigDecimal::from(-65));
+	assert_eq!(statement.fees_charged, BigDecimal::from(35));
+	assert!(statement.interest_credited.is_zero());
+	let lines: Vec<(history::EntryType, BigDecimal)> = generated.lines.iter().map(|l| (l.entry_type, l.amount.clone())).collect();
+	assert_eq!(lines, vec![
+		(history::EntryType::Withdraw, BigDecimal::from(-100)),
+		(history::EntryType::TransferOut, BigDecimal::from(-50)),
+		(history::EntryType::TransferIn, BigDecimal::from(20)),
+		(history::EntryType::Withdraw, BigDecimal::from(-400)),
+		(history::EntryType::Fee, BigDecimal::from(-35)),
+	]);
+	
+	// later changes to the month don't change the statement already generated
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(1_000), None)?;
+	diesel::update(bank_transactions::table.filter(bank_transactions::transaction_type.eq(BankTransactionType::Withdraw)))
+		.set(bank_transactions::amount.eq(BigDecimal::from(1)))
+		.execute(&f.conn())?;
+	assert_eq!(s.bank_service().generate_statement(&bob_account.id, month_start)?, generated);
+	assert_eq!(s.bank_service().get_statement(&bob_account.id, today)?, generated);
+	
+	let csv = generated.render(statement::Format::Csv);
+	let rows: Vec<&str> = csv.lines().collect();
+	assert_eq!(rows.len(), 8);
+	assert_eq!(rows[0], "date,type,amount,balance,transaction_id,counterparty_id,vault_name");
+	assert_eq!(rows[1], format!("{},opening_balance,,500.00,,,", month_start));
+	assert!(rows[2].starts_with(&format!("{},withdraw,-100.00,400.00,", today)), "{}", rows[2]);
+	assert!(rows[3].ends_with(&format!(",{},", lucy_account.id)), "{}", rows[3]);
+	assert_eq!(rows[7], format!("{},closing_balance,,-65.00,,,", statement.period_end));
+	
+	let json: serde_json::Value = serde_json::from_str(&generated.render(statement::Format::Json)).unwrap();
+	assert_eq!(json["account_id"], serde_json::json!(bob_account.id));
+	assert_eq!(json["lines"].as_array().unwrap().len(), 5);
+	
+	let text = generated.render(statement::Format::Text);
+	assert!(text.contains(&format!("Period: {} to {}", month_start, statement.period_end)), "{}", text);
+	assert!(text.lines().any(|l| l.starts_with("Fees charged") && l.ends_with("35.00")), "{}", text);
+	assert!(text.lines().any(|l| l.starts_with("Closing balance") && l.ends_with("-65.00")), "{}", text);
+	
+	// the monthly run generates last month's statement for every account, returning the ones already generated
+	let statements = s.bank_service().generate_monthly_statements()?;
+	assert_eq!(statements.len(), 2);
+	assert!(statements.contains(&generated));
+	
+	Ok(())
+}
+
+#[test]
+fn balances_at_a_point_in_time() -> Result<()> {
+	use diesel::dsl::IntervalDsl;
+	
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(1_000);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	let yesterday = |t: Time| t - chrono::Duration::days(1);
+	
+	let opened = chrono::Utc::now();
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(500), None)?;
+	let deposited = chrono::Utc::now();
+	s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &usd(120), None)?;
+	let sent = chrono::Utc::now();
+	
+	// the accounts were opened and first used yesterday, so today's end of day run snapshots the balances they left
+	diesel::update(accounts::table)
+		.set(accounts::created_at.eq(accounts::created_at - 1.day()))
+		.execute(&f.conn())?;
+	diesel::update(bank_transactions::table)
+		.set(bank_transactions::created_at.eq(bank_transactions::created_at - 1.day()))
+		.execute(&f.conn())?;
+	diesel::update(account_transactions::table)
+		.set(account_transactions::created_at.eq(account_transactions::created_at - 1.day()))
+		.execute(&f.conn())?;
+	s.bank_service().run_end_of_day_accrual()?;
+	let now = chrono::Utc::now();
+	let snapshot = s.repos.snapshot_repo.find_latest_for_account(&f.conn(), &bob_account.id, &now)?.unwrap();
+	assert_eq!(snapshot.balance, BigDecimal::from(380));
+	assert_eq!(snapshot.taken_at.naive_utc(), s.mock_calendar.curr_date.and_hms(0, 0, 0));
+	let snapshot = s.repos.snapshot_repo.find_latest_for_vault(&f.conn(), &vault.name, &now)?.unwrap();
+	assert_eq!(snapshot.balance, BigDecimal::from(1_500));
+	
+	s.bank_service().withdraw(&bob_account.id, &vault.name, &usd(80), None)?;
+	let withdrawn = chrono::Utc::now();
+	s.bank_service().send_funds(&lucy_account.id, &bob_account.id, &usd(20), None)?;
+	let now = chrono::Utc::now();
+	
+	let balance_at = |account: &Account, at: Time| s.bank_service().get_account_balance_at(&account.id, &at).unwrap();
+	assert_eq!(balance_at(&bob_account, yesterday(opened)), usd(0));
+	assert_eq!(balance_at(&bob_account, yesterday(deposited)), usd(500));
+	assert_eq!(balance_at(&bob_account, yesterday(sent)), usd(380));
+	assert_eq!(balance_at(&bob_account, withdrawn), usd(300));
+	assert_eq!(balance_at(&bob_account, now), usd(320));
+	assert_eq!(balance_at(&lucy_account, yesterday(sent)), usd(120));
+	assert_eq!(balance_at(&lucy_account, now), usd(100));
+	
+	let vault_balance_at = |at: Time| s.bank_service().get_vault_balance_at(&vault.name, &at).unwrap();
+	assert_eq!(vault_balance_at(yesterday(opened)), usd(1_000));
+	assert_eq!(vault_balance_at(yesterday(deposited)), usd(1_500));
+	assert_eq!(vault_balance_at(now), usd(1_420));
+	
+	// balances after the snapshot are rolled forward from it rather than back from the current balance
+	s.repos.account_repo.increment(&f.conn(), &bob_account.id, &BigDecimal::from(1_000))?;
+	assert_eq!(balance_at(&bob_account, withdrawn), usd(300));
+	
+	let err = s.bank_service().get_account_balance_at(&uuid::Uuid::nil(), &now).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::Database(crate::db::Error::RecordNotFound)));
+	
+	Ok(())
+}
+
+#[test]
+fn snapshots_include_movements_made_at_the_day_boundary() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let midnight = chrono::Utc.ymd(2020, 7, 21).and_hms(0, 0, 0);
+	diesel::update(accounts::table.find(account.id))
+		.set(accounts::created_at.eq(midnight - chrono::Duration::days(1)))
+		.execute(&f.conn())?;
+	
+	// the day before ends at midnight, so money moved at midnight is in its balance and a microsecond later isn't
+	for (amount, created_at) in vec![
+		(100, midnight - chrono::Duration::seconds(1)),
+		(20, midnight),
+		(3, midnight + chrono::Duration::microseconds(1)),
+	] {
+		let deposit = s.bank_service().deposit(&account.id, &vault.name, &usd(amount), None)?;
+		diesel::update(bank_transactions::table.find(deposit.id))
+			.set(bank_transactions::created_at.eq(created_at))
+			.execute(&f.conn())?;
+	}
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 7, 21));
+	s.bank_service().run_end_of_day_accrual()?;
+	let snapshot = s.repos.snapshot_repo.find_latest_for_account(&f.conn(), &account.id, &midnight)?.unwrap();
+	assert_eq!((snapshot.taken_at, snapshot.balance), (midnight, BigDecimal::from(120)));
+	let snapshot = s.repos.snapshot_repo.find_latest_for_vault(&f.conn(), &vault.name, &midnight)?.unwrap();
+	assert_eq!(snapshot.balance, BigDecimal::from(120));
+	
+	let balance_at = |at| s.bank_service().get_account_balance_at(&account.id, &at).unwrap();
+	assert_eq!(balance_at(midnight - chrono::Duration::microseconds(1)), usd(100));
+	assert_eq!(balance_at(midnight), usd(120));
+	assert_eq!(balance_at(midnight + chrono::Duration::microseconds(1)), usd(123));
+	
+	Ok(())
+}
+
+#[test]
+fn reverse_deposits_and_withdrawals() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(500), None)?;
+	s.bank_service().withdraw(&bob_account.id, &vault.name, &usd(100), None)?;
+	let transactions = s.repos.bank_transaction_repo.find_by_account(&f.conn(), &bob_account.id)?;
+	let (deposit, withdrawal) = (&transactions[0], &transactions[1]);
+	
+	// a withdrawal is reversed by depositing the funds back
+	let reversal = s.bank_service().reverse_bank_transaction(&withdrawal.id, None)?;
+	assert_eq!(reversal.transaction_type, BankTransactionType::Deposit);
+	assert_eq!(reversal.amount, withdrawal.amount);
+	assert_eq!(reversal.reversal_of, Some(withdrawal.id));
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?.amount, BigDecimal::from(500));
+	assert_eq!(s.repos.vault_repo.find_by_name(&f.conn(), &vault.name)?.amount, BigDecimal::from(500));
+	
+	let err = s.bank_service().reverse_bank_transaction(&withdrawal.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::AlreadyReversed));
+	let err = s.bank_service().reverse_bank_transaction(&reversal.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::NotReversible));
+	
+	// the deposit can't be reversed once the funds have been sent on, unless an operator forces it
+	s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &usd(200), None)?;
+	let err = s.bank_service().reverse_bank_transaction(&deposit.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	let operator = f.user_factory.user(UserFactory::defaults());
+	let err = s.bank_service().reverse_bank_transaction(&deposit.id, Some(&uuid::Uuid::nil())).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::Database(crate::db::Error::RecordNotFound)));
+	let reversal = s.bank_service().reverse_bank_transaction(&deposit.id, Some(&operator.id))?;
+	assert_eq!(reversal.transaction_type, BankTransactionType::Withdraw);
+	assert_eq!(reversal.forced_by, Some(operator.id));
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?.amount, BigDecimal::from(-200));
+	assert_eq!(s.repos.vault_repo.find_by_name(&f.conn(), &vault.name)?.amount, BigDecimal::zero());
+	
+	let history = s.bank_service().get_account_history(&bob_account.id, &history::Filter::default(), history::Page::default())?;
+	let reversals: Vec<BigDecimal> = history.entries.iter()
+		.filter(|e| e.entry_type == history::EntryType::Reversal)
+		.map(|e| e.amount.clone())
+		.collect();
+	assert_eq!(reversals, vec![BigDecimal::from(-500), BigDecimal::from(100)]);
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	// only deposits and withdrawals move funds that can be put back
+	let interest = s.repos.bank_transaction_repo.create(&f.conn(), bank_transaction::NewBankTransaction {
+		account_id: &lucy_account.id,
+		vault_name: &vault.name,
+		transaction_type: BankTransactionType::InterestCredit,
+		amount: &BigDecimal::from(1),
+		idempotency_key: None,
+		overdraft_protection: None,
+		currency: Currency::Usd,
+		reversal_of: None,
+		forced_by: None,
+	})?;
+	let err = s.bank_service().reverse_bank_transaction(&interest.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::NotReversible));
+	
+	Ok(())
+}
+
+#[test]
+fn reverse_transfers() -> Result<()> {
+	let f = Fixture::new();
+	let s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(100), None)?;
+	
+	let transfer = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &usd(40), None)?;
+	let reversal = s.bank_service().reverse_account_transaction(&transfer.id, None)?;
+	assert_eq!((reversal.sender_id, reversal.receiver_id), (lucy_account.id, bob_account.id));
+	assert_eq!(reversal.reversal_of, Some(transfer.id));
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?.amount, BigDecimal::from(100));
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &lucy_account.id)?.amount, BigDecimal::zero());
+	
+	let err = s.bank_service().reverse_account_transaction(&transfer.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::AlreadyReversed));
+	let err = s.bank_service().reverse_account_transaction(&reversal.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::NotReversible));
+	
+	// the receiver spent part of the transfer, so only an operator can reverse it
+	let transfer = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &usd(60), None)?;
+	s.bank_service().withdraw(&lucy_account.id, &vault.name, &usd(30), None)?;
+	let err = s.bank_service().reverse_account_transaction(&transfer.id, None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::InadequateFunds));
+	let operator = f.user_factory.user(UserFactory::defaults());
+	let reversal = s.bank_service().reverse_account_transaction(&transfer.id, Some(&operator.id))?;
+	assert_eq!(reversal.forced_by, Some(operator.id));
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?.amount, BigDecimal::from(100));
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &lucy_account.id)?.amount, BigDecimal::from(-30));
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	let err = s.bank_service().reverse_account_transaction(&uuid::Uuid::nil(), None).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::Database(crate::db::Error::RecordNotFound)));
+	
+	Ok(())
+}
+
+fn schedule(sender_id: uuid::Uuid, receiver_id: uuid::Uuid, amount: i64, frequency: Frequency, start_date: Date) -> TransferSchedule {
+	TransferSchedule {
+		sender_id,
+		receiver_id,
+		amount: BigDecimal::from(amount),
+		frequency,
+		day_of_month: None,
+		start_date,
+		end_date: None,
+	}
+}
+
+#[test]
+fn run_standing_orders() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(250), None)?;
+	let account_repo = &s.repos.account_repo;
+	let balance = |id| account_repo.find_by_id(&f.conn(), id).unwrap().amount;
+	let outcomes = |runs: Vec<ScheduledTransferRun>| runs.iter().map(|run| (run.scheduled_date, run.outcome)).collect::<Vec<_>>();
+	let policy = RetryPolicy { on_insufficient_funds: InsufficientFundsAction::Retry, max_retries: 1 };
+	
+	// $200 on the 1st of every month
+	let order = s.bank_service().schedule_transfer(TransferSchedule {
+		day_of_month: Some(1),
+		..schedule(bob_account.id, lucy_account.id, 200, Frequency::Monthly, Date::from_ymd(2019, 12, 20))
+	})?;
+	assert_eq!(order.next_run_date, Some(Date::from_ymd(2020, 1, 1)));
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2019, 12, 31));
+	assert!(s.bank_service().run_scheduled_transfers(&policy)?.is_empty());
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 1));
+	let runs = s.bank_service().run_scheduled_transfers(&policy)?;
+	assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 1, 1), RunOutcome::Succeeded)]);
+	assert_eq!((balance(&bob_account.id), balance(&lucy_account.id)), (BigDecimal::from(50), BigDecimal::from(200)));
+	// running again the same day doesn't send the funds twice
+	assert!(s.bank_service().run_scheduled_transfers(&policy)?.is_empty());
+	
+	// bob can't cover February's transfer until the next day
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 1));
+	let runs = s.bank_service().run_scheduled_transfers(&policy)?;
+	assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 2, 1), RunOutcome::Retrying)]);
+	assert!(s.bank_service().run_scheduled_transfers(&policy)?.is_empty());
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(550), None)?;
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 2, 2));
+	let runs = s.bank_service().run_scheduled_transfers(&policy)?;
+	assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 2, 1), RunOutcome::Succeeded)]);
+	
+	// occurrences missed while the runner wasn't run are caught up
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 4, 15));
+	let runs = s.bank_service().run_scheduled_transfers(&policy)?;
+	assert_eq!(outcomes(runs), vec![
+		(Date::from_ymd(2020, 3, 1), RunOutcome::Succeeded),
+		(Date::from_ymd(2020, 4, 1), RunOutcome::Succeeded),
+	]);
+	assert_eq!((balance(&bob_account.id), balance(&lucy_account.id)), (BigDecimal::from(0), BigDecimal::from(800)));
+	
+	// once the retries run out the occurrence is skipped
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 5, 1));
+	s.bank_service().run_scheduled_transfers(&policy)?;
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 5, 2));
+	let runs = s.bank_service().run_scheduled_transfers(&policy)?;
+	assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 5, 1), RunOutcome::Skipped)]);
+	let order = s.repos.scheduled_transfer_repo.find_by_id(&f.conn(), &order.id)?;
+	assert_eq!((order.next_run_date, order.retries), (Some(Date::from_ymd(2020, 6, 1)), 0));
+	
+	let runs = s.bank_service().get_scheduled_transfer_runs(&order.id)?;
+	assert_eq!(runs.len(), 7);
+	assert!(runs.iter().all(|run| run.transaction_id.is_some() == (run.outcome == RunOutcome::Succeeded)));
+	assert_eq!(runs[5].error, Some(Error::new(ErrorKind::InadequateFunds).to_string()));
+	
+	// a cancelled order doesn't run again
+	let order = s.bank_service().cancel_scheduled_transfer(&order.id)?;
+	assert!(order.cancelled_at.is_some());
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 6, 1));
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(200), None)?;
+	assert!(s.bank_service().run_scheduled_transfers(&policy)?.is_empty());
+	
+	let reconciliation = s.bank_service().reconcile()?;
+	assert!(reconciliation.is_reconciled(), "{:?}", reconciliation);
+	
+	Ok(())
+}
+
+#[test]
+fn skipped_occurrences_are_not_sent_and_retried_ones_are() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	let account_repo = &s.repos.account_repo;
+	let balance = |id| account_repo.find_by_id(&f.conn(), id).unwrap().amount;
+	let outcomes = |runs: Vec<ScheduledTransferRun>| runs.iter().map(|run| (run.scheduled_date, run.outcome)).collect::<Vec<_>>();
+	let skip = RetryPolicy { on_insufficient_funds: InsufficientFundsAction::Skip, max_retries: 0 };
+	let retry = RetryPolicy { on_insufficient_funds: InsufficientFundsAction::Retry, max_retries: 2 };
+	
+	// $30 every Monday
+	let order = s.bank_service().schedule_transfer(schedule(bob_account.id, lucy_account.id, 30, Frequency::Weekly, Date::from_ymd(2020, 1, 6)))?;
+	
+	// the skipped occurrence isn't sent once bob can cover it, only the next one is
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 6));
+	let runs = s.bank_service().run_scheduled_transfers(&skip)?;
+	assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 1, 6), RunOutcome::Skipped)]);
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(100), None)?;
+	assert!(s.bank_service().run_scheduled_transfers(&skip)?.is_empty());
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 13));
+	let runs = s.bank_service().run_scheduled_transfers(&skip)?;
+	assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 1, 13), RunOutcome::Succeeded)]);
+	assert_eq!((balance(&bob_account.id), balance(&lucy_account.id)), (BigDecimal::from(70), BigDecimal::from(30)));
+	
+	// a retried occurrence is sent once bob can cover it before the retries run out
+	s.bank_service().withdraw(&bob_account.id, &vault.name, &usd(70), None)?;
+	for day in 20..=21 {
+		s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, day));
+		let runs = s.bank_service().run_scheduled_transfers(&retry)?;
+		assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 1, 20), RunOutcome::Retrying)]);
+	}
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(30), None)?;
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 1, 22));
+	let runs = s.bank_service().run_scheduled_transfers(&retry)?;
+	assert_eq!(outcomes(runs), vec![(Date::from_ymd(2020, 1, 20), RunOutcome::Succeeded)]);
+	assert_eq!((balance(&bob_account.id), balance(&lucy_account.id)), (BigDecimal::from(0), BigDecimal::from(60)));
+	
+	let order = s.repos.scheduled_transfer_repo.find_by_id(&f.conn(), &order.id)?;
+	assert_eq!((order.next_run_date, order.retries), (Some(Date::from_ymd(2020, 1, 27)), 0));
+	let runs = s.bank_service().get_scheduled_transfer_runs(&order.id)?;
+	assert_eq!(runs.iter().filter(|run| run.scheduled_date == Date::from_ymd(2020, 1, 20)).count(), 3);
+	
+	Ok(())
+}
+
+#[test]
+fn schedule_transfers() -> Result<()> {
+	let f = Fixture::new();
+	let mut s = Suite::setup(&f);
+	let vault = f.insert_main_vault(0);
+	let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+	let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+	let start_date = Date::from_ymd(2020, 1, 10);
+	let skip = RetryPolicy { on_insufficient_funds: InsufficientFundsAction::Skip, max_retries: 0 };
+	
+	let err = s.bank_service().schedule_transfer(schedule(bob_account.id, bob_account.id, 10, Frequency::Weekly, start_date)).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::SelfTransfer));
+	let err = s.bank_service().schedule_transfer(schedule(bob_account.id, uuid::Uuid::nil(), 10, Frequency::Weekly, start_date)).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ReceiverNotFound));
+	let err = s.bank_service().schedule_transfer(schedule(bob_account.id, lucy_account.id, 0, Frequency::Weekly, start_date)).unwrap_err();
+	assert_eq!(err, Error::new(ErrorKind::ZeroAmount));
+	let err = s.bank_service().schedule_transfer(schedule(bob_account.id, lucy_account.id, 10, Frequency::Monthly, start_date)).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::InvalidDate(_)));
+	let err = s.bank_service().schedule_transfer(TransferSchedule {
+		day_of_month: Some(5),
+		..schedule(bob_account.id, lucy_account.id, 10, Frequency::Weekly, start_date)
+	}).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::InvalidDate(_)));
+	let err = s.bank_service().schedule_transfer(TransferSchedule {
+		end_date: Some(Date::from_ymd(2020, 1, 30)),
+		..schedule(bob_account.id, lucy_account.id, 10, Frequency::EndOfMonth, start_date)
+	}).unwrap_err();
+	assert!(matches!(err.kind(), ErrorKind::InvalidDate(_)));
+	
+	// a one-off transfer bob can't cover is skipped and the order finishes
+	let once = s.bank_service().schedule_transfer(schedule(bob_account.id, lucy_account.id, 10, Frequency::Once, start_date))?;
+	s.mock_calendar.set_curr_date(start_date);
+	let runs = s.bank_service().run_scheduled_transfers(&skip)?;
+	assert_eq!(runs.iter().map(|run| run.outcome).collect::<Vec<_>>(), vec![RunOutcome::Skipped]);
+	assert_eq!(s.repos.scheduled_transfer_repo.find_by_id(&f.conn(), &once.id)?.next_run_date, None);
+	
+	// the last day of each month until the end date
+	let order = s.bank_service().schedule_transfer(TransferSchedule {
+		end_date: Some(Date::from_ymd(2020, 3, 30)),
+		..schedule(bob_account.id, lucy_account.id, 25, Frequency::EndOfMonth, start_date)
+	})?;
+	s.bank_service().deposit(&bob_account.id, &vault.name, &usd(100), None)?;
+	// January's transfer was already sent by a run that was interrupted before it was recorded
+	let key = format!("scheduled_transfer:{}:{}", order.id, Date::from_ymd(2020, 1, 31));
+	let sent = s.bank_service().send_funds(&bob_account.id, &lucy_account.id, &usd(25), Some(&key))?;
+	
+	s.mock_calendar.set_curr_date(Date::from_ymd(2020, 4, 1));
+	let runs = s.bank_service().run_scheduled_transfers(&skip)?;
+	assert_eq!(runs.iter().map(|run| run.scheduled_date).collect::<Vec<_>>(), vec![Date::from_ymd(2020, 1, 31), Date::from_ymd(2020, 2, 29)]);
+	assert_eq!(runs[0].transaction_id, Some(sent.id));
+	assert_eq!(s.repos.account_repo.find_by_id(&f.conn(), &bob_account.id)?.amount, BigDecimal::from(50));
+	assert_eq!(s.repos.scheduled_transfer_repo.find_by_id(&f.conn(), &order.id)?.next_run_date, None);
+	
+	Ok(())
+}
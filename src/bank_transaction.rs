@@ -1,7 +1,7 @@
 use std::str::FromStr;
 use std::string::ToString;
 
-use bigdecimal::BigDecimal;
+use bigdecimal::{BigDecimal, Zero};
 use diesel::{
 	deserialize,
 	pg::Pg,
@@ -9,15 +9,19 @@ use diesel::{
 	serialize,
 	sql_types::Varchar,
 };
+use serde::Serialize;
 use strum;
 use strum_macros::{Display, EnumString};
 
 use crate::db;
+use crate::history::{EntryType, Filter};
+use crate::money::{Currency, Money};
+use crate::overdraft::OverdraftProtectionKind;
 use crate::schema::bank_transactions;
 use crate::types::Time;
 
 /// Transaction between a user's account and the bank
-#[derive(Queryable, Identifiable, PartialEq, Debug)]
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
 pub struct BankTransaction {
 	pub id: uuid::Uuid,
 	/// The user's account id
@@ -27,11 +31,49 @@ pub struct BankTransaction {
 	pub transaction_type: BankTransactionType,
 	pub amount: BigDecimal,
 	pub created_at: Time,
+	/// Key supplied by the client to make retries of the same request safe
+	pub idempotency_key: Option<String>,
+	/// The overdraft protection that covered the transaction, if it needed any
+	pub overdraft_protection: Option<OverdraftProtectionKind>,
+	pub currency: Currency,
+	/// The transaction this transaction reverses, if it's a reversal
+	pub reversal_of: Option<uuid::Uuid>,
+	/// The operator who forced the reversal through even though it overdrew the account
+	pub forced_by: Option<uuid::Uuid>,
 }
 
-#[derive(AsExpression, FromSqlRow, Eq, PartialEq, EnumString, Display, Debug)]
+impl BankTransaction {
+	/// Indicates whether a request with these parameters repeats the request that recorded this transaction
+	pub fn is_repeat_of(&self, transaction_type: BankTransactionType, account_id: &uuid::Uuid, vault_name: &str, amount: &Money) -> bool {
+		self.transaction_type == transaction_type
+			&& self.account_id.eq(account_id)
+			&& self.vault_name == vault_name
+			&& self.amount.eq(&amount.amount)
+			&& self.currency == amount.currency
+	}
+	
+	/// The amount the transaction moved into the vault, negative when it moved funds out
+	///
+	/// Every transaction moves funds held in the vault: deposits, withdrawals, loan cash, fees and interest
+	pub fn vault_amount(&self) -> BigDecimal {
+		match self.transaction_type {
+			BankTransactionType::Deposit
+			| BankTransactionType::PrincipalRepayment
+			| BankTransactionType::InterestRepayment
+			| BankTransactionType::LateFee
+			| BankTransactionType::OverdraftFee
+			| BankTransactionType::OverdraftInterest => self.amount.clone(),
+			BankTransactionType::Withdraw
+			| BankTransactionType::LoanPrincipal
+			| BankTransactionType::InterestCredit => -self.amount.clone(),
+		}
+	}
+}
+
+#[derive(AsExpression, FromSqlRow, Serialize, Eq, PartialEq, EnumString, Display, Debug)]
 #[sql_type = "Varchar"]
 #[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum BankTransactionType {
 	/// A user putting funds into their account
 	Deposit,
@@ -43,6 +85,24 @@ pub enum BankTransactionType {
 	PrincipalRepayment,
 	/// Interest repayment on a loan
 	InterestRepayment,
+	/// Fee paid for a late loan payment
+	LateFee,
+	/// Fee paid for using an overdraft line
+	OverdraftFee,
+	/// Interest paid on a negative balance
+	OverdraftInterest,
+	/// Interest paid by the bank on a savings account
+	InterestCredit,
+}
+
+impl BankTransactionType {
+	/// Indicates whether the transaction moves money into the account rather than out of it
+	pub fn is_credit(&self) -> bool {
+		match self {
+			BankTransactionType::Deposit | BankTransactionType::LoanPrincipal | BankTransactionType::InterestCredit => true,
+			_ => false,
+		}
+	}
 }
 
 
@@ -68,25 +128,151 @@ pub struct NewBankTransaction<'a> {
 	pub vault_name: &'a str,
 	pub transaction_type: BankTransactionType,
 	pub amount: &'a BigDecimal,
+	pub idempotency_key: Option<&'a str>,
+	pub overdraft_protection: Option<OverdraftProtectionKind>,
+	pub currency: Currency,
+	pub reversal_of: Option<&'a uuid::Uuid>,
+	pub forced_by: Option<&'a uuid::Uuid>,
 }
 
-/// Data store implementation for operating on bank_transactions in the database
-pub struct Repo {
-	db: db::PgPool
+/// The account's transactions that its history lists as matching the filter
+///
+/// Returns none when the filter's entry type is never a bank transaction
+fn matching_history<'a>(account_id: &'a uuid::Uuid, filter: &'a Filter) -> Option<bank_transactions::BoxedQuery<'a, Pg>> {
+	let mut query = bank_transactions::table
+		.filter(bank_transactions::account_id.eq(account_id))
+		.into_boxed();
+	match filter.entry_type {
+		Some(EntryType::Reversal) => query = query.filter(bank_transactions::reversal_of.is_not_null()),
+		Some(entry_type) => {
+			let types = entry_type.bank_transaction_types();
+			if types.is_empty() {
+				return None;
+			}
+			query = query.filter(bank_transactions::reversal_of.is_null())
+				.filter(bank_transactions::transaction_type.eq_any(types));
+		}
+		None => {}
+	}
+	if let Some(starts_at) = filter.starts_at() {
+		query = query.filter(bank_transactions::created_at.ge(starts_at));
+	}
+	if let Some(ends_before) = filter.ends_before() {
+		query = query.filter(bank_transactions::created_at.lt(ends_before));
+	}
+	if let Some(min) = &filter.min_amount {
+		query = query.filter(bank_transactions::amount.ge(min));
+	}
+	if let Some(max) = &filter.max_amount {
+		query = query.filter(bank_transactions::amount.le(max));
+	}
+	Some(query)
 }
 
+/// Data store implementation for operating on bank_transactions in the database
+pub struct Repo;
+
 impl Repo {
-	pub fn new(db: db::PgPool) -> Self {
-		Repo { db }
+	pub fn new() -> Self {
+		Repo
 	}
 	
-	pub fn create(&self, new_transaction: NewBankTransaction) -> db::Result<BankTransaction> {
-		let conn = &self.db.get()?;
+	pub fn create(&self, conn: &PgConnection, new_transaction: NewBankTransaction) -> db::Result<BankTransaction> {
 		diesel::insert_into(bank_transactions::table)
 			.values(&new_transaction)
 			.get_result::<BankTransaction>(conn)
 			.map_err(Into::into)
 	}
+	
+	pub fn find_by_idempotency_key(&self, conn: &PgConnection, key: &str) -> db::Result<BankTransaction> {
+		bank_transactions::table
+			.filter(bank_transactions::idempotency_key.eq(key))
+			.first::<BankTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_by_id(&self, conn: &PgConnection, id: &uuid::Uuid) -> db::Result<BankTransaction> {
+		bank_transactions::table
+			.find(id)
+			.first::<BankTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the transaction that reversed the transaction
+	pub fn find_reversal(&self, conn: &PgConnection, reversal_of: &uuid::Uuid) -> db::Result<BankTransaction> {
+		bank_transactions::table
+			.filter(bank_transactions::reversal_of.eq(reversal_of))
+			.first::<BankTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds every transaction between the account and the bank, oldest first
+	pub fn find_by_account(&self, conn: &PgConnection, account_id: &uuid::Uuid) -> db::Result<Vec<BankTransaction>> {
+		bank_transactions::table
+			.filter(bank_transactions::account_id.eq(account_id))
+			.order((bank_transactions::created_at.asc(), bank_transactions::id.asc()))
+			.load::<BankTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the transactions between the account and the bank made after the time, oldest first
+	pub fn find_by_account_after(&self, conn: &PgConnection, account_id: &uuid::Uuid, after: &Time) -> db::Result<Vec<BankTransaction>> {
+		bank_transactions::table
+			.filter(bank_transactions::account_id.eq(account_id)
+				.and(bank_transactions::created_at.gt(after)))
+			.order((bank_transactions::created_at.asc(), bank_transactions::id.asc()))
+			.load::<BankTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the transactions between the account and the bank made after and before the times that are given, oldest first
+	pub fn find_by_account_within(&self, conn: &PgConnection, account_id: &uuid::Uuid, after: Option<&Time>, before: Option<&Time>) -> db::Result<Vec<BankTransaction>> {
+		let mut query = bank_transactions::table
+			.filter(bank_transactions::account_id.eq(account_id))
+			.into_boxed();
+		if let Some(after) = after {
+			query = query.filter(bank_transactions::created_at.gt(after));
+		}
+		if let Some(before) = before {
+			query = query.filter(bank_transactions::created_at.lt(before));
+		}
+		query
+			.order((bank_transactions::created_at.asc(), bank_transactions::id.asc()))
+			.load::<BankTransaction>(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Counts the account's transactions that its history lists as matching the filter
+	pub fn count_history(&self, conn: &PgConnection, account_id: &uuid::Uuid, filter: &Filter) -> db::Result<i64> {
+		match matching_history(account_id, filter) {
+			Some(query) => query.count().get_result(conn).map_err(Into::into),
+			None => Ok(0),
+		}
+	}
+	
+	/// Finds when the nth most recent of the account's transactions matching the filter of its history was made
+	pub fn find_history_cutoff(&self, conn: &PgConnection, account_id: &uuid::Uuid, filter: &Filter, n: i64) -> db::Result<Option<Time>> {
+		match matching_history(account_id, filter) {
+			Some(query) => query
+				.select(bank_transactions::created_at)
+				.order(bank_transactions::created_at.desc())
+				.offset(n - 1)
+				.first::<Time>(conn)
+				.optional()
+				.map_err(Into::into),
+			None => Ok(None),
+		}
+	}
+	
+	/// Finds the transactions that moved money into or out of the vault after the time, oldest first
+	pub fn find_by_vault_after(&self, conn: &PgConnection, vault_name: &str, after: &Time) -> db::Result<Vec<BankTransaction>> {
+		bank_transactions::table
+			.filter(bank_transactions::vault_name.eq(vault_name)
+				.and(bank_transactions::created_at.gt(after)))
+			.order((bank_transactions::created_at.asc(), bank_transactions::id.asc()))
+			.load::<BankTransaction>(conn)
+			.map_err(Into::into)
+	}
 }
 
 #[cfg(test)]
@@ -106,11 +292,16 @@ mod tests {
 		
 		let amount = BigDecimal::from(250);
 		
-		let got = suite.bank_transaction_repo.create(NewBankTransaction {
+		let got = suite.bank_transaction_repo.create(&fixture.conn(), NewBankTransaction {
 			account_id: &checking.id,
 			vault_name: &vault.name,
 			transaction_type: BankTransactionType::Deposit,
 			amount: &amount,
+			idempotency_key: Some("deposit-1"),
+			overdraft_protection: None,
+			currency: Currency::Usd,
+			reversal_of: None,
+			forced_by: None,
 		}).unwrap();
 		
 		let want = BankTransaction {
@@ -120,9 +311,17 @@ mod tests {
 			transaction_type: BankTransactionType::Deposit,
 			amount,
 			created_at: got.created_at,
+			idempotency_key: Some(String::from("deposit-1")),
+			overdraft_protection: None,
+			currency: Currency::Usd,
+			reversal_of: None,
+			forced_by: None,
 		};
 		
 		assert_eq!(got, want);
+		
+		let found = suite.bank_transaction_repo.find_by_idempotency_key(&fixture.conn(), "deposit-1").unwrap();
+		assert_eq!(found, want);
 	}
 }
 
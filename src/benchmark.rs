@@ -0,0 +1,57 @@
+/*!
+benchmark keeps the published values of the benchmark rates variable rate loans are indexed to
+*/
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::schema::benchmark_rates;
+use crate::types::{Date, Id, Time};
+
+/// The value of a benchmark rate from its effective date until the next value takes effect
+#[derive(Queryable, Identifiable, Serialize, Debug)]
+pub struct BenchmarkRate {
+	pub id: Id,
+	/// the name of the benchmark, e.g. "prime"
+	pub benchmark: String,
+	pub effective_date: Date,
+	/// the rate in basis points
+	pub rate: i16,
+	pub created_at: Time,
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[table_name = "benchmark_rates"]
+pub struct NewBenchmarkRate {
+	pub benchmark: String,
+	pub effective_date: Date,
+	/// the rate in basis points
+	pub rate: i16,
+}
+
+/// Data store implementation for operating on benchmark rates in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	pub fn create(&self, conn: &PgConnection, new_rate: NewBenchmarkRate) -> db::Result<BenchmarkRate> {
+		diesel::insert_into(benchmark_rates::table)
+			.values(new_rate)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the benchmark's value in effect on the date, which is the latest value effective on or before it
+	pub fn find_effective(&self, conn: &PgConnection, benchmark: &str, date: &Date) -> db::Result<BenchmarkRate> {
+		benchmark_rates::table
+			.filter(benchmark_rates::benchmark.eq(benchmark)
+				.and(benchmark_rates::effective_date.le(date)))
+			.select(benchmark_rates::all_columns)
+			.order(benchmark_rates::effective_date.desc())
+			.first(conn)
+			.map_err(Into::into)
+	}
+}
@@ -0,0 +1,167 @@
+/*!
+day_count implements the conventions used to count the days interest accrues over
+*/
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Datelike;
+use diesel::{
+	deserialize::{self, FromSql},
+	pg::Pg,
+	serialize::{self, Output, ToSql},
+	sql_types::Varchar,
+};
+use serde::{Deserialize, Serialize};
+use strum;
+use strum_macros::{Display, EnumString};
+
+use crate::types::Date;
+
+/// Determines how the interest for a period is calculated from the days in the period
+#[derive(Debug, Clone, Copy, AsExpression, FromSqlRow, Serialize, Deserialize, Eq, PartialEq, EnumString, Display)]
+#[sql_type = "Varchar"]
+pub enum DayCountConvention {
+	/// Every month has 30 days and every year has 360 days (bond basis)
+	#[strum(serialize = "thirty_360")]
+	#[serde(rename = "thirty_360")]
+	Thirty360,
+	/// Actual days in the period over a 360 day year
+	#[strum(serialize = "actual_360")]
+	#[serde(rename = "actual_360")]
+	Actual360,
+	/// Actual days in the period over a 365 day year, including leap years
+	#[strum(serialize = "actual_365")]
+	#[serde(rename = "actual_365")]
+	Actual365,
+	/// Actual days in the period, the days falling in each calendar year over the days in that year (ISDA)
+	#[strum(serialize = "actual_actual")]
+	#[serde(rename = "actual_actual")]
+	ActualActual,
+}
+
+impl Default for DayCountConvention {
+	fn default() -> Self { DayCountConvention::Thirty360 }
+}
+
+impl DayCountConvention {
+	/// Calculates the interest on the principal at the annual rate from the start date up to the end date
+	pub fn interest(&self, principal: &BigDecimal, annual_rate: &BigDecimal, start: Date, end: Date) -> BigDecimal {
+		if end <= start {
+			return BigDecimal::zero();
+		}
+		let amount = principal * annual_rate;
+		match self {
+			DayCountConvention::Thirty360 => amount * BigDecimal::from(thirty_360_days(start, end)) / BigDecimal::from(360),
+			DayCountConvention::Actual360 => amount * BigDecimal::from(actual_days(start, end)) / BigDecimal::from(360),
+			DayCountConvention::Actual365 => amount * BigDecimal::from(actual_days(start, end)) / BigDecimal::from(365),
+			DayCountConvention::ActualActual => {
+				// split the period at each new year so each part is divided by the days in its own year
+				let mut interest = BigDecimal::zero();
+				let mut from = start;
+				while from < end {
+					let next_year = Date::from_ymd(from.year() + 1, 1, 1);
+					let to = next_year.min(end);
+					interest += &amount * BigDecimal::from(actual_days(from, to)) / BigDecimal::from(days_in_year(from.year()));
+					from = to;
+				}
+				interest
+			}
+		}
+	}
+	
+	/// The fraction of a year between the dates
+	pub fn year_fraction(&self, start: Date, end: Date) -> BigDecimal {
+		self.interest(&BigDecimal::from(1), &BigDecimal::from(1), start, end)
+	}
+}
+
+fn actual_days(start: Date, end: Date) -> i64 {
+	(end - start).num_days()
+}
+
+/// Counts the days between the dates with 30 day months
+///
+/// A start date on the 31st is moved to the 30th, and so is an end date on the 31st when the start is on the 30th or 31st
+fn thirty_360_days(start: Date, end: Date) -> i64 {
+	let start_day = start.day().min(30);
+	let end_day = if start_day == 30 { end.day().min(30) } else { end.day() };
+	
+	360 * (end.year() - start.year()) as i64
+		+ 30 * (end.month() as i64 - start.month() as i64)
+		+ (end_day as i64 - start_day as i64)
+}
+
+fn days_in_year(year: i32) -> i64 {
+	if Date::from_ymd_opt(year, 2, 29).is_some() { 366 } else { 365 }
+}
+
+impl ToSql<Varchar, Pg> for DayCountConvention {
+	fn to_sql<W: std::io::Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+		ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl FromSql<Varchar, Pg> for DayCountConvention {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		Ok(DayCountConvention::from_str(s)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn dec(s: &str) -> BigDecimal {
+		BigDecimal::from_str(s).unwrap()
+	}
+	
+	fn year_fraction(convention: DayCountConvention, start: Date, end: Date) -> BigDecimal {
+		convention.year_fraction(start, end).with_scale(12)
+	}
+	
+	/// Reference values from the ISDA 1998 paper "EMU and market conventions: recent developments"
+	#[test]
+	fn isda_reference_values() {
+		let start = Date::from_ymd(2003, 11, 1);
+		let end = Date::from_ymd(2004, 5, 1);
+		
+		assert_eq!(year_fraction(DayCountConvention::ActualActual, start, end), dec("0.497724380567"));
+		assert_eq!(year_fraction(DayCountConvention::Actual365, start, end), dec("0.498630136986"));
+		assert_eq!(year_fraction(DayCountConvention::Actual360, start, end), dec("0.505555555555"));
+		assert_eq!(year_fraction(DayCountConvention::Thirty360, start, end), dec("0.5"));
+		
+		let start = Date::from_ymd(1999, 2, 1);
+		let end = Date::from_ymd(1999, 7, 1);
+		assert_eq!(year_fraction(DayCountConvention::ActualActual, start, end), dec("0.410958904109"));
+	}
+	
+	#[test]
+	fn thirty_360_month_ends() {
+		let days = |start, end| thirty_360_days(start, end);
+		
+		assert_eq!(days(Date::from_ymd(2007, 1, 15), Date::from_ymd(2007, 2, 15)), 30);
+		assert_eq!(days(Date::from_ymd(2007, 1, 31), Date::from_ymd(2007, 2, 28)), 28);
+		assert_eq!(days(Date::from_ymd(2007, 1, 30), Date::from_ymd(2007, 3, 31)), 60);
+		assert_eq!(days(Date::from_ymd(2007, 1, 15), Date::from_ymd(2007, 3, 31)), 76);
+		assert_eq!(days(Date::from_ymd(2007, 1, 1), Date::from_ymd(2008, 1, 1)), 360);
+	}
+	
+	#[test]
+	fn interest_for_a_month() {
+		let principal = BigDecimal::from(10_000);
+		let rate = dec("0.06");
+		let start = Date::from_ymd(2020, 1, 1);
+		let end = Date::from_ymd(2020, 2, 1);
+		
+		assert_eq!(DayCountConvention::Thirty360.interest(&principal, &rate, start, end), BigDecimal::from(50));
+		assert_eq!(DayCountConvention::Actual360.interest(&principal, &rate, start, end).with_scale(4), dec("51.6666"));
+		assert_eq!(DayCountConvention::Actual365.interest(&principal, &rate, start, end).with_scale(4), dec("50.9589"));
+		// 2020 is a leap year
+		assert_eq!(DayCountConvention::ActualActual.interest(&principal, &rate, start, end).with_scale(4), dec("50.8196"));
+		
+		assert!(DayCountConvention::Actual365.interest(&principal, &rate, end, start).is_zero());
+	}
+}
@@ -1,6 +1,8 @@
 use std::{env, fmt};
 
 use diesel::PgConnection;
+use diesel::RunQueryDsl;
+use diesel::sql_types::Text;
 use diesel::r2d2::ConnectionManager;
 use diesel::result::DatabaseErrorKind::UniqueViolation;
 use diesel::result::Error::{DatabaseError, NotFound};
@@ -9,6 +11,11 @@ use r2d2;
 use uuid::Error as uuidError;
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Pool of connections to the database
+///
+/// Repos don't hold the pool, they operate on a connection supplied by the caller.
+/// This allows a caller to run several repo operations in a single transaction that commits or rolls back as a unit.
 pub type PgPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
 /// Get a pooled connection to the underlying PostgreSQL database
@@ -26,6 +33,17 @@ pub fn pg_connection() -> PgPool {
 	pool
 }
 
+/// Takes a lock on the key that is released when the connection's current transaction ends
+///
+/// Blocks until any other transaction holding a lock on the same key ends
+pub fn lock_key(conn: &PgConnection, key: &str) -> Result<()> {
+	diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+		.bind::<Text, _>(key)
+		.execute(conn)
+		.map(|_| ())
+		.map_err(Into::into)
+}
+
 #[cfg(test)]
 mod tests {
 	use crate::db::pg_connection;
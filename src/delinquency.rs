@@ -0,0 +1,103 @@
+/*!
+delinquency determines how far past due a loan is and what the bank does about it
+*/
+use bigdecimal::{BigDecimal, Zero};
+
+use crate::loan::LoanPayment;
+use crate::types::Date;
+
+/// Rules the bank applies to loans with past due payments
+#[derive(Debug, Clone)]
+pub struct DelinquencyPolicy {
+	/// the number of days a payment may be past due before a late fee is charged
+	pub grace_period_days: i64,
+	/// the fee charged once on each payment that is still unpaid after the grace period
+	pub late_fee: BigDecimal,
+	/// the number of days past due at which an active loan is moved to default
+	pub default_after_days: i64,
+}
+
+impl Default for DelinquencyPolicy {
+	fn default() -> Self {
+		DelinquencyPolicy {
+			grace_period_days: 15,
+			late_fee: BigDecimal::from(25),
+			default_after_days: 90,
+		}
+	}
+}
+
+impl DelinquencyPolicy {
+	/// Checks whether the unpaid payment should be charged a late fee as of the date
+	///
+	/// A payment is only charged once
+	pub fn charges_late_fee(&self, payment: &LoanPayment, as_of: Date) -> bool {
+		payment.late_fee.is_zero() && days_past_due(payment.due_date, as_of) > self.grace_period_days
+	}
+	
+	/// Checks whether a loan this many days past due is in default
+	pub fn is_default(&self, days_past_due: i64) -> bool {
+		days_past_due >= self.default_after_days
+	}
+}
+
+/// The number of days a payment due on the due date is past due as of the date
+pub fn days_past_due(due_date: Date, as_of: Date) -> i64 {
+	(as_of - due_date).num_days().max(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::loan::DelinquencyBucket;
+	
+	use super::*;
+	
+	fn payment(due_date: Date, late_fee: i32) -> LoanPayment {
+		LoanPayment {
+			id: uuid::Uuid::new_v4(),
+			loan_id: uuid::Uuid::new_v4(),
+			principal_due: BigDecimal::from(100),
+			interest_due: BigDecimal::from(1),
+			due_date,
+			principle_transaction_id: None,
+			interest_transaction_id: None,
+			late_fee: BigDecimal::from(late_fee),
+			fee_transaction_id: None,
+			principal_paid: BigDecimal::zero(),
+			interest_paid: BigDecimal::zero(),
+			fees_paid: BigDecimal::zero(),
+			overpaid: BigDecimal::zero(),
+		}
+	}
+	
+	#[test]
+	fn late_fee_after_grace_period() {
+		let policy = DelinquencyPolicy::default();
+		let due_date = Date::from_ymd(2020, 2, 1);
+		
+		assert!(!policy.charges_late_fee(&payment(due_date, 0), Date::from_ymd(2020, 2, 16)));
+		assert!(policy.charges_late_fee(&payment(due_date, 0), Date::from_ymd(2020, 2, 17)));
+		// fees are only charged once
+		assert!(!policy.charges_late_fee(&payment(due_date, 25), Date::from_ymd(2020, 3, 1)));
+	}
+	
+	#[test]
+	fn default_threshold() {
+		let policy = DelinquencyPolicy { default_after_days: 60, ..Default::default() };
+		assert!(!policy.is_default(59));
+		assert!(policy.is_default(60));
+	}
+	
+	#[test]
+	fn buckets() {
+		let due_date = Date::from_ymd(2020, 1, 1);
+		let bucket = |as_of| DelinquencyBucket::from_days_past_due(days_past_due(due_date, as_of));
+		
+		assert_eq!(bucket(Date::from_ymd(2019, 12, 1)), DelinquencyBucket::Current);
+		assert_eq!(bucket(Date::from_ymd(2020, 1, 1)), DelinquencyBucket::Current);
+		assert_eq!(bucket(Date::from_ymd(2020, 1, 30)), DelinquencyBucket::Days1To29);
+		assert_eq!(bucket(Date::from_ymd(2020, 1, 31)), DelinquencyBucket::Days30To59);
+		assert_eq!(bucket(Date::from_ymd(2020, 3, 1)), DelinquencyBucket::Days60To89);
+		assert_eq!(bucket(Date::from_ymd(2020, 3, 31)), DelinquencyBucket::Days90Plus);
+	}
+}
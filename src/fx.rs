@@ -0,0 +1,227 @@
+/*!
+fx keeps the exchange rates used to convert funds between accounts held in different currencies
+*/
+use bigdecimal::BigDecimal;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::history::{EntryType, Filter};
+use crate::money::Currency;
+use crate::overdraft::OverdraftProtectionKind;
+use crate::schema::{fx_conversions, fx_rates};
+use crate::types::{Date, Id, Time};
+
+/// The number of decimal places rates are kept to
+pub const RATE_SCALE: i64 = 8;
+
+/// The rate of a currency pair from its effective date until the next rate takes effect
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+pub struct FxRate {
+	pub id: Id,
+	pub base_currency: Currency,
+	pub quote_currency: Currency,
+	/// the price of one unit of the base currency in the quote currency
+	pub rate: BigDecimal,
+	pub effective_date: Date,
+	pub created_at: Time,
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[table_name = "fx_rates"]
+pub struct NewFxRate {
+	pub base_currency: Currency,
+	pub quote_currency: Currency,
+	/// the price of one unit of the base currency in the quote currency
+	pub rate: BigDecimal,
+	pub effective_date: Date,
+}
+
+/// Funds converted from the sender's account in one currency to the receiver's account in another
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+pub struct FxConversion {
+	pub id: Id,
+	pub sender_id: Id,
+	pub receiver_id: Id,
+	/// the amount taken from the sender's account
+	pub sell_amount: BigDecimal,
+	pub sell_currency: Currency,
+	/// the amount credited to the receiver's account
+	pub buy_amount: BigDecimal,
+	pub buy_currency: Currency,
+	/// the rate applied, the price of one unit of the sell currency in the buy currency
+	pub rate: BigDecimal,
+	pub created_at: Time,
+	/// Key supplied by the client to make retries of the same request safe
+	pub idempotency_key: Option<String>,
+	/// The overdraft protection that covered the conversion, if it needed any
+	pub overdraft_protection: Option<OverdraftProtectionKind>,
+}
+
+impl FxConversion {
+	/// Indicates whether a request with these parameters repeats the request that recorded this conversion
+	pub fn is_repeat_of(&self, sender_id: &Id, receiver_id: &Id, sell_amount: &BigDecimal, sell_currency: Currency) -> bool {
+		self.sender_id.eq(sender_id)
+			&& self.receiver_id.eq(receiver_id)
+			&& self.sell_amount.eq(sell_amount)
+			&& self.sell_currency == sell_currency
+	}
+}
+
+#[derive(Insertable)]
+#[table_name = "fx_conversions"]
+pub struct NewFxConversion<'a> {
+	pub sender_id: &'a Id,
+	pub receiver_id: &'a Id,
+	pub sell_amount: &'a BigDecimal,
+	pub sell_currency: Currency,
+	pub buy_amount: &'a BigDecimal,
+	pub buy_currency: Currency,
+	pub rate: &'a BigDecimal,
+	pub idempotency_key: Option<&'a str>,
+	pub overdraft_protection: Option<OverdraftProtectionKind>,
+}
+
+/// The conversions the account received, or sent, that its history lists as matching the filter
+///
+/// Returns none when the filter's entry type is never a conversion in that direction
+fn matching_history<'a>(account_id: &'a Id, filter: &'a Filter, received: bool) -> Option<fx_conversions::BoxedQuery<'a, Pg>> {
+	match (filter.entry_type, received) {
+		(None, _) | (Some(EntryType::ConversionIn), true) | (Some(EntryType::ConversionOut), false) => {}
+		_ => return None,
+	}
+	let mut query = fx_conversions::table.into_boxed();
+	if let Some(starts_at) = filter.starts_at() {
+		query = query.filter(fx_conversions::created_at.ge(starts_at));
+	}
+	if let Some(ends_before) = filter.ends_before() {
+		query = query.filter(fx_conversions::created_at.lt(ends_before));
+	}
+	// the history lists what the account received, or what it sold
+	if received {
+		query = query.filter(fx_conversions::receiver_id.eq(account_id));
+		if let Some(min) = &filter.min_amount {
+			query = query.filter(fx_conversions::buy_amount.ge(min));
+		}
+		if let Some(max) = &filter.max_amount {
+			query = query.filter(fx_conversions::buy_amount.le(max));
+		}
+	} else {
+		query = query.filter(fx_conversions::sender_id.eq(account_id));
+		if let Some(min) = &filter.min_amount {
+			query = query.filter(fx_conversions::sell_amount.ge(min));
+		}
+		if let Some(max) = &filter.max_amount {
+			query = query.filter(fx_conversions::sell_amount.le(max));
+		}
+	}
+	Some(query)
+}
+
+/// Data store implementation for operating on exchange rates and conversions in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	pub fn create_rate(&self, conn: &PgConnection, new_rate: NewFxRate) -> db::Result<FxRate> {
+		diesel::insert_into(fx_rates::table)
+			.values(new_rate)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the pair's rate in effect on the date, which is the latest rate effective on or before it
+	pub fn find_effective(&self, conn: &PgConnection, base: Currency, quote: Currency, date: &Date) -> db::Result<FxRate> {
+		fx_rates::table
+			.filter(fx_rates::base_currency.eq(base)
+				.and(fx_rates::quote_currency.eq(quote))
+				.and(fx_rates::effective_date.le(date)))
+			.select(fx_rates::all_columns)
+			.order(fx_rates::effective_date.desc())
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn create_conversion(&self, conn: &PgConnection, new_conversion: NewFxConversion) -> db::Result<FxConversion> {
+		diesel::insert_into(fx_conversions::table)
+			.values(&new_conversion)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_conversion_by_idempotency_key(&self, conn: &PgConnection, key: &str) -> db::Result<FxConversion> {
+		fx_conversions::table
+			.filter(fx_conversions::idempotency_key.eq(key))
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds every conversion the account sent or received, oldest first
+	pub fn find_conversions_by_account(&self, conn: &PgConnection, account_id: &Id) -> db::Result<Vec<FxConversion>> {
+		fx_conversions::table
+			.filter(fx_conversions::sender_id.eq(account_id).or(fx_conversions::receiver_id.eq(account_id)))
+			.order((fx_conversions::created_at.asc(), fx_conversions::id.asc()))
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the conversions the account sent or received after the time, oldest first
+	pub fn find_conversions_by_account_after(&self, conn: &PgConnection, account_id: &Id, after: &Time) -> db::Result<Vec<FxConversion>> {
+		fx_conversions::table
+			.filter(fx_conversions::sender_id.eq(account_id).or(fx_conversions::receiver_id.eq(account_id)))
+			.filter(fx_conversions::created_at.gt(after))
+			.order((fx_conversions::created_at.asc(), fx_conversions::id.asc()))
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the conversions the account sent or received after and before the times that are given, oldest first
+	pub fn find_conversions_by_account_within(&self, conn: &PgConnection, account_id: &Id, after: Option<&Time>, before: Option<&Time>) -> db::Result<Vec<FxConversion>> {
+		let mut query = fx_conversions::table
+			.filter(fx_conversions::sender_id.eq(account_id).or(fx_conversions::receiver_id.eq(account_id)))
+			.into_boxed();
+		if let Some(after) = after {
+			query = query.filter(fx_conversions::created_at.gt(after));
+		}
+		if let Some(before) = before {
+			query = query.filter(fx_conversions::created_at.lt(before));
+		}
+		query
+			.order((fx_conversions::created_at.asc(), fx_conversions::id.asc()))
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Counts the conversions that the account's history lists as matching the filter
+	pub fn count_conversion_history(&self, conn: &PgConnection, account_id: &Id, filter: &Filter) -> db::Result<i64> {
+		let mut count = 0;
+		for &received in &[true, false] {
+			if let Some(query) = matching_history(account_id, filter, received) {
+				count += query.count().get_result::<i64>(conn)?;
+			}
+		}
+		Ok(count)
+	}
+	
+	/// Finds when the nth most recent of the conversions the account received, or sent, that match the filter of
+	/// its history was made, whichever is more recent
+	pub fn find_conversion_history_cutoff(&self, conn: &PgConnection, account_id: &Id, filter: &Filter, n: i64) -> db::Result<Option<Time>> {
+		let mut cutoff = None;
+		for &received in &[true, false] {
+			if let Some(query) = matching_history(account_id, filter, received) {
+				let found = query
+					.select(fx_conversions::created_at)
+					.order(fx_conversions::created_at.desc())
+					.offset(n - 1)
+					.first::<Time>(conn)
+					.optional()?;
+				cutoff = cutoff.max(found);
+			}
+		}
+		Ok(cutoff)
+	}
+}
@@ -0,0 +1,387 @@
+/*!
+history merges everything that moved money into or out of an account into a single timeline with a running balance
+*/
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Signed, Zero};
+use chrono::{TimeZone, Utc};
+use diesel::{
+	deserialize,
+	pg::Pg,
+	serialize,
+	sql_types::Varchar,
+};
+use serde::{Deserialize, Serialize};
+use strum;
+use strum_macros::{Display, EnumString};
+
+use crate::account_transaction::AccountTransaction;
+use crate::bank_transaction::{BankTransaction, BankTransactionType};
+use crate::fx::FxConversion;
+use crate::money::Currency;
+use crate::types::{Date, Id, Time};
+
+/// The number of entries in a page of history when the page size isn't given
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// The most entries returned in a page of history
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// What moved money into or out of the account
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EntryType {
+	Deposit,
+	Withdraw,
+	/// Funds sent to the account from another account
+	TransferIn,
+	/// Funds sent from the account to another account
+	TransferOut,
+	/// Loan principal paid out to the account
+	LoanDisbursement,
+	/// Principal or interest repaid on a loan from the account
+	LoanPayment,
+	/// Late fees and overdraft fees
+	Fee,
+	/// Interest credited to a savings account or charged on a negative balance
+	Interest,
+	/// Funds converted into the account's currency from another account
+	ConversionIn,
+	/// Funds converted out of the account's currency to another account
+	ConversionOut,
+	/// A deposit, withdrawal or transfer being undone
+	Reversal,
+}
+
+impl serialize::ToSql<Varchar, Pg> for EntryType {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for EntryType {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		EntryType::from_str(s).map_err(|_| "invalid history entry type".into())
+	}
+}
+
+impl From<&BankTransactionType> for EntryType {
+	fn from(transaction_type: &BankTransactionType) -> Self {
+		match transaction_type {
+			BankTransactionType::Deposit => EntryType::Deposit,
+			BankTransactionType::Withdraw => EntryType::Withdraw,
+			BankTransactionType::LoanPrincipal => EntryType::LoanDisbursement,
+			BankTransactionType::PrincipalRepayment | BankTransactionType::InterestRepayment => EntryType::LoanPayment,
+			BankTransactionType::LateFee | BankTransactionType::OverdraftFee => EntryType::Fee,
+			BankTransactionType::OverdraftInterest | BankTransactionType::InterestCredit => EntryType::Interest,
+		}
+	}
+}
+
+impl EntryType {
+	/// The types of the bank transactions listed as the entry type when they don't reverse another transaction
+	pub fn bank_transaction_types(self) -> Vec<BankTransactionType> {
+		match self {
+			EntryType::Deposit => vec![BankTransactionType::Deposit],
+			EntryType::Withdraw => vec![BankTransactionType::Withdraw],
+			EntryType::LoanDisbursement => vec![BankTransactionType::LoanPrincipal],
+			EntryType::LoanPayment => vec![BankTransactionType::PrincipalRepayment, BankTransactionType::InterestRepayment],
+			EntryType::Fee => vec![BankTransactionType::LateFee, BankTransactionType::OverdraftFee],
+			EntryType::Interest => vec![BankTransactionType::OverdraftInterest, BankTransactionType::InterestCredit],
+			_ => vec![],
+		}
+	}
+}
+
+/// A movement of money into or out of the account
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Entry {
+	/// id of the bank transaction, account transaction or conversion that moved the money
+	pub id: Id,
+	pub entry_type: EntryType,
+	/// positive when money moved into the account, negative when it moved out
+	pub amount: BigDecimal,
+	pub currency: Currency,
+	/// the account's balance once the entry was applied
+	pub balance: BigDecimal,
+	/// the vault a bank transaction moved money to or from
+	pub vault_name: Option<String>,
+	/// the other account in a transfer or conversion
+	pub counterparty_id: Option<Id>,
+	pub created_at: Time,
+}
+
+impl Entry {
+	/// Where the entry goes among entries made at the same time
+	fn position(&self) -> u8 {
+		match self.entry_type {
+			_ if self.amount.is_positive() => 0,
+			EntryType::Fee | EntryType::Interest => 2,
+			_ => 1,
+		}
+	}
+	
+	fn from_bank_transaction(t: BankTransaction) -> Self {
+		let amount = if t.transaction_type.is_credit() { t.amount } else { -t.amount };
+		Entry {
+			id: t.id,
+			entry_type: if t.reversal_of.is_some() { EntryType::Reversal } else { EntryType::from(&t.transaction_type) },
+			amount,
+			currency: t.currency,
+			balance: BigDecimal::zero(),
+			vault_name: Some(t.vault_name),
+			counterparty_id: None,
+			created_at: t.created_at,
+		}
+	}
+	
+	fn from_account_transaction(account_id: &Id, t: AccountTransaction) -> Self {
+		let (entry_type, amount, counterparty_id) = if t.receiver_id.eq(account_id) {
+			(EntryType::TransferIn, t.amount, t.sender_id)
+		} else {
+			(EntryType::TransferOut, -t.amount, t.receiver_id)
+		};
+		let entry_type = if t.reversal_of.is_some() { EntryType::Reversal } else { entry_type };
+		Entry {
+			id: t.id,
+			entry_type,
+			amount,
+			currency: t.currency,
+			balance: BigDecimal::zero(),
+			vault_name: None,
+			counterparty_id: Some(counterparty_id),
+			created_at: t.created_at,
+		}
+	}
+	
+	fn from_conversion(account_id: &Id, c: FxConversion) -> Self {
+		let (entry_type, amount, currency, counterparty_id) = if c.receiver_id.eq(account_id) {
+			(EntryType::ConversionIn, c.buy_amount, c.buy_currency, c.sender_id)
+		} else {
+			(EntryType::ConversionOut, -c.sell_amount, c.sell_currency, c.receiver_id)
+		};
+		Entry {
+			id: c.id,
+			entry_type,
+			amount,
+			currency,
+			balance: BigDecimal::zero(),
+			vault_name: None,
+			counterparty_id: Some(counterparty_id),
+			created_at: c.created_at,
+		}
+	}
+}
+
+/// Merges the account's transactions into its history, oldest first, with the balance after each entry
+///
+/// Entries made at the same time, like an overdraft sweep and the withdrawal it covers, list money moving in first
+/// and fees after the money movement they were charged for.
+pub fn merge(account_id: &Id, bank: Vec<BankTransaction>, transfers: Vec<AccountTransaction>, conversions: Vec<FxConversion>) -> Vec<Entry> {
+	merge_from(&BigDecimal::zero(), account_id, bank, transfers, conversions)
+}
+
+/// Merges the account's transactions made since it held the opening balance into its history, like `merge`
+pub fn merge_from(opening_balance: &BigDecimal, account_id: &Id, bank: Vec<BankTransaction>, transfers: Vec<AccountTransaction>, conversions: Vec<FxConversion>) -> Vec<Entry> {
+	let mut entries: Vec<Entry> = bank.into_iter().map(Entry::from_bank_transaction)
+		.chain(transfers.into_iter().map(|t| Entry::from_account_transaction(account_id, t)))
+		.chain(conversions.into_iter().map(|c| Entry::from_conversion(account_id, c)))
+		.collect();
+	entries.sort_by(|a, b| {
+		a.created_at.cmp(&b.created_at)
+			.then_with(|| a.position().cmp(&b.position()))
+			.then_with(|| a.id.cmp(&b.id))
+	});
+	
+	let mut balance = opening_balance.clone();
+	for entry in &mut entries {
+		balance += &entry.amount;
+		entry.balance = balance.clone();
+	}
+	entries
+}
+
+/// Narrows an account's history, every field that is set must match
+#[derive(Default, Debug)]
+pub struct Filter {
+	/// the first day included
+	pub from: Option<Date>,
+	/// the last day included
+	pub to: Option<Date>,
+	pub entry_type: Option<EntryType>,
+	/// the smallest amount included, regardless of the direction the money moved
+	pub min_amount: Option<BigDecimal>,
+	/// the largest amount included, regardless of the direction the money moved
+	pub max_amount: Option<BigDecimal>,
+}
+
+impl Filter {
+	pub fn matches(&self, entry: &Entry) -> bool {
+		let amount = entry.amount.abs();
+		self.starts_at().map_or(true, |starts_at| entry.created_at >= starts_at)
+			&& self.ends_before().map_or(true, |ends_before| entry.created_at < ends_before)
+			&& self.entry_type.map_or(true, |entry_type| entry.entry_type == entry_type)
+			&& self.min_amount.as_ref().map_or(true, |min| &amount >= min)
+			&& self.max_amount.as_ref().map_or(true, |max| &amount <= max)
+	}
+	
+	/// The start of the first day included
+	pub fn starts_at(&self) -> Option<Time> {
+		self.from.map(|from| Utc.from_utc_datetime(&from.and_hms(0, 0, 0)))
+	}
+	
+	/// The start of the day after the last day included
+	pub fn ends_before(&self) -> Option<Time> {
+		self.to.map(|to| Utc.from_utc_datetime(&to.succ().and_hms(0, 0, 0)))
+	}
+}
+
+/// Which entries of the matching history to return, counted from the most recent
+#[derive(Clone, Copy, Debug)]
+pub struct Page {
+	pub offset: usize,
+	/// capped at `MAX_PAGE_SIZE`
+	pub limit: usize,
+}
+
+impl Default for Page {
+	fn default() -> Self {
+		Page { offset: 0, limit: DEFAULT_PAGE_SIZE }
+	}
+}
+
+/// A page of an account's history, most recent first
+#[derive(Serialize, PartialEq, Debug)]
+pub struct HistoryPage {
+	pub entries: Vec<Entry>,
+	/// the number of entries matching the filter across all pages
+	pub total: usize,
+	pub offset: usize,
+	pub limit: usize,
+}
+
+/// Filters the history, oldest first, and returns the page of matching entries, most recent first
+///
+/// Balances are the account's running balance, so they're unaffected by the filter.
+pub fn paginate(entries: Vec<Entry>, filter: &Filter, page: Page) -> HistoryPage {
+	let limit = page.limit.min(MAX_PAGE_SIZE);
+	let matching: Vec<Entry> = entries.into_iter().rev().filter(|e| filter.matches(e)).collect();
+	let total = matching.len();
+	HistoryPage {
+		entries: matching.into_iter().skip(page.offset).take(limit).collect(),
+		total,
+		offset: page.offset,
+		limit,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+	
+	use chrono::Duration;
+	
+	use super::*;
+	
+	fn dec(s: &str) -> BigDecimal {
+		BigDecimal::from_str(s).unwrap()
+	}
+	
+	fn bank(account_id: Id, transaction_type: BankTransactionType, amount: &str, created_at: Time) -> BankTransaction {
+		BankTransaction {
+			id: Id::new_v4(),
+			account_id,
+			vault_name: "main".to_string(),
+			transaction_type,
+			amount: dec(amount),
+			created_at,
+			idempotency_key: None,
+			overdraft_protection: None,
+			currency: Currency::Usd,
+			reversal_of: None,
+			forced_by: None,
+		}
+	}
+	
+	fn transfer(sender_id: Id, receiver_id: Id, amount: &str, created_at: Time) -> AccountTransaction {
+		AccountTransaction {
+			id: Id::new_v4(),
+			sender_id,
+			receiver_id,
+			amount: dec(amount),
+			created_at,
+			idempotency_key: None,
+			overdraft_protection: None,
+			reversal_of: None,
+			forced_by: None,
+			currency: Currency::Usd,
+		}
+	}
+	
+	#[test]
+	fn running_balance() {
+		let account_id = Id::new_v4();
+		let other_id = Id::new_v4();
+		let start = Utc.ymd(2020, 7, 1).and_hms(9, 0, 0);
+		let day = |n: i64| start + Duration::days(n);
+		
+		let entries = merge(&account_id, vec![
+			bank(account_id, BankTransactionType::Deposit, "500", day(0)),
+			bank(account_id, BankTransactionType::Withdraw, "150", day(2)),
+			bank(account_id, BankTransactionType::PrincipalRepayment, "80", day(4)),
+			bank(account_id, BankTransactionType::InterestCredit, "0.25", day(5)),
+		], vec![
+			transfer(account_id, other_id, "100", day(1)),
+			// the sweep that covered the withdrawal is listed before it
+			transfer(other_id, account_id, "50", day(2)),
+		], vec![]);
+		
+		let summary: Vec<(EntryType, BigDecimal, BigDecimal)> = entries.iter()
+			.map(|e| (e.entry_type, e.amount.clone(), e.balance.clone()))
+			.collect();
+		assert_eq!(summary, vec![
+			(EntryType::Deposit, dec("500"), dec("500")),
+			(EntryType::TransferOut, dec("-100"), dec("400")),
+			(EntryType::TransferIn, dec("50"), dec("450")),
+			(EntryType::Withdraw, dec("-150"), dec("300")),
+			(EntryType::LoanPayment, dec("-80"), dec("220")),
+			(EntryType::Interest, dec("0.25"), dec("220.25")),
+		]);
+		assert_eq!(entries[1].counterparty_id, Some(other_id));
+	}
+	
+	#[test]
+	fn filter_and_paginate() {
+		let account_id = Id::new_v4();
+		let start = Utc.ymd(2020, 7, 1).and_hms(9, 0, 0);
+		let deposits = (0..10)
+			.map(|n| bank(account_id, BankTransactionType::Deposit, &(10 * (n + 1)).to_string(), start + Duration::days(n)))
+			.collect();
+		let entries = merge(&account_id, deposits, vec![], vec![]);
+		
+		let page = paginate(entries.clone(), &Filter::default(), Page { offset: 2, limit: 3 });
+		assert_eq!(page.total, 10);
+		let amounts: Vec<BigDecimal> = page.entries.iter().map(|e| e.amount.clone()).collect();
+		assert_eq!(amounts, vec![dec("80"), dec("70"), dec("60")]);
+		assert_eq!(page.entries[0].balance, dec("360"));
+		
+		let filter = Filter {
+			from: Some(Date::from_ymd(2020, 7, 3)),
+			to: Some(Date::from_ymd(2020, 7, 8)),
+			min_amount: Some(dec("40")),
+			max_amount: Some(dec("70")),
+			..Filter::default()
+		};
+		let page = paginate(entries.clone(), &filter, Page::default());
+		let amounts: Vec<BigDecimal> = page.entries.iter().map(|e| e.amount.clone()).collect();
+		assert_eq!(amounts, vec![dec("70"), dec("60"), dec("50"), dec("40")]);
+		
+		let filter = Filter { entry_type: Some(EntryType::Withdraw), ..Filter::default() };
+		assert_eq!(paginate(entries, &filter, Page::default()).total, 0);
+	}
+}
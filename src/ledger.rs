@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Zero};
+use diesel::{
+	deserialize,
+	dsl::{sql, sum},
+	pg::Pg,
+	prelude::*,
+	serialize,
+	sql_types::{Nullable, Numeric, Varchar},
+};
+use serde::Serialize;
+use strum;
+use strum_macros::{Display, EnumString};
+
+use crate::db;
+use crate::money::Currency;
+use crate::schema::{journal_entries, journal_lines};
+use crate::types::{Id, Time};
+
+/// An account in the bank's general ledger
+///
+/// Every movement of funds is posted to the ledger as balanced debits and credits between these accounts
+#[derive(Clone, Serialize, PartialEq, Eq, Hash, Debug)]
+pub enum LedgerAccount {
+	/// funds held in a user's account, owed by the bank to the user
+	Deposit(Id),
+	/// funds held by the bank in a vault
+	Vault(String),
+	/// principal and capitalized interest owed to the bank on a loan
+	LoanReceivable(Id),
+	/// loan cash and savings interest a vault has paid out into accounts, net of the repayments and fees returned to it
+	LoanFunding(String),
+	/// interest earned by the bank on loans funded by a vault
+	InterestIncome(String),
+	/// fees earned by the bank on loans funded by a vault
+	FeeIncome(String),
+	/// interest paid by the bank on savings funded by a vault
+	InterestExpense(String),
+	/// the bank's holding of a currency bought and sold converting funds between currencies
+	FxPosition(Currency),
+}
+
+impl LedgerAccount {
+	pub fn kind(&self) -> LedgerAccountKind {
+		match self {
+			LedgerAccount::Deposit(_) => LedgerAccountKind::Deposit,
+			LedgerAccount::Vault(_) => LedgerAccountKind::Vault,
+			LedgerAccount::LoanReceivable(_) => LedgerAccountKind::LoanReceivable,
+			LedgerAccount::LoanFunding(_) => LedgerAccountKind::LoanFunding,
+			LedgerAccount::InterestIncome(_) => LedgerAccountKind::InterestIncome,
+			LedgerAccount::FeeIncome(_) => LedgerAccountKind::FeeIncome,
+			LedgerAccount::InterestExpense(_) => LedgerAccountKind::InterestExpense,
+			LedgerAccount::FxPosition(_) => LedgerAccountKind::FxPosition,
+		}
+	}
+	
+	/// The identifier of the account within its kind, i.e. an id or vault name
+	pub fn key(&self) -> String {
+		match self {
+			LedgerAccount::Deposit(id) | LedgerAccount::LoanReceivable(id) => id.to_string(),
+			LedgerAccount::Vault(name)
+			| LedgerAccount::LoanFunding(name)
+			| LedgerAccount::InterestIncome(name)
+			| LedgerAccount::FeeIncome(name)
+			| LedgerAccount::InterestExpense(name) => name.clone(),
+			LedgerAccount::FxPosition(currency) => currency.to_string(),
+		}
+	}
+}
+
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Serialize, Eq, PartialEq, Hash, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerAccountKind {
+	/// Liability: credits increase the balance
+	Deposit,
+	/// Asset: debits increase the balance
+	Vault,
+	/// Asset: debits increase the balance
+	LoanReceivable,
+	/// Asset: debits increase the balance
+	LoanFunding,
+	/// Income: credits increase the balance
+	InterestIncome,
+	/// Income: credits increase the balance
+	FeeIncome,
+	/// Expense: debits increase the balance
+	InterestExpense,
+	/// Position: credits increase the balance
+	FxPosition,
+}
+
+impl LedgerAccountKind {
+	/// Indicates whether debits increase the balance of accounts of this kind
+	pub fn is_debit_normal(&self) -> bool {
+		match self {
+			LedgerAccountKind::Vault
+			| LedgerAccountKind::LoanReceivable
+			| LedgerAccountKind::LoanFunding
+			| LedgerAccountKind::InterestExpense => true,
+			LedgerAccountKind::Deposit
+			| LedgerAccountKind::InterestIncome
+			| LedgerAccountKind::FeeIncome
+			| LedgerAccountKind::FxPosition => false,
+		}
+	}
+	
+	/// Converts net debits (debits - credits) into a balance based on the kind's normal side
+	fn balance(&self, net_debits: BigDecimal) -> BigDecimal {
+		if self.is_debit_normal() {
+			net_debits
+		} else {
+			-net_debits
+		}
+	}
+}
+
+impl serialize::ToSql<Varchar, Pg> for LedgerAccountKind {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for LedgerAccountKind {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		LedgerAccountKind::from_str(s).map_err(|_| "invalid ledger account kind".into())
+	}
+}
+
+#[derive(AsExpression, FromSqlRow, Serialize, Eq, PartialEq, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum JournalEntryType {
+	/// A user putting funds into their account
+	Deposit,
+	/// A user removing funds from their account
+	Withdraw,
+	/// Funds sent from one account to another
+	Transfer,
+	/// Loan principal credited to the borrower's account
+	LoanDisbursement,
+	/// Principal and interest repaid on a loan
+	LoanRepayment,
+	/// Unpaid interest added to a loan's balance
+	InterestCapitalization,
+	/// Interest credited to a savings account
+	InterestCredit,
+	/// Fee or interest charged for using an overdraft line
+	OverdraftCharge,
+	/// Funds converted from one currency to another
+	FxConversion,
+	/// A deposit, withdrawal or transfer being undone
+	Reversal,
+}
+
+impl serialize::ToSql<Varchar, Pg> for JournalEntryType {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for JournalEntryType {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		JournalEntryType::from_str(s).map_err(|_| "invalid journal entry type".into())
+	}
+}
+
+/// A journal entry groups the balanced lines posted for a single movement of funds
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+#[table_name = "journal_entries"]
+pub struct JournalEntry {
+	pub id: Id,
+	pub entry_type: JournalEntryType,
+	/// id of the bank or account transaction that caused the entry, if any
+	pub reference_id: Option<Id>,
+	pub created_at: Time,
+}
+
+/// A single debit or credit to a ledger account
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+pub struct JournalLine {
+	pub id: Id,
+	pub entry_id: Id,
+	pub account_kind: LedgerAccountKind,
+	pub account_key: String,
+	pub debit: BigDecimal,
+	pub credit: BigDecimal,
+}
+
+/// Moves an amount by debiting one ledger account and crediting another
+pub struct Posting<'a> {
+	pub debit: LedgerAccount,
+	pub credit: LedgerAccount,
+	pub amount: &'a BigDecimal,
+}
+
+/// Parameter object for posting a journal entry
+///
+/// Entries are made up of postings so that the total debits always equal the total credits
+pub struct NewJournalEntry<'a> {
+	pub entry_type: JournalEntryType,
+	pub reference_id: Option<&'a Id>,
+	pub postings: Vec<Posting<'a>>,
+}
+
+#[derive(Insertable)]
+#[table_name = "journal_entries"]
+struct NewEntryRow<'a> {
+	entry_type: JournalEntryType,
+	reference_id: Option<&'a Id>,
+}
+
+#[derive(Insertable)]
+#[table_name = "journal_lines"]
+struct NewLineRow<'a> {
+	entry_id: &'a Id,
+	account_kind: LedgerAccountKind,
+	account_key: String,
+	debit: BigDecimal,
+	credit: BigDecimal,
+}
+
+/// Total debits and credits across the entire ledger
+#[derive(Serialize, PartialEq, Debug)]
+pub struct TrialBalance {
+	pub debits: BigDecimal,
+	pub credits: BigDecimal,
+}
+
+impl TrialBalance {
+	pub fn is_balanced(&self) -> bool {
+		self.debits == self.credits
+	}
+}
+
+/// A ledger account whose stored balance does not match the balance derived from the journal
+#[derive(Serialize, PartialEq, Debug)]
+pub struct Discrepancy {
+	pub account: LedgerAccount,
+	/// balance stored on the account, vault or loan
+	pub stored: BigDecimal,
+	/// balance derived from the journal
+	pub derived: BigDecimal,
+}
+
+/// Result of checking stored balances against the journal
+#[derive(Serialize, PartialEq, Debug)]
+pub struct Reconciliation {
+	pub trial_balance: TrialBalance,
+	pub discrepancies: Vec<Discrepancy>,
+}
+
+impl Reconciliation {
+	/// Indicates whether the journal is balanced and agrees with every stored balance
+	pub fn is_reconciled(&self) -> bool {
+		self.trial_balance.is_balanced() && self.discrepancies.is_empty()
+	}
+}
+
+/// Data store implementation for operating on the general ledger in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	/// Posts a journal entry and its debit and credit lines
+	pub fn post(&self, conn: &PgConnection, new_entry: NewJournalEntry) -> db::Result<JournalEntry> {
+		conn.transaction(|| {
+			let entry: JournalEntry = diesel::insert_into(journal_entries::table)
+				.values(&NewEntryRow {
+					entry_type: new_entry.entry_type,
+					reference_id: new_entry.reference_id,
+				})
+				.get_result(conn)?;
+			
+			let mut lines = Vec::with_capacity(new_entry.postings.len() * 2);
+			for posting in new_entry.postings {
+				lines.push(NewLineRow {
+					entry_id: &entry.id,
+					account_kind: posting.debit.kind(),
+					account_key: posting.debit.key(),
+					debit: posting.amount.clone(),
+					credit: BigDecimal::zero(),
+				});
+				lines.push(NewLineRow {
+					entry_id: &entry.id,
+					account_kind: posting.credit.kind(),
+					account_key: posting.credit.key(),
+					debit: BigDecimal::zero(),
+					credit: posting.amount.clone(),
+				});
+			}
+			
+			diesel::insert_into(journal_lines::table)
+				.values(&lines)
+				.execute(conn)?;
+			
+			Ok(entry)
+		})
+	}
+	
+	pub fn find_lines(&self, conn: &PgConnection, entry_id: &Id) -> db::Result<Vec<JournalLine>> {
+		journal_lines::table
+			.filter(journal_lines::entry_id.eq(entry_id))
+			.select(journal_lines::all_columns)
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Derives the balance of a ledger account from its journal lines
+	pub fn balance(&self, conn: &PgConnection, account: &LedgerAccount) -> db::Result<BigDecimal> {
+		let kind = account.kind();
+		let net_debits = journal_lines::table
+			.filter(journal_lines::account_kind.eq(kind).and(journal_lines::account_key.eq(account.key())))
+			.select(sum(journal_lines::debit - journal_lines::credit))
+			.first::<Option<BigDecimal>>(conn)?;
+		
+		Ok(kind.balance(net_debits.unwrap_or_default()))
+	}
+	
+	/// Derives the balance of every ledger account of the given kind, keyed by the account's key
+	pub fn balances(&self, conn: &PgConnection, kind: LedgerAccountKind) -> db::Result<HashMap<String, BigDecimal>> {
+		let rows = journal_lines::table
+			.filter(journal_lines::account_kind.eq(kind))
+			.group_by(journal_lines::account_key)
+			.select((journal_lines::account_key, sql::<Nullable<Numeric>>("SUM(debit - credit)")))
+			.load::<(String, Option<BigDecimal>)>(conn)?;
+		
+		Ok(rows.into_iter()
+			.map(|(key, net_debits)| (key, kind.balance(net_debits.unwrap_or_default())))
+			.collect())
+	}
+	
+	pub fn trial_balance(&self, conn: &PgConnection) -> db::Result<TrialBalance> {
+		let debits = journal_lines::table
+			.select(sum(journal_lines::debit))
+			.first::<Option<BigDecimal>>(conn)?;
+		let credits = journal_lines::table
+			.select(sum(journal_lines::credit))
+			.first::<Option<BigDecimal>>(conn)?;
+		
+		Ok(TrialBalance {
+			debits: debits.unwrap_or_default(),
+			credits: credits.unwrap_or_default(),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testutil::*;
+	
+	use super::*;
+	
+	#[test]
+	fn post_balanced_entry() {
+		let f = Fixture::new();
+		let suite = Suite::setup();
+		let account = f.account_factory.checking_account(f.user_factory.bob().id);
+		let vault = f.insert_main_vault(0);
+		
+		let amount = BigDecimal::from(150);
+		let entry = suite.ledger_repo.post(&f.conn(), NewJournalEntry {
+			entry_type: JournalEntryType::Deposit,
+			reference_id: None,
+			postings: vec![Posting {
+				debit: LedgerAccount::Vault(vault.name.clone()),
+				credit: LedgerAccount::Deposit(account.id),
+				amount: &amount,
+			}],
+		}).unwrap();
+		
+		let lines = suite.ledger_repo.find_lines(&f.conn(), &entry.id).unwrap();
+		assert_eq!(lines.len(), 2);
+		
+		let vault_balance = suite.ledger_repo.balance(&f.conn(), &LedgerAccount::Vault(vault.name)).unwrap();
+		assert_eq!(vault_balance, amount);
+		let account_balance = suite.ledger_repo.balance(&f.conn(), &LedgerAccount::Deposit(account.id)).unwrap();
+		assert_eq!(account_balance, amount);
+		
+		let trial_balance = suite.ledger_repo.trial_balance(&f.conn(), ).unwrap();
+		assert!(trial_balance.is_balanced());
+		assert_eq!(trial_balance.debits, amount);
+	}
+	
+	#[test]
+	fn balances_by_kind() {
+		let f = Fixture::new();
+		let suite = Suite::setup();
+		let bob_account = f.account_factory.checking_account(f.user_factory.bob().id);
+		let lucy_account = f.account_factory.checking_account(f.user_factory.lucy().id);
+		
+		let amount = BigDecimal::from(40);
+		suite.ledger_repo.post(&f.conn(), NewJournalEntry {
+			entry_type: JournalEntryType::Transfer,
+			reference_id: None,
+			postings: vec![Posting {
+				debit: LedgerAccount::Deposit(bob_account.id),
+				credit: LedgerAccount::Deposit(lucy_account.id),
+				amount: &amount,
+			}],
+		}).unwrap();
+		
+		let balances = suite.ledger_repo.balances(&f.conn(), LedgerAccountKind::Deposit).unwrap();
+		assert_eq!(balances[&bob_account.id.to_string()], BigDecimal::from(-40));
+		assert_eq!(balances[&lucy_account.id.to_string()], amount);
+	}
+}
@@ -1,4 +1,5 @@
 #![allow(warnings)]
+#![recursion_limit = "256"]
 #[macro_use]
 extern crate diesel;
 
@@ -9,9 +10,25 @@ mod bank_transaction;
 mod account_transaction;
 mod vault;
 mod loan;
+mod benchmark;
+mod amortization;
+mod day_count;
+mod delinquency;
+mod allocation;
+mod ledger;
+mod accrual;
+mod savings;
+mod overdraft;
+mod money;
+mod fx;
+mod history;
+mod statement;
+mod snapshot;
+mod scheduled_transfer;
 mod bank;
 mod types;
-mod db;
+pub mod db;
+pub mod api;
 
 #[cfg(test)]
 mod testutil;
@@ -13,16 +13,20 @@ use diesel::{
 	sql_types::Varchar,
 };
 use diesel::pg::Pg;
+use serde::{Deserialize, Serialize};
 use strum;
 use strum_macros::{Display, EnumString};
 
+use crate::allocation::Allocation;
 use crate::db;
-use crate::schema::{loan_payments, loans};
-use crate::types::{Date, Id};
+use crate::day_count::DayCountConvention;
+use crate::money::{self, Currency, Money, RoundingMode};
+use crate::schema::{loan_payments, loan_rate_changes, loan_repayments, loans};
+use crate::types::{Date, DateExt, Id, Time};
 
 /// Loan issued by the bank to a user
 /// Loans are amortized and the borrower must make periodic payments that cover both principal and interest
-#[derive(Queryable, Identifiable, Debug)]
+#[derive(Queryable, Identifiable, Serialize, Debug)]
 pub struct Loan {
 	pub id: Id,
 	/// id of the user (borrower)
@@ -53,40 +57,186 @@ pub struct Loan {
 	pub capitalized_interest: BigDecimal,
 	/// the state of the loan
 	pub state: LoanState,
+	/// how the loan's principal is spread over its payments
+	pub amortization_method: AmortizationMethod,
+	/// id of the user who approved the loan
+	pub approved_by: Option<Id>,
+	/// the time the loan was approved
+	pub approved_at: Option<Time>,
+	/// the number of days the oldest unpaid payment is past due as of the last delinquency assessment
+	pub days_past_due: i32,
+	pub delinquency_bucket: DelinquencyBucket,
+	/// the date interest has been accrued through, none until interest is first accrued
+	pub interest_accrued_through: Option<Date>,
+	/// how the days in a period are counted when calculating its interest
+	pub day_count_convention: DayCountConvention,
+	/// the name of the benchmark a variable rate loan is indexed to, none for fixed rate loans
+	///
+	/// a variable rate loan's interest rate is the benchmark rate plus the margin, kept between the floor and cap
+	pub benchmark: Option<String>,
+	/// basis points added to the benchmark rate
+	pub rate_margin: i16,
+	/// the lowest rate in basis points the loan can reset to
+	pub rate_floor: Option<i16>,
+	/// the highest rate in basis points the loan can reset to
+	pub rate_cap: Option<i16>,
+	/// the number of months between rate resets, zero resets the rate every payment period
+	pub rate_reset_frequency: i16,
+	/// the currency the loan is lent and repaid in, the currency of its vault
+	pub currency: Currency,
 }
 
 impl Loan {
 	/// Gets the interest rate and converts it from basis points to BigDecimal
 	pub fn interest_rate(&self) -> BigDecimal {
-		BigDecimal::from(self.interest_rate) / 10_000
+		rate_from_basis_points(self.interest_rate)
 	}
 	
-	/// Calculates the months til maturity from the current date
-	pub fn months_til_maturity(&self, curr_date: Date) -> u16 {
-		let years = self.maturity_date.year() - curr_date.year();
-		let months = (self.maturity_date.month() - curr_date.month()) + (years * 12) as u32;
-		months as u16
+	/// The principal and capitalized interest left to repay in the loan's currency
+	pub fn outstanding(&self) -> Money {
+		Money::new(self.balance.clone(), self.currency)
 	}
 	
-	/// Calculates the principle due for a pay period
+	/// The interest rate in basis points
+	pub fn interest_rate_basis_points(&self) -> i16 {
+		self.interest_rate
+	}
+	
+	/// The number of months between accruals
 	///
-	/// # Arguments
-	/// `curr_date` - determines the months left til maturity and is used to calculate the principal payment
-	pub fn principal_due(&self, curr_date: Date) -> BigDecimal {
-		let months_til_maturity = self.months_til_maturity(curr_date);
-		(&self.balance)
-			.div(&BigDecimal::from(months_til_maturity))
-			.mul(BigDecimal::from(self.payment_frequency))
+	/// Interest is accrued often enough to land on every payment date, compounding date and rate reset date
+	pub fn accrual_frequency(&self) -> i16 {
+		let mut frequency = self.payment_frequency;
+		if self.compounds() {
+			frequency = gcd(frequency, self.compound_frequency);
+		}
+		if self.is_variable_rate() {
+			frequency = gcd(frequency, self.reset_frequency());
+		}
+		frequency.max(1)
+	}
+	
+	/// Checks whether the loan's rate is indexed to a benchmark
+	pub fn is_variable_rate(&self) -> bool {
+		self.benchmark.is_some()
+	}
+	
+	/// The number of months between rate resets
+	pub fn reset_frequency(&self) -> i16 {
+		let frequency = if self.rate_reset_frequency > 0 { self.rate_reset_frequency } else { self.payment_frequency };
+		frequency.max(1)
+	}
+	
+	/// Checks whether a variable rate loan's rate resets on the date
+	pub fn is_rate_reset_date(&self, date: Date) -> bool {
+		let months = self.issue_date.months_until(&date);
+		self.is_variable_rate()
+			&& date > self.issue_date
+			&& months % self.reset_frequency() as u16 == 0
+			&& self.issue_date.increment_date_by_months(months) == date
+	}
+	
+	/// The dates after the start date and before maturity that a variable rate loan's rate resets on
+	pub fn rate_reset_dates(&self, start_date: Date) -> Vec<Date> {
+		if !self.is_variable_rate() {
+			return vec![];
+		}
+		let frequency = self.reset_frequency() as u16;
+		(self.issue_date.months_until(&start_date) / frequency + 1..)
+			.map(|resets| self.issue_date.increment_date_by_months(resets * frequency))
+			.skip_while(|date| *date <= start_date)
+			.take_while(|date| *date < self.maturity_date)
+			.collect()
+	}
+	
+	/// The rate in basis points the loan is charged when its benchmark is at the benchmark rate
+	pub fn indexed_rate(&self, benchmark_rate: i16) -> i16 {
+		index_rate(benchmark_rate, self.rate_margin, self.rate_floor, self.rate_cap)
+	}
+	
+	/// Checks whether unpaid interest is ever capitalized, a compound frequency of zero means it never is
+	pub fn compounds(&self) -> bool {
+		self.compound_frequency > 0
+	}
+	
+	/// Checks whether the date ends a compounding period
+	pub fn is_compounding_date(&self, date: Date) -> bool {
+		self.compounds()
+			&& date > self.issue_date
+			&& self.issue_date.months_until(&date) % self.compound_frequency as u16 == 0
+	}
+	
+	/// Calculates the interest on the current balance for the next accrual period, rounded to the accrual scale
+	pub fn periodic_interest(&self) -> BigDecimal {
+		money::round(&self.interest_between(self.accrued_through(), self.next_accrual_date()), money::ACCRUAL_SCALE, RoundingMode::HalfEven)
+	}
+	
+	/// Calculates the interest on the current balance from the start date up to the end date using the loan's day count convention
+	pub fn interest_between(&self, start: Date, end: Date) -> BigDecimal {
+		self.day_count_convention.interest(&self.balance, &self.interest_rate(), start, end)
+	}
+	
+	/// The date interest has been accrued through, which is the issue date until interest is first accrued
+	pub fn accrued_through(&self) -> Date {
+		self.interest_accrued_through.unwrap_or(self.issue_date)
+	}
+	
+	/// The date the next accrual period ends
+	///
+	/// Periods are counted from the issue date so that month end dates don't drift
+	pub fn next_accrual_date(&self) -> Date {
+		let frequency = self.accrual_frequency() as u16;
+		let periods_accrued = self.issue_date.months_until(&self.accrued_through()) / frequency;
+		self.issue_date.increment_date_by_months((periods_accrued + 1) * frequency)
+	}
+	
+	/// Calculates the months til maturity from the current date
+	pub fn months_til_maturity(&self, curr_date: Date) -> u16 {
+		curr_date.months_until(&self.maturity_date)
+	}
+}
+
+/// Converts a rate in basis points to a decimal, e.g. 200 basis points is 0.02
+pub fn rate_from_basis_points(basis_points: i16) -> BigDecimal {
+	BigDecimal::from(basis_points) / 10_000
+}
+
+/// Adds the margin to the benchmark rate and keeps the result between the floor and cap, rates never go below zero
+pub fn index_rate(benchmark_rate: i16, margin: i16, floor: Option<i16>, cap: Option<i16>) -> i16 {
+	let mut rate = benchmark_rate as i32 + margin as i32;
+	if let Some(floor) = floor {
+		rate = rate.max(floor as i32);
 	}
+	if let Some(cap) = cap {
+		rate = rate.min(cap as i32);
+	}
+	rate.max(0).min(i16::MAX as i32) as i16
 }
 
+fn gcd(a: i16, b: i16) -> i16 {
+	if b == 0 { a } else { gcd(b, a % b) }
+}
 
-#[derive(Debug, AsExpression, FromSqlRow, Eq, PartialEq, EnumString, Display)]
+/// The state of a loan
+///
+/// A loan moves through its states as follows:
+/// - PendingApproval -> Approved, Rejected or Cancelled
+/// - Approved -> Active (once disbursed) or Cancelled
+/// - Active -> Paid or Default
+/// - Default -> Paid
+#[derive(Debug, Clone, Copy, AsExpression, FromSqlRow, Serialize, Eq, PartialEq, EnumString, Display)]
 #[sql_type = "Varchar"]
 #[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum LoanState {
 	/// The loan is pending approval
 	PendingApproval,
+	/// The loan has been approved and is waiting to be disbursed
+	Approved,
+	/// The loan application was turned down by the bank
+	Rejected,
+	/// The loan was withdrawn before it was disbursed
+	Cancelled,
 	/// Active indicates the loan balance is being repaid within the specified terms
 	Active,
 	/// All principal and interest payments have been fulfilled
@@ -95,6 +245,20 @@ pub enum LoanState {
 	Default,
 }
 
+impl LoanState {
+	/// Checks whether a loan in this state may move to the next state
+	pub fn can_transition_to(&self, next: LoanState) -> bool {
+		use LoanState::*;
+		match (self, next) {
+			(PendingApproval, Approved) | (PendingApproval, Rejected) | (PendingApproval, Cancelled) => true,
+			(Approved, Active) | (Approved, Cancelled) => true,
+			(Active, Paid) | (Active, Default) => true,
+			(Default, Paid) => true,
+			_ => false,
+		}
+	}
+}
+
 impl Default for LoanState {
 	fn default() -> Self { LoanState::PendingApproval }
 }
@@ -114,6 +278,90 @@ impl FromSql<Varchar, Pg> for LoanState {
 	}
 }
 
+/// Determines how a loan's principal is spread over its payments
+#[derive(Debug, Clone, Copy, AsExpression, FromSqlRow, Serialize, Deserialize, Eq, PartialEq, EnumString, Display)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AmortizationMethod {
+	/// Every payment is the same amount, the share of principal grows as the interest due shrinks (annuity)
+	LevelPayment,
+	/// Every payment repays the same amount of principal plus the interest due on the remaining balance
+	LevelPrincipal,
+}
+
+impl Default for AmortizationMethod {
+	fn default() -> Self { AmortizationMethod::LevelPayment }
+}
+
+impl ToSql<Varchar, Pg> for AmortizationMethod {
+	fn to_sql<W: std::io::Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+		ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl FromSql<Varchar, Pg> for AmortizationMethod {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		Ok(AmortizationMethod::from_str(s)?)
+	}
+}
+
+/// Groups loans by how far past due their oldest unpaid payment is
+#[derive(Debug, Clone, Copy, AsExpression, FromSqlRow, Serialize, Eq, PartialEq, PartialOrd, Ord, EnumString, Display)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DelinquencyBucket {
+	/// No payments are past due
+	Current,
+	#[strum(serialize = "days_1_29")]
+	#[serde(rename = "days_1_29")]
+	Days1To29,
+	#[strum(serialize = "days_30_59")]
+	#[serde(rename = "days_30_59")]
+	Days30To59,
+	#[strum(serialize = "days_60_89")]
+	#[serde(rename = "days_60_89")]
+	Days60To89,
+	#[strum(serialize = "days_90_plus")]
+	#[serde(rename = "days_90_plus")]
+	Days90Plus,
+}
+
+impl DelinquencyBucket {
+	pub fn from_days_past_due(days_past_due: i64) -> Self {
+		match days_past_due {
+			d if d <= 0 => DelinquencyBucket::Current,
+			1..=29 => DelinquencyBucket::Days1To29,
+			30..=59 => DelinquencyBucket::Days30To59,
+			60..=89 => DelinquencyBucket::Days60To89,
+			_ => DelinquencyBucket::Days90Plus,
+		}
+	}
+}
+
+impl Default for DelinquencyBucket {
+	fn default() -> Self { DelinquencyBucket::Current }
+}
+
+impl ToSql<Varchar, Pg> for DelinquencyBucket {
+	fn to_sql<W: std::io::Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+		ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl FromSql<Varchar, Pg> for DelinquencyBucket {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		Ok(DelinquencyBucket::from_str(s)?)
+	}
+}
+
 #[derive(Insertable)]
 #[table_name = "loans"]
 pub struct NewLoan {
@@ -127,38 +375,147 @@ pub struct NewLoan {
 	pub payment_frequency: i16,
 	pub compound_frequency: i16,
 	pub state: LoanState,
+	pub amortization_method: AmortizationMethod,
+	pub day_count_convention: DayCountConvention,
+	pub benchmark: Option<String>,
+	pub rate_margin: i16,
+	pub rate_floor: Option<i16>,
+	pub rate_cap: Option<i16>,
+	pub rate_reset_frequency: i16,
+	pub currency: Currency,
 }
 
-/// Data store implementation for operating on loans in the database
-pub struct Repo {
-	db: db::PgPool,
+/// A borrower's request for a loan, reviewed by the bank before the loan can be disbursed
+#[derive(Deserialize, Debug)]
+pub struct LoanApplication {
+	pub user_id: Id,
+	pub vault_name: String,
+	pub principal: BigDecimal,
+	/// the interest rate in basis points, variable rate loans take their rate from the benchmark instead
+	#[serde(default)]
+	pub interest_rate: i16,
+	pub issue_date: Date,
+	pub maturity_date: Date,
+	pub payment_frequency: i16,
+	pub compound_frequency: i16,
+	#[serde(default)]
+	pub amortization_method: AmortizationMethod,
+	#[serde(default)]
+	pub day_count_convention: DayCountConvention,
+	/// the terms of a variable rate loan, none for fixed rate loans
+	#[serde(default)]
+	pub variable_rate: Option<VariableRate>,
 }
 
+/// Terms that index a loan's rate to a benchmark
+#[derive(Deserialize, Debug, Clone)]
+pub struct VariableRate {
+	pub benchmark: String,
+	/// basis points added to the benchmark rate
+	#[serde(default)]
+	pub margin: i16,
+	pub floor: Option<i16>,
+	pub cap: Option<i16>,
+	/// the number of months between rate resets, zero resets the rate every payment period
+	#[serde(default)]
+	pub reset_frequency: i16,
+}
+
+/// The amount needed to repay a loan in full on a date
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PayoffQuote {
+	pub loan_id: Id,
+	/// the date the loan is repaid on
+	pub payoff_date: Date,
+	/// the outstanding balance, including capitalized interest
+	pub balance: BigDecimal,
+	/// the unpaid interest accrued through the loan's last accrual date
+	pub accrued_interest: BigDecimal,
+	/// the interest from the loan's last accrual date up to the payoff date
+	pub per_diem_interest: BigDecimal,
+	/// late fees charged on unpaid payments
+	pub fees: BigDecimal,
+	pub total: BigDecimal,
+}
+
+/// Data store implementation for operating on loans in the database
+pub struct Repo;
+
 impl Repo {
-	pub fn new(db: db::PgPool) -> Self {
-		Repo { db }
+	pub fn new() -> Self {
+		Repo
 	}
 	
-	pub fn create(&self, new_loan: NewLoan) -> db::Result<Loan> {
+	pub fn create(&self, conn: &PgConnection, new_loan: NewLoan) -> db::Result<Loan> {
 		//todo: validate orig_principal == curr_principal
-		let conn = &self.db.get()?;
 		diesel::insert_into(loans::table)
 			.values(&new_loan)
 			.get_result(conn)
 			.map_err(Into::into)
 	}
 	
-	pub fn find_by_id(&self, id: &uuid::Uuid) -> db::Result<Loan> {
-		let conn = &self.db.get()?;
+	pub fn find_by_id(&self, conn: &PgConnection, id: &uuid::Uuid) -> db::Result<Loan> {
+		loans::table
+			.find(id)
+			.select(loans::all_columns)
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the loan and locks it until the current transaction ends
+	pub fn find_for_update(&self, conn: &PgConnection, id: &Id) -> db::Result<Loan> {
 		loans::table
 			.find(id)
 			.select(loans::all_columns)
+			.for_no_key_update()
 			.first(conn)
 			.map_err(Into::into)
 	}
 	
-	pub fn set_state(&self, id: &uuid::Uuid, state: LoanState) -> db::Result<Loan> {
-		let conn = &self.db.get()?;
+	/// Marks the loan approved by the user at the current time
+	pub fn approve(&self, conn: &PgConnection, id: &Id, approved_by: &Id) -> db::Result<Loan> {
+		diesel::update(loans::table)
+			.filter(loans::id.eq(id))
+			.set((
+				loans::state.eq(LoanState::Approved),
+				loans::approved_by.eq(approved_by),
+				loans::approved_at.eq(diesel::dsl::now),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds all loans in any of the states
+	pub fn find_by_states(&self, conn: &PgConnection, states: &[LoanState]) -> db::Result<Vec<Loan>> {
+		loans::table
+			.filter(loans::state.eq_any(states.to_vec()))
+			.select(loans::all_columns)
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds loans with past due payments, the most delinquent first
+	pub fn find_delinquent(&self, conn: &PgConnection) -> db::Result<Vec<Loan>> {
+		loans::table
+			.filter(loans::days_past_due.gt(0))
+			.select(loans::all_columns)
+			.order(loans::days_past_due.desc())
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn set_delinquency(&self, conn: &PgConnection, id: &Id, days_past_due: i32, bucket: DelinquencyBucket) -> db::Result<Loan> {
+		diesel::update(loans::table)
+			.filter(loans::id.eq(id))
+			.set((
+				loans::days_past_due.eq(days_past_due),
+				loans::delinquency_bucket.eq(bucket),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn set_state(&self, conn: &PgConnection, id: &uuid::Uuid, state: LoanState) -> db::Result<Loan> {
 		diesel::update(loans::table)
 			.filter(loans::id.eq(id))
 			.set((loans::state.eq(state)))
@@ -166,17 +523,41 @@ impl Repo {
 			.map_err(Into::into)
 	}
 	
-	pub fn set_accrued_interest(&self, id: &uuid::Uuid, accrued_interest: &BigDecimal) -> db::Result<Loan> {
-		let conn = &self.db.get()?;
+	/// Sets the loan's interest rate in basis points
+	pub fn set_interest_rate(&self, conn: &PgConnection, id: &Id, interest_rate: i16) -> db::Result<Loan> {
 		diesel::update(loans::table)
 			.filter(loans::id.eq(id))
-			.set((loans::accrued_interest.eq(accrued_interest)))
+			.set(loans::interest_rate.eq(interest_rate))
 			.get_result(conn)
 			.map_err(Into::into)
 	}
 	
-	pub fn decrement(&self, id: &Id, amount: &BigDecimal) -> db::Result<Loan> {
-		let conn = &self.db.get()?;
+	/// Moves the loan's accrued interest into its balance and capitalized interest
+	pub fn capitalize_interest(&self, conn: &PgConnection, id: &Id) -> db::Result<Loan> {
+		diesel::update(loans::table)
+			.filter(loans::id.eq(id))
+			.set((
+				loans::balance.eq(loans::balance + loans::accrued_interest),
+				loans::capitalized_interest.eq(loans::capitalized_interest + loans::accrued_interest),
+				loans::accrued_interest.eq(BigDecimal::zero()),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Adds interest to the loan's accrued interest and records the date it was accrued through
+	pub fn accrue_interest(&self, conn: &PgConnection, id: &Id, interest: &BigDecimal, accrued_through: &Date) -> db::Result<Loan> {
+		diesel::update(loans::table)
+			.filter(loans::id.eq(id))
+			.set((
+				loans::accrued_interest.eq(loans::accrued_interest + interest),
+				loans::interest_accrued_through.eq(accrued_through),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn decrement(&self, conn: &PgConnection, id: &Id, amount: &BigDecimal) -> db::Result<Loan> {
 		diesel::update(loans::table)
 			.filter(loans::id.eq(id))
 			.set((
@@ -186,11 +567,73 @@ impl Repo {
 			.get_result(conn)
 			.map_err(Into::into)
 	}
+	
+	/// Reduces the loan's balance by the principal paid and its accrued interest by the interest paid
+	pub fn apply_payment(&self, conn: &PgConnection, id: &Id, principal: &BigDecimal, interest: &BigDecimal) -> db::Result<Loan> {
+		diesel::update(loans::table)
+			.filter(loans::id.eq(id))
+			.set((
+				loans::balance.eq(loans::balance - principal),
+				loans::accrued_interest.eq(loans::accrued_interest - interest),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
 }
 
 
+/// A change to a loan's interest rate, the first is the rate the loan was issued at
+#[derive(Queryable, Identifiable, Serialize, Debug)]
+#[table_name = "loan_rate_changes"]
+pub struct RateChange {
+	pub id: Id,
+	pub loan_id: Id,
+	/// the date the rate is charged from
+	pub effective_date: Date,
+	/// the loan's interest rate in basis points
+	pub interest_rate: i16,
+	/// the benchmark rate in basis points that a variable rate was set from
+	pub benchmark_rate: Option<i16>,
+	pub created_at: Time,
+}
+
+#[derive(Insertable)]
+#[table_name = "loan_rate_changes"]
+pub struct NewRateChange {
+	pub loan_id: Id,
+	pub effective_date: Date,
+	pub interest_rate: i16,
+	pub benchmark_rate: Option<i16>,
+}
+
+/// Data store implementation for operating on loan rate changes in the database
+pub struct RateChangeRepo;
+
+impl RateChangeRepo {
+	pub fn new() -> Self {
+		RateChangeRepo
+	}
+	
+	pub fn create(&self, conn: &PgConnection, new_change: NewRateChange) -> db::Result<RateChange> {
+		diesel::insert_into(loan_rate_changes::table)
+			.values(new_change)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the loan's rate changes, the earliest first
+	pub fn find_by_loan(&self, conn: &PgConnection, loan_id: &Id) -> db::Result<Vec<RateChange>> {
+		loan_rate_changes::table
+			.filter(loan_rate_changes::loan_id.eq(loan_id))
+			.select(loan_rate_changes::all_columns)
+			.order((loan_rate_changes::effective_date.asc(), loan_rate_changes::created_at.asc()))
+			.load(conn)
+			.map_err(Into::into)
+	}
+}
+
 /// Loan payment due based on the terms of the loan
-#[derive(Queryable, Identifiable, Debug)]
+#[derive(Queryable, Identifiable, Serialize, Debug)]
 pub struct LoanPayment {
 	pub id: uuid::Uuid,
 	pub loan_id: uuid::Uuid,
@@ -201,6 +644,39 @@ pub struct LoanPayment {
 	pub principle_transaction_id: Option<uuid::Uuid>,
 	/// id of the interest payment transaction
 	pub interest_transaction_id: Option<uuid::Uuid>,
+	/// fee charged for paying after the grace period, collected with the payment
+	pub late_fee: BigDecimal,
+	/// id of the late fee payment transaction
+	pub fee_transaction_id: Option<uuid::Uuid>,
+	/// the amounts paid towards the payment's dues, a payment is paid once its transaction ids are set
+	pub principal_paid: BigDecimal,
+	pub interest_paid: BigDecimal,
+	pub fees_paid: BigDecimal,
+	/// the amount paid beyond the dues that was prepaid against the loan's balance
+	pub overpaid: BigDecimal,
+}
+
+impl LoanPayment {
+	pub fn principal_outstanding(&self) -> BigDecimal {
+		outstanding(&self.principal_due, &self.principal_paid)
+	}
+	
+	pub fn interest_outstanding(&self) -> BigDecimal {
+		outstanding(&self.interest_due, &self.interest_paid)
+	}
+	
+	pub fn fees_outstanding(&self) -> BigDecimal {
+		outstanding(&self.late_fee, &self.fees_paid)
+	}
+	
+	/// The amount still owed on the payment's dues, which is zero once they've been paid in full
+	pub fn underpaid(&self) -> BigDecimal {
+		self.principal_outstanding() + self.interest_outstanding() + self.fees_outstanding()
+	}
+}
+
+fn outstanding(due: &BigDecimal, paid: &BigDecimal) -> BigDecimal {
+	if paid < due { due - paid } else { BigDecimal::zero() }
 }
 
 
@@ -213,26 +689,75 @@ pub struct NewPayment {
 	pub due_date: Date,
 }
 
-/// Data store implementation for operating on loan_payments in the database
-pub struct PaymentRepo {
-	db: db::PgPool,
+/// An amount paid against a loan and how it was applied, recorded so a retried payment returns the original result
+///
+/// The repayment is identified by its principal repayment transaction, which holds the request's idempotency key.
+#[derive(Queryable, Identifiable, Debug)]
+#[table_name = "loan_repayments"]
+#[primary_key(principal_transaction_id)]
+pub struct Repayment {
+	pub principal_transaction_id: Id,
+	pub loan_id: Id,
+	/// the amount the borrower asked to pay, which can be more than was applied
+	pub amount: BigDecimal,
+	pub fees: BigDecimal,
+	pub past_due_interest: BigDecimal,
+	pub current_interest: BigDecimal,
+	pub principal: BigDecimal,
+	pub prepayment: BigDecimal,
+	pub created_at: Time,
+}
+
+impl Repayment {
+	/// How the repayment was applied
+	pub fn allocation(&self) -> Allocation {
+		Allocation {
+			fees: self.fees.clone(),
+			past_due_interest: self.past_due_interest.clone(),
+			current_interest: self.current_interest.clone(),
+			principal: self.principal.clone(),
+			prepayment: self.prepayment.clone(),
+		}
+	}
 }
 
+#[derive(Insertable)]
+#[table_name = "loan_repayments"]
+pub struct NewRepayment<'a> {
+	pub principal_transaction_id: &'a Id,
+	pub loan_id: &'a Id,
+	pub amount: &'a BigDecimal,
+	pub fees: &'a BigDecimal,
+	pub past_due_interest: &'a BigDecimal,
+	pub current_interest: &'a BigDecimal,
+	pub principal: &'a BigDecimal,
+	pub prepayment: &'a BigDecimal,
+}
+
+/// Data store implementation for operating on loan_payments in the database
+pub struct PaymentRepo;
+
 impl PaymentRepo {
-	pub fn new(db: db::PgPool) -> Self {
-		PaymentRepo { db }
+	pub fn new() -> Self {
+		PaymentRepo
 	}
 	
-	pub fn create(&self, new_payment: NewPayment) -> db::Result<LoanPayment> {
-		let conn = &self.db.get()?;
+	pub fn create(&self, conn: &PgConnection, new_payment: NewPayment) -> db::Result<LoanPayment> {
 		diesel::insert_into(loan_payments::table)
 			.values(&new_payment)
 			.get_result(conn)
 			.map_err(Into::into)
 	}
 	
-	pub fn find_by_id(&self, id: &Id) -> db::Result<LoanPayment> {
-		let conn = &self.db.get()?;
+	/// Creates every payment on a loan's schedule
+	pub fn create_all(&self, conn: &PgConnection, new_payments: &[NewPayment]) -> db::Result<Vec<LoanPayment>> {
+		diesel::insert_into(loan_payments::table)
+			.values(new_payments)
+			.get_results(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_by_id(&self, conn: &PgConnection, id: &Id) -> db::Result<LoanPayment> {
 		loan_payments::table
 			.find(id)
 			.select(loan_payments::all_columns)
@@ -240,9 +765,18 @@ impl PaymentRepo {
 			.map_err(Into::into)
 	}
 	
+	/// Finds all payments on the loan's schedule ordered by due date
+	pub fn find_by_loan(&self, conn: &PgConnection, loan_id: &Id) -> db::Result<Vec<LoanPayment>> {
+		loan_payments::table
+			.filter(loan_payments::loan_id.eq(loan_id))
+			.select(loan_payments::all_columns)
+			.order(loan_payments::due_date.asc())
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
 	/// Finds the first unpaid loan payment due
-	pub fn find_first_unpaid(&self, loan_id: &Id) -> db::Result<LoanPayment> {
-		let conn = &self.db.get()?;
+	pub fn find_first_unpaid(&self, conn: &PgConnection, loan_id: &Id) -> db::Result<LoanPayment> {
 		loan_payments::table
 			.filter((
 				loan_payments::loan_id.eq(loan_id)
@@ -250,13 +784,42 @@ impl PaymentRepo {
 					.and(loan_payments::interest_transaction_id.is_null())
 			))
 			.select(loan_payments::all_columns)
+			.order(loan_payments::due_date.asc())
 			.first(conn)
 			.map_err(Into::into)
 	}
 	
+	/// Finds the loan's unpaid payments, the oldest first
+	pub fn find_unpaid(&self, conn: &PgConnection, loan_id: &Id) -> db::Result<Vec<LoanPayment>> {
+		loan_payments::table
+			.filter((
+				loan_payments::loan_id.eq(loan_id)
+					.and(loan_payments::principle_transaction_id.is_null())
+					.and(loan_payments::interest_transaction_id.is_null())
+			))
+			.select(loan_payments::all_columns)
+			.order(loan_payments::due_date.asc())
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the loan's unpaid payments that were due before the date, the oldest first
+	pub fn find_overdue(&self, conn: &PgConnection, loan_id: &Id, as_of: &Date) -> db::Result<Vec<LoanPayment>> {
+		loan_payments::table
+			.filter((
+				loan_payments::loan_id.eq(loan_id)
+					.and(loan_payments::principle_transaction_id.is_null())
+					.and(loan_payments::interest_transaction_id.is_null())
+					.and(loan_payments::due_date.lt(as_of))
+			))
+			.select(loan_payments::all_columns)
+			.order(loan_payments::due_date.asc())
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
 	/// Finds the most recently paid loan payment
-	pub fn find_last_paid(&self, loan_id: &Id) -> db::Result<LoanPayment> {
-		let conn = &self.db.get()?;
+	pub fn find_last_paid(&self, conn: &PgConnection, loan_id: &Id) -> db::Result<LoanPayment> {
 		loan_payments::table
 			.filter((
 				loan_payments::loan_id.eq(loan_id)
@@ -269,8 +832,16 @@ impl PaymentRepo {
 			.map_err(Into::into)
 	}
 	
-	pub fn set_transaction_ids(&self, id: &Id, principle_transaction_id: &Id, interest_transaction_id: &Id) -> db::Result<LoanPayment> {
-		let conn = &self.db.get()?;
+	/// Finds the loan payment that was paid by the principal repayment transaction
+	pub fn find_by_principle_transaction_id(&self, conn: &PgConnection, transaction_id: &Id) -> db::Result<LoanPayment> {
+		loan_payments::table
+			.filter(loan_payments::principle_transaction_id.eq(transaction_id))
+			.select(loan_payments::all_columns)
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn set_transaction_ids(&self, conn: &PgConnection, id: &Id, principle_transaction_id: &Id, interest_transaction_id: &Id) -> db::Result<LoanPayment> {
 		diesel::update(loan_payments::table)
 			.filter(loan_payments::id.eq(id))
 			.set((
@@ -281,9 +852,99 @@ impl PaymentRepo {
 			.map_err(Into::into)
 	}
 	
+	/// Deletes the loan's unpaid payments, returning the number deleted
+	pub fn delete_unpaid(&self, conn: &PgConnection, loan_id: &Id) -> db::Result<usize> {
+		diesel::delete(loan_payments::table)
+			.filter(loan_payments::loan_id.eq(loan_id)
+				.and(loan_payments::principle_transaction_id.is_null())
+				.and(loan_payments::interest_transaction_id.is_null()))
+			.execute(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn set_late_fee(&self, conn: &PgConnection, id: &Id, late_fee: &BigDecimal) -> db::Result<LoanPayment> {
+		diesel::update(loan_payments::table)
+			.filter(loan_payments::id.eq(id))
+			.set(loan_payments::late_fee.eq(late_fee))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn set_fee_transaction_id(&self, conn: &PgConnection, id: &Id, fee_transaction_id: &Id) -> db::Result<LoanPayment> {
+		diesel::update(loan_payments::table)
+			.filter(loan_payments::id.eq(id))
+			.set(loan_payments::fee_transaction_id.eq(fee_transaction_id))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Closes out the loan's unpaid payments with the transactions that paid off the loan, returning the number closed
+	///
+	/// The fee transaction is attached to the payments that were charged a late fee
+	pub fn close_unpaid(&self,
+						conn: &PgConnection,
+						loan_id: &Id,
+						principle_transaction_id: &Id,
+						interest_transaction_id: &Id,
+						fee_transaction_id: Option<&Id>) -> db::Result<usize> {
+		let unpaid = loan_payments::loan_id.eq(loan_id)
+			.and(loan_payments::principle_transaction_id.is_null())
+			.and(loan_payments::interest_transaction_id.is_null());
+		
+		if let Some(fee_transaction_id) = fee_transaction_id {
+			diesel::update(loan_payments::table)
+				.filter(unpaid.and(loan_payments::late_fee.gt(BigDecimal::zero())))
+				.set(loan_payments::fee_transaction_id.eq(fee_transaction_id))
+				.execute(conn)?;
+		}
+		
+		diesel::update(loan_payments::table)
+			.filter(unpaid)
+			.set((
+				loan_payments::principle_transaction_id.eq(principle_transaction_id),
+				loan_payments::interest_transaction_id.eq(interest_transaction_id),
+			))
+			.execute(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Adds the amounts paid towards the loan payment
+	pub fn record_payment(&self,
+						  conn: &PgConnection,
+						  id: &Id,
+						  principal: &BigDecimal,
+						  interest: &BigDecimal,
+						  fees: &BigDecimal,
+						  overpaid: &BigDecimal) -> db::Result<LoanPayment> {
+		diesel::update(loan_payments::table)
+			.filter(loan_payments::id.eq(id))
+			.set((
+				loan_payments::principal_paid.eq(loan_payments::principal_paid + principal),
+				loan_payments::interest_paid.eq(loan_payments::interest_paid + interest),
+				loan_payments::fees_paid.eq(loan_payments::fees_paid + fees),
+				loan_payments::overpaid.eq(loan_payments::overpaid + overpaid),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn create_repayment(&self, conn: &PgConnection, new_repayment: NewRepayment) -> db::Result<Repayment> {
+		diesel::insert_into(loan_repayments::table)
+			.values(new_repayment)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the repayment made by the principal repayment transaction
+	pub fn find_repayment(&self, conn: &PgConnection, principal_transaction_id: &Id) -> db::Result<Repayment> {
+		loan_repayments::table
+			.find(principal_transaction_id)
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
 	/// Updates the principal and interest due on the loan payment
-	pub fn set_dues(&self, id: &Id, principal_due: &BigDecimal, interest_due: &BigDecimal) -> db::Result<LoanPayment> {
-		let conn = &self.db.get()?;
+	pub fn set_dues(&self, conn: &PgConnection, id: &Id, principal_due: &BigDecimal, interest_due: &BigDecimal) -> db::Result<LoanPayment> {
 		diesel::update(loan_payments::table)
 			.filter(loan_payments::id.eq(id))
 			.set((
@@ -309,7 +970,7 @@ mod tests {
 		let bob = f.user_factory.bob();
 		let vault = f.insert_main_vault(0);
 		
-		let loan = suite.loan_repo.create(NewLoan {
+		let loan = suite.loan_repo.create(&f.conn(), NewLoan {
 			user_id: bob.id,
 			vault_name: vault.name,
 			orig_principal: Default::default(),
@@ -320,14 +981,40 @@ mod tests {
 			payment_frequency: 0,
 			compound_frequency: 0,
 			state: Default::default(),
+			amortization_method: Default::default(),
+			day_count_convention: Default::default(),
+			benchmark: None,
+			rate_margin: 0,
+			rate_floor: None,
+			rate_cap: None,
+			rate_reset_frequency: 0,
+			currency: Currency::Usd,
 		}).unwrap();
 		
 		// create loan payment
-		let loan_payment = suite.loan_payment_repo.create(NewPayment {
+		let loan_payment = suite.loan_payment_repo.create(&f.conn(), NewPayment {
 			loan_id: loan.id,
 			principal_due: Default::default(),
 			interest_due: Default::default(),
 			due_date: chrono::NaiveDate::from_yo(2020, 1),
 		});
 	}
+	
+	#[test]
+	fn loan_state_transitions() {
+		use LoanState::*;
+		
+		assert!(PendingApproval.can_transition_to(Approved));
+		assert!(PendingApproval.can_transition_to(Rejected));
+		assert!(Approved.can_transition_to(Active));
+		assert!(Approved.can_transition_to(Cancelled));
+		assert!(Active.can_transition_to(Paid));
+		assert!(Active.can_transition_to(Default));
+		
+		assert!(!PendingApproval.can_transition_to(Active));
+		assert!(!Rejected.can_transition_to(Approved));
+		assert!(!Cancelled.can_transition_to(Active));
+		assert!(!Active.can_transition_to(Cancelled));
+		assert!(!Paid.can_transition_to(Active));
+	}
 }
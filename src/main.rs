@@ -0,0 +1,20 @@
+use std::env;
+use std::net::SocketAddr;
+
+use bank_api::{api, db};
+
+/// Starts the bank's JSON API server
+///
+/// `DATABASE_URL` must be set in the environment
+/// `BIND_ADDRESS` may be set to override the default address of 127.0.0.1:3030
+#[tokio::main]
+async fn main() {
+	pretty_env_logger::init();
+	
+	let addr: SocketAddr = env::var("BIND_ADDRESS")
+		.unwrap_or_else(|_| String::from("127.0.0.1:3030"))
+		.parse()
+		.expect("BIND_ADDRESS must be a valid socket address");
+	
+	api::serve(db::pg_connection(), addr).await
+}
@@ -0,0 +1,245 @@
+/*!
+money pairs amounts with the currency they're held in so amounts in different currencies are never mixed,
+and rounds amounts to the precision of their currency
+*/
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Signed, Zero};
+use diesel::{
+	deserialize,
+	pg::Pg,
+	serialize,
+	sql_types::Varchar,
+};
+use serde::{Deserialize, Serialize};
+use strum;
+use strum_macros::{Display, EnumString};
+
+/// An ISO 4217 currency
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Hash, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "shouty_snake_case")]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+	Usd,
+	Eur,
+	Gbp,
+	Jpy,
+	Chf,
+	Cad,
+	Aud,
+}
+
+impl Currency {
+	/// The number of decimal places in the currency's minor unit, e.g. 2 for cents
+	pub fn minor_units(&self) -> i64 {
+		match self {
+			Currency::Jpy => 0,
+			_ => 2,
+		}
+	}
+}
+
+impl Default for Currency {
+	fn default() -> Self { Currency::Usd }
+}
+
+impl serialize::ToSql<Varchar, Pg> for Currency {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for Currency {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		Currency::from_str(s).map_err(|_| "invalid currency".into())
+	}
+}
+
+/// The scale amounts are stored at, interest accrues in fractions of a minor unit at this scale
+pub const ACCRUAL_SCALE: i64 = 4;
+
+/// How an amount is rounded to fewer decimal places
+#[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+	/// Halves round to the nearest even digit, also known as banker's rounding
+	HalfEven,
+	/// Halves round away from zero
+	HalfUp,
+	/// Drops the extra digits, used where the remainder is carried forward rather than lost
+	Down,
+}
+
+impl Default for RoundingMode {
+	fn default() -> Self { RoundingMode::HalfEven }
+}
+
+/// Rounds the amount to the scale
+pub fn round(amount: &BigDecimal, scale: i64, mode: RoundingMode) -> BigDecimal {
+	let truncated = amount.with_scale(scale);
+	let remainder = (amount - &truncated).abs();
+	if remainder.is_zero() || mode == RoundingMode::Down {
+		return truncated;
+	}
+	
+	let unit = BigDecimal::from(1) / BigDecimal::from(10_i64.pow(scale as u32));
+	let away_from_zero = match (&remainder * BigDecimal::from(2)).cmp(&unit) {
+		Ordering::Greater => true,
+		Ordering::Less => false,
+		Ordering::Equal => match mode {
+			RoundingMode::HalfUp => true,
+			_ => !(&truncated / &unit % BigDecimal::from(2)).is_zero(),
+		},
+	};
+	if !away_from_zero {
+		truncated
+	} else if amount.is_negative() {
+		(truncated - unit).with_scale(scale)
+	} else {
+		(truncated + unit).with_scale(scale)
+	}
+}
+
+/// An amount of money in a currency
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Money {
+	pub amount: BigDecimal,
+	pub currency: Currency,
+}
+
+impl Money {
+	pub fn new(amount: BigDecimal, currency: Currency) -> Self {
+		Money { amount, currency }
+	}
+	
+	pub fn zero(currency: Currency) -> Self {
+		Money::new(BigDecimal::zero(), currency)
+	}
+	
+	pub fn is_positive(&self) -> bool {
+		self.amount.is_positive()
+	}
+	
+	/// Whether the amount is a whole number of the currency's minor unit
+	pub fn is_whole_minor_units(&self) -> bool {
+		round(&self.amount, self.currency.minor_units(), RoundingMode::Down) == self.amount
+	}
+	
+	/// Rounds the amount to the currency's minor unit
+	pub fn round(&self, mode: RoundingMode) -> Money {
+		Money::new(round(&self.amount, self.currency.minor_units(), mode), self.currency)
+	}
+	
+	/// Adds money in the same currency
+	pub fn checked_add(&self, other: &Money) -> Result<Money, CurrencyMismatch> {
+		check_currency(self.currency, other.currency)?;
+		Ok(Money::new(&self.amount + &other.amount, self.currency))
+	}
+	
+	/// Subtracts money in the same currency
+	pub fn checked_sub(&self, other: &Money) -> Result<Money, CurrencyMismatch> {
+		check_currency(self.currency, other.currency)?;
+		Ok(Money::new(&self.amount - &other.amount, self.currency))
+	}
+	
+	/// Converts the money to another currency at the rate, the price of one unit of this currency in the other
+	///
+	/// The converted amount is rounded to the other currency's minor unit with banker's rounding
+	pub fn convert(&self, rate: &BigDecimal, to: Currency) -> Money {
+		Money::new(&self.amount * rate, to).round(RoundingMode::HalfEven)
+	}
+}
+
+impl fmt::Display for Money {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} {}", self.amount, self.currency)
+	}
+}
+
+/// Money in one currency was used where another currency was expected
+#[derive(PartialEq, Debug)]
+pub struct CurrencyMismatch {
+	pub expected: Currency,
+	pub found: Currency,
+}
+
+/// Checks that the currency found is the one expected
+pub fn check_currency(expected: Currency, found: Currency) -> Result<(), CurrencyMismatch> {
+	if expected == found {
+		Ok(())
+	} else {
+		Err(CurrencyMismatch { expected, found })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn usd(amount: &str) -> Money {
+		Money::new(BigDecimal::from_str(amount).unwrap(), Currency::Usd)
+	}
+	
+	#[test]
+	fn currency_codes() {
+		assert_eq!(Currency::Usd.to_string(), "USD");
+		assert_eq!(Currency::from_str("EUR").unwrap(), Currency::Eur);
+		assert!(Currency::from_str("usd").is_err());
+		assert_eq!(serde_json::to_string(&Currency::Gbp).unwrap(), "\"GBP\"");
+	}
+	
+	#[test]
+	fn arithmetic_requires_the_same_currency() {
+		assert_eq!(usd("10.50").checked_add(&usd("2.25")).unwrap(), usd("12.75"));
+		assert_eq!(usd("10.50").checked_sub(&usd("2.25")).unwrap(), usd("8.25"));
+		
+		let euros = Money::new(BigDecimal::from(1), Currency::Eur);
+		assert_eq!(usd("10").checked_add(&euros), Err(CurrencyMismatch { expected: Currency::Usd, found: Currency::Eur }));
+	}
+	
+	#[test]
+	fn convert() {
+		let rate = BigDecimal::from_str("0.91234567").unwrap();
+		let euros = usd("100.25").convert(&rate, Currency::Eur);
+		assert_eq!(euros, Money::new(BigDecimal::from_str("91.46").unwrap(), Currency::Eur));
+		
+		let yen = usd("10.25").convert(&BigDecimal::from_str("106.9").unwrap(), Currency::Jpy);
+		assert_eq!(yen, Money::new(BigDecimal::from(1096), Currency::Jpy));
+	}
+	
+	#[test]
+	fn rounding_modes() {
+		let cases = vec![
+			("2.345", "2.34", "2.35", "2.34"),
+			("2.355", "2.36", "2.36", "2.35"),
+			("2.3451", "2.35", "2.35", "2.34"),
+			("-2.345", "-2.34", "-2.35", "-2.34"),
+			("-2.355", "-2.36", "-2.36", "-2.35"),
+			("2.3", "2.30", "2.30", "2.30"),
+		];
+		for (amount, half_even, half_up, down) in cases {
+			let amount = BigDecimal::from_str(amount).unwrap();
+			assert_eq!(round(&amount, 2, RoundingMode::HalfEven), BigDecimal::from_str(half_even).unwrap(), "{} half even", amount);
+			assert_eq!(round(&amount, 2, RoundingMode::HalfUp), BigDecimal::from_str(half_up).unwrap(), "{} half up", amount);
+			assert_eq!(round(&amount, 2, RoundingMode::Down), BigDecimal::from_str(down).unwrap(), "{} down", amount);
+		}
+		
+		assert_eq!(usd("0.125").round(RoundingMode::HalfEven), usd("0.12"));
+		assert_eq!(Money::new(BigDecimal::from_str("1234.5").unwrap(), Currency::Jpy).round(RoundingMode::HalfEven).amount, BigDecimal::from(1234));
+	}
+	
+	#[test]
+	fn whole_minor_units() {
+		assert!(usd("10.25").is_whole_minor_units());
+		assert!(usd("10.2500").is_whole_minor_units());
+		assert!(!usd("10.255").is_whole_minor_units());
+		assert!(Money::new(BigDecimal::from(1096), Currency::Jpy).is_whole_minor_units());
+		assert!(!Money::new(BigDecimal::from_str("1096.5").unwrap(), Currency::Jpy).is_whole_minor_units());
+	}
+}
@@ -0,0 +1,191 @@
+/*!
+overdraft keeps the protection that covers withdrawals and transfers an account can't cover on its own
+*/
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Signed, Zero};
+use diesel::{
+	deserialize,
+	pg::Pg,
+	prelude::*,
+	serialize,
+	sql_types::Varchar,
+	pg::upsert::excluded,
+};
+use serde::{Deserialize, Serialize};
+use strum;
+use strum_macros::{Display, EnumString};
+
+use crate::db;
+use crate::day_count::DayCountConvention;
+use crate::loan;
+use crate::money::{self, RoundingMode};
+use crate::schema::overdraft_protections;
+use crate::types::{Date, Id, Time};
+
+/// An account's overdraft protection
+///
+/// A shortfall is first swept from the linked account when it can cover it, otherwise the overdraft line lets the
+/// balance go negative down to the limit. Each use of the line is charged a fee, and the negative balance is charged
+/// interest that accrues daily and is charged monthly.
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+#[primary_key(account_id)]
+pub struct OverdraftProtection {
+	pub account_id: Id,
+	/// an account owned by the same user that shortfalls are swept from
+	pub linked_account_id: Option<Id>,
+	/// how far the balance may go below zero, zero means the account has no overdraft line
+	pub overdraft_limit: BigDecimal,
+	/// the fee charged each time the overdraft line is used
+	pub overdraft_fee: BigDecimal,
+	/// the annual interest rate in basis points charged on a negative balance
+	pub interest_rate: i16,
+	/// the vault whose income the fees and interest are booked to
+	pub vault_name: String,
+	/// interest charged on the negative balance but not yet collected
+	pub accrued_interest: BigDecimal,
+	/// the day interest was last accrued up to, exclusive
+	pub interest_accrued_through: Date,
+	pub created_at: Time,
+}
+
+impl OverdraftProtection {
+	/// Checks whether the overdraft line lets the balance go down to the amount
+	pub fn covers(&self, balance_after: &BigDecimal) -> bool {
+		self.overdraft_limit.is_positive() && balance_after >= &-&self.overdraft_limit
+	}
+	
+	/// The interest charged on the balance at the end of the day, rounded to the accrual scale
+	///
+	/// Only a negative balance is charged interest
+	pub fn daily_interest(&self, balance: &BigDecimal, day: Date) -> BigDecimal {
+		if !balance.is_negative() {
+			return BigDecimal::zero();
+		}
+		let rate = loan::rate_from_basis_points(self.interest_rate);
+		let interest = DayCountConvention::Actual365.interest(&-balance, &rate, day, day.succ());
+		money::round(&interest, money::ACCRUAL_SCALE, RoundingMode::HalfEven)
+	}
+}
+
+/// The terms of an account's overdraft protection
+#[derive(Deserialize, Debug)]
+pub struct OverdraftTerms {
+	#[serde(default)]
+	pub linked_account_id: Option<Id>,
+	#[serde(default)]
+	pub overdraft_limit: BigDecimal,
+	#[serde(default)]
+	pub overdraft_fee: BigDecimal,
+	/// the annual interest rate in basis points
+	#[serde(default)]
+	pub interest_rate: i16,
+	pub vault_name: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "overdraft_protections"]
+pub struct NewOverdraftProtection {
+	pub account_id: Id,
+	pub linked_account_id: Option<Id>,
+	pub overdraft_limit: BigDecimal,
+	pub overdraft_fee: BigDecimal,
+	pub interest_rate: i16,
+	pub vault_name: String,
+	pub interest_accrued_through: Date,
+}
+
+/// The overdraft protection that covered a transaction
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Serialize, Eq, PartialEq, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OverdraftProtectionKind {
+	/// The shortfall was swept from the linked account
+	LinkedAccount,
+	/// The balance went negative within the overdraft limit
+	OverdraftLine,
+}
+
+impl serialize::ToSql<Varchar, Pg> for OverdraftProtectionKind {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for OverdraftProtectionKind {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		OverdraftProtectionKind::from_str(s).map_err(|_| "invalid overdraft protection kind".into())
+	}
+}
+
+/// Data store implementation for operating on overdraft protections in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	/// Sets the account's overdraft protection, replacing the terms of any it already has
+	///
+	/// Interest already accrued under the old terms is kept
+	pub fn set(&self, conn: &PgConnection, new_protection: NewOverdraftProtection) -> db::Result<OverdraftProtection> {
+		use crate::schema::overdraft_protections::dsl::*;
+		
+		diesel::insert_into(overdraft_protections)
+			.values(&new_protection)
+			.on_conflict(account_id)
+			.do_update()
+			.set((
+				linked_account_id.eq(excluded(linked_account_id)),
+				overdraft_limit.eq(excluded(overdraft_limit)),
+				overdraft_fee.eq(excluded(overdraft_fee)),
+				interest_rate.eq(excluded(interest_rate)),
+				vault_name.eq(excluded(vault_name)),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_by_account(&self, conn: &PgConnection, account_id: &Id) -> db::Result<OverdraftProtection> {
+		overdraft_protections::table
+			.filter(overdraft_protections::account_id.eq(account_id))
+			.select(overdraft_protections::all_columns)
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the protections that charge interest on a negative balance
+	pub fn find_charging_interest(&self, conn: &PgConnection) -> db::Result<Vec<OverdraftProtection>> {
+		overdraft_protections::table
+			.filter(overdraft_protections::interest_rate.gt(0))
+			.select(overdraft_protections::all_columns)
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Adds a day's interest to the protection's accrued interest
+	pub fn accrue_interest(&self, conn: &PgConnection, account_id: &Id, interest: &BigDecimal, accrued_through: &Date) -> db::Result<OverdraftProtection> {
+		diesel::update(overdraft_protections::table)
+			.filter(overdraft_protections::account_id.eq(account_id))
+			.set((
+				overdraft_protections::accrued_interest.eq(overdraft_protections::accrued_interest + interest),
+				overdraft_protections::interest_accrued_through.eq(accrued_through),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Takes the amount collected from the account off the protection's accrued interest
+	pub fn collect_interest(&self, conn: &PgConnection, account_id: &Id, amount: &BigDecimal) -> db::Result<OverdraftProtection> {
+		diesel::update(overdraft_protections::table)
+			.filter(overdraft_protections::account_id.eq(account_id))
+			.set(overdraft_protections::accrued_interest.eq(overdraft_protections::accrued_interest - amount))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+}
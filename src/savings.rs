@@ -0,0 +1,147 @@
+/*!
+savings defines the interest bearing products savings accounts can be opened with
+*/
+use bigdecimal::{BigDecimal, One, Signed, Zero};
+use chrono::Datelike;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::money::{self, RoundingMode};
+use crate::schema::savings_products;
+use crate::types::{Date, DateExt, Time};
+
+/// precision kept while finding the monthly rate
+const RATE_PRECISION: u64 = 32;
+/// decimal places the monthly rate is kept to
+const RATE_SCALE: i64 = 12;
+/// Newton's method steps taken to find the monthly rate
+const ROOT_STEPS: u8 = 8;
+
+/// A savings product pays interest on the balance of the accounts opened with it
+///
+/// Interest accrues daily on the end of day balance and is credited to the account monthly,
+/// funded by the product's vault
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+#[primary_key(name)]
+pub struct SavingsProduct {
+	pub name: String,
+	/// the vault that funds the interest credited to accounts
+	pub vault_name: String,
+	/// the annual percentage yield in basis points
+	pub apy: i16,
+	pub created_at: Time,
+}
+
+impl SavingsProduct {
+	/// The interest earned on the balance at the end of the day, rounded to the accrual scale
+	///
+	/// Each month's rate is the rate that compounds monthly to the APY, split evenly over the days in the month.
+	/// A balance that isn't positive earns nothing.
+	pub fn daily_interest(&self, balance: &BigDecimal, day: Date) -> BigDecimal {
+		if !balance.is_positive() {
+			return BigDecimal::zero();
+		}
+		let interest = balance * monthly_rate(self.apy) / BigDecimal::from(days_in_month(day));
+		money::round(&interest, money::ACCRUAL_SCALE, RoundingMode::HalfEven)
+	}
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[table_name = "savings_products"]
+pub struct NewSavingsProduct {
+	pub name: String,
+	pub vault_name: String,
+	/// the annual percentage yield in basis points
+	pub apy: i16,
+}
+
+/// The monthly rate that compounds to the APY in basis points over a year
+///
+/// The twelfth root of the yearly growth is found with Newton's method, starting from the simple monthly rate.
+/// Each step doubles the digits that are right, so a few steps reach the rate's precision.
+fn monthly_rate(apy: i16) -> BigDecimal {
+	let year = BigDecimal::one() + BigDecimal::from(apy) / BigDecimal::from(10_000);
+	let mut growth = BigDecimal::one() + (&year - BigDecimal::one()) / BigDecimal::from(12);
+	for _ in 0..ROOT_STEPS {
+		let mut power = BigDecimal::one();
+		for _ in 0..11 {
+			power = (&power * &growth).with_prec(RATE_PRECISION);
+		}
+		growth = ((BigDecimal::from(11) * &growth + &year / power) / BigDecimal::from(12)).with_prec(RATE_PRECISION);
+	}
+	money::round(&(growth - BigDecimal::one()), RATE_SCALE, RoundingMode::HalfEven)
+}
+
+fn days_in_month(day: Date) -> i64 {
+	let first = Date::from_ymd(day.year(), day.month(), 1);
+	(first.increment_date_by_months(1) - first).num_days()
+}
+
+/// Data store implementation for operating on savings products in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	pub fn create(&self, conn: &PgConnection, new_product: NewSavingsProduct) -> db::Result<SavingsProduct> {
+		diesel::insert_into(savings_products::table)
+			.values(new_product)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_by_name(&self, conn: &PgConnection, name: &str) -> db::Result<SavingsProduct> {
+		savings_products::table
+			.filter(savings_products::name.eq(name))
+			.select(savings_products::all_columns)
+			.first(conn)
+			.map_err(Into::into)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+	
+	use super::*;
+	
+	fn product(apy: i16) -> SavingsProduct {
+		SavingsProduct {
+			name: "high_yield".to_string(),
+			vault_name: "main".to_string(),
+			apy,
+			created_at: chrono::Utc::now(),
+		}
+	}
+	
+	#[test]
+	fn monthly_rate_compounds_to_apy() {
+		assert_eq!(monthly_rate(1268).with_scale(6), BigDecimal::from_str("0.009998").unwrap());
+		
+		let monthly = BigDecimal::from(1) + monthly_rate(500);
+		let mut year = BigDecimal::from(1);
+		for _ in 0..12 {
+			year = &year * &monthly;
+		}
+		assert_eq!(year.with_scale(6), BigDecimal::from_str("1.05").unwrap());
+		
+		assert!(monthly_rate(0).is_zero());
+		assert_eq!(monthly_rate(10_000), BigDecimal::from_str("0.059463094359").unwrap());
+	}
+	
+	#[test]
+	fn daily_interest_is_split_over_the_month() {
+		let product = product(1268);
+		let balance = BigDecimal::from(10_000);
+		
+		let february = product.daily_interest(&balance, Date::from_ymd(2020, 2, 10));
+		let march = product.daily_interest(&balance, Date::from_ymd(2020, 3, 10));
+		assert_eq!((february * BigDecimal::from(29)).with_scale(2), BigDecimal::from_str("99.98").unwrap());
+		assert_eq!((march * BigDecimal::from(31)).with_scale(2), BigDecimal::from_str("99.98").unwrap());
+		
+		assert!(product.daily_interest(&BigDecimal::from(-10), Date::from_ymd(2020, 3, 10)).is_zero());
+	}
+}
@@ -0,0 +1,386 @@
+/*!
+scheduled_transfer keeps standing orders that move money between accounts on a recurring schedule
+
+Each order runs on the dates its frequency gives, starting on or after its start date and ending after its end date.
+Every attempt to run an occurrence is recorded. An occurrence the sender can't cover is retried on the following days
+or skipped according to the bank's retry policy.
+*/
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use chrono::Datelike;
+use diesel::{
+	deserialize,
+	pg::Pg,
+	prelude::*,
+	serialize,
+	sql_types::Varchar,
+};
+use serde::{Deserialize, Serialize};
+use strum;
+use strum_macros::{Display, EnumString};
+
+use crate::db;
+use crate::money::Currency;
+use crate::schema::{scheduled_transfer_runs, scheduled_transfers};
+use crate::types::{Date, DateExt, Id, Time};
+
+/// A standing order to send funds from one account to another
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+pub struct ScheduledTransfer {
+	pub id: Id,
+	pub sender_id: Id,
+	pub receiver_id: Id,
+	pub amount: BigDecimal,
+	pub currency: Currency,
+	pub frequency: Frequency,
+	/// the day of the month a monthly transfer runs on, clamped to the last day of shorter months
+	pub day_of_month: Option<i16>,
+	pub start_date: Date,
+	/// the last date the transfer may run on, none when it recurs until cancelled
+	pub end_date: Option<Date>,
+	/// the date of the occurrence that runs next, none once the transfer is finished or cancelled
+	pub next_run_date: Option<Date>,
+	/// the date the next occurrence is attempted on, later than its run date while it's being retried
+	pub next_attempt_date: Option<Date>,
+	/// the number of times the next occurrence has been retried
+	pub retries: i32,
+	pub cancelled_at: Option<Time>,
+	pub created_at: Time,
+}
+
+impl ScheduledTransfer {
+	/// The date of the occurrence to attempt on the date, none when no attempt is due by then
+	pub fn due_occurrence(&self, date: Date) -> Option<Date> {
+		self.next_run_date.filter(|_| self.next_attempt_date.map_or(false, |attempt_date| attempt_date <= date))
+	}
+	
+	/// The date of the occurrence after the one on the date, none when the transfer doesn't run again before its end date
+	pub fn next_occurrence(&self, date: Date) -> Option<Date> {
+		self.frequency.next_after(date, self.day_of_month_u32())
+			.filter(|next| self.end_date.map_or(true, |end_date| next <= &end_date))
+	}
+	
+	fn day_of_month_u32(&self) -> u32 {
+		self.day_of_month.unwrap_or(1) as u32
+	}
+}
+
+/// A request to schedule a transfer
+#[derive(Deserialize, Debug)]
+pub struct TransferSchedule {
+	pub sender_id: Id,
+	pub receiver_id: Id,
+	/// the amount sent each time, in the sender's currency
+	pub amount: BigDecimal,
+	pub frequency: Frequency,
+	/// the day of the month a monthly transfer runs on, required for monthly transfers and unused otherwise
+	#[serde(default)]
+	pub day_of_month: Option<i16>,
+	pub start_date: Date,
+	#[serde(default)]
+	pub end_date: Option<Date>,
+}
+
+#[derive(Insertable)]
+#[table_name = "scheduled_transfers"]
+pub struct NewScheduledTransfer {
+	pub sender_id: Id,
+	pub receiver_id: Id,
+	pub amount: BigDecimal,
+	pub currency: Currency,
+	pub frequency: Frequency,
+	pub day_of_month: Option<i16>,
+	pub start_date: Date,
+	pub end_date: Option<Date>,
+	pub next_run_date: Option<Date>,
+	pub next_attempt_date: Option<Date>,
+}
+
+/// How often a scheduled transfer runs
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+	/// Runs on the start date only
+	Once,
+	/// Runs every seven days from the start date
+	Weekly,
+	/// Runs on a day of every month
+	Monthly,
+	/// Runs on the last day of every month
+	EndOfMonth,
+}
+
+impl Frequency {
+	/// The first date on or after the start date the transfer runs on
+	///
+	/// `day_of_month` - the day a monthly transfer runs on
+	pub fn first_on_or_after(self, start_date: Date, day_of_month: u32) -> Date {
+		match self {
+			Frequency::Once | Frequency::Weekly => start_date,
+			Frequency::Monthly => {
+				let date = day_in_month(start_date, day_of_month);
+				if date < start_date { day_in_month(next_month(start_date), day_of_month) } else { date }
+			}
+			Frequency::EndOfMonth => last_day_of_month(start_date),
+		}
+	}
+	
+	/// The date the transfer runs on after the occurrence on the date, none for a transfer that only runs once
+	///
+	/// `day_of_month` - the day a monthly transfer runs on
+	pub fn next_after(self, date: Date, day_of_month: u32) -> Option<Date> {
+		match self {
+			Frequency::Once => None,
+			Frequency::Weekly => Some(date + chrono::Duration::days(7)),
+			Frequency::Monthly => Some(day_in_month(next_month(date), day_of_month)),
+			Frequency::EndOfMonth => Some(last_day_of_month(next_month(date))),
+		}
+	}
+}
+
+/// The first day of the month after the date's
+fn next_month(date: Date) -> Date {
+	date.with_day(1).unwrap().increment_date_by_months(1)
+}
+
+/// The day in the date's month, clamped to the month's last day
+fn day_in_month(date: Date, day: u32) -> Date {
+	date.with_day(day).unwrap_or_else(|| last_day_of_month(date))
+}
+
+fn last_day_of_month(date: Date) -> Date {
+	next_month(date).pred()
+}
+
+impl serialize::ToSql<Varchar, Pg> for Frequency {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for Frequency {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		Frequency::from_str(s).map_err(|_| "invalid frequency".into())
+	}
+}
+
+/// An attempt to run an occurrence of a scheduled transfer
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+pub struct ScheduledTransferRun {
+	pub id: Id,
+	pub scheduled_transfer_id: Id,
+	/// the date of the occurrence that was attempted
+	pub scheduled_date: Date,
+	/// the date the attempt was made on
+	pub run_date: Date,
+	pub outcome: RunOutcome,
+	/// the transfer made when the attempt succeeded
+	pub transaction_id: Option<Id>,
+	/// why the attempt didn't succeed
+	pub error: Option<String>,
+	pub created_at: Time,
+}
+
+#[derive(Insertable)]
+#[table_name = "scheduled_transfer_runs"]
+pub struct NewScheduledTransferRun {
+	pub scheduled_transfer_id: Id,
+	pub scheduled_date: Date,
+	pub run_date: Date,
+	pub outcome: RunOutcome,
+	pub transaction_id: Option<Id>,
+	pub error: Option<String>,
+}
+
+/// The result of an attempt to run an occurrence of a scheduled transfer
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Serialize, Eq, PartialEq, EnumString, Display, Debug)]
+#[sql_type = "Varchar"]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+	/// The funds were sent
+	Succeeded,
+	/// The sender couldn't cover the transfer and it's attempted again the next day
+	Retrying,
+	/// The sender couldn't cover the transfer and the occurrence was skipped
+	Skipped,
+	/// The transfer couldn't be made for another reason and the occurrence was skipped
+	Failed,
+}
+
+impl serialize::ToSql<Varchar, Pg> for RunOutcome {
+	fn to_sql<W: std::io::Write>(&self, out: &mut serialize::Output<W, Pg>) -> serialize::Result {
+		serialize::ToSql::<Varchar, Pg>::to_sql(&self.to_string(), out)
+	}
+}
+
+impl deserialize::FromSql<Varchar, Pg> for RunOutcome {
+	fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+		let bytes = bytes.ok_or_else(|| "error deserializing from varchar")?;
+		let s = std::str::from_utf8(bytes)?;
+		
+		RunOutcome::from_str(s).map_err(|_| "invalid run outcome".into())
+	}
+}
+
+/// What the bank does with an occurrence the sender can't cover
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum InsufficientFundsAction {
+	/// Attempt the occurrence again the next day, up to the policy's retry limit
+	Retry,
+	/// Skip the occurrence and wait for the next one
+	Skip,
+}
+
+/// Rules the bank applies to scheduled transfers the sender can't cover
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+	pub on_insufficient_funds: InsufficientFundsAction,
+	/// the number of days an occurrence is retried before it's skipped
+	pub max_retries: i32,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		RetryPolicy {
+			on_insufficient_funds: InsufficientFundsAction::Retry,
+			max_retries: 3,
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Checks whether an occurrence the sender couldn't cover, already retried this many times, is retried again
+	pub fn retries(&self, retries: i32) -> bool {
+		self.on_insufficient_funds == InsufficientFundsAction::Retry && retries < self.max_retries
+	}
+}
+
+/// Data store implementation for operating on scheduled transfers in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	pub fn create(&self, conn: &PgConnection, new_transfer: NewScheduledTransfer) -> db::Result<ScheduledTransfer> {
+		diesel::insert_into(scheduled_transfers::table)
+			.values(&new_transfer)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn find_by_id(&self, conn: &PgConnection, id: &Id) -> db::Result<ScheduledTransfer> {
+		scheduled_transfers::table
+			.find(id)
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the scheduled transfer and locks it until the current transaction ends
+	pub fn find_for_update(&self, conn: &PgConnection, id: &Id) -> db::Result<ScheduledTransfer> {
+		scheduled_transfers::table
+			.find(id)
+			.for_no_key_update()
+			.first(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the transfers with an occurrence to attempt on or before the date, the earliest first
+	pub fn find_due(&self, conn: &PgConnection, date: &Date) -> db::Result<Vec<ScheduledTransfer>> {
+		scheduled_transfers::table
+			.filter(scheduled_transfers::next_attempt_date.le(date))
+			.order((scheduled_transfers::next_attempt_date, scheduled_transfers::created_at))
+			.load(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Moves the transfer on to its next occurrence, finishing it when there isn't one
+	pub fn advance(&self, conn: &PgConnection, id: &Id, next_run_date: Option<Date>) -> db::Result<ScheduledTransfer> {
+		diesel::update(scheduled_transfers::table)
+			.filter(scheduled_transfers::id.eq(id))
+			.set((
+				scheduled_transfers::next_run_date.eq(next_run_date),
+				scheduled_transfers::next_attempt_date.eq(next_run_date),
+				scheduled_transfers::retries.eq(0),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Attempts the transfer's next occurrence again on the date
+	pub fn retry(&self, conn: &PgConnection, id: &Id, attempt_date: &Date) -> db::Result<ScheduledTransfer> {
+		diesel::update(scheduled_transfers::table)
+			.filter(scheduled_transfers::id.eq(id))
+			.set((
+				scheduled_transfers::next_attempt_date.eq(attempt_date),
+				scheduled_transfers::retries.eq(scheduled_transfers::retries + 1),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Stops the transfer from running again
+	pub fn cancel(&self, conn: &PgConnection, id: &Id) -> db::Result<ScheduledTransfer> {
+		diesel::update(scheduled_transfers::table)
+			.filter(scheduled_transfers::id.eq(id))
+			.set((
+				scheduled_transfers::next_run_date.eq(None::<Date>),
+				scheduled_transfers::next_attempt_date.eq(None::<Date>),
+				scheduled_transfers::cancelled_at.eq(diesel::dsl::now),
+			))
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn create_run(&self, conn: &PgConnection, new_run: NewScheduledTransferRun) -> db::Result<ScheduledTransferRun> {
+		diesel::insert_into(scheduled_transfer_runs::table)
+			.values(&new_run)
+			.get_result(conn)
+			.map_err(Into::into)
+	}
+	
+	/// Finds the attempts made to run the transfer, ordered by occurrence and then by the date they were made on
+	pub fn find_runs(&self, conn: &PgConnection, scheduled_transfer_id: &Id) -> db::Result<Vec<ScheduledTransferRun>> {
+		scheduled_transfer_runs::table
+			.filter(scheduled_transfer_runs::scheduled_transfer_id.eq(scheduled_transfer_id))
+			.order((scheduled_transfer_runs::scheduled_date, scheduled_transfer_runs::run_date))
+			.load(conn)
+			.map_err(Into::into)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn occurrences() {
+		let start = Date::from_ymd(2020, 1, 15);
+		
+		assert_eq!(Frequency::Once.first_on_or_after(start, 1), start);
+		assert_eq!(Frequency::Once.next_after(start, 1), None);
+		
+		assert_eq!(Frequency::Weekly.first_on_or_after(start, 1), start);
+		assert_eq!(Frequency::Weekly.next_after(start, 1), Some(Date::from_ymd(2020, 1, 22)));
+		
+		assert_eq!(Frequency::Monthly.first_on_or_after(start, 20), Date::from_ymd(2020, 1, 20));
+		assert_eq!(Frequency::Monthly.first_on_or_after(start, 1), Date::from_ymd(2020, 2, 1));
+		assert_eq!(Frequency::Monthly.first_on_or_after(start, 15), start);
+		// the day is clamped to shorter months without drifting in later ones
+		assert_eq!(Frequency::Monthly.next_after(Date::from_ymd(2020, 1, 31), 31), Some(Date::from_ymd(2020, 2, 29)));
+		assert_eq!(Frequency::Monthly.next_after(Date::from_ymd(2020, 2, 29), 31), Some(Date::from_ymd(2020, 3, 31)));
+		assert_eq!(Frequency::Monthly.next_after(Date::from_ymd(2020, 12, 1), 1), Some(Date::from_ymd(2021, 1, 1)));
+		
+		assert_eq!(Frequency::EndOfMonth.first_on_or_after(start, 1), Date::from_ymd(2020, 1, 31));
+		assert_eq!(Frequency::EndOfMonth.next_after(Date::from_ymd(2020, 1, 31), 1), Some(Date::from_ymd(2020, 2, 29)));
+		assert_eq!(Frequency::EndOfMonth.next_after(Date::from_ymd(2020, 12, 31), 1), Some(Date::from_ymd(2021, 1, 31)));
+	}
+}
@@ -1,3 +1,26 @@
+table! {
+    accrual_runs (id) {
+        id -> Uuid,
+        run_date -> Date,
+        started_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        loans_accrued -> Int4,
+        interest_accrued -> Numeric,
+        accounts_accrued -> Int4,
+        savings_interest_accrued -> Numeric,
+    }
+}
+
+table! {
+    account_balance_snapshots (id) {
+        id -> Uuid,
+        account_id -> Uuid,
+        balance -> Numeric,
+        taken_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     account_transactions (id) {
         id -> Uuid,
@@ -5,6 +28,11 @@ table! {
         receiver_id -> Uuid,
         amount -> Numeric,
         created_at -> Timestamptz,
+        idempotency_key -> Nullable<Varchar>,
+        overdraft_protection -> Nullable<Varchar>,
+        reversal_of -> Nullable<Uuid>,
+        forced_by -> Nullable<Uuid>,
+        currency -> Varchar,
     }
 }
 
@@ -15,7 +43,12 @@ table! {
         account_type -> Varchar,
         amount -> Numeric,
         created_at -> Timestamptz,
-        is_open -> Bool,
+        savings_product -> Nullable<Varchar>,
+        accrued_interest -> Numeric,
+        interest_accrued_through -> Nullable<Date>,
+        status -> Varchar,
+        closed_at -> Nullable<Timestamptz>,
+        currency -> Varchar,
     }
 }
 
@@ -27,6 +60,93 @@ table! {
         transaction_type -> Varchar,
         amount -> Numeric,
         created_at -> Timestamptz,
+        idempotency_key -> Nullable<Varchar>,
+        overdraft_protection -> Nullable<Varchar>,
+        currency -> Varchar,
+        reversal_of -> Nullable<Uuid>,
+        forced_by -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    benchmark_rates (id) {
+        id -> Uuid,
+        benchmark -> Varchar,
+        effective_date -> Date,
+        rate -> Int2,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    fx_conversions (id) {
+        id -> Uuid,
+        sender_id -> Uuid,
+        receiver_id -> Uuid,
+        sell_amount -> Numeric,
+        sell_currency -> Varchar,
+        buy_amount -> Numeric,
+        buy_currency -> Varchar,
+        rate -> Numeric,
+        created_at -> Timestamptz,
+        idempotency_key -> Nullable<Varchar>,
+        overdraft_protection -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    fx_rates (id) {
+        id -> Uuid,
+        base_currency -> Varchar,
+        quote_currency -> Varchar,
+        rate -> Numeric,
+        effective_date -> Date,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    journal_entries (id) {
+        id -> Uuid,
+        entry_type -> Varchar,
+        reference_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    journal_lines (id) {
+        id -> Uuid,
+        entry_id -> Uuid,
+        account_kind -> Varchar,
+        account_key -> Varchar,
+        debit -> Numeric,
+        credit -> Numeric,
+    }
+}
+
+table! {
+    loan_repayments (principal_transaction_id) {
+        principal_transaction_id -> Uuid,
+        loan_id -> Uuid,
+        amount -> Numeric,
+        fees -> Numeric,
+        past_due_interest -> Numeric,
+        current_interest -> Numeric,
+        principal -> Numeric,
+        prepayment -> Numeric,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    loan_rate_changes (id) {
+        id -> Uuid,
+        loan_id -> Uuid,
+        effective_date -> Date,
+        interest_rate -> Int2,
+        benchmark_rate -> Nullable<Int2>,
+        created_at -> Timestamptz,
     }
 }
 
@@ -39,6 +159,12 @@ table! {
         due_date -> Date,
         principle_transaction_id -> Nullable<Uuid>,
         interest_transaction_id -> Nullable<Uuid>,
+        late_fee -> Numeric,
+        fee_transaction_id -> Nullable<Uuid>,
+        principal_paid -> Numeric,
+        interest_paid -> Numeric,
+        fees_paid -> Numeric,
+        overpaid -> Numeric,
     }
 }
 
@@ -57,6 +183,105 @@ table! {
         accrued_interest -> Numeric,
         capitalized_interest -> Numeric,
         state -> Varchar,
+        amortization_method -> Varchar,
+        approved_by -> Nullable<Uuid>,
+        approved_at -> Nullable<Timestamptz>,
+        days_past_due -> Int4,
+        delinquency_bucket -> Varchar,
+        interest_accrued_through -> Nullable<Date>,
+        day_count_convention -> Varchar,
+        benchmark -> Nullable<Varchar>,
+        rate_margin -> Int2,
+        rate_floor -> Nullable<Int2>,
+        rate_cap -> Nullable<Int2>,
+        rate_reset_frequency -> Int2,
+        currency -> Varchar,
+    }
+}
+
+table! {
+    overdraft_protections (account_id) {
+        account_id -> Uuid,
+        linked_account_id -> Nullable<Uuid>,
+        overdraft_limit -> Numeric,
+        overdraft_fee -> Numeric,
+        interest_rate -> Int2,
+        vault_name -> Varchar,
+        accrued_interest -> Numeric,
+        interest_accrued_through -> Date,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    savings_products (name) {
+        name -> Varchar,
+        vault_name -> Varchar,
+        apy -> Int2,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    scheduled_transfer_runs (id) {
+        id -> Uuid,
+        scheduled_transfer_id -> Uuid,
+        scheduled_date -> Date,
+        run_date -> Date,
+        outcome -> Varchar,
+        transaction_id -> Nullable<Uuid>,
+        error -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    scheduled_transfers (id) {
+        id -> Uuid,
+        sender_id -> Uuid,
+        receiver_id -> Uuid,
+        amount -> Numeric,
+        currency -> Varchar,
+        frequency -> Varchar,
+        day_of_month -> Nullable<Int2>,
+        start_date -> Date,
+        end_date -> Nullable<Date>,
+        next_run_date -> Nullable<Date>,
+        next_attempt_date -> Nullable<Date>,
+        retries -> Int4,
+        cancelled_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    statement_lines (id) {
+        id -> Uuid,
+        statement_id -> Uuid,
+        line_number -> Int4,
+        transaction_id -> Uuid,
+        entry_type -> Varchar,
+        amount -> Numeric,
+        balance -> Numeric,
+        vault_name -> Nullable<Varchar>,
+        counterparty_id -> Nullable<Uuid>,
+        posted_at -> Timestamptz,
+    }
+}
+
+table! {
+    statements (id) {
+        id -> Uuid,
+        account_id -> Uuid,
+        period_start -> Date,
+        period_end -> Date,
+        currency -> Varchar,
+        opening_balance -> Numeric,
+        closing_balance -> Numeric,
+        interest_credited -> Numeric,
+        interest_charged -> Numeric,
+        fees_charged -> Numeric,
+        created_at -> Timestamptz,
     }
 }
 
@@ -70,26 +295,65 @@ table! {
     }
 }
 
+table! {
+    vault_balance_snapshots (id) {
+        id -> Uuid,
+        vault_name -> Varchar,
+        balance -> Numeric,
+        taken_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     vaults (name) {
         name -> Varchar,
         amount -> Numeric,
+        currency -> Varchar,
     }
 }
 
+joinable!(account_balance_snapshots -> accounts (account_id));
 joinable!(accounts -> users (user_id));
 joinable!(bank_transactions -> accounts (account_id));
 joinable!(bank_transactions -> vaults (vault_name));
+joinable!(journal_lines -> journal_entries (entry_id));
 joinable!(loan_payments -> loans (loan_id));
+joinable!(loan_rate_changes -> loans (loan_id));
+joinable!(loan_repayments -> bank_transactions (principal_transaction_id));
+joinable!(loan_repayments -> loans (loan_id));
 joinable!(loans -> users (user_id));
 joinable!(loans -> vaults (vault_name));
+joinable!(overdraft_protections -> vaults (vault_name));
+joinable!(savings_products -> vaults (vault_name));
+joinable!(scheduled_transfer_runs -> account_transactions (transaction_id));
+joinable!(scheduled_transfer_runs -> scheduled_transfers (scheduled_transfer_id));
+joinable!(statement_lines -> statements (statement_id));
+joinable!(statements -> accounts (account_id));
+joinable!(vault_balance_snapshots -> vaults (vault_name));
 
 allow_tables_to_appear_in_same_query!(
+    accrual_runs,
+    account_balance_snapshots,
     account_transactions,
     accounts,
     bank_transactions,
+    benchmark_rates,
+    fx_conversions,
+    fx_rates,
+    journal_entries,
+    journal_lines,
     loan_payments,
+    loan_rate_changes,
+    loan_repayments,
     loans,
+    overdraft_protections,
+    savings_products,
+    scheduled_transfer_runs,
+    scheduled_transfers,
+    statement_lines,
+    statements,
     users,
+    vault_balance_snapshots,
     vaults,
 );
@@ -0,0 +1,168 @@
+/*!
+snapshot keeps the daily balances of accounts and vaults so their balance at any time can be worked out
+
+The balance at a time starts from the latest snapshot taken at or before it and rolls forward through the money that
+moved since. Without such a snapshot it rolls the current balance back through the money that moved after the time.
+*/
+use bigdecimal::{BigDecimal, Zero};
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::db;
+use crate::schema::{account_balance_snapshots, vault_balance_snapshots};
+use crate::types::{Id, Time};
+
+/// An account's balance at the end of a day
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+#[table_name = "account_balance_snapshots"]
+pub struct AccountSnapshot {
+	pub id: Id,
+	pub account_id: Id,
+	pub balance: BigDecimal,
+	/// the time the balance was taken at, the start of the following day
+	pub taken_at: Time,
+	pub created_at: Time,
+}
+
+#[derive(Insertable)]
+#[table_name = "account_balance_snapshots"]
+struct NewAccountSnapshot<'a> {
+	account_id: &'a Id,
+	balance: &'a BigDecimal,
+	taken_at: &'a Time,
+}
+
+/// A vault's balance at the end of a day
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+#[table_name = "vault_balance_snapshots"]
+pub struct VaultSnapshot {
+	pub id: Id,
+	pub vault_name: String,
+	pub balance: BigDecimal,
+	/// the time the balance was taken at, the start of the following day
+	pub taken_at: Time,
+	pub created_at: Time,
+}
+
+#[derive(Insertable)]
+#[table_name = "vault_balance_snapshots"]
+struct NewVaultSnapshot<'a> {
+	vault_name: &'a str,
+	balance: &'a BigDecimal,
+	taken_at: &'a Time,
+}
+
+/// Money that moved into or out of an account or vault
+#[derive(Clone, PartialEq, Debug)]
+pub struct Movement {
+	/// positive when money moved in, negative when it moved out
+	pub amount: BigDecimal,
+	pub created_at: Time,
+}
+
+/// Rolls the balance a snapshot was taken with forward through the movements made after it, up to and including the time
+pub fn roll_forward(balance: &BigDecimal, movements: &[Movement], at: &Time) -> BigDecimal {
+	movements.iter()
+		.filter(|m| &m.created_at <= at)
+		.fold(balance.clone(), |balance, m| balance + &m.amount)
+}
+
+/// Rolls the current balance back through the movements made after the time
+pub fn roll_back(balance: &BigDecimal, movements: &[Movement], at: &Time) -> BigDecimal {
+	let moved = movements.iter()
+		.filter(|m| &m.created_at > at)
+		.fold(BigDecimal::zero(), |moved, m| moved + &m.amount);
+	balance - moved
+}
+
+/// Data store implementation for operating on balance snapshots in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	/// Stores the account's balance at the time, keeping the snapshot already taken at that time if there is one
+	pub fn create_for_account(&self, conn: &PgConnection, account_id: &Id, balance: &BigDecimal, taken_at: &Time) -> db::Result<()> {
+		diesel::insert_into(account_balance_snapshots::table)
+			.values(NewAccountSnapshot { account_id, balance, taken_at })
+			.on_conflict((account_balance_snapshots::account_id, account_balance_snapshots::taken_at))
+			.do_nothing()
+			.execute(conn)?;
+		Ok(())
+	}
+	
+	/// Stores the vault's balance at the time, keeping the snapshot already taken at that time if there is one
+	pub fn create_for_vault(&self, conn: &PgConnection, vault_name: &str, balance: &BigDecimal, taken_at: &Time) -> db::Result<()> {
+		diesel::insert_into(vault_balance_snapshots::table)
+			.values(NewVaultSnapshot { vault_name, balance, taken_at })
+			.on_conflict((vault_balance_snapshots::vault_name, vault_balance_snapshots::taken_at))
+			.do_nothing()
+			.execute(conn)?;
+		Ok(())
+	}
+	
+	/// Finds the account's latest snapshot taken at or before the time
+	pub fn find_latest_for_account(&self, conn: &PgConnection, account_id: &Id, at: &Time) -> db::Result<Option<AccountSnapshot>> {
+		account_balance_snapshots::table
+			.filter(account_balance_snapshots::account_id.eq(account_id)
+				.and(account_balance_snapshots::taken_at.le(at)))
+			.order(account_balance_snapshots::taken_at.desc())
+			.first(conn)
+			.optional()
+			.map_err(Into::into)
+	}
+	
+	/// Finds the vault's latest snapshot taken at or before the time
+	pub fn find_latest_for_vault(&self, conn: &PgConnection, vault_name: &str, at: &Time) -> db::Result<Option<VaultSnapshot>> {
+		vault_balance_snapshots::table
+			.filter(vault_balance_snapshots::vault_name.eq(vault_name)
+				.and(vault_balance_snapshots::taken_at.le(at)))
+			.order(vault_balance_snapshots::taken_at.desc())
+			.first(conn)
+			.optional()
+			.map_err(Into::into)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::{Duration, TimeZone, Utc};
+	
+	use super::*;
+	
+	#[test]
+	fn roll_balances_to_a_time() {
+		let start = Utc.ymd(2020, 7, 1).and_hms(0, 0, 0);
+		let hour = |n: i64| start + Duration::hours(n);
+		let movements = vec![
+			Movement { amount: BigDecimal::from(100), created_at: hour(1) },
+			Movement { amount: BigDecimal::from(-30), created_at: hour(2) },
+			Movement { amount: BigDecimal::from(5), created_at: hour(3) },
+		];
+		
+		// a snapshot of 200 taken at the start of the day
+		assert_eq!(roll_forward(&BigDecimal::from(200), &movements, &start), BigDecimal::from(200));
+		assert_eq!(roll_forward(&BigDecimal::from(200), &movements, &hour(2)), BigDecimal::from(270));
+		assert_eq!(roll_forward(&BigDecimal::from(200), &movements, &hour(5)), BigDecimal::from(275));
+		
+		// a current balance of 275 once every movement was made
+		assert_eq!(roll_back(&BigDecimal::from(275), &movements, &hour(5)), BigDecimal::from(275));
+		assert_eq!(roll_back(&BigDecimal::from(275), &movements, &hour(2)), BigDecimal::from(270));
+		assert_eq!(roll_back(&BigDecimal::from(275), &movements, &start), BigDecimal::from(200));
+	}
+	
+	#[test]
+	fn movements_at_the_snapshot_time_are_in_its_balance() {
+		let start = Utc.ymd(2020, 7, 1).and_hms(0, 0, 0);
+		let after = start + Duration::microseconds(1);
+		let at_start = Movement { amount: BigDecimal::from(100), created_at: start };
+		let just_after = Movement { amount: BigDecimal::from(-30), created_at: after };
+		
+		// the balance taken at the start of the day includes money moved at that instant but not a moment later
+		assert_eq!(roll_back(&BigDecimal::from(70), &[at_start, just_after.clone()], &start), BigDecimal::from(100));
+		assert_eq!(roll_forward(&BigDecimal::from(100), &[just_after.clone()], &start), BigDecimal::from(100));
+		assert_eq!(roll_forward(&BigDecimal::from(100), &[just_after], &after), BigDecimal::from(70));
+	}
+}
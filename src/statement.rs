@@ -0,0 +1,326 @@
+/*!
+statement keeps the monthly statements generated for accounts
+
+A statement is a snapshot of the account's history when it was generated. Its figures and lines are stored with it and
+never recalculated, so a statement shows the same figures however the underlying transactions change later.
+*/
+use std::fmt::Write;
+
+use bigdecimal::{BigDecimal, Signed, Zero};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::history::{Entry, EntryType};
+use crate::money::{self, Currency, RoundingMode};
+use crate::schema::{statement_lines, statements};
+use crate::types::{Date, Id, Time};
+
+/// The opening and closing balances of an account for a calendar month and what moved money in between
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+pub struct Statement {
+	pub id: Id,
+	pub account_id: Id,
+	/// the first day of the month
+	pub period_start: Date,
+	/// the last day of the month
+	pub period_end: Date,
+	pub currency: Currency,
+	pub opening_balance: BigDecimal,
+	pub closing_balance: BigDecimal,
+	/// interest paid to the account
+	pub interest_credited: BigDecimal,
+	/// interest charged on a negative balance
+	pub interest_charged: BigDecimal,
+	/// late fees and overdraft fees charged to the account
+	pub fees_charged: BigDecimal,
+	pub created_at: Time,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "statements"]
+pub struct NewStatement {
+	pub account_id: Id,
+	pub period_start: Date,
+	pub period_end: Date,
+	pub currency: Currency,
+	pub opening_balance: BigDecimal,
+	pub closing_balance: BigDecimal,
+	pub interest_credited: BigDecimal,
+	pub interest_charged: BigDecimal,
+	pub fees_charged: BigDecimal,
+}
+
+/// A movement of money on a statement, as it was when the statement was generated
+#[derive(Queryable, Identifiable, Serialize, PartialEq, Debug)]
+pub struct StatementLine {
+	pub id: Id,
+	#[serde(skip)]
+	pub statement_id: Id,
+	/// the line's position on the statement, starting at 1 for the oldest
+	pub line_number: i32,
+	/// id of the bank transaction, account transaction or conversion that moved the money
+	pub transaction_id: Id,
+	pub entry_type: EntryType,
+	/// positive when money moved into the account, negative when it moved out
+	pub amount: BigDecimal,
+	/// the account's balance once the line was applied
+	pub balance: BigDecimal,
+	pub vault_name: Option<String>,
+	pub counterparty_id: Option<Id>,
+	pub posted_at: Time,
+}
+
+#[derive(Insertable)]
+#[table_name = "statement_lines"]
+struct NewStatementLine<'a> {
+	statement_id: &'a Id,
+	line_number: i32,
+	transaction_id: &'a Id,
+	entry_type: EntryType,
+	amount: &'a BigDecimal,
+	balance: &'a BigDecimal,
+	vault_name: Option<&'a str>,
+	counterparty_id: Option<&'a Id>,
+	posted_at: &'a Time,
+}
+
+/// A statement that hasn't been stored yet, with the history entries that become its lines
+#[derive(Debug)]
+pub struct Draft {
+	pub statement: NewStatement,
+	pub lines: Vec<Entry>,
+}
+
+impl Draft {
+	/// Drafts the statement for the period from the account's whole history, oldest first
+	pub fn new(account_id: Id, currency: Currency, period_start: Date, period_end: Date, history: Vec<Entry>) -> Self {
+		let date = |e: &Entry| e.created_at.naive_utc().date();
+		let opening_balance = history.iter()
+			.take_while(|e| date(e) < period_start)
+			.last()
+			.map_or_else(BigDecimal::zero, |e| e.balance.clone());
+		let lines: Vec<Entry> = history.into_iter()
+			.filter(|e| date(e) >= period_start && date(e) <= period_end)
+			.collect();
+		let closing_balance = lines.last().map_or_else(|| opening_balance.clone(), |e| e.balance.clone());
+		
+		let sum = |matches: &dyn Fn(&Entry) -> bool| lines.iter()
+			.filter(|e| matches(e))
+			.fold(BigDecimal::zero(), |sum, e| sum + e.amount.abs());
+		let interest_credited = sum(&|e| e.entry_type == EntryType::Interest && e.amount.is_positive());
+		let interest_charged = sum(&|e| e.entry_type == EntryType::Interest && e.amount.is_negative());
+		let fees_charged = sum(&|e| e.entry_type == EntryType::Fee);
+		
+		Draft {
+			statement: NewStatement {
+				account_id,
+				period_start,
+				period_end,
+				currency,
+				opening_balance,
+				closing_balance,
+				interest_credited,
+				interest_charged,
+				fees_charged,
+			},
+			lines,
+		}
+	}
+}
+
+/// The formats a statement is rendered in
+#[derive(Clone, Copy, Deserialize, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+	Csv,
+	Json,
+	Text,
+}
+
+impl Default for Format {
+	fn default() -> Self { Format::Json }
+}
+
+impl Format {
+	/// The media type of a statement rendered in the format
+	pub fn content_type(&self) -> &'static str {
+		match self {
+			Format::Csv => "text/csv",
+			Format::Json => "application/json",
+			Format::Text => "text/plain",
+		}
+	}
+}
+
+/// A statement with its lines, oldest first
+#[derive(Serialize, PartialEq, Debug)]
+pub struct Document {
+	#[serde(flatten)]
+	pub statement: Statement,
+	pub lines: Vec<StatementLine>,
+}
+
+impl Document {
+	pub fn render(&self, format: Format) -> String {
+		match format {
+			Format::Csv => self.to_csv(),
+			Format::Json => serde_json::to_string_pretty(self).expect("statement serializes to json"),
+			Format::Text => self.to_text(),
+		}
+	}
+	
+	/// Renders the statement as CSV, one row per line between rows for the opening and closing balances
+	pub fn to_csv(&self) -> String {
+		let s = &self.statement;
+		let mut csv = String::from("date,type,amount,balance,transaction_id,counterparty_id,vault_name\n");
+		writeln!(csv, "{},opening_balance,,{},,,", s.period_start, self.amount(&s.opening_balance)).unwrap();
+		for line in &self.lines {
+			writeln!(csv, "{},{},{},{},{},{},{}",
+				line.posted_at.naive_utc().date(),
+				line.entry_type,
+				self.amount(&line.amount),
+				self.amount(&line.balance),
+				line.transaction_id,
+				line.counterparty_id.map(|id| id.to_string()).unwrap_or_default(),
+				line.vault_name.as_deref().unwrap_or_default(),
+			).unwrap();
+		}
+		writeln!(csv, "{},closing_balance,,{},,,", s.period_end, self.amount(&s.closing_balance)).unwrap();
+		csv
+	}
+	
+	/// Renders the statement as plain text for printing or email
+	pub fn to_text(&self) -> String {
+		let s = &self.statement;
+		let mut text = String::new();
+		writeln!(text, "Statement for account {}", s.account_id).unwrap();
+		writeln!(text, "Period: {} to {}", s.period_start, s.period_end).unwrap();
+		writeln!(text, "Currency: {}", s.currency).unwrap();
+		writeln!(text).unwrap();
+		writeln!(text, "{:<20}{:>16}", "Opening balance", self.amount(&s.opening_balance)).unwrap();
+		writeln!(text).unwrap();
+		writeln!(text, "{:<12}{:<20}{:>16}{:>16}", "Date", "Type", "Amount", "Balance").unwrap();
+		for line in &self.lines {
+			writeln!(text, "{:<12}{:<20}{:>16}{:>16}",
+				line.posted_at.naive_utc().date().to_string(),
+				line.entry_type.to_string(),
+				self.amount(&line.amount),
+				self.amount(&line.balance),
+			).unwrap();
+		}
+		writeln!(text).unwrap();
+		writeln!(text, "{:<20}{:>16}", "Interest credited", self.amount(&s.interest_credited)).unwrap();
+		writeln!(text, "{:<20}{:>16}", "Interest charged", self.amount(&s.interest_charged)).unwrap();
+		writeln!(text, "{:<20}{:>16}", "Fees charged", self.amount(&s.fees_charged)).unwrap();
+		writeln!(text, "{:<20}{:>16}", "Closing balance", self.amount(&s.closing_balance)).unwrap();
+		text
+	}
+	
+	/// Formats the amount to the statement currency's minor unit
+	fn amount(&self, amount: &BigDecimal) -> String {
+		let currency = self.statement.currency;
+		money::round(amount, currency.minor_units(), RoundingMode::HalfEven).with_scale(currency.minor_units()).to_string()
+	}
+}
+
+/// Data store implementation for operating on statements in the database
+pub struct Repo;
+
+impl Repo {
+	pub fn new() -> Self {
+		Repo
+	}
+	
+	/// Stores the drafted statement and its lines
+	pub fn create(&self, conn: &PgConnection, draft: Draft) -> db::Result<Document> {
+		let statement: Statement = diesel::insert_into(statements::table)
+			.values(&draft.statement)
+			.get_result(conn)?;
+		
+		let new_lines: Vec<NewStatementLine> = draft.lines.iter().enumerate()
+			.map(|(i, e)| NewStatementLine {
+				statement_id: &statement.id,
+				line_number: i as i32 + 1,
+				transaction_id: &e.id,
+				entry_type: e.entry_type,
+				amount: &e.amount,
+				balance: &e.balance,
+				vault_name: e.vault_name.as_deref(),
+				counterparty_id: e.counterparty_id.as_ref(),
+				posted_at: &e.created_at,
+			})
+			.collect();
+		let lines = diesel::insert_into(statement_lines::table)
+			.values(&new_lines)
+			.get_results(conn)?;
+		
+		Ok(Document { statement, lines })
+	}
+	
+	/// Finds the account's statement for the month starting on the date, with its lines
+	pub fn find(&self, conn: &PgConnection, account_id: &Id, period_start: &Date) -> db::Result<Document> {
+		let statement: Statement = statements::table
+			.filter(statements::account_id.eq(account_id).and(statements::period_start.eq(period_start)))
+			.first(conn)?;
+		let lines = statement_lines::table
+			.filter(statement_lines::statement_id.eq(&statement.id))
+			.order(statement_lines::line_number.asc())
+			.load(conn)?;
+		
+		Ok(Document { statement, lines })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+	
+	use chrono::{TimeZone, Utc};
+	
+	use super::*;
+	
+	fn dec(s: &str) -> BigDecimal {
+		BigDecimal::from_str(s).unwrap()
+	}
+	
+	fn entry(entry_type: EntryType, amount: &str, balance: &str, day: u32) -> Entry {
+		Entry {
+			id: Id::new_v4(),
+			entry_type,
+			amount: dec(amount),
+			currency: Currency::Usd,
+			balance: dec(balance),
+			vault_name: Some("main".to_string()),
+			counterparty_id: None,
+			created_at: Utc.ymd(2020, 7, day).and_hms(12, 0, 0),
+		}
+	}
+	
+	#[test]
+	fn draft_statement_for_the_period() {
+		let history = vec![
+			entry(EntryType::Deposit, "500", "500", 1),
+			entry(EntryType::Withdraw, "-100", "400", 9),
+			entry(EntryType::Fee, "-35", "365", 10),
+			entry(EntryType::Interest, "1.25", "366.25", 15),
+			entry(EntryType::Interest, "-0.50", "365.75", 20),
+			entry(EntryType::Deposit, "50", "415.75", 25),
+		];
+		
+		let draft = Draft::new(Id::new_v4(), Currency::Usd, Date::from_ymd(2020, 7, 5), Date::from_ymd(2020, 7, 20), history);
+		let s = &draft.statement;
+		assert_eq!(s.opening_balance, dec("500"));
+		assert_eq!(s.closing_balance, dec("365.75"));
+		assert_eq!(s.interest_credited, dec("1.25"));
+		assert_eq!(s.interest_charged, dec("0.50"));
+		assert_eq!(s.fees_charged, dec("35"));
+		assert_eq!(draft.lines.len(), 4);
+		
+		// a period without any activity opens and closes at the balance carried into it
+		let history = vec![entry(EntryType::Deposit, "500", "500", 1)];
+		let draft = Draft::new(Id::new_v4(), Currency::Usd, Date::from_ymd(2020, 7, 5), Date::from_ymd(2020, 7, 20), history);
+		assert_eq!((draft.statement.opening_balance, draft.statement.closing_balance), (dec("500"), dec("500")));
+		assert!(draft.lines.is_empty());
+	}
+}
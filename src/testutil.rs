@@ -11,9 +11,11 @@ use diesel::query_builder::InsertStatement;
 use diesel::r2d2::ConnectionManager;
 use r2d2::PooledConnection;
 
-use crate::{account, account_transaction, bank_transaction, db, loan, user, vault};
+use crate::{account, account_transaction, accrual, bank_transaction, benchmark, db, fx, ledger, loan, overdraft, savings, scheduled_transfer, snapshot, statement, user, vault};
 use crate::account::{Account, AccountType, NewAccount};
+use crate::money::Currency;
 use crate::schema::{accounts, users, vaults};
+use crate::types::{Date, DateExt};
 use crate::user::{NewUser, User};
 use crate::vault::{NewVault, Vault};
 
@@ -45,11 +47,17 @@ impl Fixture {
 	
 	/// Inserts a bank vault named "main" with an initial amount
 	pub fn insert_main_vault(&self, initial_amount: u32) -> Vault {
+		self.insert_vault("main", initial_amount, Currency::Usd)
+	}
+	
+	/// Inserts a bank vault holding funds in the currency
+	pub fn insert_vault(&self, name: &str, initial_amount: u32, currency: Currency) -> Vault {
 		let initial_amount = BigDecimal::from(initial_amount);
 		diesel::insert_into(vaults::table)
 			.values(NewVault {
-				name: "main",
+				name,
 				initial_amount,
+				currency,
 			})
 			.get_result(&self.conn())
 			.unwrap()
@@ -58,11 +66,27 @@ impl Fixture {
 	pub fn teardown(&self) {
 		// Order matters here since tables hold foreign keys
 		let tables = vec![
+			"accrual_runs",
+			"journal_lines",
+			"journal_entries",
+			"loan_repayments",
 			"loan_payments",
+			"loan_rate_changes",
 			"loans",
+			"benchmark_rates",
+			"overdraft_protections",
+			"fx_conversions",
+			"fx_rates",
+			"statement_lines",
+			"statements",
+			"account_balance_snapshots",
+			"vault_balance_snapshots",
+			"scheduled_transfer_runs",
+			"scheduled_transfers",
 			"account_transactions",
 			"bank_transactions",
 			"accounts",
+			"savings_products",
 			"vaults",
 			"users",
 		];
@@ -85,6 +109,16 @@ pub struct Suite {
 	pub account_transaction_repo: account_transaction::Repo,
 	pub loan_repo: loan::Repo,
 	pub loan_payment_repo: loan::PaymentRepo,
+	pub loan_rate_change_repo: loan::RateChangeRepo,
+	pub benchmark_repo: benchmark::Repo,
+	pub ledger_repo: ledger::Repo,
+	pub accrual_run_repo: accrual::Repo,
+	pub savings_product_repo: savings::Repo,
+	pub overdraft_repo: overdraft::Repo,
+	pub fx_repo: fx::Repo,
+	pub statement_repo: statement::Repo,
+	pub snapshot_repo: snapshot::Repo,
+	pub scheduled_transfer_repo: scheduled_transfer::Repo,
 }
 
 impl Suite {
@@ -93,13 +127,23 @@ impl Suite {
 		fixture.teardown();
 		
 		let suite = Suite {
-			user_repo: user::Repo::new(fixture.pool.clone()),
-			account_repo: account::Repo::new(fixture.pool.clone()),
-			vault_repo: vault::Repo::new(fixture.pool.clone()),
-			bank_transaction_repo: bank_transaction::Repo::new(fixture.pool.clone()),
-			account_transaction_repo: account_transaction::Repo::new(fixture.pool.clone()),
-			loan_repo: loan::Repo::new(fixture.pool.clone()),
-			loan_payment_repo: loan::PaymentRepo::new(fixture.pool.clone()),
+			user_repo: user::Repo::new(),
+			account_repo: account::Repo::new(),
+			vault_repo: vault::Repo::new(),
+			bank_transaction_repo: bank_transaction::Repo::new(),
+			account_transaction_repo: account_transaction::Repo::new(),
+			loan_repo: loan::Repo::new(),
+			loan_payment_repo: loan::PaymentRepo::new(),
+			loan_rate_change_repo: loan::RateChangeRepo::new(),
+			benchmark_repo: benchmark::Repo::new(),
+			ledger_repo: ledger::Repo::new(),
+			accrual_run_repo: accrual::Repo::new(),
+			savings_product_repo: savings::Repo::new(),
+			overdraft_repo: overdraft::Repo::new(),
+			fx_repo: fx::Repo::new(),
+			statement_repo: statement::Repo::new(),
+			snapshot_repo: snapshot::Repo::new(),
+			scheduled_transfer_repo: scheduled_transfer::Repo::new(),
 		};
 		
 		suite
@@ -157,6 +201,34 @@ impl<'a> UserFactory {
 	}
 }
 
+/// A level payment loan of the principal from the main vault, awaiting approval
+///
+/// It's issued on Jan 1st 2020 at 2% and repaid and compounded monthly over a year. Tests override the terms they
+/// depend on.
+pub fn new_loan(user_id: uuid::Uuid, principal: u32) -> loan::NewLoan {
+	let issue_date = Date::from_ymd(2020, 1, 1);
+	loan::NewLoan {
+		user_id,
+		vault_name: "main".to_string(),
+		orig_principal: BigDecimal::from(principal),
+		balance: BigDecimal::from(principal),
+		interest_rate: 200,
+		issue_date,
+		maturity_date: issue_date.increment_date_by_months(12),
+		payment_frequency: 1,
+		compound_frequency: 1,
+		state: Default::default(),
+		amortization_method: Default::default(),
+		day_count_convention: Default::default(),
+		benchmark: None,
+		rate_margin: 0,
+		rate_floor: None,
+		rate_cap: None,
+		rate_reset_frequency: 0,
+		currency: Currency::Usd,
+	}
+}
+
 /// Generates Account test data
 pub struct AccountFactory {
 	pool: db::PgPool
@@ -168,9 +240,15 @@ impl<'a> AccountFactory {
 	}
 	
 	pub fn checking_account(&self, user_id: uuid::Uuid) -> Account {
+		self.checking_account_in(user_id, Currency::Usd)
+	}
+	
+	/// Inserts a checking account that holds its balance in the currency
+	pub fn checking_account_in(&self, user_id: uuid::Uuid, currency: Currency) -> Account {
 		let payload = NewAccount {
 			user_id,
 			account_type: AccountType::Checking,
+			currency,
 		};
 		let conn = self.pool.get().unwrap();
 		diesel::insert_into(accounts::table)
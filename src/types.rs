@@ -5,27 +5,54 @@ pub type Time = DateTime<Utc>;
 pub type Date = NaiveDate;
 
 pub trait DateExt {
+	/// Adds months to the date
+	///
+	/// The day is clamped to the last day of the resulting month, e.g. Jan 31 + 1 month is Feb 28 (or 29)
 	fn increment_date_by_months(&self, num_months: u16) -> Date;
+	
+	/// Counts the whole calendar months between the date and a later date, ignoring the day of the month
+	fn months_until(&self, later: &Date) -> u16;
 }
 
 impl DateExt for Date {
 	fn increment_date_by_months(&self, num_months: u16) -> Date {
-		let mut add_years: u32 = (num_months / 12) as u32;
-		let mut add_months: u32 = (num_months % 12) as u32;
+		let total_months = self.month0() + num_months as u32;
+		let result_year = self.year() + (total_months / 12) as i32;
+		let result_month = total_months % 12 + 1;
 		
-		let result_month: u32;
-		
-		let total_months = self.month() + (add_months as u32);
-		if total_months > 12 {
-			result_month = (total_months / 12);
-			add_years += 1;
-		} else {
-			result_month = total_months;
+		let mut result_day = self.day();
+		loop {
+			if let Some(date) = chrono::NaiveDate::from_ymd_opt(result_year, result_month, result_day) {
+				return date;
+			}
+			result_day -= 1;
 		}
-		
-		let result_year: i32 = self.year() + add_years as i32;
-		
-		chrono::NaiveDate::from_ymd(result_year, result_month, self.day())
+	}
+	
+	fn months_until(&self, later: &Date) -> u16 {
+		let months = (later.year() - self.year()) * 12 + later.month() as i32 - self.month() as i32;
+		months.max(0) as u16
 	}
 }
 
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn increment_date_by_months() {
+		let date = Date::from_ymd(2020, 1, 31);
+		assert_eq!(date.increment_date_by_months(1), Date::from_ymd(2020, 2, 29));
+		assert_eq!(date.increment_date_by_months(11), Date::from_ymd(2020, 12, 31));
+		assert_eq!(date.increment_date_by_months(14), Date::from_ymd(2021, 3, 31));
+		assert_eq!(date.increment_date_by_months(25), Date::from_ymd(2022, 2, 28));
+	}
+	
+	#[test]
+	fn months_until() {
+		let date = Date::from_ymd(2020, 11, 15);
+		assert_eq!(date.months_until(&Date::from_ymd(2021, 2, 1)), 3);
+		assert_eq!(date.months_until(&Date::from_ymd(2020, 11, 30)), 0);
+		assert_eq!(date.months_until(&Date::from_ymd(2019, 1, 1)), 0);
+	}
+}
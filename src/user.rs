@@ -20,25 +20,21 @@ pub struct User {
 }
 
 /// Data store implementation for operating on users in the database
-pub struct Repo {
-	db: db::PgPool,
-}
+pub struct Repo;
 
 impl Repo {
-	pub fn new(db: db::PgPool) -> Self {
-		Repo { db }
+	pub fn new() -> Self {
+		Repo
 	}
 	
-	pub fn create(&self, new_user: NewUser) -> db::Result<User> {
-		let conn = &self.db.get()?;
+	pub fn create(&self, conn: &PgConnection, new_user: NewUser) -> db::Result<User> {
 		diesel::insert_into(users::table)
 			.values(&new_user)
 			.get_result(conn)
 			.map_err(Into::into)
 	}
 	
-	pub fn find_by_key(&self, key: FindKey) -> db::Result<User> {
-		let conn = &self.db.get()?;
+	pub fn find_by_key(&self, conn: &PgConnection, key: FindKey) -> db::Result<User> {
 		match key {
 			FindKey::ID(id) => {
 				users::table
@@ -83,7 +79,7 @@ mod tests {
 	fn insert_user() {
 		let fixture = Fixture::new();
 		let suite = Suite::setup();
-		let user = suite.user_repo.create(NewUser {
+		let user = suite.user_repo.create(&fixture.conn(), NewUser {
 			email: "example@gmail.com",
 			first_name: "Tom",
 			family_name: "Riddle",
@@ -97,10 +93,10 @@ mod tests {
 	#[test]
 	fn find_user_with_key() {
 		let fixture = Fixture::new();
-		let user = fixture.user_factory.bob();
-		
 		let suite = Suite::setup();
 		
+		let user = fixture.user_factory.bob();
+		
 		let email = user.email.borrow();
 		let id = user.id;
 		
@@ -112,7 +108,7 @@ mod tests {
 		
 		
 		for user_key in test_cases {
-			let got = suite.user_repo.find_by_key(user_key)
+			let got = suite.user_repo.find_by_key(&fixture.conn(), user_key)
 				.expect("found user");
 			
 			assert_eq!(user, got)
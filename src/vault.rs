@@ -1,10 +1,12 @@
 use std::ops::Neg;
 
 use bigdecimal::BigDecimal;
+use diesel::PgConnection;
 use diesel::prelude::*;
 
 use crate::bank_transaction::BankTransactionType;
 use crate::db;
+use crate::money::{Currency, Money};
 use crate::schema::vaults;
 
 /// Vault tracks funds stored by the bank
@@ -12,6 +14,8 @@ use crate::schema::vaults;
 pub struct Vault {
 	pub name: String,
 	pub amount: BigDecimal,
+	/// the currency the vault's funds are held in
+	pub currency: Currency,
 }
 
 #[derive(Insertable)]
@@ -20,18 +24,23 @@ pub struct NewVault<'a> {
 	pub name: &'a str,
 	#[column_name = "amount"]
 	pub initial_amount: BigDecimal,
+	pub currency: Currency,
 }
 
-/// Data store implementation for operating on vaults in the database
-pub struct Repo {
-	db: db::PgPool,
+impl Vault {
+	/// The funds held in the vault in its currency
+	pub fn balance(&self) -> Money {
+		Money::new(self.amount.clone(), self.currency)
+	}
 }
 
+/// Data store implementation for operating on vaults in the database
+pub struct Repo;
+
 impl Repo {
-	pub fn new(db: db::PgPool) -> Self { Repo { db } }
+	pub fn new() -> Self { Repo }
 	
-	pub fn find_by_name(&self, name: &str) -> db::Result<Vault> {
-		let conn = &self.db.get()?;
+	pub fn find_by_name(&self, conn: &PgConnection, name: &str) -> db::Result<Vault> {
 		vaults::table
 			.filter(vaults::name.eq(name))
 			.select((vaults::all_columns))
@@ -39,17 +48,23 @@ impl Repo {
 			.map_err(Into::into)
 	}
 	
-	pub fn increment(&self, vault_name: &str, amount: &BigDecimal) -> db::Result<Vault> {
-		self.transact(vault_name, amount)
+	pub fn find_all(&self, conn: &PgConnection) -> db::Result<Vec<Vault>> {
+		vaults::table
+			.select((vaults::all_columns))
+			.load::<Vault>(conn)
+			.map_err(Into::into)
+	}
+	
+	pub fn increment(&self, conn: &PgConnection, vault_name: &str, amount: &BigDecimal) -> db::Result<Vault> {
+		self.transact(conn, vault_name, amount)
 	}
 	
-	pub fn decrement(&self, vault_name: &str, amount: &BigDecimal) -> db::Result<Vault> {
+	pub fn decrement(&self, conn: &PgConnection, vault_name: &str, amount: &BigDecimal) -> db::Result<Vault> {
 		let neg = amount.neg();
-		self.transact(vault_name, &neg)
+		self.transact(conn, vault_name, &neg)
 	}
 	
-	fn transact(&self, vault_name: &str, amount: &BigDecimal) -> db::Result<Vault> {
-		let conn = &self.db.get()?;
+	fn transact(&self, conn: &PgConnection, vault_name: &str, amount: &BigDecimal) -> db::Result<Vault> {
 		diesel::update(vaults::table)
 			.filter(vaults::name.eq(vault_name))
 			.set(vaults::amount.eq(vaults::amount + amount))